@@ -1,4 +1,5 @@
 #[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Def {
     Type(TypeDef),
     Var(VarDef),
@@ -7,31 +8,59 @@ pub enum Def {
 pub enum Program {
     Def(Vec<Def>),
     Exp(Exp),
+    Module(Module, Vec<Def>),
 }
+// module Name
+// in  gpio16 : Bool
+// out gpio5 : Bool
+// use Std
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub span: Span,
+    pub inputs: Vec<(Id, TypeName)>,
+    pub outputs: Vec<(Id, TypeName)>,
+    // after ParserWrapper::load_program, contains every module whose definitions were inlined
+    pub uses: Vec<String>,
+}
+pub enum ModuleDecl {
+    In(Vec<(Id, TypeName)>),
+    Out(Vec<(Id, TypeName)>),
+    Use(Vec<String>),
+}
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum VarDef {
+    // omitted type annotations are None until Compiler::infer_types fills them
     Node {
         name: Id,
-        typ: TypeName,
+        typ: Option<TypeName>,
         init: Option<Exp>,
         val: Exp,
+        span: Span,
     },
     Data {
         name: Id,
-        typ: TypeName,
+        typ: Option<TypeName>,
         val: Exp,
+        span: Span,
     },
     Func {
         name: Id,
-        ret: TypeName,
-        params: Vec<(Id, TypeName)>,
+        ret: Option<TypeName>,
+        params: Vec<(Id, Option<TypeName>)>,
         body: Exp,
+        span: Span,
     },
 }
 #[derive(Debug, Clone)]
 pub struct TypeDef {
     pub name: TypeName,
+    pub params: Vec<String>, // type Maybe<a> = ...
     pub variants: Vec<Variant>,
+    // names of the fields of a record, whose only variant is named after the type
+    pub fields: Vec<Id>,
+    pub span: Span,
 }
 #[derive(Debug, Clone)]
 pub struct Variant {
@@ -45,6 +74,7 @@ pub enum Exp {
         cond: Box<Term>,
         then: Box<Exp>,
         els: Box<Exp>,
+        span: Span,
     },
     Term(Term),
     Block(Block),
@@ -53,28 +83,32 @@ pub enum Exp {
 pub struct Block {
     pub stmt: Vec<Stmt>,
     pub exp: Box<Exp>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Stmt {
     pub id: Id,
-    pub tname: TypeName,
+    pub tname: Option<TypeName>,
     pub val: Exp,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Branch {
     pub pat: Pattern,
+    // `pat when guard -> exp` is taken only if guard is true
+    pub guard: Option<Term>,
     pub exp: Block,
 }
 #[derive(Debug, Clone)]
 pub enum Pattern {
-    Int(i32),
-    Id(Id),
-    Variant(VariantName, Vec<Pattern>),
-    Tuple(Vec<Pattern>),
-    Bool(bool),
-    None,
+    Int(i32, Span),
+    Id(Id, Span),
+    Variant(VariantName, Vec<Pattern>, Span),
+    Tuple(Vec<Pattern>, Span),
+    Bool(bool, Span),
+    None(Span),
 }
 pub type Term = Logical;
 #[derive(Debug, Clone)]
@@ -125,20 +159,88 @@ pub enum Factor {
 }
 #[derive(Debug, Clone)]
 pub enum Unary {
-    Not(Primary),
-    Minus(Primary),
+    // span of the operator
+    Not(Primary, Span),
+    Minus(Primary, Span),
     Primary(Primary),
 }
 #[derive(Debug, Clone)]
 pub enum Primary {
-    Int(i32),
-    Bool(bool),
-    Exp(Box<Exp>),
-    Id(Id),
-    Last(Id),
-    Variant(VariantName, Vec<Exp>),
-    Tuple(Vec<Exp>),
-    FnCall(Id, Vec<Exp>),
+    Int(i32, Span),
+    Float(f32, Span),
+    Bool(bool, Span),
+    Exp(Box<Exp>, Span),
+    Id(Id, Span),
+    Last(Id, Span),
+    // type args of a generic type or func are filled by Compiler::infer_types
+    Variant(VariantName, Vec<Exp>, Vec<TypeName>, Span),
+    Tuple(Vec<Exp>, Span),
+    FnCall(Id, Vec<Exp>, Vec<TypeName>, Span),
+    // toFloat(e), toInt(e)
+    ToFloat(Box<Exp>, Span),
+    ToInt(Box<Exp>, Span),
+    // r.x and t.0
+    Field(Box<Primary>, Id, Span),
+    Index(Box<Primary>, usize, Span),
+    // Reading { x = 1, ok = true }, whose type args are filled as those of Variant
+    Record(VariantName, Vec<(Id, Exp)>, Vec<TypeName>, Span),
+    // { r with x = 1 }
+    Update(Box<Term>, Vec<(Id, Exp)>, Span),
+}
+// integer literal as written, before its sign is known.
+// val is None if it does not even fit in u64
+#[derive(Debug, Clone, Copy)]
+pub struct IntLit {
+    pub val: Option<u64>,
+    pub bits: bool, // 0x.. or 0b.., which may use the sign bit as in 0xFFFFFFFF
+}
+impl IntLit {
+    pub fn parse(digits: &str, radix: u32, bits: bool) -> Self {
+        let val = digits.chars().filter(|c| *c != '_').try_fold(0u64, |n, c| {
+            n.checked_mul(radix as u64)?
+                .checked_add(c.to_digit(radix)? as u64)
+        });
+        IntLit { val, bits }
+    }
+    // 'a', '\n'
+    pub fn char(s: &str) -> Self {
+        let c = match &s[1..s.len() - 1] {
+            "\\n" => '\n',
+            "\\r" => '\r',
+            "\\t" => '\t',
+            "\\0" => '\0',
+            "\\\\" => '\\',
+            "\\'" => '\'',
+            c => c.chars().next().unwrap(),
+        };
+        IntLit {
+            val: Some(c as u64),
+            bits: false,
+        }
+    }
+    pub fn to_i32(self, neg: bool) -> Option<i32> {
+        let v = self.val?;
+        if neg {
+            (v <= 1 << 31).then(|| (v as i64).wrapping_neg() as i32)
+        } else if self.bits {
+            u32::try_from(v).ok().map(|v| v as i32)
+        } else {
+            i32::try_from(v).ok()
+        }
+    }
+    // for a fallible action of the grammar
+    pub fn value(self, neg: bool, span: Span) -> Result<i32, LitError> {
+        self.to_i32(neg).ok_or(LitError {
+            msg: "integer literal is out of range for Int".to_string(),
+            span,
+        })
+    }
+}
+// a literal the lexer accepted, but which is not a value
+#[derive(Debug, Clone)]
+pub struct LitError {
+    pub msg: String,
+    pub span: Span,
 }
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Id(pub String);
@@ -146,9 +248,368 @@ pub struct Id(pub String);
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TypeName {
     Tuple(Vec<TypeName>),
-    User(String),
+    User(String, Vec<TypeName>), // Maybe<Int>
+    Var(String),                 // type parameter
     Bool,
     Int,
+    Float,
 }
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VariantName(pub String);
+
+// byte range start..end of the source registered as `file` in diagnostic::SourceMap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: usize,
+    pub start: usize,
+    pub end: usize,
+}
+impl Span {
+    pub fn new(file: usize, start: usize, end: usize) -> Self {
+        Self { file, start, end }
+    }
+    // span from the start of self to the end of other
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.file, self.start, other.end)
+    }
+}
+impl VarDef {
+    pub fn span(&self) -> Span {
+        match self {
+            VarDef::Node { span, .. } | VarDef::Data { span, .. } | VarDef::Func { span, .. } => {
+                *span
+            }
+        }
+    }
+}
+impl Exp {
+    pub fn span(&self) -> Span {
+        match self {
+            Exp::Match(t, branches) => match branches.last() {
+                Some(b) => t.span().to(b.exp.span),
+                None => t.span(),
+            },
+            Exp::If { span, .. } => *span,
+            Exp::Term(t) => t.span(),
+            Exp::Block(b) => b.span,
+        }
+    }
+}
+impl Branch {
+    pub fn span(&self) -> Span {
+        self.pat.span().to(self.exp.span)
+    }
+}
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Int(_, span)
+            | Pattern::Id(_, span)
+            | Pattern::Variant(_, _, span)
+            | Pattern::Tuple(_, span)
+            | Pattern::Bool(_, span)
+            | Pattern::None(span) => *span,
+        }
+    }
+}
+impl Logical {
+    pub fn span(&self) -> Span {
+        match self {
+            Logical::And(a, b) | Logical::Or(a, b) => a.span().to(b.span()),
+            Logical::BitWise(b) => b.span(),
+        }
+    }
+}
+impl BitWise {
+    pub fn span(&self) -> Span {
+        match self {
+            BitWise::And(a, b) | BitWise::Or(a, b) | BitWise::Xor(a, b) => a.span().to(b.span()),
+            BitWise::Comp(c) => c.span(),
+        }
+    }
+}
+impl Comp {
+    pub fn span(&self) -> Span {
+        match self {
+            Comp::Eq(a, b) | Comp::Neq(a, b) => a.span().to(b.span()),
+            Comp::Comp2(c) => c.span(),
+        }
+    }
+}
+impl Comp2 {
+    pub fn span(&self) -> Span {
+        match self {
+            Comp2::Leq(a, b) | Comp2::Ls(a, b) | Comp2::Geq(a, b) | Comp2::Gt(a, b) => {
+                a.span().to(b.span())
+            }
+            Comp2::Shift(s) => s.span(),
+        }
+    }
+}
+impl Shift {
+    pub fn span(&self) -> Span {
+        match self {
+            Shift::Left(a, b) | Shift::Right(a, b) => a.span().to(b.span()),
+            Shift::Add(a) => a.span(),
+        }
+    }
+}
+impl Add {
+    pub fn span(&self) -> Span {
+        match self {
+            Add::Plus(a, b) | Add::Minus(a, b) => a.span().to(b.span()),
+            Add::Factor(f) => f.span(),
+        }
+    }
+}
+impl Factor {
+    pub fn span(&self) -> Span {
+        match self {
+            Factor::Mul(a, b) | Factor::Div(a, b) | Factor::Mod(a, b) => a.span().to(b.span()),
+            Factor::Unary(u) => u.span(),
+        }
+    }
+}
+impl Unary {
+    pub fn span(&self) -> Span {
+        match self {
+            Unary::Not(p, span) | Unary::Minus(p, span) => span.to(p.span()),
+            Unary::Primary(p) => p.span(),
+        }
+    }
+}
+impl Primary {
+    pub fn span(&self) -> Span {
+        match self {
+            Primary::Int(_, span)
+            | Primary::Float(_, span)
+            | Primary::Bool(_, span)
+            | Primary::Exp(_, span)
+            | Primary::Id(_, span)
+            | Primary::Last(_, span)
+            | Primary::Variant(_, _, _, span)
+            | Primary::Tuple(_, span)
+            | Primary::FnCall(_, _, _, span)
+            | Primary::ToFloat(_, span)
+            | Primary::ToInt(_, span)
+            | Primary::Field(_, _, span)
+            | Primary::Index(_, _, span)
+            | Primary::Record(_, _, _, span)
+            | Primary::Update(_, _, span) => *span,
+        }
+    }
+}
+impl std::fmt::Display for TypeName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |ts: &[TypeName]| {
+            let ts: Vec<String> = ts.iter().map(|t| t.to_string()).collect();
+            ts.join(", ")
+        };
+        match self {
+            TypeName::Tuple(ts) => write!(f, "({})", join(ts)),
+            TypeName::User(name, args) if args.is_empty() => write!(f, "{name}"),
+            TypeName::User(name, args) => write!(f, "{name}<{}>", join(args)),
+            TypeName::Var(name) => write!(f, "{name}"),
+            TypeName::Bool => write!(f, "Bool"),
+            TypeName::Int => write!(f, "Int"),
+            TypeName::Float => write!(f, "Float"),
+        }
+    }
+}
+impl TypeName {
+    // replaces type parameters bound in env
+    pub fn subst(&self, env: &[(String, TypeName)]) -> TypeName {
+        match self {
+            TypeName::Tuple(ts) => TypeName::Tuple(ts.iter().map(|t| t.subst(env)).collect()),
+            TypeName::User(name, args) => {
+                TypeName::User(name.clone(), args.iter().map(|t| t.subst(env)).collect())
+            }
+            TypeName::Var(name) => match env.iter().find(|(v, _)| v == name) {
+                Some((_, t)) => t.clone(),
+                None => self.clone(),
+            },
+            TypeName::Bool | TypeName::Int | TypeName::Float => self.clone(),
+        }
+    }
+    // type parameters in order of appearance
+    pub fn vars(&self, res: &mut Vec<String>) {
+        match self {
+            TypeName::Tuple(ts) | TypeName::User(_, ts) => ts.iter().for_each(|t| t.vars(res)),
+            TypeName::Var(name) => {
+                if !res.contains(name) {
+                    res.push(name.clone())
+                }
+            }
+            TypeName::Bool | TypeName::Int | TypeName::Float => (),
+        }
+    }
+}
+
+// visits statements and primaries of expressions, to rewrite the annotations in them
+pub trait VisitMut {
+    fn stmt(&mut self, _: &mut Stmt) {}
+    fn primary(&mut self, _: &mut Primary) {}
+}
+impl VarDef {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            VarDef::Node { init, val, .. } => {
+                if let Some(init) = init {
+                    init.visit_mut(v);
+                }
+                val.visit_mut(v)
+            }
+            VarDef::Data { val, .. } => val.visit_mut(v),
+            VarDef::Func { body, .. } => body.visit_mut(v),
+        }
+    }
+}
+impl Exp {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            Exp::Match(t, branches) => {
+                t.visit_mut(v);
+                for b in branches {
+                    if let Some(g) = &mut b.guard {
+                        g.visit_mut(v);
+                    }
+                    b.exp.visit_mut(v);
+                }
+            }
+            Exp::If {
+                cond, then, els, ..
+            } => {
+                cond.visit_mut(v);
+                then.visit_mut(v);
+                els.visit_mut(v);
+            }
+            Exp::Term(t) => t.visit_mut(v),
+            Exp::Block(b) => b.visit_mut(v),
+        }
+    }
+}
+impl Block {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        for s in &mut self.stmt {
+            v.stmt(s);
+            s.val.visit_mut(v);
+        }
+        self.exp.visit_mut(v)
+    }
+}
+impl Logical {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            Logical::And(a, b) | Logical::Or(a, b) => {
+                a.visit_mut(v);
+                b.visit_mut(v)
+            }
+            Logical::BitWise(b) => b.visit_mut(v),
+        }
+    }
+}
+impl BitWise {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            BitWise::And(a, b) | BitWise::Or(a, b) | BitWise::Xor(a, b) => {
+                a.visit_mut(v);
+                b.visit_mut(v)
+            }
+            BitWise::Comp(c) => c.visit_mut(v),
+        }
+    }
+}
+impl Comp {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            Comp::Eq(a, b) | Comp::Neq(a, b) => {
+                a.visit_mut(v);
+                b.visit_mut(v)
+            }
+            Comp::Comp2(c) => c.visit_mut(v),
+        }
+    }
+}
+impl Comp2 {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            Comp2::Leq(a, b) | Comp2::Ls(a, b) | Comp2::Geq(a, b) | Comp2::Gt(a, b) => {
+                a.visit_mut(v);
+                b.visit_mut(v)
+            }
+            Comp2::Shift(s) => s.visit_mut(v),
+        }
+    }
+}
+impl Shift {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            Shift::Left(a, b) | Shift::Right(a, b) => {
+                a.visit_mut(v);
+                b.visit_mut(v)
+            }
+            Shift::Add(a) => a.visit_mut(v),
+        }
+    }
+}
+impl Add {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            Add::Plus(a, b) | Add::Minus(a, b) => {
+                a.visit_mut(v);
+                b.visit_mut(v)
+            }
+            Add::Factor(f) => f.visit_mut(v),
+        }
+    }
+}
+impl Factor {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            Factor::Mul(a, b) | Factor::Div(a, b) | Factor::Mod(a, b) => {
+                a.visit_mut(v);
+                b.visit_mut(v)
+            }
+            Factor::Unary(u) => u.visit_mut(v),
+        }
+    }
+}
+impl Unary {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        match self {
+            Unary::Not(p, _) | Unary::Minus(p, _) | Unary::Primary(p) => p.visit_mut(v),
+        }
+    }
+}
+impl Primary {
+    pub fn visit_mut(&mut self, v: &mut impl VisitMut) {
+        v.primary(self);
+        match self {
+            Primary::Exp(e, _) | Primary::ToFloat(e, _) | Primary::ToInt(e, _) => e.visit_mut(v),
+            Primary::Variant(_, es, _, _)
+            | Primary::Tuple(es, _)
+            | Primary::FnCall(_, es, _, _) => {
+                for e in es {
+                    e.visit_mut(v)
+                }
+            }
+            Primary::Field(p, _, _) | Primary::Index(p, _, _) => p.visit_mut(v),
+            Primary::Record(_, fields, _, _) => {
+                for (_, e) in fields {
+                    e.visit_mut(v)
+                }
+            }
+            Primary::Update(t, fields, _) => {
+                t.visit_mut(v);
+                for (_, e) in fields {
+                    e.visit_mut(v)
+                }
+            }
+            Primary::Int(..)
+            | Primary::Float(..)
+            | Primary::Bool(..)
+            | Primary::Id(..)
+            | Primary::Last(..) => (),
+        }
+    }
+}
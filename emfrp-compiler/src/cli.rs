@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::compile::compile_common::Type;
+
+pub type IoNode = (String, Type);
+
+pub const USAGE: &str = "\
+usage: emfrp-compiler [<command>] [options]
+
+commands:
+  repl                  start the REPL (default)
+  compile <file>        compile <file> into an image the machine loads at boot (<file>.bin and <file>.h)
+  run <file>            compile <file> and load it on the machine (<file> may be an image)
+  disasm <file>         print the instructions <file> compiles into (<file> may be an image)
+  asm <file>            assemble a listing printed by `disasm` into an image
+  graph <file>          print the dependency graph of the nodes of <file> in DOT
+  sim <file>            run <file> on the host VM for --ticks updates or the rows of --trace,
+                        and print the values of the nodes after each update in CSV
+
+options:
+  -p, --port <spec>     serial device, tcp:<host:port>, pipe:<path>[,<rx path>] or loopback
+  -b, --baud <rate>     baud rate of the serial port
+  -t, --timeout <ms>    how long to wait for a reply before sending a request again
+      --board <name>    board profile: esp32, uno, microbit, host
+      --input <name:Type>   input node of the machine (replaces the nodes of the board profile)
+      --output <name:Type>  output node of the machine (replaces the nodes of the board profile)
+  -o, --out <file>      image file of `compile` and `asm`, or the file `graph` and `sim` write
+      --json            `graph` prints JSON with the types and the update order, and `sim` JSON
+      --trace <file>    CSV or JSON values of input nodes per update for `sim`, and the values
+                        other nodes are expected to have
+      --ticks <n>       number of updates `sim` runs (the number of rows of --trace by default)
+      --header <file>   C header of `compile` which embeds the image
+  -d, --debug           print compiled code and raw bytes
+  -h, --help            print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Board {
+    Esp32,
+    Uno,
+    Microbit,
+    Host,
+}
+impl Board {
+    fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "esp32" => Ok(Board::Esp32),
+            "uno" => Ok(Board::Uno),
+            "microbit" => Ok(Board::Microbit),
+            "host" => Ok(Board::Host),
+            _ => bail!("unknown board {name}"),
+        }
+    }
+    // must be the same as the nodes registered in main of each machine
+    fn io_nodes(&self) -> (Vec<IoNode>, Vec<IoNode>) {
+        let node = |name: &str| vec![(name.to_string(), Type::Bool)];
+        match self {
+            Board::Esp32 => (node("gpio16"), node("gpio5")),
+            Board::Uno => (vec![], node("led")),
+            Board::Microbit => (node("gpio2"), node("gpio1")),
+            Board::Host => (vec![], vec![]),
+        }
+    }
+    fn default_port(&self) -> &'static str {
+        match self {
+            Board::Esp32 => "/dev/cu.usbserial-0001",
+            Board::Uno | Board::Microbit => "/dev/cu.usbmodem142101",
+            Board::Host => "loopback",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Repl,
+    Compile(String),
+    Run(String),
+    Disasm(String),
+    Asm(String),
+    Graph(String),
+    Sim(String),
+    Help,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    pub port: String,
+    pub baud_rate: u32,
+    pub timeout: Duration,
+    pub board: Board,
+    pub inputs: Vec<IoNode>,
+    pub outputs: Vec<IoNode>,
+    pub out: Option<String>,
+    pub header: Option<String>,
+    pub json: bool,
+    pub trace: Option<String>,
+    pub ticks: Option<usize>,
+    pub debug: bool,
+}
+
+fn parse_type(s: &str) -> Result<Type> {
+    match s {
+        "Int" => Ok(Type::Int),
+        "Bool" => Ok(Type::Bool),
+        "Float" => Ok(Type::Float),
+        _ => bail!("type of an input/output node must be Int, Bool or Float, but {s} was given"),
+    }
+}
+pub fn parse_node(s: &str) -> Result<IoNode> {
+    let (name, typ) = s
+        .split_once(':')
+        .ok_or(anyhow!("expected <name:Type>, but {s} was given"))?;
+    Ok((name.trim().to_string(), parse_type(typ.trim())?))
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options> {
+    let mut args = args.into_iter();
+    let mut positional = vec![];
+    let mut help = false;
+    let mut port = None;
+    let mut baud_rate = 115200;
+    let mut timeout = Duration::from_secs(2);
+    let mut board = Board::Esp32;
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    let mut io_given = false;
+    let mut out = None;
+    let mut header = None;
+    let mut json = false;
+    let mut trace = None;
+    let mut ticks = None;
+    let mut debug = false;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(anyhow!("{name} requires a value"));
+        match arg.as_str() {
+            "-p" | "--port" => port = Some(value(&arg)?),
+            "-b" | "--baud" => {
+                let v = value(&arg)?;
+                baud_rate = v.parse().map_err(|_| anyhow!("invalid baud rate {v}"))?
+            }
+            "-t" | "--timeout" => {
+                let v = value(&arg)?;
+                let ms = v.parse().map_err(|_| anyhow!("invalid timeout {v}"))?;
+                timeout = Duration::from_millis(ms)
+            }
+            "--board" => board = Board::from_name(&value(&arg)?)?,
+            "--input" => {
+                inputs.push(parse_node(&value(&arg)?)?);
+                io_given = true;
+            }
+            "--output" => {
+                outputs.push(parse_node(&value(&arg)?)?);
+                io_given = true;
+            }
+            "-o" | "--out" => out = Some(value(&arg)?),
+            "--header" => header = Some(value(&arg)?),
+            "--json" => json = true,
+            "--trace" => trace = Some(value(&arg)?),
+            "--ticks" => {
+                let v = value(&arg)?;
+                ticks = Some(
+                    v.parse()
+                        .map_err(|_| anyhow!("invalid number of ticks {v}"))?,
+                )
+            }
+            "-d" | "--debug" => debug = true,
+            "-h" | "--help" => help = true,
+            s if s.starts_with('-') => bail!("unknown option {s}"),
+            _ => positional.push(arg),
+        }
+    }
+    let positional: Vec<&str> = positional.iter().map(|s| s.as_str()).collect();
+    let command = match positional[..] {
+        _ if help => Command::Help,
+        [] | ["repl"] => Command::Repl,
+        ["compile", f] => Command::Compile(f.to_string()),
+        ["run", f] => Command::Run(f.to_string()),
+        ["disasm", f] => Command::Disasm(f.to_string()),
+        ["asm", f] => Command::Asm(f.to_string()),
+        ["graph", f] => Command::Graph(f.to_string()),
+        ["sim", f] => Command::Sim(f.to_string()),
+        [cmd @ ("compile" | "run" | "disasm" | "asm" | "graph" | "sim")] => {
+            bail!("usage: {cmd} <file>")
+        }
+        [cmd] => bail!("unknown command {cmd}"),
+        [_, .., arg] => bail!("unexpected argument {arg}"),
+    };
+    if !io_given {
+        (inputs, outputs) = board.io_nodes();
+    }
+    Ok(Options {
+        command,
+        port: port.unwrap_or(board.default_port().to_string()),
+        baud_rate,
+        timeout,
+        board,
+        inputs,
+        outputs,
+        out,
+        header,
+        json,
+        trace,
+        ticks,
+        debug,
+    })
+}
@@ -2,6 +2,7 @@ use std::collections::HashSet;
 
 use crate::ast::*;
 use crate::compile::compile_common::*;
+use crate::compile::dependency::uses;
 use crate::compile::typed_ast::*;
 use crate::insn::*;
 
@@ -11,39 +12,134 @@ impl Compiler {
     //in that case, this function returns Ok(None)
     pub fn compile(&mut self, prog: Program) -> Result<Option<CompiledCode>, CompileErr> {
         match prog {
-            Program::Def(defs) => {
-                self.compile_type_def(&defs)?;
-                let defs: Vec<VarDef> = defs
-                    .into_iter()
-                    .filter_map(|def| match def {
-                        Def::Type(_) => None,
-                        Def::Var(v) => Some(v),
-                    })
-                    .collect();
+            Program::Def(defs) => self.compile_defs(defs),
+            Program::Module(m, defs) => {
+                self.declare_module(&m).map_err(|e| e.at(m.span))?;
+                let res = self.compile_defs(defs)?;
+                self.imported.extend(m.uses);
+                Ok(res)
+            }
+            Program::Exp(e) => {
+                let (t, init) = self.compile_exp(e)?;
+                Ok(Some(CompiledCode::Eval(t, init)))
+            }
+        }
+    }
+    fn compile_defs(&mut self, defs: Vec<Def>) -> Result<Option<CompiledCode>, CompileErr> {
+        self.compile_type_def(&defs)?;
+        let mut defs: Vec<VarDef> = defs
+            .into_iter()
+            .filter_map(|def| match def {
+                Def::Type(_) => None,
+                Def::Var(v) => Some(v),
+            })
+            .collect();
 
-                if defs.len() == 0 {
-                    return Ok(None);
-                }
-                let res = self.compile_var_def(defs)?;
-                for nd in &mut self.node_info {
-                    nd.is_new = false;
-                    nd.has_value = true;
+        if defs.is_empty() {
+            return Ok(None);
+        }
+        self.infer_types(&mut defs)?;
+        let defs = self.instantiate_generics(defs)?;
+        self.compile_instantiated(defs)
+    }
+    fn compile_instantiated(&mut self, defs: Vec<VarDef>) -> CResult<Option<CompiledCode>> {
+        if defs.is_empty() {
+            return Ok(None);
+        }
+        let res = self.compile_var_def(defs)?;
+        for nd in &mut self.node_info {
+            nd.is_new = false;
+            nd.has_value = true;
+        }
+        for f in &mut self.func_info {
+            f.is_new = false;
+        }
+        for data in &mut self.data_info {
+            data.is_new = false;
+        }
+        Ok(Some(res))
+    }
+    // declares `in` and `out` nodes of the module header.
+    // nodes already declared must have the same type.
+    // returns nodes that were not declared yet, which the machine also needs to register
+    pub fn declare_module(&mut self, m: &Module) -> Result<NewIoNodes, CompileErr> {
+        let mut new_nodes = NewIoNodes::default();
+        for (is_input, (name, tname)) in m
+            .inputs
+            .iter()
+            .map(|nd| (true, nd))
+            .chain(m.outputs.iter().map(|nd| (false, nd)))
+        {
+            let typ = self.get_type_with_type_name(tname)?;
+            if typ.is_obj_type() {
+                return Err(CompileErr::InvalidIoDecl(format!(
+                    "type of {} must be Int, Bool or Float",
+                    name.0
+                )));
+            }
+            if let Some(i) = self.node_offset(name) {
+                let nd = &self.node_info[i];
+                // output nodes keep output_offset after being defined by the user
+                let declared_as = match nd.input_kind {
+                    NodeInputKind::Dev => Some(true),
+                    _ if nd.output_offset.is_some() => Some(false),
+                    _ => None,
+                };
+                if declared_as != Some(is_input) {
+                    return Err(CompileErr::InvalidIoDecl(format!(
+                        "{} is already defined as another kind of node",
+                        name.0
+                    )));
                 }
-                for f in &mut self.func_info {
-                    f.is_new = false;
+                if nd.typ != typ {
+                    return Err(CompileErr::InvalidIoDecl(format!(
+                        "{} is already declared with type {:?}",
+                        name.0, nd.typ
+                    )));
                 }
-                for data in &mut self.data_info {
-                    data.is_new = false;
+                continue;
+            }
+            // the machine numbers nodes in the order of registration:
+            // inputs, outputs, and then user-defined nodes
+            let blocked_by = if is_input {
+                |nd: &NodeInfo| !matches!(nd.input_kind, NodeInputKind::Dev)
+            } else {
+                |nd: &NodeInfo| {
+                    !matches!(nd.input_kind, NodeInputKind::Dev) && nd.output_offset.is_none()
                 }
-                Ok(Some(res))
+            };
+            if self.node_info.iter().any(blocked_by) {
+                let later = if is_input {
+                    "output nodes and nodes"
+                } else {
+                    "nodes"
+                };
+                return Err(CompileErr::InvalidIoDecl(format!(
+                    "{} must be declared before {later} defined by the user",
+                    name.0
+                )));
             }
-            Program::Exp(e) => {
-                let (t, init) = self.compile_exp(e)?;
-                Ok(Some(CompiledCode::Eval(t, init)))
+            if is_input {
+                self.add_input_node(&name.0, typ.clone());
+                new_nodes.inputs.push((name.0.clone(), typ));
+            } else {
+                self.add_output_node(&name.0, typ.clone());
+                new_nodes.outputs.push((name.0.clone(), typ));
             }
         }
+        Ok(new_nodes)
     }
-    pub fn compile_exp(&mut self, e: Exp) -> Result<(Type, Vec<Insn>), CompileErr> {
+    // generic funcs called in e are compiled for the types they are called with.
+    // the returned code defines the funcs that have not been compiled yet,
+    // and must be sent before the code of e
+    pub fn compile_instances(&mut self, e: &Exp) -> Result<Option<CompiledCode>, CompileErr> {
+        let mut e = e.clone();
+        self.infer_exp(&mut e)?;
+        let defs = self.instantiate_calls(&mut e)?;
+        self.compile_instantiated(defs)
+    }
+    pub fn compile_exp(&mut self, mut e: Exp) -> Result<(Type, Vec<Insn>), CompileErr> {
+        self.infer_exp(&mut e)?;
         let typed_exp = self.exp_type_check(e)?;
         let mut e = self.emit_code_exp(&typed_exp)?;
         if typed_exp.get_type().is_obj_type() {
@@ -57,42 +153,113 @@ impl Compiler {
     }
     pub fn compile_type_def(&mut self, defs: &Vec<Def>) -> Result<(), CompileErr> {
         for def in defs {
-            if let Def::Type(TypeDef { name, variants }) = def {
-                if self.types.contains_key(name) {
-                    return Err(CompileErr::TypeAlreadyExists);
-                } else {
-                    let s = match name {
-                        TypeName::User(s) => s,
-                        _ => return Err(CompileErr::TypeAlreadyExists),
-                    };
-
-                    let mut vars = Vec::with_capacity(variants.len());
-                    for Variant { constructor, elems } in variants {
-                        if elems.len() > 7 {
-                            return Err(CompileErr::TooManyFields);
-                        }
-                        let mut types = Vec::with_capacity(elems.len());
-                        for tname in elems {
-                            let typ = self.get_type_with_type_name(&tname)?;
-                            types.push(typ);
-                        }
-                        vars.push((constructor.clone(), types));
-                    }
-                    self.types
-                        .insert(name.clone(), Type::User(s.to_string(), vars));
+            if let Def::Type(TypeDef {
+                name,
+                params,
+                variants,
+                fields,
+                span,
+            }) = def
+            {
+                self.register_type(name, params, variants, fields)
+                    .map_err(|e| e.at(*span))?;
+                if let (TypeName::User(s, _), false) = (name, fields.is_empty()) {
+                    self.records.insert(s.clone(), fields.clone());
                 }
             }
         }
         Ok(())
     }
+    fn register_type(
+        &mut self,
+        name: &TypeName,
+        params: &[String],
+        variants: &Vec<Variant>,
+        fields: &[Id],
+    ) -> CResult<()> {
+        let s = match name {
+            TypeName::User(s, _) => s,
+            _ => return Err(CompileErr::TypeAlreadyExists),
+        };
+        if self.types.contains_key(name) || self.generic_types.contains_key(s) {
+            return Err(CompileErr::TypeAlreadyExists);
+        }
+        for (i, f) in fields.iter().enumerate() {
+            if fields[..i].contains(f) {
+                return Err(CompileErr::DuplicateField(f.0.clone()));
+            }
+        }
+        // tags of the variants are 16 bits in an extended object header
+        if variants.len() > u16::MAX as usize {
+            return Err(CompileErr::TooManyVariants);
+        }
+        if !params.is_empty() {
+            return self.register_generic_type(s, params, variants);
+        }
+
+        let mut vars = Vec::with_capacity(variants.len());
+        for Variant { constructor, elems } in variants {
+            if elems.len() > u8::MAX as usize {
+                return Err(CompileErr::TooManyFields);
+            }
+            let mut types = Vec::with_capacity(elems.len());
+            for tname in elems {
+                let typ = self.get_type_with_type_name(tname)?;
+                types.push(typ);
+            }
+            vars.push((constructor.clone(), types));
+        }
+        self.types
+            .insert(name.clone(), Type::User(s.to_string(), vec![], vars));
+        Ok(())
+    }
+    // the variants are checked by instantiating the type with Int for every parameter
+    fn register_generic_type(
+        &mut self,
+        name: &str,
+        params: &[String],
+        variants: &[Variant],
+    ) -> CResult<()> {
+        let env: Vec<(String, TypeName)> =
+            params.iter().map(|p| (p.clone(), TypeName::Int)).collect();
+        for (i, p) in params.iter().enumerate() {
+            if params[..i].contains(p) {
+                return Err(CompileErr::TypeAlreadyExists);
+            }
+        }
+        for Variant { elems, .. } in variants {
+            if elems.len() > u8::MAX as usize {
+                return Err(CompileErr::TooManyFields);
+            }
+            for tname in elems {
+                self.get_type_with_type_name(&tname.subst(&env))?;
+            }
+        }
+        self.generic_types
+            .insert(name.to_string(), (params.to_vec(), variants.to_vec()));
+        Ok(())
+    }
 
-    pub fn compile_var_def(&mut self, defs: Vec<VarDef>) -> Result<CompiledCode, CompileErr> {
+    // defs must be inferred and instantiated
+    pub fn compile_var_def(&mut self, mut defs: Vec<VarDef>) -> Result<CompiledCode, CompileErr> {
         let node_def_included = defs.iter().any(|def| matches!(def, VarDef::Node { .. }));
+        for def in &mut defs {
+            let key = match def {
+                VarDef::Node { name, .. } => (DefKind::Node, name.0.clone()),
+                VarDef::Data { name, .. } => (DefKind::Data, name.0.clone()),
+                VarDef::Func { name, .. } => (DefKind::Func, name.0.clone()),
+            };
+            self.uses.insert(key, uses(def));
+        }
+        let n_nodes = self.node_info.len();
+        let n_func = self.func_info.len();
+        let n_data = self.data_info.len();
         self.register_nodes(&defs)?;
         self.register_vars(&defs)?;
-        let n_new_nodes = self.node_info.iter().filter(|nd| nd.is_new).count();
-        let n_new_func = self.func_info.iter().filter(|f| f.is_new).count();
-        let n_new_data = self.data_info.iter().filter(|d| d.is_new).count();
+        // definitions put in the slots of removed ones are new, but the machine already has the slots
+        let n_new_nodes = self.node_info.len() - n_nodes;
+        let n_new_func = self.func_info.len() - n_func;
+        let n_new_data = self.data_info.len() - n_data;
 
         let tdefs = defs
             .into_iter()
@@ -102,13 +269,31 @@ impl Compiler {
         let node = self.emit_code_def_nodes(&tdefs)?;
         let func = self.emit_code_def_funcs(&tdefs)?;
 
-        let save_last = self.atlast_manager.atlast_offset();
-        let n_last = save_last.len();
         let upd_order = if node_def_included {
             self.topological_sort()?
         } else {
             vec![]
         };
+        let (n_last, update) = self.update_code(upd_order);
+
+        let init = self.emit_code_init(&tdefs)?;
+
+        Ok(CompiledCode::Def(BcDefVar {
+            n_new_nodes,
+            n_new_func,
+            n_new_data,
+            n_last,
+            init,
+            node,
+            func,
+            update,
+        }))
+    }
+    // code the machine runs in every cycle: values referred to by @last are saved,
+    // nodes are updated in upd_order and then the saved objects are dropped
+    fn update_code(&mut self, upd_order: Vec<usize>) -> (usize, Vec<Insn>) {
+        let save_last = self.atlast_manager.atlast_offset();
+        let n_last = save_last.len();
         let mut update = vec![];
         for (i, &nd_i) in save_last.iter().enumerate() {
             match nd_i {
@@ -136,31 +321,167 @@ impl Compiler {
             }
         }
         for (i, &nd_i) in save_last.iter().enumerate() {
-            match nd_i {
-                None => (),
-                Some(nd_i) => {
-                    if self.node_info[nd_i].typ.is_obj_type() {
-                        update.push(Insn::DropLast(UnsignedNum::from_usize(i).unwrap()));
-                    }
+            if let Some(nd_i) = nd_i {
+                if self.node_info[nd_i].typ.is_obj_type() {
+                    update.push(Insn::DropLast(UnsignedNum::from_usize(i).unwrap()));
                 }
             }
         }
         if !update.is_empty() {
             update.push(Insn::Halt);
         }
+        (n_last, update)
+    }
+    // removes the node, data or func `name`, which no other definition may refer to.
+    // its slot on the machine is freed and reused by the next definition
+    pub fn remove(&mut self, name: &Id) -> Result<BcRemove, CompileErr> {
+        let mut slots = vec![];
+        if let Some(i) = self.node_offset(name) {
+            let nd = &self.node_info[i];
+            if nd.input_kind == NodeInputKind::Dev || nd.output_offset.is_some() {
+                return Err(CompileErr::RemoveIoNode(name.0.clone()));
+            }
+            slots.push((DefKind::Node, nd.typ.is_obj_type(), i));
+        } else if let Some(i) = self.data_offset(name) {
+            slots.push((DefKind::Data, self.data_info[i].typ.is_obj_type(), i));
+        } else if let Some(i) = self.func_offset(name) {
+            slots.push((DefKind::Func, false, i));
+        } else if let Some(g) = self.generic_funcs.iter().find(|g| &g.name == name) {
+            // instances of a generic func are removed with it
+            for targs in &g.instances {
+                let i = self.func_offset(&instance_name(name, targs)).unwrap();
+                slots.push((DefKind::Func, false, i));
+            }
+        } else {
+            return Err(CompileErr::IdNotFound(name.clone()));
+        }
+        let removed: Vec<(DefKind, String)> = slots
+            .iter()
+            .map(|&(kind, _, i)| match kind {
+                DefKind::Node => (kind, self.node_info[i].name.0.clone()),
+                DefKind::Data => (kind, self.data_info[i].name.0.clone()),
+                DefKind::Func => (kind, self.func_info[i].name.0.clone()),
+            })
+            .collect();
+        let names: HashSet<&String> = removed.iter().map(|(_, n)| n).chain([&name.0]).collect();
+        let mut dependents: Vec<String> = self
+            .uses
+            .iter()
+            .filter(|(def, used)| !removed.contains(def) && used.iter().any(|n| names.contains(n)))
+            .map(|((_, n), _)| n.clone())
+            .collect();
+        // generic funcs are compiled when they are called, so their bodies are checked here
+        for g in &self.generic_funcs {
+            if &g.name != name && uses(&mut g.def.clone()).iter().any(|n| names.contains(n)) {
+                dependents.push(g.name.0.clone());
+            }
+        }
+        if !dependents.is_empty() {
+            dependents.sort();
+            return Err(CompileErr::RemoveDependedOn(name.0.clone(), dependents));
+        }
 
-        let init = self.emit_code_init(&tdefs)?;
-
-        Ok(CompiledCode::Def(BcDefVar {
-            n_new_nodes,
-            n_new_func,
-            n_new_data,
+        for &(kind, _, i) in &slots {
+            match kind {
+                DefKind::Node => {
+                    let nd = std::mem::replace(&mut self.node_info[i], removed_node());
+                    self.unregister_node(nd);
+                }
+                DefKind::Data => self.data_info[i].name = Id::default(),
+                DefKind::Func => self.func_info[i].name = Id::default(),
+            }
+        }
+        for def in &removed {
+            self.uses.remove(def);
+        }
+        self.generic_funcs.retain(|g| &g.name != name);
+        let upd_order = self.topological_sort()?;
+        let (n_last, mut update) = self.update_code(upd_order);
+        // the machine keeps its update code if it is given an empty one
+        if update.is_empty() {
+            update.push(Insn::Halt);
+        }
+        Ok(BcRemove {
+            slots,
             n_last,
-            init,
-            node,
-            func,
             update,
-        }))
+        })
+    }
+
+    // renames the node, data or func `name` to `new`. its slot on the machine is kept, and so is the
+    // code of the definitions referring to it, which refer to it by `new` from now on
+    pub fn rename(&mut self, name: &Id, new: &Id) -> Result<(), CompileErr> {
+        if self.node_offset(new).is_some()
+            || self.data_offset(new).is_some()
+            || self.func_offset(new).is_some()
+            || self.generic_funcs.iter().any(|g| &g.name == new)
+        {
+            return Err(CompileErr::RenameToDefined(new.0.clone()));
+        }
+        // generic funcs are compiled from their bodies when they are called, so they must not refer to it
+        let mut dependents: Vec<String> = self
+            .generic_funcs
+            .iter()
+            .filter(|g| uses(&mut g.def.clone()).contains(&name.0))
+            .map(|g| g.name.0.clone())
+            .collect();
+        if !dependents.is_empty() {
+            dependents.sort();
+            return Err(CompileErr::RenameDependedOn(name.0.clone(), dependents));
+        }
+        // the old and new names of the slots, which are the instances for a generic func
+        let mut renamed = vec![];
+        if let Some(i) = self.node_offset(name) {
+            let nd = &mut self.node_info[i];
+            if nd.input_kind == NodeInputKind::Dev || nd.output_offset.is_some() {
+                return Err(CompileErr::RenameIoNode(name.0.clone()));
+            }
+            nd.name = new.clone();
+            renamed.push((DefKind::Node, name.clone(), new.clone()));
+        } else if let Some(i) = self.data_offset(name) {
+            self.data_info[i].name = new.clone();
+            renamed.push((DefKind::Data, name.clone(), new.clone()));
+        } else if let Some(i) = self.func_offset(name) {
+            self.func_info[i].name = new.clone();
+            renamed.push((DefKind::Func, name.clone(), new.clone()));
+        } else if let Some(g) = self.generic_funcs.iter_mut().find(|g| &g.name == name) {
+            g.name = new.clone();
+            if let VarDef::Func { name, .. } = &mut g.def {
+                *name = new.clone();
+            }
+            for targs in &g.instances {
+                renamed.push((
+                    DefKind::Func,
+                    instance_name(name, targs),
+                    instance_name(new, targs),
+                ));
+            }
+            for (_, old, new) in &renamed {
+                let i = self.func_offset(old).unwrap();
+                self.func_info[i].name = new.clone();
+            }
+        } else {
+            return Err(CompileErr::IdNotFound(name.clone()));
+        }
+        // a call to a generic func uses both the func and its instance
+        let names: Vec<(&String, &String)> = renamed
+            .iter()
+            .map(|(_, old, new)| (&old.0, &new.0))
+            .chain([(&name.0, &new.0)])
+            .collect();
+        for (kind, old, new) in &renamed {
+            if let Some(used) = self.uses.remove(&(*kind, old.0.clone())) {
+                self.uses.insert((*kind, new.0.clone()), used);
+            }
+        }
+        for used in self.uses.values_mut() {
+            for &(old, new) in &names {
+                if used.remove(old) {
+                    used.insert(new.clone());
+                }
+            }
+        }
+        Ok(())
     }
 
     fn register_nodes(&mut self, defs: &Vec<VarDef>) -> CResult<()> {
@@ -172,10 +493,13 @@ impl Compiler {
                     typ,
                     init: _,
                     val: _,
+                    span,
                 } => {
-                    let t = self.get_type_with_type_name(typ)?;
+                    let t = self
+                        .get_type_with_type_name(annotation(typ))
+                        .map_err(|e| e.at(*span))?;
                     if let Some(i) = self.node_offset(name) {
-                        if &self.node_info[i].typ != &t {
+                        if self.node_info[i].typ != t {
                             let mut dep_list = vec![];
                             for nd in &self.node_info {
                                 if nd.prev.contains(&i) {
@@ -185,17 +509,13 @@ impl Compiler {
                             if dep_list.iter().any(|id| {
                                 defs.iter()
                                     .find(|def| match def {
-                                        VarDef::Node {
-                                            name,
-                                            typ: _,
-                                            init: _,
-                                            val: _,
-                                        } => &name.0 == id,
+                                        VarDef::Node { name, .. } => &name.0 == id,
                                         _ => false,
                                     })
                                     .is_none()
                             }) {
-                                return Err(CompileErr::ConflictNodeType(name.0.clone(), dep_list));
+                                return Err(CompileErr::ConflictNodeType(name.0.clone(), dep_list)
+                                    .at(*span));
                             }
                         }
                     }
@@ -210,8 +530,11 @@ impl Compiler {
                     typ,
                     init,
                     val: _,
+                    span,
                 } => {
-                    let t = self.get_type_with_type_name(typ)?;
+                    let t = self
+                        .get_type_with_type_name(annotation(typ))
+                        .map_err(|e| e.at(*span))?;
                     let mut nd = NodeInfo {
                         name: name.clone(),
                         typ: t.clone(),
@@ -221,12 +544,13 @@ impl Compiler {
                         atlast: HashSet::new(),
                         output_offset: None,
                         input_kind: NodeInputKind::User,
+                        span: Some(*span),
                     };
 
                     match self.node_offset(name) {
                         Some(i) => {
                             if matches!(self.node_info[i].input_kind, NodeInputKind::Dev) {
-                                return Err(CompileErr::OverwriteDevInput);
+                                return Err(CompileErr::OverwriteDevInput.at(*span));
                             }
                             nd.output_offset = self.node_info[i].output_offset;
                             std::mem::swap(&mut nd, &mut self.node_info[i]);
@@ -234,7 +558,10 @@ impl Compiler {
                         }
                         None => {
                             nd.is_new = true;
-                            self.node_info.push(nd)
+                            match self.node_info.iter().position(|nd| nd.name.0.is_empty()) {
+                                Some(i) => self.node_info[i] = nd,
+                                None => self.node_info.push(nd),
+                            }
                         }
                     }
                 }
@@ -253,6 +580,7 @@ impl Compiler {
             has_value: _,
             output_offset: _,
             input_kind: _,
+            span: _,
         }: NodeInfo,
     ) {
         for nd in atlast {
@@ -261,46 +589,53 @@ impl Compiler {
     }
     fn register_vars(&mut self, defs: &Vec<VarDef>) -> CResult<()> {
         for def in defs {
-            match def {
-                VarDef::Node { .. } => continue, // node must be registerd already
-                VarDef::Data { name, typ, val: _ } => {
-                    let t = self.get_type_with_type_name(typ)?;
-                    let mut data = DataInfo {
-                        name: name.clone(),
-                        typ: t.clone(),
-                        is_new: false,
-                    };
-                    match self.data_offset(name) {
-                        Some(i) => self.data_info[i] = data,
-                        None => {
-                            data.is_new = true;
-                            self.data_info.push(data)
+            self.register_var(def).map_err(|e| e.at(def.span()))?;
+        }
+        Ok(())
+    }
+    fn register_var(&mut self, def: &VarDef) -> CResult<()> {
+        match def {
+            VarDef::Node { .. } => (), // node must be registerd already
+            VarDef::Data { name, typ, .. } => {
+                let t = self.get_type_with_type_name(annotation(typ))?;
+                let mut data = DataInfo {
+                    name: name.clone(),
+                    typ: t.clone(),
+                    is_new: false,
+                };
+                match self.data_offset(name) {
+                    Some(i) => self.data_info[i] = data,
+                    None => {
+                        data.is_new = true;
+                        match self.data_info.iter().position(|d| d.name.0.is_empty()) {
+                            Some(i) => self.data_info[i] = data,
+                            None => self.data_info.push(data),
                         }
                     }
                 }
-                VarDef::Func {
-                    name,
-                    ret,
-                    params,
-                    body: _,
-                } => {
-                    let mut prms = Vec::with_capacity(params.len());
-                    for (id, typ) in params {
-                        let t = self.get_type_with_type_name(typ)?;
-                        prms.push((id.clone(), t.clone()))
-                    }
-                    let t = self.get_type_with_type_name(ret)?;
-                    let mut func = FuncInfo {
-                        name: name.clone(),
-                        prms,
-                        ret: t.clone(),
-                        is_new: false,
-                    };
-                    match self.func_offset(name) {
-                        Some(i) => self.func_info[i] = func,
-                        None => {
-                            func.is_new = true;
-                            self.func_info.push(func)
+            }
+            VarDef::Func {
+                name, ret, params, ..
+            } => {
+                let mut prms = Vec::with_capacity(params.len());
+                for (id, typ) in params {
+                    let t = self.get_type_with_type_name(annotation(typ))?;
+                    prms.push((id.clone(), t.clone()))
+                }
+                let t = self.get_type_with_type_name(annotation(ret))?;
+                let mut func = FuncInfo {
+                    name: name.clone(),
+                    prms,
+                    ret: t.clone(),
+                    is_new: false,
+                };
+                match self.func_offset(name) {
+                    Some(i) => self.func_info[i] = func,
+                    None => {
+                        func.is_new = true;
+                        match self.func_info.iter().position(|f| f.name.0.is_empty()) {
+                            Some(i) => self.func_info[i] = func,
+                            None => self.func_info.push(func),
                         }
                     }
                 }
@@ -309,3 +644,18 @@ impl Compiler {
         Ok(())
     }
 }
+// slot of a removed node, which is reused by the next new node.
+// it has no name and is not updated
+fn removed_node() -> NodeInfo {
+    NodeInfo {
+        name: Id::default(),
+        typ: Type::Int,
+        prev: HashSet::new(),
+        atlast: HashSet::new(),
+        is_new: false,
+        has_value: false,
+        output_offset: None,
+        input_kind: NodeInputKind::None,
+        span: None,
+    }
+}
@@ -1,5 +1,6 @@
 use crate::ast::*;
 use crate::insn::*;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt::Debug;
@@ -11,10 +12,22 @@ pub struct Compiler {
     pub(super) node_info: Vec<NodeInfo>,
     pub(super) symbol_table: Vec<(Option<Id>, IsObjType)>,
     pub(super) types: HashMap<TypeName, Type>,
+    // type definitions with type parameters, instantiated by get_type_with_type_name
+    pub(super) generic_types: HashMap<String, (Vec<String>, Vec<Variant>)>,
+    // names of the fields of records, by the name of the type
+    pub(super) records: HashMap<String, Vec<Id>>,
+    // funcs with type parameters, compiled for each type they are used with
+    pub(super) generic_funcs: Vec<GenericFunc>,
     pub(super) func_info: Vec<FuncInfo>,
     pub(super) data_info: Vec<DataInfo>,
     pub(super) atlast_manager: AtLastManager,
     pub(super) local_len: usize,
+    // modules whose definitions have been compiled
+    pub(super) imported: HashSet<String>,
+    // names each node, data and func refers to, which must not be removed before it
+    pub(super) uses: HashMap<(DefKind, String), HashSet<String>>,
+    // found while type checking, which only borrows the compiler
+    pub(super) warnings: RefCell<Vec<(Span, String)>>,
 }
 pub type IsObjType = bool;
 #[derive(Clone)]
@@ -33,6 +46,33 @@ pub struct BcDefVar {
     pub func: Vec<(usize, Vec<Insn>)>,
     pub update: Vec<Insn>,
 }
+// slots freed by Compiler::remove. update replaces the update code of the machine
+#[derive(Clone)]
+pub struct BcRemove {
+    pub slots: Vec<(DefKind, IsObjType, usize)>,
+    pub n_last: usize,
+    pub update: Vec<Insn>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefKind {
+    Node,
+    Data,
+    Func,
+}
+// input and output nodes added by Compiler::declare_module (or all of them, see Compiler::io_nodes)
+#[derive(Debug, Clone, Default)]
+pub struct NewIoNodes {
+    pub inputs: Vec<(String, Type)>,
+    pub outputs: Vec<(String, Type)>,
+}
+// nodes a node refers to now and at last, and the nodes referring to it (see Compiler::node_deps).
+// the last value of the node itself is not listed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeDeps {
+    pub now: Vec<String>,
+    pub last: Vec<String>,
+    pub used_by: Vec<String>,
+}
 #[derive(Debug, Clone)]
 pub(super) struct DataInfo {
     pub name: Id,
@@ -40,6 +80,13 @@ pub(super) struct DataInfo {
     pub is_new: bool,
 }
 #[derive(Debug, Clone)]
+pub(super) struct GenericFunc {
+    pub name: Id,
+    pub tparams: Vec<String>,
+    pub def: VarDef, // every annotation is filled
+    pub instances: Vec<Vec<TypeName>>,
+}
+#[derive(Debug, Clone)]
 pub(super) struct FuncInfo {
     pub name: Id,
     pub prms: Vec<(Id, Type)>,
@@ -54,11 +101,12 @@ pub(super) struct NodeInfo {
     pub prev: HashSet<usize>, // nodes that must be updated before this node
     // nodes that this node points to
     // node b = a@last  => nodeinfo of b contains a
-    pub atlast: HashSet<usize>, 
+    pub atlast: HashSet<usize>,
     pub is_new: bool,
     pub has_value: bool,
     pub output_offset: Option<u8>,
     pub input_kind: NodeInputKind,
+    pub span: Option<Span>, // definition of the node
 }
 
 pub enum CompileErr {
@@ -68,21 +116,51 @@ pub enum CompileErr {
     VariantNotFound(VariantName),
     TooManyLocalVars,
     TooManyFields,
-    TypeErr(TypeErr),
+    TooManyVariants,
+    TypeErr(Box<TypeErr>),
     InvalidAtLast,
     TypeAlreadyExists,
-    ConflictNodeType(String,Vec<String>),
-    OverwriteDevInput
+    ConflictNodeType(String, Vec<String>),
+    OverwriteDevInput,
+    InvalidIoDecl(String),
+    TooManyInstances(Id),
+    RemoveDependedOn(String, Vec<String>),
+    RemoveIoNode(String),
+    RenameDependedOn(String, Vec<String>),
+    RenameIoNode(String),
+    RenameToDefined(String),
+    NonExhaustive(String),
+    NoField(String, String),
+    FieldNotGiven(String, String),
+    DuplicateField(String),
+    At(Span, Box<CompileErr>),
+}
+impl CompileErr {
+    // attaches the location of the error.
+    // the innermost location is kept when called more than once
+    pub fn at(self, span: Span) -> Self {
+        match self {
+            CompileErr::At(_, _) => self,
+            e => CompileErr::At(span, Box::new(e)),
+        }
+    }
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CompileErr::At(span, _) => Some(*span),
+            _ => None,
+        }
+    }
 }
 impl Debug for CompileErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::IdNotFound(arg0) => write!(f, "Identifier {} not found.", arg0.0),
             Self::CircularRef => write!(f, "Circular reference detected."),
-            Self::InvalidTypeName(arg0) => write!(f, "Type {:?} not found.", arg0),
+            Self::InvalidTypeName(arg0) => write!(f, "Type {} not found.", arg0),
             Self::VariantNotFound(arg0) => write!(f, "Variant {} not found.", arg0.0),
             Self::TooManyLocalVars => write!(f, "Too many local variables."),
-            Self::TooManyFields => write!(f, "Data type with more than 8 fields is prohibited."),
+            Self::TooManyFields => write!(f, "Data type with more than 255 fields is prohibited."),
+            Self::TooManyVariants => write!(f, "Data type with more than 65535 variants is prohibited."),
             Self::TypeErr(arg0) => write!(f, "{:?}", arg0),
             Self::InvalidAtLast => write!(
                 f,
@@ -92,6 +170,26 @@ impl Debug for CompileErr {
             Self::ConflictNodeType(s,ss) => 
             write!(f, "In order to overwrite node {}, with different type, node {:?} also needs re-defining",s,ss),
             Self::OverwriteDevInput => write!(f, "Cannot overwrite input node"),
+            Self::InvalidIoDecl(s) => write!(f, "Invalid input/output declaration: {}", s),
+            Self::TooManyInstances(id) => write!(
+                f,
+                "Generic func {} is instantiated with too many types",
+                id.0
+            ),
+            Self::RemoveDependedOn(s, ss) => {
+                write!(f, "In order to remove {}, {:?} also needs removing", s, ss)
+            }
+            Self::RemoveIoNode(s) => write!(f, "Cannot remove input/output node {}", s),
+            Self::RenameDependedOn(s, ss) => {
+                write!(f, "In order to rename {}, generic func {:?} also needs re-defining", s, ss)
+            }
+            Self::RenameIoNode(s) => write!(f, "Cannot rename input/output node {}", s),
+            Self::RenameToDefined(s) => write!(f, "{} is already defined", s),
+            Self::NonExhaustive(s) => write!(f, "Non-exhaustive match: {} is not covered", s),
+            Self::NoField(t, s) => write!(f, "{} has no field {}", t, s),
+            Self::FieldNotGiven(t, s) => write!(f, "Field {} of {} is not given", s, t),
+            Self::DuplicateField(s) => write!(f, "Field {} appears more than once", s),
+            Self::At(_, e) => write!(f, "{:?}", e),
         }
     }
 }
@@ -100,9 +198,14 @@ pub enum TypeErr {
     Mismatch(Type, Type),
     IncorrectVarN(usize, usize),
     TypeNotFound(TypeName),
+    IncorrectTypeArgN(TypeName, usize, usize),
     VarNotFound(VariantName),
     InvalidFuncType(Id),
     IdNotFound(Id),
+    NotFunc(Id),
+    CannotUnify(String, String),
+    InfiniteType(String, String),
+    CannotInfer(String),
 }
 impl Debug for TypeErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -113,7 +216,10 @@ impl Debug for TypeErr {
             Self::IncorrectVarN(arg0, arg1) => {
                 write!(f, "expected {arg0} args but {arg1} were given")
             }
-            Self::TypeNotFound(arg0) => write!(f, "type {:?} not found", arg0),
+            Self::TypeNotFound(arg0) => write!(f, "type {} not found", arg0),
+            Self::IncorrectTypeArgN(arg0, arg1, arg2) => {
+                write!(f, "{arg0} expects {arg1} type args but {arg2} were given")
+            }
             Self::VarNotFound(arg0) => write!(f, "variant {:?} not founc", arg0.0),
             Self::InvalidFuncType(arg0) => write!(
                 f,
@@ -121,6 +227,16 @@ impl Debug for TypeErr {
                 arg0.0
             ),
             Self::IdNotFound(arg0) => write!(f, "variable {:?} not founc", arg0.0),
+            Self::NotFunc(arg0) => write!(f, "{:?} is not a func", arg0.0),
+            Self::CannotUnify(arg0, arg1) => {
+                write!(f, "type mismatch: expected {arg0}, found {arg1}")
+            }
+            Self::InfiniteType(arg0, arg1) => {
+                write!(f, "type {arg1} would have to contain itself to match {arg0}")
+            }
+            Self::CannotInfer(arg0) => {
+                write!(f, "cannot infer the type of {arg0}, add a type annotation")
+            }
         }
     }
 }
@@ -141,13 +257,15 @@ pub(super) enum VarType<'a> {
 pub enum Type {
     Int,
     Bool,
-    User(String, Vec<(VariantName, Vec<Type>)>),
+    Float,
+    // name, type args and variants with the args substituted
+    User(String, Vec<Type>, Vec<(VariantName, Vec<Type>)>),
     Tuple(Vec<Type>),
 }
 impl PartialEq for Type {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Self::User(l0, _), Self::User(r0, _)) => l0 == r0,
+            (Self::User(l0, l1, _), Self::User(r0, r1, _)) => l0 == r0 && l1 == r1,
             _ => core::mem::discriminant(self) == core::mem::discriminant(other),
         }
     }
@@ -156,11 +274,43 @@ impl Eq for Type {}
 
 impl Type {
     pub fn is_obj_type(&self) -> bool {
-        matches!(self, Type::User(_, _) | Type::Tuple(_))
+        matches!(self, Type::User(..) | Type::Tuple(_))
+    }
+    // operands of arithmetic and comparison
+    pub fn is_num(&self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+    pub fn variants(&self) -> &[(VariantName, Vec<Type>)] {
+        match self {
+            Type::User(_, _, vars) => vars,
+            _ => &[],
+        }
     }
 }
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |ts: &[Type]| {
+            let ts: Vec<String> = ts.iter().map(|t| t.to_string()).collect();
+            ts.join(", ")
+        };
+        match self {
+            Type::Int => write!(f, "Int"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Float => write!(f, "Float"),
+            Type::User(name, args, _) if args.is_empty() => write!(f, "{name}"),
+            Type::User(name, args, _) => write!(f, "{name}<{}>", join(args)),
+            Type::Tuple(ts) => write!(f, "({})", join(ts)),
+        }
+    }
+}
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Compiler {
-    pub fn add_input_node(&mut self, name: &'static str, typ: Type) {
+    pub fn add_input_node(&mut self, name: &str, typ: Type) {
         self.node_info.push(NodeInfo {
             name: Id(name.to_string()),
             typ,
@@ -170,10 +320,16 @@ impl Compiler {
             output_offset: None,
             has_value: true,
             input_kind: NodeInputKind::Dev,
+            span: None,
         })
     }
 
-    pub fn add_output_node(&mut self, name: &'static str, typ: Type) {
+    pub fn add_output_node(&mut self, name: &str, typ: Type) {
+        let n_output = self
+            .node_info
+            .iter()
+            .filter(|nd| nd.output_offset.is_some())
+            .count();
         self.node_info.push(NodeInfo {
             name: Id(name.to_string()),
             typ,
@@ -181,8 +337,9 @@ impl Compiler {
             atlast: HashSet::new(),
             is_new: false,
             has_value: true,
-            output_offset: Some(0),
+            output_offset: Some(n_output as u8),
             input_kind: NodeInputKind::None,
+            span: None,
         })
     }
 
@@ -193,33 +350,66 @@ impl Compiler {
             node_info,
             symbol_table: vec![],
             types: HashMap::new(),
+            generic_types: HashMap::new(),
+            records: HashMap::new(),
+            generic_funcs: vec![],
             func_info: vec![],
             data_info: vec![],
             atlast_manager: AtLastManager::new(),
             local_len: 0,
+            imported: HashSet::new(),
+            uses: HashMap::new(),
+            warnings: RefCell::new(vec![]),
         }
     }
+    pub(super) fn warn(&self, span: Span, msg: &str) {
+        let w = (span, msg.to_string());
+        // the body of a generic func is checked for each of its instances
+        if !self.warnings.borrow().contains(&w) {
+            self.warnings.borrow_mut().push(w);
+        }
+    }
+    pub fn take_warnings(&mut self) -> Vec<(Span, String)> {
+        std::mem::take(self.warnings.get_mut())
+    }
 
     pub(super) fn get_type_from_variant<'a>(
         &'a self,
         name: &VariantName,
     ) -> CResult<(&'a Type, usize, &'a Vec<Type>)> {
-        for (_, t) in &self.types {
-            match t {
-                Type::User(_, vars) => {
-                    for (i, &(ref vname, ref elems)) in vars.iter().enumerate() {
-                        if vname == name {
-                            return Ok((t, i + 1, elems));
-                        }
+        for t in self.types.values() {
+            if let Type::User(_, _, vars) = t {
+                for (i, (vname, elems)) in vars.iter().enumerate() {
+                    if vname == name {
+                        return Ok((t, i + 1, elems));
                     }
                 }
-                _ => (),
             }
         }
         Err(CompileErr::VariantNotFound(name.clone()))
     }
 
-    pub(super) fn get_type_with_type_name<'a>(&'a self, name: &TypeName) -> CResult<Type> {
+    // names of the fields of t if it is a record
+    pub(super) fn record_fields(&self, t: &Type) -> Option<&Vec<Id>> {
+        match t {
+            Type::User(name, _, _) => self.records.get(name),
+            _ => None,
+        }
+    }
+    // type of the values made by variant name, with the type args of a generic type
+    pub(super) fn get_type_with_variant(
+        &self,
+        name: &VariantName,
+        targs: &[TypeName],
+    ) -> CResult<Type> {
+        for (tname, (_, variants)) in &self.generic_types {
+            if variants.iter().any(|v| &v.constructor == name) {
+                return self.get_type_with_type_name(&TypeName::User(tname.clone(), targs.to_vec()));
+            }
+        }
+        self.get_type_from_variant(name).map(|(t, _, _)| t.clone())
+    }
+    pub(super) fn get_type_with_type_name(&self, name: &TypeName) -> CResult<Type> {
         match name {
             TypeName::Tuple(typs) => {
                 if typs.len() > u8::MAX as usize {
@@ -231,13 +421,42 @@ impl Compiler {
                 }
                 Ok(Type::Tuple(ret))
             }
-            TypeName::User(_) => self
-                .types
-                .get(name)
-                .map(|c| c.clone())
-                .ok_or(CompileErr::TypeErr(TypeErr::TypeNotFound(name.clone()))),
+            TypeName::User(s, args) => match self.generic_types.get(s) {
+                Some((params, variants)) => {
+                    if params.len() != args.len() {
+                        let err = TypeErr::IncorrectTypeArgN(name.clone(), params.len(), args.len());
+                        return Err(CompileErr::TypeErr(Box::new(err)));
+                    }
+                    let env: Vec<(String, TypeName)> =
+                        params.iter().cloned().zip(args.iter().cloned()).collect();
+                    let mut targs = Vec::with_capacity(args.len());
+                    for arg in args {
+                        targs.push(self.get_type_with_type_name(arg)?);
+                    }
+                    let mut vars = Vec::with_capacity(variants.len());
+                    for Variant { constructor, elems } in variants {
+                        let mut types = Vec::with_capacity(elems.len());
+                        for tname in elems {
+                            types.push(self.get_type_with_type_name(&tname.subst(&env))?);
+                        }
+                        vars.push((constructor.clone(), types));
+                    }
+                    Ok(Type::User(s.clone(), targs, vars))
+                }
+                None if !args.is_empty() => {
+                    let err = TypeErr::IncorrectTypeArgN(name.clone(), 0, args.len());
+                    Err(CompileErr::TypeErr(Box::new(err)))
+                }
+                None => self
+                    .types
+                    .get(name)
+                    .cloned()
+                    .ok_or(CompileErr::TypeErr(Box::new(TypeErr::TypeNotFound(name.clone())))),
+            },
+            TypeName::Var(_) => Err(CompileErr::InvalidTypeName(name.clone())),
             TypeName::Bool => Ok(Type::Bool),
             TypeName::Int => Ok(Type::Int),
+            TypeName::Float => Ok(Type::Float),
         }
     }
 
@@ -248,9 +467,10 @@ impl Compiler {
             prev: _,
             is_new: _,
             atlast: _,
-            has_value:_,
+            has_value: _,
             output_offset: _,
             input_kind: _,
+            span: _,
         } in &self.node_info
         {
             if name == id {
@@ -297,6 +517,24 @@ impl Compiler {
         }
         None
     }
+    pub fn imported_modules(&self) -> &HashSet<String> {
+        &self.imported
+    }
+    // input and output nodes in the order the machine registers them
+    pub fn io_nodes(&self) -> NewIoNodes {
+        let mut res = NewIoNodes::default();
+        let mut outputs = vec![];
+        for nd in &self.node_info {
+            if let NodeInputKind::Dev = nd.input_kind {
+                res.inputs.push((nd.name.0.clone(), nd.typ.clone()));
+            } else if let Some(i) = nd.output_offset {
+                outputs.push((i, (nd.name.0.clone(), nd.typ.clone())));
+            }
+        }
+        outputs.sort_by_key(|(i, _)| *i);
+        res.outputs = outputs.into_iter().map(|(_, nd)| nd).collect();
+        res
+    }
     pub fn node_name(&self, offset: usize) -> &Id {
         &self.node_info[offset].name
     }
@@ -309,13 +547,26 @@ impl Compiler {
         None
     }
 }
+// name of the func compiled from a generic func for targs
+pub(super) fn instance_name(name: &Id, targs: &[TypeName]) -> Id {
+    if targs.is_empty() {
+        return name.clone();
+    }
+    let targs: Vec<String> = targs.iter().map(|t| t.to_string()).collect();
+    Id(format!("{}<{}>", name.0, targs.join(", ")))
+}
+// annotations omitted in the source are filled by Compiler::infer_types
+pub(super) fn annotation(typ: &Option<TypeName>) -> &TypeName {
+    typ.as_ref().expect("type annotation is not inferred")
+}
 pub(super) fn max_entry(t: &Type) -> usize {
     match t {
         Type::Int => 1,
         Type::Bool => 1,
-        Type::User(_, vars) => {
+        Type::Float => 1,
+        Type::User(_, _, vars) => {
             let mut max = 0;
-            for &(_, ref types) in vars {
+            for (_, types) in vars {
                 if max < types.len() {
                     max = types.len();
                 }
@@ -362,7 +613,7 @@ impl Debug for CompiledCode {
         for i in update {
             writeln!(f, " {:?}", i)?;
         }
-        writeln!(f, "")?;
+        writeln!(f)?;
         writeln!(f, "init:")?;
         for insn in init {
             writeln!(f, "  {:?}", insn)?;
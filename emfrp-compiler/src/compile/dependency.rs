@@ -1,4 +1,5 @@
-use crate::DEBUG;
+use crate::ast::*;
+use crate::debug;
 
 use super::compile_common::*;
 use super::typed_ast::*;
@@ -68,6 +69,29 @@ impl AtLastManager {
     }
 }
 
+// names of nodes, data and funcs a definition refers to.
+// local variables are included when they have the same name as one of them
+struct Uses<'a>(&'a mut HashSet<String>);
+impl VisitMut for Uses<'_> {
+    fn primary(&mut self, p: &mut Primary) {
+        match p {
+            Primary::Id(id, _) | Primary::Last(id, _) => {
+                self.0.insert(id.0.clone());
+            }
+            Primary::FnCall(id, _, targs, _) => {
+                self.0.insert(id.0.clone());
+                self.0.insert(instance_name(id, targs).0);
+            }
+            _ => (),
+        }
+    }
+}
+pub(super) fn uses(def: &mut VarDef) -> HashSet<String> {
+    let mut res = HashSet::new();
+    def.visit_mut(&mut Uses(&mut res));
+    res
+}
+
 impl Compiler {
     pub(super) fn topological_sort(&self) -> CResult<Vec<usize>> {
         let mut q = VecDeque::new();
@@ -84,10 +108,11 @@ impl Compiler {
                 output_offset: _,
                 has_value: _,
                 input_kind: _,
+                span: _,
             },
         ) in self.node_info.iter().enumerate()
         {
-            if DEBUG {
+            if debug() && !name.0.is_empty() {
                 assert_eq!(self.node_offset(name), Some(i));
             }
 
@@ -112,6 +137,7 @@ impl Compiler {
                     output_offset: _,
                     has_value: _,
                     input_kind: _,
+                    span: _,
                 },
             ) in self.node_info.iter().enumerate()
             {
@@ -125,15 +151,27 @@ impl Compiler {
             ret.push(nd);
         }
         if ret.len() == cnt.len() {
-            Ok(ret)
-        } else {
-            Err(CompileErr::CircularRef)
+            return Ok(ret);
+        }
+        // nodes left unsorted are on a cycle or depend on one
+        let unsorted = self
+            .node_info
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !ret.contains(i));
+        let span = unsorted
+            .clone()
+            .find_map(|(_, nd)| nd.span.filter(|_| nd.is_new))
+            .or(unsorted.clone().find_map(|(_, nd)| nd.span));
+        match span {
+            Some(span) => Err(CompileErr::CircularRef.at(span)),
+            None => Err(CompileErr::CircularRef),
         }
     }
     pub(super) fn add_dependency(&mut self, defs: &Vec<TVarDef>) -> CResult<()> {
         for def in defs {
             match def {
-                TVarDef::Node { name, init: _, val } => {
+                TVarDef::Node { name, val, .. } => {
                     let mut st = HashSet::new();
                     let nd_i = self.node_offset(name).unwrap();
                     val.to_dependency(nd_i, &mut st, self)?;
@@ -149,16 +187,16 @@ impl Compiler {
 impl TPattern {
     fn add_local_variables(&self, c: &mut Compiler) {
         match self {
-            TPattern::Int(_) => return,
+            TPattern::Int(_) => (),
             TPattern::Id(t, id) => c.symbol_table.push((Some(id.clone()), t.is_obj_type())),
             TPattern::Variant(_, pats) => {
                 for pat in pats {
                     pat.add_local_variables(c)
                 }
             }
-            TPattern::Bool(_) => return,
-            TPattern::Tuple(_) => return,
-            TPattern::None => return,
+            TPattern::Bool(_) => (),
+            TPattern::Tuple(_) => (),
+            TPattern::None => (),
         }
     }
 }
@@ -174,11 +212,14 @@ impl TExp {
             TExp::Match(e, branches) => {
                 e.to_dependency(nd_i, lst, c)?;
                 let i0 = c.symbol_table.len();
-                for TBranch { pat, exp: _ } in branches {
+                for TBranch { pat, .. } in branches {
                     pat.add_local_variables(c);
                 }
                 let n_local = c.symbol_table.len() - i0;
-                for TBranch { pat: _, exp } in branches {
+                for TBranch { guard, exp, .. } in branches {
+                    if let Some(g) = guard {
+                        g.to_dependency(nd_i, lst, c)?;
+                    }
                     exp.to_dependency(nd_i, lst, c)?;
                 }
                 for _ in 0..n_local {
@@ -348,9 +389,11 @@ impl TPrimary {
         c: &mut Compiler,
     ) -> CResult<()> {
         match self {
-            TPrimary::Int(_) | TPrimary::Bool(_) => Ok(()),
-            TPrimary::Exp(e) => e.to_dependency(nd_i, lst, c),
-            TPrimary::Id(id, _) => {
+            TPrimary::Int(_) | TPrimary::Float(_) | TPrimary::Bool(_) => Ok(()),
+            TPrimary::Exp(e) | TPrimary::ToFloat(e) | TPrimary::ToInt(e) => {
+                e.to_dependency(nd_i, lst, c)
+            }
+            TPrimary::Id(id, _, span) => {
                 for (id2, _) in &c.symbol_table {
                     match id2 {
                         Some(id2) if id == id2 => return Ok(()),
@@ -358,7 +401,7 @@ impl TPrimary {
                     }
                 }
                 match c.node_offset(id) {
-                    Some(u) if u == nd_i => Err(CompileErr::CircularRef),
+                    Some(u) if u == nd_i => Err(CompileErr::CircularRef.at(*span)),
                     Some(u) => {
                         lst.insert(u);
                         Ok(())
@@ -366,8 +409,10 @@ impl TPrimary {
                     None => Ok(()),
                 }
             }
-            TPrimary::Last(id, _) => {
-                let i = c.node_offset(id).ok_or(CompileErr::InvalidAtLast)?;
+            TPrimary::Last(id, _, span) => {
+                let i = c
+                    .node_offset(id)
+                    .ok_or(CompileErr::InvalidAtLast.at(*span))?;
                 if i == nd_i {
                     return Ok(());
                 }
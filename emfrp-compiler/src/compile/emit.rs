@@ -20,19 +20,19 @@ impl Compiler {
         assert_eq!(self.codes.len(), 0);
         let mut ret = vec![];
         for def in defs {
-            if let TVarDef::Node { name, init: _, val } = def {
+            if let TVarDef::Node { name, val, .. } = def {
                 assert_eq!(self.codes.len(), 0);
                 let i = self.node_offset(name).unwrap();
-                if self.symbol_table.len() != 0 {
+                if !self.symbol_table.is_empty() {
                     panic!(
                         "symbol table len is not zero({}) at the beginning of emit_code_entry",
                         self.symbol_table.len()
                     );
                 }
                 self.symbol_table.push((None, false)); // next ip (at runtime)
-                val.emit_code_entry(self)?;
+                val.emit_code_entry(self).map_err(|e| e.at(def.span()))?;
                 self.symbol_table.pop();
-                if self.symbol_table.len() != 0 {
+                if !self.symbol_table.is_empty() {
                     panic!(
                         "symbol table len is not zero({}) at the end of emit_code_entry",
                         self.symbol_table.len()
@@ -59,10 +59,13 @@ impl Compiler {
         assert_eq!(self.codes.len(), 0);
         let mut ret = vec![];
         for def in defs {
-            if let TVarDef::Func { name, params, body } = def {
-                assert!(self.symbol_table.len() == 0);
+            if let TVarDef::Func {
+                name, params, body, ..
+            } = def
+            {
+                assert!(self.symbol_table.is_empty());
                 if params.len() > u8::MAX as usize {
-                    return Err(CompileErr::TooManyLocalVars);
+                    return Err(CompileErr::TooManyLocalVars.at(def.span()));
                 }
                 let mut drop_list = vec![];
                 for (i, (id, t)) in params.iter().enumerate() {
@@ -71,7 +74,7 @@ impl Compiler {
                         drop_list.push(i);
                     }
                 }
-                body.emit_code_entry(self)?;
+                body.emit_code_entry(self).map_err(|e| e.at(def.span()))?;
                 for i in drop_list {
                     self.push_insn(Insn::DropLocalObj(SignedNum::from_usize(i).unwrap()))
                 }
@@ -88,14 +91,14 @@ impl Compiler {
         assert_eq!(self.codes.len(), 0);
         for def in defs {
             match def {
-                TVarDef::Node { name, init, val: _ } => {
+                TVarDef::Node { name, init, .. } => {
                     let i = self.node_offset(name).unwrap();
                     let is_new = self.node_info[i].is_new;
                     let is_obj = self.node_info[i].typ.is_obj_type();
                     if let Some(e) = init {
-                        assert!(self.symbol_table.len() == 0);
-                        e.emit_code_entry(self)?;
-                        assert!(self.symbol_table.len() == 0);
+                        assert!(self.symbol_table.is_empty());
+                        e.emit_code_entry(self).map_err(|e| e.at(def.span()))?;
+                        assert!(self.symbol_table.is_empty());
                         if is_obj && !is_new {
                             self.push_insn(Insn::SetNodeRef(UnsignedNum::from_usize(i).unwrap()))
                         } else {
@@ -103,11 +106,11 @@ impl Compiler {
                         }
                     }
                 }
-                TVarDef::Data { name, val } => {
+                TVarDef::Data { name, val, .. } => {
                     let i = self.data_offset(name).unwrap();
-                    assert!(self.symbol_table.len() == 0);
-                    val.emit_code_entry(self)?;
-                    assert!(self.symbol_table.len() == 0);
+                    assert!(self.symbol_table.is_empty());
+                    val.emit_code_entry(self).map_err(|e| e.at(def.span()))?;
+                    assert!(self.symbol_table.is_empty());
                     if val.get_type().is_obj_type() {
                         self.codes
                             .push(Insn::SetDataRef(UnsignedNum::from_usize(i).unwrap()))
@@ -125,10 +128,10 @@ impl Compiler {
     }
 
     pub(super) fn emit_code_exp(&mut self, texp: &TExp) -> CResult<Vec<Insn>> {
-        assert!(self.codes.len() == 0);
-        assert!(self.symbol_table.len() == 0);
+        assert!(self.codes.is_empty());
+        assert!(self.symbol_table.is_empty());
         texp.emit_code_entry(self)?;
-        assert!(self.symbol_table.len() == 0);
+        assert!(self.symbol_table.is_empty());
         Ok(self.insn_clear())
     }
 }
@@ -137,13 +140,11 @@ impl TExp {
     fn local_var_size(&self) -> usize {
         match self {
             TExp::Match(mat, branches) => {
-                let mut ret = mat.local_var_size();
+                // the match exp is evaluated after its slot is allocated
+                let mut ret = 1 + mat.local_var_size();
                 for b in branches {
                     ret = max(ret, 1 + b.local_var_size())
                 }
-                if let Type::User(..) = mat.get_type() {
-                    ret += 1; // for tag
-                }
                 ret
             }
             TExp::If { cond, then, els } => max(
@@ -235,10 +236,11 @@ impl TPrimary {
     fn local_var_size(&self) -> usize {
         match self {
             TPrimary::Int(_) => 0,
+            TPrimary::Float(_) => 0,
             TPrimary::Bool(_) => 0,
-            TPrimary::Exp(e) => e.local_var_size(),
-            TPrimary::Id(_, _) => 0,
-            TPrimary::Last(_, _) => 0,
+            TPrimary::Exp(e) | TPrimary::ToFloat(e) | TPrimary::ToInt(e) => e.local_var_size(),
+            TPrimary::Id(..) => 0,
+            TPrimary::Last(..) => 0,
             TPrimary::Variant(_, _, exps)
             | TPrimary::FnCall(_, _, exps)
             | TPrimary::Tuple(exps, _) => {
@@ -276,14 +278,15 @@ impl TPattern {
 }
 impl TBranch {
     fn local_var_size(&self) -> usize {
-        let TBranch { pat, exp } = self;
-        pat.local_var_size() + exp.local_var_size()
+        let TBranch { pat, guard, exp } = self;
+        let guard = guard.as_ref().map_or(0, |g| g.local_var_size());
+        pat.local_var_size() + max(guard, exp.local_var_size())
     }
 }
 fn emit_alloc_local(size: usize, c: &mut Compiler) -> CResult<()> {
     match size {
         0 => (),
-        n if n <= std::u8::MAX as usize => c.push_insn(Insn::AllocLocal(UnsignedNum::U8(n as u8))),
+        n if n <= u8::MAX as usize => c.push_insn(Insn::AllocLocal(UnsignedNum::U8(n as u8))),
         _ => return Err(CompileErr::TooManyLocalVars),
     }
     Ok(())
@@ -306,6 +309,165 @@ fn emit_push_top_on_local_stack(var: Option<Id>, b: IsObjType, c: &mut Compiler)
     }
 }
 
+// a value inside the match exp, reached by taking the fields of `path` in order
+type Occurrence = (Vec<usize>, Type);
+const ANY: &TPattern = &TPattern::None;
+
+impl TPattern {
+    // variables bound by the pattern, with the path to their value in the match exp
+    fn bindings<'a>(&'a self, path: Vec<usize>, res: &mut Vec<(&'a Id, Vec<usize>, &'a Type)>) {
+        match self {
+            TPattern::Id(t, id) => res.push((id, path, t)),
+            TPattern::Variant(_, pats) | TPattern::Tuple(pats) => {
+                for (i, p) in pats.iter().enumerate() {
+                    let mut path = path.clone();
+                    path.push(i);
+                    p.bindings(path, res);
+                }
+            }
+            TPattern::Int(_) | TPattern::Bool(_) | TPattern::None => (),
+        }
+    }
+    fn is_any(&self) -> bool {
+        matches!(self, TPattern::Id(_, _) | TPattern::None)
+    }
+    fn same_head(&self, other: &TPattern) -> bool {
+        match (self, other) {
+            (TPattern::Int(a), TPattern::Int(b)) => a == b,
+            (TPattern::Bool(a), TPattern::Bool(b)) => a == b,
+            (TPattern::Variant(a, _), TPattern::Variant(b, _)) => a == b,
+            _ => false,
+        }
+    }
+}
+fn emit_occurrence(match_exp_offset: i32, path: &[usize], c: &mut Compiler) {
+    c.push_insn(Insn::GetLocal(SignedNum::from_i32(match_exp_offset)));
+    for i in path {
+        c.push_insn(Insn::ObjField(UnsignedNum::from_usize(*i).unwrap()));
+    }
+}
+// replaces the pattern at column j of a row, which is `head` or matches anything, with its args
+fn specialize<'a>(
+    row: &[&'a TPattern],
+    j: usize,
+    head: &TPattern,
+    arity: usize,
+) -> Option<Vec<&'a TPattern>> {
+    let args: Vec<&TPattern> = match row[j] {
+        p if p.is_any() => vec![ANY; arity],
+        TPattern::Variant(_, pats) | TPattern::Tuple(pats) if row[j].same_head(head) => {
+            pats.iter().collect()
+        }
+        TPattern::Tuple(pats) if matches!(head, TPattern::Tuple(_)) => pats.iter().collect(),
+        p if p.same_head(head) => vec![],
+        _ => return None,
+    };
+    Some([&row[..j], &args[..], &row[j + 1..]].concat())
+}
+// rows are the patterns of branches for the values at occs, and the index of the branch.
+// the first row whose patterns all match is selected, by tests on one column at a time.
+// leaf emits the code of the selected branch, and returns whether it falls through to the rows
+// below, as a branch whose guard is false does
+fn emit_decision_tree(
+    match_exp_offset: i32,
+    occs: &[Occurrence],
+    rows: Vec<(Vec<&TPattern>, usize)>,
+    c: &mut Compiler,
+    leaf: &mut dyn FnMut(usize, &mut Compiler) -> CResult<bool>,
+) -> CResult<()> {
+    let Some((first, branch)) = rows.first() else {
+        // no branch matches, which typecheck rejects
+        c.push_insn(Insn::Abort);
+        return Ok(());
+    };
+    let Some(j) = first.iter().position(|p| !p.is_any()) else {
+        if leaf(*branch, c)? {
+            return emit_decision_tree(match_exp_offset, occs, rows[1..].to_vec(), c, leaf);
+        }
+        return Ok(());
+    };
+    let (path, t) = &occs[j];
+    let field_occs = |types: &[Type]| -> Vec<Occurrence> {
+        let fields = types.iter().enumerate().map(|(i, t)| {
+            let mut path = path.clone();
+            path.push(i);
+            (path, t.clone())
+        });
+        occs[..j]
+            .iter()
+            .cloned()
+            .chain(fields)
+            .chain(occs[j + 1..].iter().cloned())
+            .collect()
+    };
+    // a tuple always matches, so its fields are tested instead
+    if let Type::Tuple(types) = t {
+        let rows = rows
+            .iter()
+            .map(|(r, b)| (specialize(r, j, first[j], types.len()).unwrap(), *b))
+            .collect();
+        return emit_decision_tree(match_exp_offset, &field_occs(types), rows, c, leaf);
+    }
+    let mut heads: Vec<&TPattern> = vec![];
+    for (r, _) in &rows {
+        if !r[j].is_any() && !heads.iter().any(|h| h.same_head(r[j])) {
+            heads.push(r[j]);
+        }
+    }
+    let complete = match t {
+        Type::Bool => heads.len() == 2,
+        Type::User(_, _, vars) => heads.len() == vars.len(),
+        _ => false,
+    };
+    for (k, head) in heads.iter().enumerate() {
+        let types = match (t, head) {
+            (Type::User(_, _, vars), TPattern::Variant(tag, _)) => vars[tag - 1].1.clone(),
+            _ => vec![],
+        };
+        let rows = rows
+            .iter()
+            .filter_map(|(r, b)| Some((specialize(r, j, head, types.len())?, *b)))
+            .collect();
+        // the last of all the constructors needs no test
+        if complete && k == heads.len() - 1 {
+            return emit_decision_tree(match_exp_offset, &field_occs(&types), rows, c, leaf);
+        }
+        emit_occurrence(match_exp_offset, path, c);
+        let skip_if_true = match head {
+            TPattern::Int(i) => {
+                c.push_insn(Insn::Int(SignedNum::from_i32(*i)));
+                c.push_insn(Insn::Eq);
+                false
+            }
+            TPattern::Variant(tag, _) => {
+                c.push_insn(Insn::ObjTag);
+                c.push_insn(Insn::Int(SignedNum::from_usize(*tag).unwrap()));
+                c.push_insn(Insn::Eq);
+                false
+            }
+            TPattern::Bool(b) => !*b,
+            _ => unreachable!(),
+        };
+        c.push_insn(Insn::Placeholder);
+        let i0 = c.codes.len();
+        emit_decision_tree(match_exp_offset, &field_occs(&types), rows, c, leaf)?;
+        let len = bytecode_len(&c.codes[i0..]) as i32;
+        c.codes[i0 - 1] = if skip_if_true {
+            Insn::je(len)
+        } else {
+            Insn::jne(len)
+        };
+    }
+    // values none of the heads match
+    let occs: Vec<Occurrence> = [&occs[..j], &occs[j + 1..]].concat();
+    let rows = rows
+        .into_iter()
+        .filter(|(r, _)| r[j].is_any())
+        .map(|(r, b)| ([&r[..j], &r[j + 1..]].concat(), b))
+        .collect();
+    emit_decision_tree(match_exp_offset, &occs, rows, c, leaf)
+}
+
 impl TExp {
     fn emit_code_entry(&self, c: &mut Compiler) -> CResult<()> {
         let tbl_len = c.symbol_table.len();
@@ -342,249 +504,82 @@ impl TExp {
                 exp.emit_code(c)?;
                 c.push_insn(Insn::SetLocal(SignedNum::from_i32(match_exp_offset)));
 
-                match exp.get_type() {
-                    Type::Int => {
-                        let mut gotoend_offset = vec![];
-                        for TBranch { pat, exp } in branches {
-                            match pat {
-                                TPattern::Int(i) => {
-                                    c.push_insn(Insn::GetLocal(SignedNum::from_i32(
-                                        match_exp_offset,
-                                    )));
-                                    c.push_insn(Insn::Int(SignedNum::from_i32(*i)));
-                                    c.push_insn(Insn::Eq);
-                                    c.push_insn(Insn::Placeholder);
-                                    let i0 = c.codes.len();
-                                    exp.emit_code_body(c)?;
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                    let i1 = c.codes.len();
-                                    c.codes[i0 - 1] =
-                                        Insn::jne(bytecode_len(&c.codes[i0..i1]) as i32);
-                                }
-                                TPattern::Id(_, id) => {
-                                    c.push_insn(Insn::GetLocal(SignedNum::from_i32(
-                                        match_exp_offset,
-                                    )));
-                                    emit_push_top_on_local_stack(Some(id.clone()), false, c);
-                                    exp.emit_code_body(c)?;
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                    c.symbol_table.pop();
-                                }
-                                TPattern::Variant(_, _) => {
-                                    panic!("typecheck")
-                                }
-                                TPattern::Bool(_) => panic!("typecheck"),
-                                TPattern::Tuple(_) => panic!("typecheck"),
-                                TPattern::None => {
-                                    exp.emit_code_body(c)?;
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                }
-                            }
-                        }
-                        c.push_insn(Insn::Abort);
-                        let end = c.codes.len();
-                        for st in gotoend_offset {
-                            c.codes[st] = Insn::J32(bytecode_len(&c.codes[st + 1..end]) as i32);
-                        }
+                // the tests of the patterns select a branch. a branch selected at one place is
+                // emitted there, and the others are emitted after the tests and jumped to.
+                // variables of the pattern and its guard are set where it is selected
+                let rows: Vec<(Vec<&TPattern>, usize)> = branches
+                    .iter()
+                    .enumerate()
+                    .map(|(i, b)| (vec![&b.pat], i))
+                    .collect();
+                let occs = vec![(vec![], exp.get_type().clone())];
+                let mut n_leaves = vec![0; branches.len()];
+                let start = c.codes.len();
+                emit_decision_tree(match_exp_offset, &occs, rows.clone(), c, &mut |b, c| {
+                    n_leaves[b] += 1;
+                    c.push_insn(Insn::J32(0));
+                    Ok(branches[b].guard.is_some())
+                })?;
+                c.codes.truncate(start);
+
+                let mut gotoend_offset = vec![];
+                let mut to_branch = vec![];
+                let binds: Vec<_> = branches
+                    .iter()
+                    .map(|b| {
+                        let mut binds = vec![];
+                        b.pat.bindings(vec![], &mut binds);
+                        binds
+                    })
+                    .collect();
+                let mut emit_body = |k: usize, c: &mut Compiler| -> CResult<()> {
+                    branches[k].exp.emit_code_body(c)?;
+                    gotoend_offset.push(c.codes.len());
+                    c.codes.push(Insn::J32(0)); //0 is placeholder
+                    Ok(())
+                };
+                emit_decision_tree(match_exp_offset, &occs, rows, c, &mut |b, c| {
+                    for (id, path, t) in &binds[b] {
+                        emit_occurrence(match_exp_offset, path, c);
+                        emit_push_top_on_local_stack(Some((*id).clone()), t.is_obj_type(), c);
                     }
-                    Type::Bool => {
-                        let mut gotoend_offset = vec![];
-                        for TBranch { pat, exp } in branches {
-                            match pat {
-                                TPattern::Int(_) => {
-                                    panic!("typecheck")
-                                }
-                                TPattern::Id(_, id) => {
-                                    c.push_insn(Insn::GetLocal(SignedNum::from_i32(
-                                        match_exp_offset,
-                                    )));
-                                    emit_push_top_on_local_stack(Some(id.clone()), false, c);
-                                    exp.emit_code_body(c)?;
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                    c.symbol_table.pop();
-                                }
-                                TPattern::Variant(_, _) => {
-                                    panic!("typecheck")
-                                }
-                                TPattern::Bool(b) => {
-                                    c.push_insn(Insn::GetLocal(SignedNum::from_i32(
-                                        match_exp_offset,
-                                    )));
-                                    c.push_insn(Insn::Placeholder);
-                                    let i0 = c.codes.len();
-                                    exp.emit_code_body(c)?;
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                    let i1 = c.codes.len();
-                                    c.codes[i0 - 1] = if *b {
-                                        Insn::jne(bytecode_len(&c.codes[i0..i1]) as i32)
-                                    } else {
-                                        Insn::je(bytecode_len(&c.codes[i0..i1]) as i32)
-                                    };
-                                }
-                                TPattern::Tuple(_) => panic!("typecheck"),
-                                TPattern::None => {
-                                    exp.emit_code_body(c)?;
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                }
-                            }
-                        }
-                        let end = c.codes.len();
-                        for st in gotoend_offset {
-                            c.codes[st] = Insn::J32(bytecode_len(&c.codes[st + 1..end]) as i32);
-                        }
+                    let guard = &branches[b].guard;
+                    if let Some(g) = guard {
+                        g.emit_code(c)?;
+                        c.push_insn(Insn::Placeholder);
                     }
-                    Type::User(_, _) => {
-                        let mut gotoend_offset = vec![];
-                        c.push_insn(Insn::GetLocal(SignedNum::from_i32(match_exp_offset)));
-                        c.push_insn(Insn::ObjTag);
-                        let tag_offset = c.symbol_table.len();
-                        emit_push_top_on_local_stack(None, false, c); //tag space
-                        for TBranch { pat, exp } in branches {
-                            match pat {
-                                TPattern::Int(_) => panic!("typecheck"),
-                                TPattern::Bool(_) => panic!("typecheck"),
-                                TPattern::Tuple(_) => panic!("typecheck"),
-                                TPattern::None => {
-                                    exp.emit_code_body(c)?;
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                }
-                                TPattern::Id(_, id) => {
-                                    c.push_insn(Insn::GetLocal(SignedNum::from_i32(
-                                        match_exp_offset,
-                                    )));
-                                    emit_push_top_on_local_stack(Some(id.clone()), true, c);
-                                    exp.emit_code_body(c)?;
-                                    c.symbol_table.pop();
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                }
-                                TPattern::Variant(tag, pats) => {
-                                    c.push_insn(Insn::GetLocal(
-                                        SignedNum::from_usize(tag_offset).unwrap(),
-                                    ));
-                                    c.push_insn(Insn::Int(SignedNum::from_usize(*tag).unwrap()));
-                                    c.push_insn(Insn::Eq);
-                                    c.push_insn(Insn::Placeholder);
-                                    let i0 = c.codes.len();
-                                    let mut local_cnt = 0;
-                                    for (i, pat) in pats.iter().enumerate() {
-                                        match pat {
-                                            TPattern::Id(t, id) => {
-                                                local_cnt += 1;
-                                                c.push_insn(Insn::GetLocal(SignedNum::from_i32(
-                                                    match_exp_offset,
-                                                )));
-                                                c.push_insn(Insn::ObjField(
-                                                    UnsignedNum::from_usize(i).unwrap(),
-                                                ));
-                                                emit_push_top_on_local_stack(
-                                                    Some(id.clone()),
-                                                    t.is_obj_type(),
-                                                    c,
-                                                );
-                                            }
-                                            TPattern::Variant(_, _) => todo!(),
-                                            TPattern::Bool(_) => todo!(),
-                                            TPattern::Int(_) => todo!(),
-                                            TPattern::Tuple(_) => todo!(),
-                                            TPattern::None => {
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                    exp.emit_code_body(c)?;
-                                    for _ in 0..local_cnt {
-                                        c.symbol_table.pop();
-                                    }
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                    let i1 = c.codes.len();
-                                    c.codes[i0 - 1] =
-                                        Insn::jne(bytecode_len(&c.codes[i0..i1]) as i32);
-                                }
-                            }
-                        }
-                        c.push_insn(Insn::Abort);
-                        c.symbol_table.pop(); // pop tag
-                        let end = c.codes.len();
-                        for st in gotoend_offset {
-                            c.codes[st] = Insn::J32(bytecode_len(&c.codes[st + 1..end]) as i32);
-                        }
+                    let i0 = c.codes.len();
+                    if n_leaves[b] == 1 {
+                        emit_body(b, c)?;
+                    } else {
+                        to_branch.push((c.codes.len(), b));
+                        c.push_insn(Insn::J32(0)); //0 is placeholder
                     }
-                    Type::Tuple(_) => {
-                        let mut gotoend_offset = vec![];
-                        for TBranch { pat, exp } in branches {
-                            match pat {
-                                TPattern::Int(_) => panic!("typecheck"),
-                                TPattern::Bool(_) => panic!("typecheck"),
-                                TPattern::Tuple(pats) => {
-                                    let mut local_cnt = 0;
-                                    for (i, pat) in pats.iter().enumerate() {
-                                        match pat {
-                                            TPattern::Id(t, id) => {
-                                                local_cnt += 1;
-                                                c.push_insn(Insn::GetLocal(SignedNum::from_i32(
-                                                    match_exp_offset,
-                                                )));
-                                                c.push_insn(Insn::ObjField(
-                                                    UnsignedNum::from_usize(i).unwrap(),
-                                                ));
-                                                emit_push_top_on_local_stack(
-                                                    Some(id.clone()),
-                                                    t.is_obj_type(),
-                                                    c,
-                                                );
-                                            }
-                                            TPattern::Variant(_, _) => todo!(),
-                                            TPattern::Bool(_) => todo!(),
-                                            TPattern::Int(_) => todo!(),
-                                            TPattern::Tuple(_) => todo!(),
-                                            TPattern::None => {
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                    exp.emit_code_body(c)?;
-                                    for _ in 0..local_cnt {
-                                        c.symbol_table.pop();
-                                    }
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                }
-                                TPattern::None => todo!(),
-                                TPattern::Id(t, id) => {
-                                    c.push_insn(Insn::GetLocal(SignedNum::from_i32(
-                                        match_exp_offset,
-                                    )));
-                                    emit_push_top_on_local_stack(
-                                        Some(id.clone()),
-                                        t.is_obj_type(),
-                                        c,
-                                    );
-                                    exp.emit_code_body(c)?;
-                                    c.symbol_table.pop();
-                                    gotoend_offset.push(c.codes.len());
-                                    c.codes.push(Insn::J32(0)); //0 is placeholder
-                                }
-                                TPattern::Variant(..) => {
-                                    panic!("typecheck")
-                                }
-                            }
-                        }
-                        c.push_insn(Insn::Abort);
-                        let end = c.codes.len();
-                        for st in gotoend_offset {
-                            c.codes[st] = Insn::J32(bytecode_len(&c.codes[st + 1..end]) as i32);
-                        }
+                    if guard.is_some() {
+                        c.codes[i0 - 1] = Insn::jne(bytecode_len(&c.codes[i0..]) as i32);
                     }
-                };
+                    for _ in &binds[b] {
+                        c.symbol_table.pop();
+                    }
+                    Ok(guard.is_some())
+                })?;
+                for (k, _) in n_leaves.iter().enumerate().filter(|(_, n)| **n > 1) {
+                    let start = c.codes.len();
+                    for (st, _) in to_branch.iter().filter(|(_, b)| *b == k) {
+                        c.codes[*st] = Insn::J32(bytecode_len(&c.codes[st + 1..start]) as i32);
+                    }
+                    for (id, _, t) in &binds[k] {
+                        c.symbol_table.push((Some((*id).clone()), t.is_obj_type()));
+                    }
+                    emit_body(k, c)?;
+                    for _ in &binds[k] {
+                        c.symbol_table.pop();
+                    }
+                }
+                let end = c.codes.len();
+                for st in gotoend_offset {
+                    c.codes[st] = Insn::J32(bytecode_len(&c.codes[st + 1..end]) as i32);
+                }
                 c.symbol_table.pop(); // pop match exp
                 if exp.get_type().is_obj_type() {
                     c.push_insn(Insn::DropLocalObj(SignedNum::from_i32(match_exp_offset)));
@@ -600,9 +595,9 @@ impl TExp {
                 let i1 = c.codes.len();
                 then.emit_code_body(c)?;
                 let i2 = c.codes.len();
-                c.codes[i1 as usize - 1] = Insn::j(bytecode_len(&c.codes[i1..i2]) as i32);
+                c.codes[i1 - 1] = Insn::j(bytecode_len(&c.codes[i1..i2]) as i32);
 
-                c.codes[i0 as usize - 1] = Insn::je(bytecode_len(&c.codes[i0..i1]) as i32);
+                c.codes[i0 - 1] = Insn::je(bytecode_len(&c.codes[i0..i1]) as i32);
 
                 Ok(())
             }
@@ -617,8 +612,12 @@ impl TBlock {
         let mut drop_list = vec![];
         for TStmt { id, val } in stmt {
             val.emit_code_body(c)?;
-            drop_list.push(c.symbol_table.len());
-            emit_push_top_on_local_stack(Some(id.clone()), val.get_type().is_obj_type(), c);
+            let is_obj = val.get_type().is_obj_type();
+            // an Int or Bool local has no refcount, and DropLocalObj would take it for a pointer
+            if is_obj {
+                drop_list.push(c.symbol_table.len());
+            }
+            emit_push_top_on_local_stack(Some(id.clone()), is_obj, c);
         }
         exp.emit_code_body(c)?;
         for _ in 0..stmt.len() {
@@ -692,13 +691,21 @@ impl TComp {
             TComp::Eq(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Eq);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FEq
+                } else {
+                    Insn::Eq
+                });
                 Ok(())
             }
             TComp::Neq(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Neq);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FNeq
+                } else {
+                    Insn::Neq
+                });
                 Ok(())
             }
             TComp::Comp2(a) => a.emit_code(c),
@@ -711,25 +718,41 @@ impl TComp2 {
             TComp2::Leq(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Leq);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FLeq
+                } else {
+                    Insn::Leq
+                });
                 Ok(())
             }
             TComp2::Ls(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Ls);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FLs
+                } else {
+                    Insn::Ls
+                });
                 Ok(())
             }
             TComp2::Geq(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Geq);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FGeq
+                } else {
+                    Insn::Geq
+                });
                 Ok(())
             }
             TComp2::Gt(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Gt);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FGt
+                } else {
+                    Insn::Gt
+                });
                 Ok(())
             }
             TComp2::Shift(a) => a.emit_code(c),
@@ -761,13 +784,21 @@ impl TAdd {
             TAdd::Plus(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Add);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FAdd
+                } else {
+                    Insn::Add
+                });
                 Ok(())
             }
             TAdd::Minus(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Sub);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FSub
+                } else {
+                    Insn::Sub
+                });
                 Ok(())
             }
             TAdd::Factor(f) => f.emit_code(c),
@@ -780,12 +811,20 @@ impl TFactor {
             TFactor::Mul(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Mul);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FMul
+                } else {
+                    Insn::Mul
+                });
             }
             TFactor::Div(a, b) => {
                 a.emit_code(c)?;
                 b.emit_code(c)?;
-                c.push_insn(Insn::Div);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FDiv
+                } else {
+                    Insn::Div
+                });
             }
             TFactor::Mod(a, b) => {
                 a.emit_code(c)?;
@@ -806,7 +845,11 @@ impl TUnary {
             }
             TUnary::Minus(a) => {
                 a.emit_code(c)?;
-                c.push_insn(Insn::Minus);
+                c.push_insn(if a.get_type() == &Type::Float {
+                    Insn::FMinus
+                } else {
+                    Insn::Minus
+                });
             }
             TUnary::Primary(a) => return a.emit_code(c),
         }
@@ -817,9 +860,18 @@ impl TPrimary {
     pub(super) fn emit_code(&self, c: &mut Compiler) -> CResult<()> {
         match self {
             TPrimary::Int(i) => c.push_insn(Insn::Int(SignedNum::from_i32(*i))),
+            TPrimary::Float(f) => c.push_insn(Insn::Float(f.to_bits())),
             TPrimary::Bool(b) => c.push_insn(if *b { Insn::PushTrue } else { Insn::PushFalse }),
             TPrimary::Exp(e) => return e.emit_code_body(c),
-            TPrimary::Id(id, _) => {
+            TPrimary::ToFloat(e) => {
+                e.emit_code_body(c)?;
+                c.push_insn(Insn::IntToFloat);
+            }
+            TPrimary::ToInt(e) => {
+                e.emit_code_body(c)?;
+                c.push_insn(Insn::FloatToInt);
+            }
+            TPrimary::Id(id, _, _) => {
                 for i in (0..c.symbol_table.len()).rev() {
                     if let (Some(id2), is_obj) = &c.symbol_table[i] {
                         if id == id2 {
@@ -848,10 +900,10 @@ impl TPrimary {
                     panic!("typecheck")
                 }
             }
-            TPrimary::Last(id, t) => {
+            TPrimary::Last(id, t, span) => {
                 if let Some(i) = c.node_offset(id) {
                     if c.node_info[i].is_new && !c.node_info[i].has_value {
-                        return Err(CompileErr::InvalidAtLast);
+                        return Err(CompileErr::InvalidAtLast.at(*span));
                     }
                     let last_offset = c.atlast_manager.runtime_offset(i);
                     // if last_offset is none, x@last is referenced
@@ -883,8 +935,8 @@ impl TPrimary {
                     UnsignedNum::from_usize(f).unwrap(),
                 ));
             }
-            TPrimary::Variant(vname, _, exps) => {
-                let (t, tag, _) = c.get_type_from_variant(&vname).unwrap();
+            TPrimary::Variant(vname, t, exps) => {
+                let tag = 1 + t.variants().iter().position(|(v, _)| v == vname).unwrap();
                 let max_entry = max_entry(t);
                 let mut objbit = vec![];
                 for e in exps {
@@ -0,0 +1,192 @@
+// exhaustiveness and redundancy of the branches of `of:`, by the usefulness of pattern rows
+// (Maranget, "Warnings for pattern matching").
+// a row is a list of patterns matched against a list of values of the given types
+use super::compile_common::*;
+use super::typed_ast::*;
+
+#[derive(Debug, Clone)]
+enum Pat {
+    // `_` and identifiers
+    Any,
+    Int(i32),
+    Bool(bool),
+    // tag of a variant, or 0 for a tuple
+    Ctor(usize, Vec<Pat>),
+}
+impl Pat {
+    fn new(p: &TPattern) -> Self {
+        match p {
+            TPattern::Id(_, _) | TPattern::None => Pat::Any,
+            TPattern::Int(i) => Pat::Int(*i),
+            TPattern::Bool(b) => Pat::Bool(*b),
+            TPattern::Variant(tag, pats) => Pat::Ctor(*tag, pats.iter().map(Pat::new).collect()),
+            TPattern::Tuple(pats) => Pat::Ctor(0, pats.iter().map(Pat::new).collect()),
+        }
+    }
+}
+
+// constructors of a type and the types of their args, or None if there are too many to list
+fn constructors(t: &Type) -> Option<Vec<(Pat, Vec<Type>)>> {
+    match t {
+        Type::Bool => Some(vec![(Pat::Bool(false), vec![]), (Pat::Bool(true), vec![])]),
+        Type::Tuple(types) => Some(vec![(Pat::Ctor(0, vec![]), types.clone())]),
+        Type::User(_, _, vars) => Some(
+            vars.iter()
+                .enumerate()
+                .map(|(i, (_, args))| (Pat::Ctor(i + 1, vec![]), args.clone()))
+                .collect(),
+        ),
+        Type::Int | Type::Float => None,
+    }
+}
+fn same_ctor(a: &Pat, b: &Pat) -> bool {
+    match (a, b) {
+        (Pat::Int(a), Pat::Int(b)) => a == b,
+        (Pat::Bool(a), Pat::Bool(b)) => a == b,
+        (Pat::Ctor(a, _), Pat::Ctor(b, _)) => a == b,
+        _ => false,
+    }
+}
+// the rest of a row whose first pattern matches ctor, with the args of ctor in front
+fn specialize(row: &[Pat], ctor: &Pat, arity: usize) -> Option<Vec<Pat>> {
+    let mut res = match &row[0] {
+        Pat::Any => vec![Pat::Any; arity],
+        Pat::Ctor(_, args) if same_ctor(&row[0], ctor) => args.clone(),
+        p if same_ctor(p, ctor) => vec![],
+        _ => return None,
+    };
+    res.extend_from_slice(&row[1..]);
+    Some(res)
+}
+// constructors of the first column if all of them appear in it
+fn complete(rows: &[Vec<Pat>], t: &Type) -> Option<Vec<(Pat, Vec<Type>)>> {
+    let ctors = constructors(t)?;
+    let all = ctors
+        .iter()
+        .all(|(c, _)| rows.iter().any(|r| same_ctor(&r[0], c)));
+    // a tuple is matched by `_` as well as by its only constructor
+    (all || matches!(t, Type::Tuple(_))).then_some(ctors)
+}
+fn with_types(args: &[Type], rest: &[Type]) -> Vec<Type> {
+    args.iter().chain(rest).cloned().collect()
+}
+
+// whether a value matched by q is not matched by any of rows
+fn useful(rows: &[Vec<Pat>], q: &[Pat], types: &[Type]) -> bool {
+    if types.is_empty() {
+        return rows.is_empty();
+    }
+    let ctors: Vec<(Pat, Vec<Type>)> = match &q[0] {
+        Pat::Any => match complete(rows, &types[0]) {
+            Some(ctors) => ctors,
+            None => {
+                let rows: Vec<Vec<Pat>> = rows
+                    .iter()
+                    .filter(|r| matches!(r[0], Pat::Any))
+                    .map(|r| r[1..].to_vec())
+                    .collect();
+                return useful(&rows, &q[1..], &types[1..]);
+            }
+        },
+        p => {
+            let args = constructors(&types[0])
+                .and_then(|cs| cs.into_iter().find(|(c, _)| same_ctor(c, p)))
+                .map_or(vec![], |(_, args)| args);
+            vec![(p.clone(), args)]
+        }
+    };
+    ctors.iter().any(|(c, args)| {
+        let rows: Vec<Vec<Pat>> = rows
+            .iter()
+            .filter_map(|r| specialize(r, c, args.len()))
+            .collect();
+        let q = specialize(q, c, args.len()).unwrap();
+        useful(&rows, &q, &with_types(args, &types[1..]))
+    })
+}
+
+// values of the types matched by none of rows, written as patterns
+fn missing(rows: &[Vec<Pat>], types: &[Type]) -> Option<Vec<String>> {
+    if types.is_empty() {
+        return rows.is_empty().then_some(vec![]);
+    }
+    let t = &types[0];
+    if let Some(ctors) = complete(rows, t) {
+        for (c, args) in &ctors {
+            let spec: Vec<Vec<Pat>> = rows
+                .iter()
+                .filter_map(|r| specialize(r, c, args.len()))
+                .collect();
+            if let Some(mut w) = missing(&spec, &with_types(args, &types[1..])) {
+                let rest = w.split_off(args.len());
+                let mut res = vec![show_ctor(t, c, w)];
+                res.extend(rest);
+                return Some(res);
+            }
+        }
+        return None;
+    }
+    let default: Vec<Vec<Pat>> = rows
+        .iter()
+        .filter(|r| matches!(r[0], Pat::Any))
+        .map(|r| r[1..].to_vec())
+        .collect();
+    let rest = missing(&default, &types[1..])?;
+    let used: Vec<&Pat> = rows
+        .iter()
+        .map(|r| &r[0])
+        .filter(|p| !matches!(p, Pat::Any))
+        .collect();
+    let head = match constructors(t) {
+        _ if used.is_empty() => "_".to_string(),
+        Some(ctors) => {
+            let (c, args) = ctors
+                .into_iter()
+                .find(|(c, _)| !used.iter().any(|u| same_ctor(u, c)))
+                .unwrap();
+            show_ctor(t, &c, vec!["_".to_string(); args.len()])
+        }
+        // the smallest integer no branch is written for
+        None => {
+            let i = (0..).find(|i| !used.iter().any(|u| same_ctor(u, &Pat::Int(*i))));
+            i.unwrap().to_string()
+        }
+    };
+    let mut res = vec![head];
+    res.extend(rest);
+    Some(res)
+}
+fn show_ctor(t: &Type, c: &Pat, args: Vec<String>) -> String {
+    match (t, c) {
+        (_, Pat::Bool(b)) => b.to_string(),
+        (Type::Tuple(_), _) => format!("({})", args.join(", ")),
+        (Type::User(_, _, vars), Pat::Ctor(tag, _)) => {
+            let name = &vars[tag - 1].0 .0;
+            if args.is_empty() {
+                name.clone()
+            } else {
+                format!("{name}({})", args.join(", "))
+            }
+        }
+        _ => "_".to_string(),
+    }
+}
+
+// a value of t no branch matches, and the indices of branches which are never taken.
+// a branch with a guard may not be taken for the values of its pattern, so it covers none of them
+pub(super) fn check_branches(t: &Type, pats: &[(TPattern, bool)]) -> (Option<String>, Vec<usize>) {
+    let types = [t.clone()];
+    let mut rows: Vec<Vec<Pat>> = vec![];
+    let mut unreachable = vec![];
+    for (i, (p, guarded)) in pats.iter().enumerate() {
+        let row = vec![Pat::new(p)];
+        if !useful(&rows, &row, &types) {
+            unreachable.push(i);
+        }
+        if !guarded {
+            rows.push(row);
+        }
+    }
+    let missing = missing(&rows, &types).map(|mut w| w.remove(0));
+    (missing, unreachable)
+}
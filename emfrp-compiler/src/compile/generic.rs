@@ -0,0 +1,149 @@
+use super::compile_common::*;
+use crate::ast::*;
+
+// bounds the instances compiled at once and the nesting of their type args,
+// since a func such as f<a> calling f<(a, a)> has infinitely many instances
+const MAX_INSTANCES: usize = 256;
+const MAX_TYPE_DEPTH: usize = 16;
+
+// calls of generic funcs
+struct Calls<'a>(&'a mut Vec<(Id, Vec<TypeName>)>);
+impl VisitMut for Calls<'_> {
+    fn primary(&mut self, p: &mut Primary) {
+        if let Primary::FnCall(id, _, targs, _) = p {
+            if !targs.is_empty() {
+                self.0.push((id.clone(), targs.clone()))
+            }
+        }
+    }
+}
+// replaces the type parameters in the body of a generic func
+struct Subst<'a>(&'a [(String, TypeName)]);
+impl VisitMut for Subst<'_> {
+    fn stmt(&mut self, s: &mut Stmt) {
+        if let Some(t) = &mut s.tname {
+            *t = t.subst(self.0)
+        }
+    }
+    fn primary(&mut self, p: &mut Primary) {
+        match p {
+            Primary::Variant(_, _, targs, _)
+            | Primary::Record(_, _, targs, _)
+            | Primary::FnCall(_, _, targs, _) => {
+                for t in targs {
+                    *t = t.subst(self.0)
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn depth(t: &TypeName) -> usize {
+    match t {
+        TypeName::Tuple(ts) | TypeName::User(_, ts) => 1 + ts.iter().map(depth).max().unwrap_or(0),
+        TypeName::Var(_) | TypeName::Bool | TypeName::Int | TypeName::Float => 0,
+    }
+}
+// type parameters of an inferred func
+fn type_params(def: &VarDef) -> Vec<String> {
+    let mut vars = vec![];
+    if let VarDef::Func { ret, params, .. } = def {
+        for (_, t) in params {
+            annotation(t).vars(&mut vars);
+        }
+        annotation(ret).vars(&mut vars);
+    }
+    vars
+}
+
+impl Compiler {
+    // generic funcs in defs are kept in generic_funcs instead of being compiled.
+    // returns the other defs and the instances of generic funcs they call,
+    // which are compiled as funcs named f<Int, Bool>
+    pub(super) fn instantiate_generics(&mut self, defs: Vec<VarDef>) -> CResult<Vec<VarDef>> {
+        let mut ret = Vec::with_capacity(defs.len());
+        let mut calls = vec![];
+        for def in defs {
+            let tparams = type_params(&def);
+            let name = match &def {
+                VarDef::Func { name, .. } => name.clone(),
+                _ => {
+                    ret.push(def);
+                    continue;
+                }
+            };
+            let old = self.generic_funcs.iter().position(|g| g.name == name);
+            let old = old.map(|i| self.generic_funcs.remove(i));
+            if tparams.is_empty() {
+                ret.push(def);
+                continue;
+            }
+            // instances compiled with the old definition are compiled again
+            for targs in old.map_or(vec![], |g| g.instances) {
+                calls.push((name.clone(), targs));
+            }
+            self.generic_funcs.push(GenericFunc {
+                name,
+                tparams,
+                def,
+                instances: vec![],
+            });
+        }
+        for def in &mut ret {
+            def.visit_mut(&mut Calls(&mut calls));
+        }
+        self.add_instances(calls, ret)
+    }
+    // instances of generic funcs called in e
+    pub(super) fn instantiate_calls(&mut self, e: &mut Exp) -> CResult<Vec<VarDef>> {
+        let mut calls = vec![];
+        e.visit_mut(&mut Calls(&mut calls));
+        self.add_instances(calls, vec![])
+    }
+    fn add_instances(
+        &mut self,
+        mut calls: Vec<(Id, Vec<TypeName>)>,
+        mut defs: Vec<VarDef>,
+    ) -> CResult<Vec<VarDef>> {
+        let mut n = 0;
+        while let Some((name, targs)) = calls.pop() {
+            let Some(g) = self.generic_funcs.iter_mut().find(|g| g.name == name) else {
+                continue;
+            };
+            if g.instances.contains(&targs) {
+                continue;
+            }
+            n += 1;
+            if n > MAX_INSTANCES || targs.iter().any(|t| depth(t) > MAX_TYPE_DEPTH) {
+                return Err(CompileErr::TooManyInstances(name).at(g.def.span()));
+            }
+            g.instances.push(targs.clone());
+            let env: Vec<(String, TypeName)> = g
+                .tparams
+                .iter()
+                .cloned()
+                .zip(targs.iter().cloned())
+                .collect();
+            let mut def = g.def.clone();
+            if let VarDef::Func {
+                name: fname,
+                ret,
+                params,
+                body,
+                ..
+            } = &mut def
+            {
+                *fname = instance_name(&name, &targs);
+                for (_, t) in params {
+                    *t = t.as_ref().map(|t| t.subst(&env));
+                }
+                *ret = ret.as_ref().map(|t| t.subst(&env));
+                body.visit_mut(&mut Subst(&env));
+            }
+            def.visit_mut(&mut Calls(&mut calls));
+            defs.push(def);
+        }
+        Ok(defs)
+    }
+}
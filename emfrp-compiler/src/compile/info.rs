@@ -0,0 +1,169 @@
+// what is defined, for the commands of the REPL. slots of removed definitions are skipped
+use crate::ast::*;
+
+use super::compile_common::*;
+
+impl Compiler {
+    // `in`, `out`, `node` or `data`, the name and the type
+    pub fn var_list(&self) -> Vec<(&'static str, String, Type)> {
+        let mut res = vec![];
+        for nd in &self.node_info {
+            if nd.name.0.is_empty() {
+                continue;
+            }
+            let kind = match (&nd.input_kind, nd.output_offset) {
+                (NodeInputKind::Dev, _) => "in",
+                (_, Some(_)) => "out",
+                _ => "node",
+            };
+            res.push((kind, nd.name.0.clone(), nd.typ.clone()));
+        }
+        for data in &self.data_info {
+            if !data.name.0.is_empty() {
+                res.push(("data", data.name.0.clone(), data.typ.clone()));
+            }
+        }
+        res
+    }
+    // funcs on the machine, including the instances of generic funcs, and then generic funcs
+    pub fn func_list(&self) -> Vec<String> {
+        let mut res = vec![];
+        for f in &self.func_info {
+            if f.name.0.is_empty() {
+                continue;
+            }
+            let prms: Vec<String> = f
+                .prms
+                .iter()
+                .map(|(x, t)| format!("{}: {t}", x.0))
+                .collect();
+            res.push(format!(
+                "func {}({}) -> {}",
+                f.name.0,
+                prms.join(", "),
+                f.ret
+            ));
+        }
+        for g in &self.generic_funcs {
+            if let VarDef::Func {
+                name, ret, params, ..
+            } = &g.def
+            {
+                let prms: Vec<String> = params
+                    .iter()
+                    .map(|(x, t)| format!("{}: {}", x.0, annotation(t)))
+                    .collect();
+                res.push(format!(
+                    "func {}({}) -> {}",
+                    name.0,
+                    prms.join(", "),
+                    annotation(ret)
+                ));
+            }
+        }
+        res
+    }
+    // type definitions as they are written, sorted by name
+    pub fn type_list(&self) -> Vec<String> {
+        let variant = |name: &VariantName, elems: Vec<String>| {
+            if elems.is_empty() {
+                name.0.clone()
+            } else {
+                format!("{}({})", name.0, elems.join(", "))
+            }
+        };
+        let record = |name: &str, params: String, elems: Vec<String>| {
+            let fields: Vec<String> = self.records[name]
+                .iter()
+                .zip(elems)
+                .map(|(f, t)| format!("{}: {t}", f.0))
+                .collect();
+            format!("record {name}{params} = {{ {} }}", fields.join(", "))
+        };
+        let mut res: Vec<(String, String)> = vec![];
+        for t in self.types.values() {
+            if let Type::User(name, _, vars) = t {
+                if self.records.contains_key(name) {
+                    let elems = vars[0].1.iter().map(|t| t.to_string()).collect();
+                    res.push((name.clone(), record(name, String::new(), elems)));
+                    continue;
+                }
+                let vars: Vec<String> = vars
+                    .iter()
+                    .map(|(v, ts)| variant(v, ts.iter().map(|t| t.to_string()).collect()))
+                    .collect();
+                res.push((name.clone(), format!("type {name} = {}", vars.join(" | "))));
+            }
+        }
+        for (name, (params, vars)) in &self.generic_types {
+            if self.records.contains_key(name) {
+                let elems = vars[0].elems.iter().map(|t| t.to_string()).collect();
+                let params = format!("<{}>", params.join(", "));
+                res.push((name.clone(), record(name, params, elems)));
+                continue;
+            }
+            let vars: Vec<String> = vars
+                .iter()
+                .map(|v| {
+                    variant(
+                        &v.constructor,
+                        v.elems.iter().map(|t| t.to_string()).collect(),
+                    )
+                })
+                .collect();
+            res.push((
+                name.clone(),
+                format!("type {name}<{}> = {}", params.join(", "), vars.join(" | ")),
+            ));
+        }
+        res.sort();
+        res.into_iter().map(|(_, s)| s).collect()
+    }
+    // type of e, which is not compiled into the program
+    pub fn type_of(&self, e: &Exp) -> Result<Type, CompileErr> {
+        let mut cmp = self.clone();
+        cmp.compile_instances(e)?;
+        let (t, _) = cmp.compile_exp(e.clone())?;
+        Ok(t)
+    }
+    pub fn node_deps(&self, name: &Id) -> Result<NodeDeps, CompileErr> {
+        let i = self
+            .node_offset(name)
+            .ok_or(CompileErr::IdNotFound(name.clone()))?;
+        let names = |offsets: Vec<usize>| {
+            let mut names: Vec<String> = offsets
+                .into_iter()
+                .map(|j| self.node_name(j).0.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        let nd = &self.node_info[i];
+        let used_by = (0..self.node_info.len())
+            .filter(|&j| {
+                self.node_info[j].prev.contains(&i) || self.node_info[j].atlast.contains(&i)
+            })
+            .collect();
+        Ok(NodeDeps {
+            now: names(nd.prev.iter().copied().collect()),
+            last: names(nd.atlast.iter().copied().collect()),
+            used_by: names(used_by),
+        })
+    }
+    // nodes in the order the machine updates them
+    pub fn update_order(&self) -> Result<Vec<String>, CompileErr> {
+        let order = self.topological_sort()?;
+        Ok(order
+            .into_iter()
+            .map(|i| self.node_name(i).0.clone())
+            .filter(|name| !name.is_empty())
+            .collect())
+    }
+    // offset of a node on the machine and its type
+    pub fn node_slot(&self, name: &Id) -> Result<(usize, Type), CompileErr> {
+        let i = self
+            .node_offset(name)
+            .ok_or(CompileErr::IdNotFound(name.clone()))?;
+        Ok((i, self.node_info[i].typ.clone()))
+    }
+}
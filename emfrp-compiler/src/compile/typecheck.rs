@@ -1,24 +1,27 @@
 use super::compile_common::*;
+use super::exhaustive::check_branches;
 use super::typed_ast::*;
 use crate::ast::*;
 
 impl Compiler {
     pub(super) fn exp_type_check(&self, e: Exp) -> CResult<TExp> {
         let mut locals = vec![];
-        let ret = e.typed(self, &mut locals)?;
+        let span = e.span();
+        let ret = e.typed(self, &mut locals).map_err(|e| e.at(span))?;
         assert_eq!(locals.len(), 0);
-        return Ok(ret);
+        Ok(ret)
     }
     pub(super) fn vardef_type_check(&self, d: VarDef) -> CResult<TVarDef> {
         let mut locals = vec![];
-        let ret = d.typed(self, &mut locals)?;
+        let span = d.span();
+        let ret = d.typed(self, &mut locals).map_err(|e| e.at(span))?;
         assert_eq!(locals.len(), 0);
-        return Ok(ret);
+        Ok(ret)
     }
 }
 
-fn terr<T>(t: TypeErr) -> CResult<T> {
-    Err(CompileErr::TypeErr(t))
+fn terr<T>(span: Span, t: TypeErr) -> CResult<T> {
+    Err(CompileErr::TypeErr(Box::new(t)).at(span))
 }
 
 impl VarDef {
@@ -29,43 +32,54 @@ impl VarDef {
                 typ,
                 init,
                 val,
+                span,
             } => {
                 if let Some(init) = init {
-                    let typ = c.get_type_with_type_name(&typ)?;
+                    let typ = c.get_type_with_type_name(annotation(&typ))?;
+                    let (init_span, val_span) = (init.span(), val.span());
                     let tinit = init.typed(c, locals)?;
                     let val = val.typed(c, locals)?;
                     if &typ != tinit.get_type() {
-                        return terr(TypeErr::Mismatch(typ, tinit.get_type().clone()));
+                        return terr(init_span, TypeErr::Mismatch(typ, tinit.get_type().clone()));
                     }
                     if &typ != val.get_type() {
-                        return terr(TypeErr::Mismatch(typ, val.get_type().clone()));
+                        return terr(val_span, TypeErr::Mismatch(typ, val.get_type().clone()));
                     }
                     Ok(TVarDef::Node {
                         name,
                         init: Some(tinit),
                         val,
+                        span,
                     })
                 } else {
-                    let typ = c.get_type_with_type_name(&typ)?;
+                    let typ = c.get_type_with_type_name(annotation(&typ))?;
+                    let val_span = val.span();
                     let val = val.typed(c, locals)?;
                     if &typ == val.get_type() {
                         Ok(TVarDef::Node {
                             name,
                             init: None,
                             val,
+                            span,
                         })
                     } else {
-                        terr(TypeErr::Mismatch(typ, val.get_type().clone()))
+                        terr(val_span, TypeErr::Mismatch(typ, val.get_type().clone()))
                     }
                 }
             }
-            VarDef::Data { name, typ, val } => {
-                let typ = c.get_type_with_type_name(&typ)?;
+            VarDef::Data {
+                name,
+                typ,
+                val,
+                span,
+            } => {
+                let typ = c.get_type_with_type_name(annotation(&typ))?;
+                let val_span = val.span();
                 let val = val.typed(c, locals)?;
                 if &typ == val.get_type() {
-                    Ok(TVarDef::Data { name, val })
+                    Ok(TVarDef::Data { name, val, span })
                 } else {
-                    terr(TypeErr::Mismatch(typ, val.get_type().clone()))
+                    terr(val_span, TypeErr::Mismatch(typ, val.get_type().clone()))
                 }
             }
 
@@ -74,16 +88,18 @@ impl VarDef {
                 ret,
                 params,
                 body,
+                span,
             } => {
                 let mut params_ast = Vec::with_capacity(params.len());
                 let mut prms = Vec::with_capacity(params.len());
                 for (id, typ) in params {
-                    let typ = c.get_type_with_type_name(&typ)?;
+                    let typ = c.get_type_with_type_name(annotation(&typ))?;
                     locals.push((id.clone(), typ.clone()));
                     prms.push(typ.clone());
                     params_ast.push((id, typ.clone()))
                 }
-                let ret = c.get_type_with_type_name(&ret)?;
+                let ret = c.get_type_with_type_name(annotation(&ret))?;
+                let body_span = body.span();
                 let body = body.typed(c, locals)?;
                 if body.get_type() == &ret {
                     for _ in 0..params_ast.len() {
@@ -93,9 +109,10 @@ impl VarDef {
                         name,
                         params: params_ast,
                         body,
+                        span,
                     })
                 } else {
-                    terr(TypeErr::Mismatch(ret, body.get_type().clone()))
+                    terr(body_span, TypeErr::Mismatch(ret, body.get_type().clone()))
                 }
             }
         }
@@ -103,17 +120,34 @@ impl VarDef {
 }
 impl Exp {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TExp> {
+        let span = self.span();
         match self {
             Exp::Match(e, branches) => {
+                let pat_spans: Vec<Span> = branches.iter().map(|b| b.pat.span()).collect();
                 let e = e.typed(c, locals)?;
                 let etype = e.get_type();
                 let mut rettype = None;
                 let mut branch_ret = Vec::with_capacity(branches.len());
-                for Branch { pat, exp } in branches {
+                for Branch { pat, guard, exp } in branches {
                     // check whether pat is the same type as etype
+                    let exp_span = exp.span;
                     let l0 = locals.len();
-                    let pat = pat.typed(c, locals, &etype)?;
+                    let pat = pat.typed(c, locals, etype)?;
                     let l1 = locals.len();
+                    let guard = match guard {
+                        Some(g) => {
+                            let g_span = g.span();
+                            let g = g.typed(c, locals)?;
+                            if g.get_type() != &Type::Bool {
+                                return terr(
+                                    g_span,
+                                    TypeErr::Mismatch(Type::Bool, g.get_type().clone()),
+                                );
+                            }
+                            Some(g)
+                        }
+                        None => None,
+                    };
                     let exp = exp.typed(c, locals)?;
                     assert_eq!(locals.len(), l1);
                     for _ in 0..l1 - l0 {
@@ -123,27 +157,47 @@ impl Exp {
                     match rettype {
                         None => rettype = Some(exp.get_type().clone()),
                         Some(ret) if &ret != exp.get_type() => {
-                            return terr(TypeErr::Mismatch(ret, exp.get_type().clone()))
+                            return terr(exp_span, TypeErr::Mismatch(ret, exp.get_type().clone()))
                         }
                         _ => (),
                     }
-                    branch_ret.push(TBranch { pat, exp });
+                    branch_ret.push(TBranch { pat, guard, exp });
+                }
+                let pats: Vec<(TPattern, bool)> = branch_ret
+                    .iter()
+                    .map(|b| (b.pat.clone(), b.guard.is_some()))
+                    .collect();
+                let (missing, unreachable) = check_branches(etype, &pats);
+                if let Some(missing) = missing {
+                    return Err(CompileErr::NonExhaustive(missing).at(span));
+                }
+                for i in unreachable {
+                    c.warn(pat_spans[i], "this branch is never taken");
                 }
                 Ok(TExp::Match(Box::new(e), branch_ret))
             }
-            Exp::If { cond, then, els } => {
+            Exp::If {
+                cond,
+                then,
+                els,
+                span: _,
+            } => {
+                let (cond_span, els_span) = (cond.span(), els.span());
                 let cond = cond.typed(c, locals)?;
                 let then = then.typed(c, locals)?;
                 let els = els.typed(c, locals)?;
 
                 if (&Type::Bool) != cond.get_type() {
-                    return terr(TypeErr::Mismatch(Type::Bool, cond.get_type().clone()));
+                    return terr(
+                        cond_span,
+                        TypeErr::Mismatch(Type::Bool, cond.get_type().clone()),
+                    );
                 }
                 if then.get_type() != els.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        then.get_type().clone(),
-                        els.get_type().clone(),
-                    ));
+                    return terr(
+                        els_span,
+                        TypeErr::Mismatch(then.get_type().clone(), els.get_type().clone()),
+                    );
                 }
                 Ok(TExp::If {
                     cond: Box::new(cond),
@@ -159,13 +213,24 @@ impl Exp {
 impl Block {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TBlock> {
         let i0 = locals.len();
-        let Block { stmt, exp } = self;
+        let Block { stmt, exp, span: _ } = self;
         let mut tstmt = Vec::with_capacity(stmt.len());
-        for Stmt { id, tname, val } in stmt {
-            let t = c.get_type_with_type_name(&tname)?;
+        for Stmt {
+            id,
+            tname,
+            val,
+            span,
+        } in stmt
+        {
+            let val_span = val.span();
             let tval = val.typed(c, locals)?;
+            // without annotation, a local has the type of its value
+            let t = match tname {
+                Some(tname) => c.get_type_with_type_name(&tname).map_err(|e| e.at(span))?,
+                None => tval.get_type().clone(),
+            };
             if tval.get_type() != &t {
-                return terr(TypeErr::Mismatch(tval.get_type().clone(), t));
+                return terr(val_span, TypeErr::Mismatch(t, tval.get_type().clone()));
             }
             locals.push((id.clone(), t.clone()));
             tstmt.push(TStmt { id, val: tval })
@@ -189,40 +254,42 @@ impl Pattern {
         match_type: &Type,
     ) -> CResult<TPattern> {
         match self {
-            Pattern::Int(i) => match &match_type {
+            Pattern::Int(i, span) => match &match_type {
                 Type::Int => Ok(TPattern::Int(i)),
-                _ => terr(TypeErr::Mismatch(Type::Int, match_type.clone())),
+                _ => terr(span, TypeErr::Mismatch(match_type.clone(), Type::Int)),
             },
-            Pattern::Id(id) => {
+            Pattern::Id(id, _) => {
                 locals.push((id.clone(), match_type.clone()));
                 Ok(TPattern::Id(match_type.clone(), id))
             }
-            Pattern::Variant(vname, pat) => {
-                let (tinfo, tag, prms) = c.get_type_from_variant(&vname)?;
-                if tinfo != match_type {
-                    return terr(TypeErr::Mismatch(tinfo.clone(), match_type.clone()));
-                }
+            Pattern::Variant(vname, pat, span) => {
+                let vars = match_type.variants();
+                let Some(i) = vars.iter().position(|(name, _)| name == &vname) else {
+                    let (tinfo, _, _) = c.get_type_from_variant(&vname).map_err(|e| e.at(span))?;
+                    return terr(span, TypeErr::Mismatch(match_type.clone(), tinfo.clone()));
+                };
+                let (tag, prms) = (i + 1, &vars[i].1);
                 if prms.len() != pat.len() {
-                    return terr(TypeErr::IncorrectVarN(prms.len(), pat.len()));
+                    return terr(span, TypeErr::IncorrectVarN(prms.len(), pat.len()));
                 }
                 let mut tpats = Vec::with_capacity(pat.len());
-                for (typ, pat) in prms.iter().zip(pat.into_iter()) {
+                for (typ, pat) in prms.iter().zip(pat) {
                     tpats.push(pat.typed(c, locals, typ)?);
                 }
 
                 Ok(TPattern::Variant(tag, tpats))
             }
-            Pattern::Bool(b) => match match_type {
+            Pattern::Bool(b, span) => match match_type {
                 Type::Bool => Ok(TPattern::Bool(b)),
-                _ => terr(TypeErr::Mismatch(Type::Bool, match_type.clone())),
+                _ => terr(span, TypeErr::Mismatch(match_type.clone(), Type::Bool)),
             },
-            Pattern::Tuple(pats) => {
+            Pattern::Tuple(pats, span) => {
                 let types = match match_type {
                     Type::Tuple(ref inside) => inside,
-                    t => return terr(TypeErr::Mismatch(Type::Tuple(vec![]), t.clone())),
+                    t => return terr(span, TypeErr::Mismatch(t.clone(), Type::Tuple(vec![]))),
                 };
                 if types.len() != pats.len() {
-                    return terr(TypeErr::IncorrectVarN(types.len(), pats.len()));
+                    return terr(span, TypeErr::IncorrectVarN(types.len(), pats.len()));
                 }
                 let len = types.len();
                 let mut tpats = Vec::with_capacity(len);
@@ -231,7 +298,7 @@ impl Pattern {
                 }
                 Ok(TPattern::Tuple(tpats))
             }
-            Pattern::None => Ok(TPattern::None),
+            Pattern::None(_) => Ok(TPattern::None),
         }
     }
 }
@@ -239,30 +306,32 @@ impl Term {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TTerm> {
         match self {
             Logical::And(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
-                }
                 if a.get_type() != (&Type::Bool) {
-                    return terr(TypeErr::Mismatch(Type::Bool, a.get_type().clone()));
+                    return terr(sa, TypeErr::Mismatch(Type::Bool, a.get_type().clone()));
+                }
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TLogical::And(Box::new(a), b))
             }
             Logical::Or(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
-                }
                 if a.get_type() != (&Type::Bool) {
-                    return terr(TypeErr::Mismatch(Type::Bool, a.get_type().clone()));
+                    return terr(sa, TypeErr::Mismatch(Type::Bool, a.get_type().clone()));
+                }
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TLogical::Or(Box::new(a), b))
             }
@@ -274,44 +343,47 @@ impl BitWise {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TBitWise> {
         match self {
             BitWise::And(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
-                }
                 if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                }
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TBitWise::And(Box::new(a), b))
             }
             BitWise::Or(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
-                }
                 if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                }
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TBitWise::Or(Box::new(a), b))
             }
             BitWise::Xor(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
-                }
                 if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                }
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TBitWise::Xor(Box::new(a), b))
             }
@@ -323,30 +395,32 @@ impl Comp {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TComp> {
         match self {
             Comp::Eq(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TComp::Eq(Box::new(a), b))
             }
             Comp::Neq(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TComp::Neq(Box::new(a), b))
             }
@@ -358,58 +432,62 @@ impl Comp2 {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TComp2> {
         match self {
             Comp2::Leq(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TComp2::Leq(Box::new(a), b))
             }
             Comp2::Ls(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TComp2::Ls(Box::new(a), b))
             }
             Comp2::Geq(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TComp2::Geq(Box::new(a), b))
             }
             Comp2::Gt(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TComp2::Gt(Box::new(a), b))
             }
@@ -421,30 +499,32 @@ impl Shift {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TShift> {
         match self {
             Shift::Left(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
-                }
                 if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                }
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TShift::Left(Box::new(a), b))
             }
             Shift::Right(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
-                }
                 if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                }
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TShift::Right(Box::new(a), b))
             }
@@ -456,30 +536,32 @@ impl Add {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TAdd> {
         match self {
             Add::Plus(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TAdd::Plus(Box::new(a), b))
             }
             Add::Minus(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TAdd::Minus(Box::new(a), b))
             }
@@ -491,44 +573,47 @@ impl Factor {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TFactor> {
         match self {
             Factor::Mul(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TFactor::Mul(Box::new(a), b))
             }
             Factor::Div(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
+                if !a.get_type().is_num() {
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
-                if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TFactor::Div(Box::new(a), b))
             }
             Factor::Mod(a, b) => {
+                let (sa, sb) = (a.span(), b.span());
                 let a = a.typed(c, locals)?;
                 let b = b.typed(c, locals)?;
-                if a.get_type() != b.get_type() {
-                    return terr(TypeErr::Mismatch(
-                        a.get_type().clone(),
-                        b.get_type().clone(),
-                    ));
-                }
                 if a.get_type() != (&Type::Int) {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                    return terr(sa, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                }
+                if a.get_type() != b.get_type() {
+                    return terr(
+                        sb,
+                        TypeErr::Mismatch(a.get_type().clone(), b.get_type().clone()),
+                    );
                 }
                 Ok(TFactor::Mod(Box::new(a), b))
             }
@@ -539,17 +624,19 @@ impl Factor {
 impl Unary {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TUnary> {
         match self {
-            Unary::Not(prim) => {
+            Unary::Not(prim, _) => {
+                let span = prim.span();
                 let a = prim.typed(c, locals)?;
                 if a.get_type() != &Type::Bool {
-                    return terr(TypeErr::Mismatch(Type::Bool, a.get_type().clone()));
+                    return terr(span, TypeErr::Mismatch(Type::Bool, a.get_type().clone()));
                 }
                 Ok(TUnary::Not(a))
             }
-            Unary::Minus(prim) => {
+            Unary::Minus(prim, _) => {
+                let span = prim.span();
                 let a = prim.typed(c, locals)?;
-                if a.get_type() != &Type::Int {
-                    return terr(TypeErr::Mismatch(Type::Int, a.get_type().clone()));
+                if !a.get_type().is_num() {
+                    return terr(span, TypeErr::Mismatch(Type::Int, a.get_type().clone()));
                 }
                 Ok(TUnary::Minus(a))
             }
@@ -560,62 +647,95 @@ impl Unary {
 impl Primary {
     fn typed(self, c: &Compiler, locals: &mut Vec<(Id, Type)>) -> CResult<TPrimary> {
         match self {
-            Primary::Int(i) => Ok(TPrimary::Int(i)),
-            Primary::Bool(b) => Ok(TPrimary::Bool(b)),
-            Primary::Exp(e) => Ok(TPrimary::Exp(Box::new(e.typed(c, locals)?))),
-            Primary::Id(id) => {
+            Primary::Int(i, _) => Ok(TPrimary::Int(i)),
+            Primary::Float(f, _) => Ok(TPrimary::Float(f)),
+            Primary::Bool(b, _) => Ok(TPrimary::Bool(b)),
+            Primary::Exp(e, _) => Ok(TPrimary::Exp(Box::new(e.typed(c, locals)?))),
+            Primary::ToFloat(e, _) => {
+                let span = e.span();
+                let e = e.typed(c, locals)?;
+                if e.get_type() != &Type::Int {
+                    return terr(span, TypeErr::Mismatch(Type::Int, e.get_type().clone()));
+                }
+                Ok(TPrimary::ToFloat(Box::new(e)))
+            }
+            Primary::ToInt(e, _) => {
+                let span = e.span();
+                let e = e.typed(c, locals)?;
+                if e.get_type() != &Type::Float {
+                    return terr(span, TypeErr::Mismatch(Type::Float, e.get_type().clone()));
+                }
+                Ok(TPrimary::ToInt(Box::new(e)))
+            }
+            Primary::Id(id, span) => {
                 for i in (0..locals.len()).rev() {
-                    if &locals[i].0 == &id {
-                        return Ok(TPrimary::Id(id, locals[i].1.clone()));
+                    if locals[i].0 == id {
+                        return Ok(TPrimary::Id(id, locals[i].1.clone(), span));
                     }
                 }
-                match c.get_type_with_var_name(&id)? {
-                    VarType::Prim(p) => Ok(TPrimary::Id(id, p.clone())),
-                    VarType::Func(_, _) => terr(TypeErr::InvalidFuncType(id.clone())),
+                match c.get_type_with_var_name(&id).map_err(|e| e.at(span))? {
+                    VarType::Prim(p) => Ok(TPrimary::Id(id, p.clone(), span)),
+                    VarType::Func(_, _) => terr(span, TypeErr::InvalidFuncType(id.clone())),
                 }
             }
-            Primary::Last(id) => match c.get_type_with_var_name(&id)? {
-                VarType::Prim(p) => Ok(TPrimary::Last(id, p.clone())),
-                VarType::Func(_, _) => terr(TypeErr::InvalidFuncType(id.clone())),
-            },
-            Primary::FnCall(id, args) => {
-                if let VarType::Func(prms, ret) = c.get_type_with_var_name(&id)? {
+            Primary::Last(id, span) => {
+                match c.get_type_with_var_name(&id).map_err(|e| e.at(span))? {
+                    VarType::Prim(p) => Ok(TPrimary::Last(id, p.clone(), span)),
+                    VarType::Func(_, _) => terr(span, TypeErr::InvalidFuncType(id.clone())),
+                }
+            }
+            Primary::FnCall(id, args, targs, span) => {
+                let id = instance_name(&id, &targs);
+                if let VarType::Func(prms, ret) =
+                    c.get_type_with_var_name(&id).map_err(|e| e.at(span))?
+                {
                     if prms.len() != args.len() {
-                        return terr(TypeErr::IncorrectVarN(prms.len(), args.len()));
+                        return terr(span, TypeErr::IncorrectVarN(prms.len(), args.len()));
                     }
                     let mut targs = Vec::with_capacity(args.len());
-                    for (typ_expected, arg) in prms.into_iter().zip(args.into_iter()) {
+                    for (typ_expected, arg) in prms.into_iter().zip(args) {
+                        let arg_span = arg.span();
                         let arg = arg.typed(c, locals)?;
                         if arg.get_type() != typ_expected {
-                            return terr(TypeErr::Mismatch(
-                                typ_expected.clone(),
-                                arg.get_type().clone(),
-                            ));
+                            return terr(
+                                arg_span,
+                                TypeErr::Mismatch(typ_expected.clone(), arg.get_type().clone()),
+                            );
                         }
                         targs.push(arg);
                     }
                     Ok(TPrimary::FnCall(id, ret.clone(), targs))
                 } else {
-                    terr(TypeErr::InvalidFuncType(id.clone()))
+                    terr(span, TypeErr::NotFunc(id.clone()))
                 }
             }
-            Primary::Variant(name, exps) => {
-                let (ret_t, _, e_t) = c.get_type_from_variant(&name)?;
+            Primary::Variant(name, exps, targs, span) => {
+                let ret_t = c
+                    .get_type_with_variant(&name, &targs)
+                    .map_err(|e| e.at(span))?;
+                let e_t = match ret_t.variants().iter().find(|(v, _)| v == &name) {
+                    Some((_, e_t)) => e_t.clone(),
+                    None => return Err(CompileErr::VariantNotFound(name).at(span)),
+                };
                 if exps.len() != e_t.len() {
-                    return terr(TypeErr::IncorrectVarN(exps.len(), e_t.len()));
+                    return terr(span, TypeErr::IncorrectVarN(e_t.len(), exps.len()));
                 }
                 let mut texps = Vec::with_capacity(exps.len());
                 for (e, t) in exps.into_iter().zip(e_t.iter()) {
+                    let e_span = e.span();
                     let texp = e.typed(c, locals)?;
                     if texp.get_type() != t {
-                        return terr(TypeErr::Mismatch(t.clone(), texp.get_type().clone()));
+                        return terr(
+                            e_span,
+                            TypeErr::Mismatch(t.clone(), texp.get_type().clone()),
+                        );
                     } else {
                         texps.push(texp)
                     }
                 }
                 Ok(TPrimary::Variant(name, ret_t.clone(), texps))
             }
-            Primary::Tuple(v) => {
+            Primary::Tuple(v, _) => {
                 let mut texp = Vec::with_capacity(v.len());
                 let mut tv = Vec::with_capacity(v.len());
                 for e in v {
@@ -626,6 +746,149 @@ impl Primary {
 
                 Ok(TPrimary::Tuple(texp, Type::Tuple(tv)))
             }
+            Primary::Field(p, id, span) => {
+                let p = p.typed(c, locals)?;
+                let t = p.get_type();
+                let Some(i) = c
+                    .record_fields(t)
+                    .and_then(|fs| fs.iter().position(|f| f == &id))
+                else {
+                    return Err(CompileErr::NoField(t.to_string(), id.0).at(span));
+                };
+                Ok(field(p, i, span))
+            }
+            Primary::Index(p, i, span) => {
+                let p = p.typed(c, locals)?;
+                match p.get_type() {
+                    Type::Tuple(ts) if i < ts.len() => Ok(field(p, i, span)),
+                    t => Err(CompileErr::NoField(t.to_string(), i.to_string()).at(span)),
+                }
+            }
+            Primary::Record(name, mut values, targs, span) => {
+                let t = c
+                    .get_type_with_variant(&name, &targs)
+                    .map_err(|e| e.at(span))?;
+                let Some(fields) = c.record_fields(&t) else {
+                    let err = CompileErr::NoField(t.to_string(), values[0].0 .0.clone());
+                    return Err(err.at(span));
+                };
+                check_fields(&t, fields, &values, span)?;
+                let mut args = Vec::with_capacity(fields.len());
+                for f in fields {
+                    let Some(i) = values.iter().position(|(g, _)| g == f) else {
+                        let err = CompileErr::FieldNotGiven(t.to_string(), f.0.clone());
+                        return Err(err.at(span));
+                    };
+                    args.push(values.swap_remove(i).1);
+                }
+                Primary::Variant(name, args, targs, span).typed(c, locals)
+            }
+            Primary::Update(r, values, span) => {
+                let r = r.typed(c, locals)?;
+                let t = r.get_type().clone();
+                let Some(fields) = c.record_fields(&t) else {
+                    let err = CompileErr::NoField(t.to_string(), values[0].0 .0.clone());
+                    return Err(err.at(span));
+                };
+                check_fields(&t, fields, &values, span)?;
+                let types = &t.variants()[0].1;
+                let mut new = vec![None; fields.len()];
+                for (f, e) in values {
+                    let i = fields.iter().position(|g| g == &f).unwrap();
+                    let e_span = e.span();
+                    let e = e.typed(c, locals)?;
+                    if e.get_type() != &types[i] {
+                        return terr(
+                            e_span,
+                            TypeErr::Mismatch(types[i].clone(), e.get_type().clone()),
+                        );
+                    }
+                    new[i] = Some(e);
+                }
+                Ok(update(r, new, span))
+            }
+        }
+    }
+}
+
+// fields given to a record are its fields, and given once
+fn check_fields(t: &Type, fields: &[Id], values: &[(Id, Exp)], span: Span) -> CResult<()> {
+    for (i, (f, _)) in values.iter().enumerate() {
+        if !fields.contains(f) {
+            return Err(CompileErr::NoField(t.to_string(), f.0.clone()).at(span));
+        }
+        if values[..i].iter().any(|(g, _)| g == f) {
+            return Err(CompileErr::DuplicateField(f.0.clone()).at(span));
+        }
+    }
+    Ok(())
+}
+fn term(p: TPrimary) -> TTerm {
+    let shift = TShift::Add(TAdd::Factor(TFactor::Unary(TUnary::Primary(p))));
+    TLogical::BitWise(TBitWise::Comp(TComp::Comp2(TComp2::Shift(Box::new(shift)))))
+}
+// fields of a record or a tuple are taken by a match with one branch, whose variables can not be
+// written in a program
+fn field_var(i: usize) -> Id {
+    Id(format!("%{i}"))
+}
+fn fields_pattern(t: &Type, pats: Vec<TPattern>) -> TPattern {
+    match t {
+        Type::Tuple(_) => TPattern::Tuple(pats),
+        _ => TPattern::Variant(1, pats),
+    }
+}
+fn field_types(t: &Type) -> Vec<Type> {
+    match t {
+        Type::Tuple(ts) => ts.clone(),
+        _ => t.variants()[0].1.clone(),
+    }
+}
+fn single_branch(e: TTerm, pat: TPattern, body: TPrimary) -> TPrimary {
+    let exp = TBlock {
+        stmt: vec![],
+        exp: Box::new(TExp::Term(term(body))),
+    };
+    let branch = TBranch {
+        pat,
+        guard: None,
+        exp,
+    };
+    TPrimary::Exp(Box::new(TExp::Match(Box::new(e), vec![branch])))
+}
+// e.i as `e of: (_, %1, _) -> { %1 }`
+fn field(e: TPrimary, i: usize, span: Span) -> TPrimary {
+    let t = e.get_type().clone();
+    let types = field_types(&t);
+    let pats = (0..types.len())
+        .map(|j| match j == i {
+            true => TPattern::Id(types[j].clone(), field_var(j)),
+            false => TPattern::None,
+        })
+        .collect();
+    let body = TPrimary::Id(field_var(i), types[i].clone(), span);
+    single_branch(term(e), fields_pattern(&t, pats), body)
+}
+// { r with y = e } as `r of: R(%0, _, %2) -> { R(%0, e, %2) }`
+fn update(r: TTerm, new: Vec<Option<TExp>>, span: Span) -> TPrimary {
+    let t = r.get_type().clone();
+    let types = field_types(&t);
+    let mut pats = Vec::with_capacity(new.len());
+    let mut args = Vec::with_capacity(new.len());
+    for (j, e) in new.into_iter().enumerate() {
+        match e {
+            Some(e) => {
+                pats.push(TPattern::None);
+                args.push(e);
+            }
+            None => {
+                pats.push(TPattern::Id(types[j].clone(), field_var(j)));
+                let v = TPrimary::Id(field_var(j), types[j].clone(), span);
+                args.push(TExp::Term(term(v)));
+            }
         }
     }
+    let name = t.variants()[0].0.clone();
+    let body = TPrimary::Variant(name, t.clone(), args);
+    single_branch(r, fields_pattern(&t, pats), body)
 }
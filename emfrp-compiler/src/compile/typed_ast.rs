@@ -1,24 +1,29 @@
 use super::compile_common::*;
 use crate::ast::*;
 //Typed Ast
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub(super) enum TVarDef {
     Node {
         name: Id,
         init: Option<TExp>,
         val: TExp,
+        span: Span,
     },
     Data {
         name: Id,
         val: TExp,
+        span: Span,
     },
     Func {
         name: Id,
         params: Vec<(Id, Type)>,
         body: TExp,
+        span: Span,
     },
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub(super) enum TExp {
     Match(Box<TTerm>, Vec<TBranch>),
@@ -44,6 +49,7 @@ pub(super) struct TStmt {
 #[derive(Debug, Clone)]
 pub(super) struct TBranch {
     pub pat: TPattern,
+    pub guard: Option<TTerm>,
     pub exp: TBlock,
 }
 #[derive(Debug, Clone)]
@@ -111,13 +117,25 @@ pub(super) enum TUnary {
 #[derive(Debug, Clone)]
 pub(super) enum TPrimary {
     Int(i32),
+    Float(f32),
     Bool(bool),
     Exp(Box<TExp>),
-    Id(Id, Type),
-    Last(Id, Type),
+    Id(Id, Type, Span),
+    Last(Id, Type, Span),
     Variant(VariantName, Type, Vec<TExp>),
     FnCall(Id, Type, Vec<TExp>),
     Tuple(Vec<TExp>, Type),
+    ToFloat(Box<TExp>),
+    ToInt(Box<TExp>),
+}
+impl TVarDef {
+    pub(super) fn span(&self) -> Span {
+        match self {
+            TVarDef::Node { span, .. }
+            | TVarDef::Data { span, .. }
+            | TVarDef::Func { span, .. } => *span,
+        }
+    }
 }
 impl TBlock {
     pub(super) fn get_type(&self) -> &Type {
@@ -189,8 +207,9 @@ impl TShift {
 impl TAdd {
     pub(super) fn get_type(&self) -> &Type {
         match self {
-            TAdd::Plus(_, _) => &Type::Int,
-            TAdd::Minus(_, _) => &Type::Int,
+            // Int or Float, same as the operands
+            TAdd::Plus(a, _) => a.get_type(),
+            TAdd::Minus(a, _) => a.get_type(),
             TAdd::Factor(f) => f.get_type(),
         }
     }
@@ -198,8 +217,8 @@ impl TAdd {
 impl TFactor {
     pub(super) fn get_type(&self) -> &Type {
         match self {
-            TFactor::Mul(_, _) => &Type::Int,
-            TFactor::Div(_, _) => &Type::Int,
+            TFactor::Mul(a, _) => a.get_type(),
+            TFactor::Div(a, _) => a.get_type(),
             TFactor::Mod(_, _) => &Type::Int,
             TFactor::Unary(u) => u.get_type(),
         }
@@ -209,7 +228,7 @@ impl TUnary {
     pub(super) fn get_type(&self) -> &Type {
         match self {
             TUnary::Not(_) => &Type::Bool,
-            TUnary::Minus(_) => &Type::Int,
+            TUnary::Minus(a) => a.get_type(),
             TUnary::Primary(p) => p.get_type(),
         }
     }
@@ -218,13 +237,16 @@ impl TPrimary {
     pub(super) fn get_type(&self) -> &Type {
         match self {
             TPrimary::Int(_) => &Type::Int,
+            TPrimary::Float(_) => &Type::Float,
             TPrimary::Bool(_) => &Type::Bool,
             TPrimary::Exp(e) => e.get_type(),
-            TPrimary::Id(_, t) => t,
-            TPrimary::Last(_, t) => t,
+            TPrimary::Id(_, t, _) => t,
+            TPrimary::Last(_, t, _) => t,
             TPrimary::FnCall(_, t, _) => t,
             TPrimary::Variant(_, t, _) => t,
             TPrimary::Tuple(_, t) => t,
+            TPrimary::ToFloat(_) => &Type::Float,
+            TPrimary::ToInt(_) => &Type::Int,
         }
     }
 }
@@ -1 +1,850 @@
+use std::collections::HashMap;
 
+use super::compile_common::*;
+use crate::ast::*;
+
+// type whose parts may still be unknown
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ty {
+    Var(usize),
+    Int,
+    Bool,
+    Float,
+    User(String, Vec<Ty>),
+    Tuple(Vec<Ty>),
+    // type parameter of a generic func.
+    // it only matches itself inside the func, and is replaced with a fresh Var at each call
+    Param(String),
+}
+#[derive(Debug, Clone)]
+enum Sig {
+    Var(Ty),
+    Func(Vec<Ty>, Ty),
+}
+
+struct Infer<'a> {
+    c: &'a Compiler,
+    subst: Vec<Option<Ty>>,
+    // nodes, data and funcs defined together with the expression being inferred
+    globals: Vec<(Id, Sig)>,
+    // funcs in globals whose body is inferred and whose signature is generalized
+    done: Vec<bool>,
+    // func whose body is being inferred
+    current: Option<usize>,
+    locals: Vec<(Id, Ty)>,
+    // types to be written back to Primary::Variant and Primary::FnCall, by their span
+    variant_targs: HashMap<Span, Vec<Ty>>,
+    call_targs: HashMap<Span, Vec<Ty>>,
+    // calls of funcs in globals that were not generalized yet : (caller, callee)
+    pending_calls: HashMap<Span, (Option<usize>, usize)>,
+}
+
+impl Compiler {
+    // fills in the type annotations omitted in defs.
+    // defs are inferred at once, so they can refer to each other without annotations.
+    // funcs are inferred before nodes and data, and their type variables that are
+    // left unknown become type parameters
+    pub(super) fn infer_types(&self, defs: &mut [VarDef]) -> CResult<()> {
+        let mut inf = Infer::new(self);
+        let mut sigs = Vec::with_capacity(defs.len());
+        for def in defs.iter() {
+            let sig = match def {
+                VarDef::Node { name, typ, .. } | VarDef::Data { name, typ, .. } => {
+                    let mut vars = vec![];
+                    if let Some(typ) = typ {
+                        typ.vars(&mut vars);
+                    }
+                    if let Some(v) = vars.pop() {
+                        let err = CompileErr::InvalidTypeName(TypeName::Var(v));
+                        return Err(err.at(def.span()));
+                    }
+                    (
+                        name,
+                        Sig::Var(inf.annotated(typ).map_err(|e| e.at(def.span()))?),
+                    )
+                }
+                VarDef::Func {
+                    name, ret, params, ..
+                } => {
+                    let mut prms = Vec::with_capacity(params.len());
+                    for (_, typ) in params {
+                        prms.push(inf.annotated(typ).map_err(|e| e.at(def.span()))?);
+                    }
+                    let ret = inf.annotated(ret).map_err(|e| e.at(def.span()))?;
+                    (name, Sig::Func(prms, ret))
+                }
+            };
+            sigs.push(sig.1.clone());
+            inf.globals.push((sig.0.clone(), sig.1));
+            inf.done.push(false);
+        }
+        for (i, (def, sig)) in defs.iter().zip(&sigs).enumerate() {
+            if let (VarDef::Func { params, body, .. }, Sig::Func(prms, ret)) = (def, sig) {
+                inf.current = Some(i);
+                for ((id, _), t) in params.iter().zip(prms) {
+                    inf.locals.push((id.clone(), t.clone()));
+                }
+                let tbody = inf.exp(body)?;
+                inf.unify(ret, &tbody, body.span())?;
+                inf.locals.clear();
+                inf.generalize(i);
+            }
+        }
+        inf.current = None;
+        for (def, sig) in defs.iter().zip(&sigs) {
+            match (def, sig) {
+                (VarDef::Node { init, val, .. }, Sig::Var(t)) => {
+                    if let Some(init) = init {
+                        let tinit = inf.exp(init)?;
+                        inf.unify(t, &tinit, init.span())?;
+                    }
+                    let tval = inf.exp(val)?;
+                    inf.unify(t, &tval, val.span())?;
+                }
+                (VarDef::Data { val, .. }, Sig::Var(t)) => {
+                    let tval = inf.exp(val)?;
+                    inf.unify(t, &tval, val.span())?;
+                }
+                (VarDef::Func { .. }, Sig::Func(..)) => (),
+                _ => unreachable!(),
+            }
+        }
+        for (def, sig) in defs.iter_mut().zip(&sigs) {
+            let span = def.span();
+            match (&mut *def, sig) {
+                (VarDef::Node { name, typ, .. } | VarDef::Data { name, typ, .. }, Sig::Var(t)) => {
+                    *typ = Some(inf.type_name(t, &name.0).map_err(|e| e.at(span))?);
+                }
+                (
+                    VarDef::Func {
+                        name, ret, params, ..
+                    },
+                    Sig::Func(prms, tret),
+                ) => {
+                    for ((id, typ), t) in params.iter_mut().zip(prms) {
+                        *typ = Some(inf.type_name(t, &id.0).map_err(|e| e.at(span))?);
+                    }
+                    let what = format!("return value of {}", name.0);
+                    *ret = Some(inf.type_name(tret, &what).map_err(|e| e.at(span))?);
+                }
+                _ => unreachable!(),
+            }
+            inf.write_back(|v| def.visit_mut(v))?;
+        }
+        Ok(())
+    }
+    // fills in the types of variants and type args of generic funcs in e
+    pub(super) fn infer_exp(&self, e: &mut Exp) -> CResult<()> {
+        let mut inf = Infer::new(self);
+        inf.exp(e)?;
+        inf.write_back(|v| e.visit_mut(v))
+    }
+}
+
+// writes the inferred types back to the ast
+struct WriteBack<'a, 'b> {
+    inf: &'a Infer<'b>,
+    err: Option<CompileErr>,
+}
+impl VisitMut for WriteBack<'_, '_> {
+    fn primary(&mut self, p: &mut Primary) {
+        if self.err.is_some() {
+            return;
+        }
+        let res = match p {
+            Primary::Variant(name, _, targs, span) | Primary::Record(name, _, targs, span) => {
+                match self.inf.variant_targs.get(span) {
+                    Some(ts) => ts
+                        .iter()
+                        .map(|t| self.inf.type_name(t, &name.0))
+                        .collect::<CResult<_>>()
+                        .map(|ts| *targs = ts)
+                        .map_err(|e| e.at(*span)),
+                    None => Ok(()),
+                }
+            }
+            Primary::FnCall(id, _, targs, span) => self
+                .inf
+                .targs(id, *span)
+                .map(|t| *targs = t)
+                .map_err(|e| e.at(*span)),
+            _ => Ok(()),
+        };
+        self.err = res.err();
+    }
+}
+
+impl<'a> Infer<'a> {
+    fn new(c: &'a Compiler) -> Self {
+        Self {
+            c,
+            subst: vec![],
+            globals: vec![],
+            done: vec![],
+            current: None,
+            locals: vec![],
+            variant_targs: HashMap::new(),
+            call_targs: HashMap::new(),
+            pending_calls: HashMap::new(),
+        }
+    }
+    fn write_back(&self, visit: impl FnOnce(&mut WriteBack)) -> CResult<()> {
+        let mut v = WriteBack {
+            inf: self,
+            err: None,
+        };
+        visit(&mut v);
+        v.err.map_or(Ok(()), Err)
+    }
+    fn fresh(&mut self) -> Ty {
+        self.subst.push(None);
+        Ty::Var(self.subst.len() - 1)
+    }
+    fn annotated(&mut self, typ: &Option<TypeName>) -> CResult<Ty> {
+        match typ {
+            Some(typ) => self.ty_of(typ, &[]),
+            None => Ok(self.fresh()),
+        }
+    }
+    // type variables not bound in env are type parameters
+    fn ty_of(&self, name: &TypeName, env: &[(String, Ty)]) -> CResult<Ty> {
+        match name {
+            TypeName::Int => Ok(Ty::Int),
+            TypeName::Bool => Ok(Ty::Bool),
+            TypeName::Float => Ok(Ty::Float),
+            TypeName::Tuple(ts) => {
+                let mut ret = Vec::with_capacity(ts.len());
+                for t in ts {
+                    ret.push(self.ty_of(t, env)?);
+                }
+                Ok(Ty::Tuple(ret))
+            }
+            TypeName::Var(v) => match env.iter().find(|(w, _)| v == w) {
+                Some((_, t)) => Ok(t.clone()),
+                None => Ok(Ty::Param(v.clone())),
+            },
+            TypeName::User(s, args) => match self.c.generic_types.get(s) {
+                Some((params, _)) if params.len() == args.len() => {
+                    let mut targs = Vec::with_capacity(args.len());
+                    for t in args {
+                        targs.push(self.ty_of(t, env)?);
+                    }
+                    Ok(Ty::User(s.clone(), targs))
+                }
+                // reports why the type is invalid unless it is a type without parameters
+                _ => Ok(from_type(&self.c.get_type_with_type_name(name)?)),
+            },
+        }
+    }
+    // type of the value and of the fields of a variant.
+    // the type args of a generic type are fresh variables
+    fn variant(&mut self, name: &VariantName, span: Span) -> CResult<(Ty, Vec<Ty>)> {
+        let c = self.c;
+        for (tname, (params, variants)) in &c.generic_types {
+            if let Some(v) = variants.iter().find(|v| &v.constructor == name) {
+                let env: Vec<(String, Ty)> =
+                    params.iter().map(|p| (p.clone(), self.fresh())).collect();
+                let mut fields = Vec::with_capacity(v.elems.len());
+                for t in &v.elems {
+                    fields.push(self.ty_of(t, &env).map_err(|e| e.at(span))?);
+                }
+                let targs = env.into_iter().map(|(_, t)| t).collect();
+                return Ok((Ty::User(tname.clone(), targs), fields));
+            }
+        }
+        let (typ, _, elems) = c.get_type_from_variant(name).map_err(|e| e.at(span))?;
+        Ok((from_type(typ), elems.iter().map(from_type).collect()))
+    }
+    // type parameters in order of appearance, which is also the order of the type args
+    fn params(&self, t: &Ty, res: &mut Vec<String>) {
+        match self.resolve(t) {
+            Ty::Param(p) if !res.contains(&p) => res.push(p),
+            Ty::Tuple(ts) | Ty::User(_, ts) => ts.iter().for_each(|t| self.params(t, res)),
+            _ => (),
+        }
+    }
+    fn free_vars(&self, t: &Ty, res: &mut Vec<usize>) {
+        match self.resolve(t) {
+            Ty::Var(v) if !res.contains(&v) => res.push(v),
+            Ty::Tuple(ts) | Ty::User(_, ts) => ts.iter().for_each(|t| self.free_vars(t, res)),
+            _ => (),
+        }
+    }
+    // replaces the type parameters of a func with fresh variables, which are returned as type args
+    fn instantiate(&mut self, prms: &[Ty], ret: &Ty) -> (Vec<Ty>, Ty, Vec<Ty>) {
+        let mut names = vec![];
+        for t in prms.iter().chain([ret]) {
+            self.params(t, &mut names);
+        }
+        let env: Vec<(String, Ty)> = names.into_iter().map(|p| (p, self.fresh())).collect();
+        let prms = prms.iter().map(|t| self.subst_params(t, &env)).collect();
+        let ret = self.subst_params(ret, &env);
+        (prms, ret, env.into_iter().map(|(_, t)| t).collect())
+    }
+    fn subst_params(&self, t: &Ty, env: &[(String, Ty)]) -> Ty {
+        match self.resolve(t) {
+            Ty::Param(p) => match env.iter().find(|(q, _)| &p == q) {
+                Some((_, t)) => t.clone(),
+                None => Ty::Param(p),
+            },
+            Ty::Tuple(ts) => Ty::Tuple(ts.iter().map(|t| self.subst_params(t, env)).collect()),
+            Ty::User(s, ts) => Ty::User(s, ts.iter().map(|t| self.subst_params(t, env)).collect()),
+            t => t,
+        }
+    }
+    // variables in the signature of globals[i] become type parameters,
+    // unless another definition of the batch shares them
+    fn generalize(&mut self, i: usize) {
+        let Sig::Func(prms, ret) = self.globals[i].1.clone() else {
+            return;
+        };
+        let mut vars = vec![];
+        let mut used = vec![];
+        for t in prms.iter().chain([&ret]) {
+            self.free_vars(t, &mut vars);
+            self.params(t, &mut used);
+        }
+        for v in vars {
+            let shared = self.globals.iter().enumerate().any(|(j, (_, sig))| {
+                j != i
+                    && match sig {
+                        Sig::Var(t) => self.occurs(v, t),
+                        Sig::Func(prms, ret) => prms.iter().chain([ret]).any(|t| self.occurs(v, t)),
+                    }
+            });
+            if shared {
+                continue;
+            }
+            let name = (b'a'..=b'z')
+                .map(|c| (c as char).to_string())
+                .find(|p| !used.contains(p))
+                .unwrap_or(format!("t{v}"));
+            used.push(name.clone());
+            self.subst[v] = Some(Ty::Param(name));
+        }
+        self.done[i] = true;
+    }
+    // type args of the call of func id at span
+    fn targs(&self, id: &Id, span: Span) -> CResult<Vec<TypeName>> {
+        if let Some(targs) = self.call_targs.get(&span) {
+            let what = format!("type args of {}", id.0);
+            return targs.iter().map(|t| self.type_name(t, &what)).collect();
+        }
+        let Some(&(caller, callee)) = self.pending_calls.get(&span) else {
+            return Ok(vec![]);
+        };
+        let mut names = vec![];
+        if let Sig::Func(prms, ret) = &self.globals[callee].1 {
+            for t in prms.iter().chain([ret]) {
+                self.params(t, &mut names);
+            }
+        }
+        // a generic func calls itself with its own type parameters
+        if names.is_empty() || caller == Some(callee) {
+            Ok(names.into_iter().map(TypeName::Var).collect())
+        } else {
+            let what = format!("type args of {}", id.0);
+            Err(CompileErr::TypeErr(Box::new(TypeErr::CannotInfer(what))))
+        }
+    }
+    // follows the substitution until t is not an assigned variable
+    fn resolve(&self, t: &Ty) -> Ty {
+        match t {
+            Ty::Var(v) => match &self.subst[*v] {
+                Some(t) => self.resolve(t),
+                None => t.clone(),
+            },
+            _ => t.clone(),
+        }
+    }
+    fn zonk(&self, t: &Ty) -> Ty {
+        match self.resolve(t) {
+            Ty::Tuple(ts) => Ty::Tuple(ts.iter().map(|t| self.zonk(t)).collect()),
+            Ty::User(s, ts) => Ty::User(s, ts.iter().map(|t| self.zonk(t)).collect()),
+            t => t,
+        }
+    }
+    fn occurs(&self, v: usize, t: &Ty) -> bool {
+        match self.resolve(t) {
+            Ty::Var(w) => v == w,
+            Ty::Tuple(ts) | Ty::User(_, ts) => ts.iter().any(|t| self.occurs(v, t)),
+            _ => false,
+        }
+    }
+    // the error reports whole types, not the parts that differ
+    fn unify(&mut self, expected: &Ty, given: &Ty, span: Span) -> CResult<()> {
+        match self.unify_inner(expected, given) {
+            Ok(()) => Ok(()),
+            Err(infinite) => {
+                let (e, g) = (self.show(expected), self.show(given));
+                let err = if infinite {
+                    TypeErr::InfiniteType(e, g)
+                } else {
+                    TypeErr::CannotUnify(e, g)
+                };
+                Err(CompileErr::TypeErr(Box::new(err)).at(span))
+            }
+        }
+    }
+    // Err(true) if the types are infinite
+    fn unify_inner(&mut self, a: &Ty, b: &Ty) -> Result<(), bool> {
+        match (self.resolve(a), self.resolve(b)) {
+            (Ty::Var(v), Ty::Var(w)) if v == w => Ok(()),
+            (Ty::Var(v), t) | (t, Ty::Var(v)) => {
+                if self.occurs(v, &t) {
+                    return Err(true);
+                }
+                self.subst[v] = Some(t);
+                Ok(())
+            }
+            (Ty::Tuple(ts), Ty::Tuple(us)) if ts.len() == us.len() => {
+                for (t, u) in ts.iter().zip(&us) {
+                    self.unify_inner(t, u)?;
+                }
+                Ok(())
+            }
+            (Ty::User(s, ts), Ty::User(r, us)) if s == r && ts.len() == us.len() => {
+                for (t, u) in ts.iter().zip(&us) {
+                    self.unify_inner(t, u)?;
+                }
+                Ok(())
+            }
+            (a, b) if a == b => Ok(()),
+            _ => Err(false),
+        }
+    }
+    fn show(&self, t: &Ty) -> String {
+        match self.zonk(t) {
+            Ty::Var(v) => format!("'t{v}"),
+            Ty::Int => "Int".to_string(),
+            Ty::Bool => "Bool".to_string(),
+            Ty::Float => "Float".to_string(),
+            Ty::User(name, ts) if ts.is_empty() => name,
+            Ty::User(name, ts) => {
+                let ts: Vec<String> = ts.iter().map(|t| self.show(t)).collect();
+                format!("{name}<{}>", ts.join(", "))
+            }
+            Ty::Tuple(ts) => {
+                let ts: Vec<String> = ts.iter().map(|t| self.show(t)).collect();
+                format!("({})", ts.join(", "))
+            }
+            Ty::Param(p) => p,
+        }
+    }
+    // what : name of the value that has type t
+    fn type_name(&self, t: &Ty, what: &str) -> CResult<TypeName> {
+        fn to_name(t: &Ty) -> Option<TypeName> {
+            match t {
+                Ty::Var(_) => None,
+                Ty::Int => Some(TypeName::Int),
+                Ty::Bool => Some(TypeName::Bool),
+                Ty::Float => Some(TypeName::Float),
+                Ty::User(name, ts) => ts
+                    .iter()
+                    .map(to_name)
+                    .collect::<Option<_>>()
+                    .map(|ts| TypeName::User(name.clone(), ts)),
+                Ty::Tuple(ts) => ts
+                    .iter()
+                    .map(to_name)
+                    .collect::<Option<_>>()
+                    .map(TypeName::Tuple),
+                Ty::Param(p) => Some(TypeName::Var(p.clone())),
+            }
+        }
+        to_name(&self.zonk(t)).ok_or(CompileErr::TypeErr(Box::new(TypeErr::CannotInfer(
+            what.to_string(),
+        ))))
+    }
+
+    fn lookup(&self, id: &Id, span: Span) -> CResult<Sig> {
+        for (name, t) in self.locals.iter().rev() {
+            if name == id {
+                return Ok(Sig::Var(t.clone()));
+            }
+        }
+        for (name, sig) in &self.globals {
+            if name == id {
+                return Ok(sig.clone());
+            }
+        }
+        if let Some(g) = self.c.generic_funcs.iter().find(|g| &g.name == id) {
+            if let VarDef::Func { ret, params, .. } = &g.def {
+                let mut prms = Vec::with_capacity(params.len());
+                for (_, t) in params {
+                    prms.push(self.ty_of(annotation(t), &[])?);
+                }
+                return Ok(Sig::Func(prms, self.ty_of(annotation(ret), &[])?));
+            }
+        }
+        match self.c.get_type_with_var_name(id).map_err(|e| e.at(span))? {
+            VarType::Prim(t) => Ok(Sig::Var(from_type(t))),
+            VarType::Func(prms, ret) => Ok(Sig::Func(
+                prms.into_iter().map(from_type).collect(),
+                from_type(ret),
+            )),
+        }
+    }
+    fn lookup_var(&self, id: &Id, span: Span) -> CResult<Ty> {
+        match self.lookup(id, span)? {
+            Sig::Var(t) => Ok(t),
+            Sig::Func(_, _) => {
+                Err(CompileErr::TypeErr(Box::new(TypeErr::InvalidFuncType(id.clone()))).at(span))
+            }
+        }
+    }
+
+    fn exp(&mut self, e: &Exp) -> CResult<Ty> {
+        match e {
+            Exp::Match(e, branches) => {
+                let te = self.logical(e)?;
+                let ret = self.fresh();
+                for Branch { pat, guard, exp } in branches {
+                    let l = self.locals.len();
+                    self.pattern(pat, &te)?;
+                    if let Some(g) = guard {
+                        let tg = self.logical(g)?;
+                        self.unify(&Ty::Bool, &tg, g.span())?;
+                    }
+                    let tb = self.block(exp)?;
+                    self.unify(&ret, &tb, exp.span)?;
+                    self.locals.truncate(l);
+                }
+                Ok(ret)
+            }
+            Exp::If {
+                cond, then, els, ..
+            } => {
+                let tc = self.logical(cond)?;
+                self.unify(&Ty::Bool, &tc, cond.span())?;
+                let tt = self.exp(then)?;
+                let te = self.exp(els)?;
+                self.unify(&tt, &te, els.span())?;
+                Ok(tt)
+            }
+            Exp::Term(t) => self.logical(t),
+            Exp::Block(b) => self.block(b),
+        }
+    }
+    fn block(&mut self, b: &Block) -> CResult<Ty> {
+        let l = self.locals.len();
+        for Stmt {
+            id,
+            tname,
+            val,
+            span,
+        } in &b.stmt
+        {
+            let t = self.annotated(tname).map_err(|e| e.at(*span))?;
+            let tval = self.exp(val)?;
+            self.unify(&t, &tval, val.span())?;
+            self.locals.push((id.clone(), t));
+        }
+        let t = self.exp(&b.exp)?;
+        self.locals.truncate(l);
+        Ok(t)
+    }
+    fn pattern(&mut self, pat: &Pattern, t: &Ty) -> CResult<()> {
+        match pat {
+            Pattern::Int(_, span) => self.unify(t, &Ty::Int, *span),
+            Pattern::Bool(_, span) => self.unify(t, &Ty::Bool, *span),
+            Pattern::Id(id, _) => {
+                self.locals.push((id.clone(), t.clone()));
+                Ok(())
+            }
+            Pattern::None(_) => Ok(()),
+            Pattern::Variant(name, pats, span) => {
+                let (typ, elems) = self.variant(name, *span)?;
+                self.unify(t, &typ, *span)?;
+                if elems.len() != pats.len() {
+                    let err = TypeErr::IncorrectVarN(elems.len(), pats.len());
+                    return Err(CompileErr::TypeErr(Box::new(err)).at(*span));
+                }
+                for (pat, elem) in pats.iter().zip(&elems) {
+                    self.pattern(pat, elem)?;
+                }
+                Ok(())
+            }
+            Pattern::Tuple(pats, span) => {
+                let ts: Vec<Ty> = pats.iter().map(|_| self.fresh()).collect();
+                self.unify(t, &Ty::Tuple(ts.clone()), *span)?;
+                for (pat, t) in pats.iter().zip(&ts) {
+                    self.pattern(pat, t)?;
+                }
+                Ok(())
+            }
+        }
+    }
+    // both operands have type `operand`
+    fn binop(&mut self, a: (Ty, Span), b: (Ty, Span), operand: Ty, result: Ty) -> CResult<Ty> {
+        self.unify(&operand, &a.0, a.1)?;
+        self.unify(&operand, &b.0, b.1)?;
+        Ok(result)
+    }
+    // operands of arithmetic and comparison are both Int or both Float.
+    // they are Float if either of them is already known to be Float, otherwise Int
+    fn num_operand(&self, a: &Ty, b: &Ty) -> Ty {
+        if self.resolve(a) == Ty::Float || self.resolve(b) == Ty::Float {
+            Ty::Float
+        } else {
+            Ty::Int
+        }
+    }
+    fn logical(&mut self, e: &Logical) -> CResult<Ty> {
+        match e {
+            Logical::And(a, b) | Logical::Or(a, b) => {
+                let a = (self.logical(a)?, a.span());
+                let b = (self.bitwise(b)?, b.span());
+                self.binop(a, b, Ty::Bool, Ty::Bool)
+            }
+            Logical::BitWise(b) => self.bitwise(b),
+        }
+    }
+    fn bitwise(&mut self, e: &BitWise) -> CResult<Ty> {
+        match e {
+            BitWise::And(a, b) | BitWise::Or(a, b) | BitWise::Xor(a, b) => {
+                let a = (self.bitwise(a)?, a.span());
+                let b = (self.comp(b)?, b.span());
+                self.binop(a, b, Ty::Int, Ty::Int)
+            }
+            BitWise::Comp(c) => self.comp(c),
+        }
+    }
+    fn comp(&mut self, e: &Comp) -> CResult<Ty> {
+        match e {
+            Comp::Eq(a, b) | Comp::Neq(a, b) => {
+                let a = (self.comp(a)?, a.span());
+                let b = (self.comp2(b)?, b.span());
+                let operand = self.num_operand(&a.0, &b.0);
+                self.binop(a, b, operand, Ty::Bool)
+            }
+            Comp::Comp2(c) => self.comp2(c),
+        }
+    }
+    fn comp2(&mut self, e: &Comp2) -> CResult<Ty> {
+        match e {
+            Comp2::Leq(a, b) | Comp2::Ls(a, b) | Comp2::Geq(a, b) | Comp2::Gt(a, b) => {
+                let a = (self.comp2(a)?, a.span());
+                let b = (self.shift(b)?, b.span());
+                let operand = self.num_operand(&a.0, &b.0);
+                self.binop(a, b, operand, Ty::Bool)
+            }
+            Comp2::Shift(s) => self.shift(s),
+        }
+    }
+    fn shift(&mut self, e: &Shift) -> CResult<Ty> {
+        match e {
+            Shift::Left(a, b) | Shift::Right(a, b) => {
+                let a = (self.shift(a)?, a.span());
+                let b = (self.add(b)?, b.span());
+                self.binop(a, b, Ty::Int, Ty::Int)
+            }
+            Shift::Add(a) => self.add(a),
+        }
+    }
+    fn add(&mut self, e: &Add) -> CResult<Ty> {
+        match e {
+            Add::Plus(a, b) | Add::Minus(a, b) => {
+                let a = (self.add(a)?, a.span());
+                let b = (self.factor(b)?, b.span());
+                let operand = self.num_operand(&a.0, &b.0);
+                self.binop(a, b, operand.clone(), operand)
+            }
+            Add::Factor(f) => self.factor(f),
+        }
+    }
+    fn factor(&mut self, e: &Factor) -> CResult<Ty> {
+        match e {
+            Factor::Mul(a, b) | Factor::Div(a, b) => {
+                let a = (self.factor(a)?, a.span());
+                let b = (self.unary(b)?, b.span());
+                let operand = self.num_operand(&a.0, &b.0);
+                self.binop(a, b, operand.clone(), operand)
+            }
+            Factor::Mod(a, b) => {
+                let a = (self.factor(a)?, a.span());
+                let b = (self.unary(b)?, b.span());
+                self.binop(a, b, Ty::Int, Ty::Int)
+            }
+            Factor::Unary(u) => self.unary(u),
+        }
+    }
+    fn unary(&mut self, e: &Unary) -> CResult<Ty> {
+        match e {
+            Unary::Not(p, _) => {
+                let t = self.primary(p)?;
+                self.unify(&Ty::Bool, &t, p.span())?;
+                Ok(Ty::Bool)
+            }
+            Unary::Minus(p, _) => {
+                let t = self.primary(p)?;
+                let operand = self.num_operand(&t, &t);
+                self.unify(&operand, &t, p.span())?;
+                Ok(operand)
+            }
+            Unary::Primary(p) => self.primary(p),
+        }
+    }
+    fn primary(&mut self, e: &Primary) -> CResult<Ty> {
+        match e {
+            Primary::Int(_, _) => Ok(Ty::Int),
+            Primary::Float(_, _) => Ok(Ty::Float),
+            Primary::Bool(_, _) => Ok(Ty::Bool),
+            Primary::Exp(e, _) => self.exp(e),
+            Primary::ToFloat(e, _) => {
+                let t = self.exp(e)?;
+                self.unify(&Ty::Int, &t, e.span())?;
+                Ok(Ty::Float)
+            }
+            Primary::ToInt(e, _) => {
+                let t = self.exp(e)?;
+                self.unify(&Ty::Float, &t, e.span())?;
+                Ok(Ty::Int)
+            }
+            Primary::Id(id, span) | Primary::Last(id, span) => self.lookup_var(id, *span),
+            Primary::Variant(name, args, _, span) => {
+                let (typ, elems) = self.variant(name, *span)?;
+                if elems.len() != args.len() {
+                    let err = TypeErr::IncorrectVarN(elems.len(), args.len());
+                    return Err(CompileErr::TypeErr(Box::new(err)).at(*span));
+                }
+                for (arg, elem) in args.iter().zip(&elems) {
+                    let t = self.exp(arg)?;
+                    self.unify(elem, &t, arg.span())?;
+                }
+                if let Ty::User(_, targs) = &typ {
+                    self.variant_targs.insert(*span, targs.clone());
+                }
+                Ok(typ)
+            }
+            Primary::Tuple(es, _) => {
+                let mut ts = Vec::with_capacity(es.len());
+                for e in es {
+                    ts.push(self.exp(e)?);
+                }
+                Ok(Ty::Tuple(ts))
+            }
+            Primary::FnCall(id, args, _, span) => {
+                let (prms, ret) = match self.lookup(id, *span)? {
+                    Sig::Func(prms, ret) => (prms, ret),
+                    Sig::Var(_) => {
+                        let err = TypeErr::NotFunc(id.clone());
+                        return Err(CompileErr::TypeErr(Box::new(err)).at(*span));
+                    }
+                };
+                // the type parameters of a func defined in this batch are known
+                // only after its body is inferred
+                let callee = self.globals.iter().position(|(name, _)| name == id);
+                let mut vars = vec![];
+                for t in prms.iter().chain([&ret]) {
+                    self.free_vars(t, &mut vars);
+                }
+                let (prms, ret) = match callee {
+                    Some(i) if !self.done[i] && !vars.is_empty() => {
+                        self.pending_calls.insert(*span, (self.current, i));
+                        (prms, ret)
+                    }
+                    _ => {
+                        let (prms, ret, targs) = self.instantiate(&prms, &ret);
+                        self.call_targs.insert(*span, targs);
+                        (prms, ret)
+                    }
+                };
+                if prms.len() != args.len() {
+                    let err = TypeErr::IncorrectVarN(prms.len(), args.len());
+                    return Err(CompileErr::TypeErr(Box::new(err)).at(*span));
+                }
+                for (arg, prm) in args.iter().zip(&prms) {
+                    let t = self.exp(arg)?;
+                    self.unify(prm, &t, arg.span())?;
+                }
+                Ok(ret)
+            }
+            Primary::Field(p, id, span) => {
+                let t = self.primary(p)?;
+                let fields = self.record(&t, &[id], *span)?;
+                Ok(fields.into_iter().find(|(f, _)| f == id).unwrap().1)
+            }
+            Primary::Index(p, i, span) => {
+                let t = self.primary(p)?;
+                match self.resolve(&t) {
+                    Ty::Tuple(ts) if *i < ts.len() => Ok(ts[*i].clone()),
+                    Ty::Var(_) => {
+                        let err = TypeErr::CannotInfer(format!("the tuple of .{i}"));
+                        Err(CompileErr::TypeErr(Box::new(err)).at(*span))
+                    }
+                    _ => Err(CompileErr::NoField(self.show(&t), i.to_string()).at(*span)),
+                }
+            }
+            Primary::Record(name, values, _, span) => {
+                let (t, _) = self.variant(name, *span)?;
+                let ids: Vec<&Id> = values.iter().map(|(f, _)| f).collect();
+                let fields = self.record(&t, &ids, *span)?;
+                for (f, e) in values {
+                    let te = self.exp(e)?;
+                    let (_, tf) = fields.iter().find(|(g, _)| g == f).unwrap();
+                    self.unify(tf, &te, e.span())?;
+                }
+                if let Ty::User(_, targs) = &t {
+                    self.variant_targs.insert(*span, targs.clone());
+                }
+                Ok(t)
+            }
+            Primary::Update(r, values, span) => {
+                let t = self.logical(r)?;
+                let ids: Vec<&Id> = values.iter().map(|(f, _)| f).collect();
+                let fields = self.record(&t, &ids, *span)?;
+                for (f, e) in values {
+                    let te = self.exp(e)?;
+                    let (_, tf) = fields.iter().find(|(g, _)| g == f).unwrap();
+                    self.unify(tf, &te, e.span())?;
+                }
+                Ok(t)
+            }
+        }
+    }
+    // fields of the record t, which has the fields ids.
+    // if t is not known yet, it is the only record with those fields
+    fn record(&mut self, t: &Ty, ids: &[&Id], span: Span) -> CResult<Vec<(Id, Ty)>> {
+        let c = self.c;
+        let has_all = |fields: &Vec<Id>| ids.iter().all(|id| fields.contains(id));
+        let name = match self.resolve(t) {
+            Ty::User(name, _) => name,
+            Ty::Var(_) => {
+                let mut names = c.records.iter().filter(|(_, fs)| has_all(fs));
+                match (names.next(), names.next()) {
+                    (Some((name, _)), None) => name.clone(),
+                    _ => {
+                        let what = format!("the record of .{}", ids[0].0);
+                        let err = TypeErr::CannotInfer(what);
+                        return Err(CompileErr::TypeErr(Box::new(err)).at(span));
+                    }
+                }
+            }
+            _ => String::new(),
+        };
+        let fields = match c.records.get(&name) {
+            Some(fields) if has_all(fields) => fields,
+            _ => {
+                let id = ids
+                    .iter()
+                    .find(|id| !c.records.get(&name).is_some_and(|fs| fs.contains(id)));
+                let err =
+                    CompileErr::NoField(self.show(t), id.map_or(String::new(), |id| id.0.clone()));
+                return Err(err.at(span));
+            }
+        };
+        let (rt, elems) = self.variant(&VariantName(name), span)?;
+        self.unify(&rt, t, span)?;
+        Ok(fields.iter().cloned().zip(elems).collect())
+    }
+}
+fn from_type(t: &Type) -> Ty {
+    match t {
+        Type::Int => Ty::Int,
+        Type::Bool => Ty::Bool,
+        Type::Float => Ty::Float,
+        Type::User(name, targs, _) => Ty::User(name.clone(), targs.iter().map(from_type).collect()),
+        Type::Tuple(ts) => Ty::Tuple(ts.iter().map(from_type).collect()),
+    }
+}
@@ -0,0 +1,125 @@
+use lalrpop_util::lexer::Token;
+use lalrpop_util::ParseError;
+
+use crate::ast::LitError;
+use crate::ast::Span;
+use crate::compile::compile_common::CompileErr;
+
+// sources given to the parser, indexed by Span::file
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<(String, String)>,
+}
+impl SourceMap {
+    pub fn new() -> Self {
+        Self { files: vec![] }
+    }
+    pub fn add(&mut self, name: &str, src: &str) -> usize {
+        self.files.push((name.to_string(), src.to_string()));
+        self.files.len() - 1
+    }
+    // 1-based line and column of the byte offset pos
+    pub fn line_col(&self, file: usize, pos: usize) -> (usize, usize) {
+        let src = &self.files[file].1;
+        let pos = floor_char_boundary(src, pos);
+        let line_start = src[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let line = src[..line_start].matches('\n').count() + 1;
+        (line, src[line_start..pos].chars().count() + 1)
+    }
+    // error: <msg>
+    //   --> <file>:<line>:<col>
+    //    |
+    //  3 | node x : Int = 1 + True
+    //    |                    ^^^^
+    pub fn render(&self, span: Span, msg: &str) -> String {
+        self.render_as("error", span, msg)
+    }
+    pub fn warning(&self, span: Span, msg: &str) -> String {
+        self.render_as("warning", span, msg)
+    }
+    fn render_as(&self, level: &str, span: Span, msg: &str) -> String {
+        let Some((name, src)) = self.files.get(span.file) else {
+            return format!("{level}: {msg}");
+        };
+        let (line, col) = self.line_col(span.file, span.start);
+        let start = floor_char_boundary(src, span.start);
+        let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+        let text = src[line_start..line_end].trim_end_matches('\r');
+        // spans over several lines are underlined up to the end of the first line
+        let end = floor_char_boundary(src, span.end).clamp(start, line_start + text.len());
+        let indent: String = src[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = "^".repeat(src[start..end].chars().count().max(1));
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "{level}: {msg}\n{gutter}--> {name}:{line}:{col}\n{gutter} |\n{line} | {text}\n{gutter} | {indent}{carets}"
+        )
+    }
+    pub fn compile_error(&self, e: &CompileErr) -> String {
+        match e.span() {
+            Some(span) => self.render(span, &format!("{:?}", e)),
+            None => format!("error: {:?}", e),
+        }
+    }
+    pub fn parse_error(&self, file: usize, e: &ParseError<usize, Token<'_>, LitError>) -> String {
+        let (span, msg) = match e {
+            ParseError::InvalidToken { location } => (
+                Span::new(file, *location, *location),
+                "invalid token".to_string(),
+            ),
+            ParseError::UnrecognizedEof { location, expected } => (
+                Span::new(file, *location, *location),
+                format!("unexpected end of input{}", expected_list(expected)),
+            ),
+            ParseError::UnrecognizedToken {
+                token: (l, tok, r),
+                expected,
+            } => (
+                Span::new(file, *l, *r),
+                format!("unexpected token `{}`{}", tok.1, expected_list(expected)),
+            ),
+            ParseError::ExtraToken { token: (l, tok, r) } => {
+                (Span::new(file, *l, *r), format!("extra token `{}`", tok.1))
+            }
+            ParseError::User { error } => (error.span, error.msg.clone()),
+        };
+        self.render(span, &msg)
+    }
+}
+fn expected_list(expected: &[String]) -> String {
+    if expected.is_empty() {
+        String::new()
+    } else {
+        let mut names: Vec<&str> = vec![];
+        for t in expected {
+            let name = terminal_name(t);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        format!(", expected one of {}", names.join(", "))
+    }
+}
+// lalrpop names a regex terminal by its regex, so it is replaced by what it matches
+fn terminal_name(t: &str) -> &str {
+    match t.strip_prefix("r#\"") {
+        Some(re) if re.starts_with('\'') => "char literal",
+        Some(re) if re.starts_with("[1-9]") || re.starts_with("0[") => "integer literal",
+        Some(re) if re.starts_with("[0-9]") => "float literal",
+        Some(re) if re.ends_with("@last\"#") => "identifier@last",
+        Some(re) if re.starts_with("[a-z]") => "identifier",
+        Some(re) if re.starts_with("[A-Z]") => "type or constructor name",
+        _ if t == "\"0\"" => "integer literal",
+        _ => t,
+    }
+}
+fn floor_char_boundary(s: &str, mut pos: usize) -> usize {
+    pos = pos.min(s.len());
+    while !s.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    pos
+}
@@ -0,0 +1,826 @@
+// decoder of the frames made by insn::to_byte_code, a listing of the decoded code and an assembler
+// which reads the listing back.
+// listing:
+//   input gpio16 : Bool        (input and output nodes of an image)
+//   output gpio5 : Bool
+//   def                        (or `eval` followed by the instructions of the expression)
+//   new_nodes 2
+//   new_funcs 0
+//   new_data 1
+//   num_last 1
+//   node 3                     (offset of the node, followed by its instructions)
+//     0000: GetNode 2          ; 85 02
+//     0002: Je8 3              ; 53 03 -> 0007
+//   update
+//     ...
+//   init
+//     ...
+// each instruction is `<name>[.<width>] <operands>`. the width (i8, u16, ...) is only written when the
+// operand is not encoded in the shortest form. offsets before `:` and comments after `;` are ignored.
+use std::fmt::Write;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::cli::parse_node;
+use crate::compile::compile_common::BcDefVar;
+use crate::compile::compile_common::CompiledCode;
+use crate::compile::compile_common::Type;
+use crate::image::Image;
+use crate::insn::*;
+use crate::opcode::*;
+
+struct Reader<'a> {
+    code: &'a [u8],
+    pc: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(code: &'a [u8]) -> Self {
+        Self { code, pc: 0 }
+    }
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let b = self
+            .code
+            .get(self.pc..self.pc + n)
+            .ok_or(anyhow!("unexpected end of bytecode at {}", self.pc))?;
+        self.pc += n;
+        Ok(b)
+    }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+    fn i16(&mut self) -> Result<i16> {
+        Ok(self.u16()? as i16)
+    }
+    fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+    // k-th opcode of insns encoded as u8,u16,u32
+    fn unsigned(&mut self, k: u8) -> Result<UnsignedNum> {
+        Ok(match k {
+            0 => UnsignedNum::U8(self.u8()?),
+            1 => UnsignedNum::U16(self.u16()?),
+            _ => UnsignedNum::U32(self.u32()?),
+        })
+    }
+    // k-th opcode of insns encoded as 0,..,n-1,u8,u16,u32
+    fn short_unsigned(&mut self, k: u8, n: u8) -> Result<UnsignedNum> {
+        if k < n {
+            Ok(UnsignedNum::U8(k))
+        } else {
+            self.unsigned(k - n)
+        }
+    }
+    // k-th opcode of insns encoded as 0,1,2,3,4,5,6,i8,i16,i32
+    fn short_signed(&mut self, k: u8) -> Result<SignedNum> {
+        Ok(match k {
+            0..=6 => SignedNum::I8(k as i8),
+            7 => SignedNum::I8(self.i8()?),
+            8 => SignedNum::I16(self.i16()?),
+            _ => SignedNum::I32(self.i32()?),
+        })
+    }
+}
+
+pub fn decode_insn(code: &[u8], pc: &mut usize) -> Result<Insn> {
+    let mut r = Reader { code, pc: *pc };
+    let op = r.u8()?;
+    let insn = match op {
+        NONE => Insn::None,
+        NIL => Insn::Nil,
+        NOT => Insn::Not,
+        MINUS => Insn::Minus,
+        ADD => Insn::Add,
+        SUB => Insn::Sub,
+        MUL => Insn::Mul,
+        DIV => Insn::Div,
+        MOD => Insn::Mod,
+        SHIFT_L => Insn::ShiftL,
+        SHIFT_R => Insn::ShiftR,
+        LS => Insn::Ls,
+        LEQ => Insn::Leq,
+        GT => Insn::Gt,
+        GEQ => Insn::Geq,
+        EQ => Insn::Eq,
+        NEQ => Insn::Neq,
+        BIT_AND => Insn::BitAnd,
+        BIT_OR => Insn::BitOr,
+        BIT_XOR => Insn::BitXor,
+        RETURN => Insn::Return,
+        PRINT => Insn::Print,
+        PRINT_OBJ => Insn::PrintObj,
+        HALT => Insn::Halt,
+        PEEK => Insn::Peek,
+        PUSH_TRUE => Insn::PushTrue,
+        PUSH_FALSE => Insn::PushFalse,
+        ABORT => Insn::Abort,
+        INT_0..=INT_I32 => Insn::Int(r.short_signed(op - INT_0)?),
+        GET_LOCAL_0..=GET_LOCAL_I32 => Insn::GetLocal(r.short_signed(op - GET_LOCAL_0)?),
+        SET_LOCAL_0..=SET_LOCAL_I32 => Insn::SetLocal(r.short_signed(op - SET_LOCAL_0)?),
+        ALLOC_LOCAL_1..=ALLOC_LOCAL_U32 => {
+            Insn::AllocLocal(r.short_unsigned(op + 1 - ALLOC_LOCAL_1, 7)?)
+        }
+        POP_0..=POP_U32 => Insn::Pop(r.short_unsigned(op - POP_0, 7)?),
+        JNE8 => Insn::Jne8(r.i8()?),
+        JNE16 => Insn::Jne16(r.i16()?),
+        JNE32 => Insn::Jne32(r.i32()?),
+        JE8 => Insn::Je8(r.i8()?),
+        JE16 => Insn::Je16(r.i16()?),
+        JE32 => Insn::Je32(r.i32()?),
+        J8 => Insn::J8(r.i8()?),
+        J16 => Insn::J16(r.i16()?),
+        J32 => Insn::J32(r.i32()?),
+        GET_LAST_0..=GET_LAST_U32 => Insn::GetLast(r.short_unsigned(op - GET_LAST_0, 4)?),
+        SET_NODE_U8..=SET_NODE_U32 => Insn::SetNode(r.unsigned(op - SET_NODE_U8)?),
+        OBJ_FIELD_0..=OBJ_FIELD_6 => Insn::ObjField(UnsignedNum::U8(op - OBJ_FIELD_0)),
+        OBJ_FIELD_U8 => Insn::ObjField(UnsignedNum::U8(r.u8()?)),
+        UPD_DEV_0..=UPD_DEV_U8 => Insn::UpdateDev(r.short_unsigned(op - UPD_DEV_0, 4)?),
+        UPD_NODE_U8..=UPD_NODE_U32 => Insn::UpdateNode(r.unsigned(op - UPD_NODE_U8)?),
+        O_ACTION_0..=O_ACTION_U8 => Insn::OutputAction(r.short_unsigned(op - O_ACTION_0, 4)?),
+        CALL_U8..=CALL_U32 => {
+            let nargs = r.u8()?;
+            Insn::Call(nargs, r.unsigned(op - CALL_U8)?)
+        }
+        GET_DATA_U8..=GET_DATA_U32 => Insn::GetData(r.unsigned(op - GET_DATA_U8)?),
+        GET_NODE_U8..=GET_NODE_U32 => Insn::GetNode(r.unsigned(op - GET_NODE_U8)?),
+        F_ADD => Insn::FAdd,
+        F_SUB => Insn::FSub,
+        F_MUL => Insn::FMul,
+        F_DIV => Insn::FDiv,
+        F_MINUS => Insn::FMinus,
+        SET_DATA_U8..=SET_DATA_U32 => Insn::SetData(r.unsigned(op - SET_DATA_U8)?),
+        OBJ_TAG => Insn::ObjTag,
+        F_LS => Insn::FLs,
+        F_LEQ => Insn::FLeq,
+        F_GT => Insn::FGt,
+        F_GEQ => Insn::FGeq,
+        F_EQ => Insn::FEq,
+        SET_LAST_0..=SET_LAST_U32 => Insn::SetLast(r.short_unsigned(op - SET_LAST_0, 4)?),
+        END_UPD_U8..=END_UPD_U32 => Insn::EndUpdateNode(r.unsigned(op - END_UPD_U8)?),
+        ALLOC_OBJ_0..=ALLOC_OBJ_U8 => {
+            let max = r.short_unsigned(op - ALLOC_OBJ_0, 7)?;
+            Insn::AllocObj(max, ObjHeader::read(|| r.u32())?)
+        }
+        F_NEQ => Insn::FNeq,
+        FLOAT => Insn::Float(r.u32()?),
+        DROP_LOCAL_OBJ_0..=DROP_LOCAL_OBJ_I32 => {
+            Insn::DropLocalObj(r.short_signed(op - DROP_LOCAL_OBJ_0)?)
+        }
+        GET_LOCAL_REF_0..=GET_LOCAL_REF_I32 => {
+            Insn::GetLocalRef(r.short_signed(op - GET_LOCAL_REF_0)?)
+        }
+        SET_LOCAL_REF_0..=SET_LOCAL_REF_I32 => {
+            Insn::SetLocalRef(r.short_signed(op - SET_LOCAL_REF_0)?)
+        }
+        OBJ_FIELD_REF_0..=OBJ_FIELD_REF_6 => {
+            Insn::ObjFieldRef(UnsignedNum::U8(op - OBJ_FIELD_REF_0))
+        }
+        OBJ_FIELD_REF_U8 => Insn::ObjFieldRef(UnsignedNum::U8(r.u8()?)),
+        END_UPD_OBJ_U8..=END_UPD_OBJ_U32 => {
+            Insn::EndUpdateNodeObj(r.unsigned(op - END_UPD_OBJ_U8)?)
+        }
+        GET_NODE_REF_U8..=GET_NODE_REF_U32 => Insn::GetNodeRef(r.unsigned(op - GET_NODE_REF_U8)?),
+        GET_DATA_REF_U8..=GET_DATA_REF_U32 => Insn::GetDataRef(r.unsigned(op - GET_DATA_REF_U8)?),
+        GET_LAST_REF_0..=GET_LAST_REF_U32 => {
+            Insn::GetLastRef(r.short_unsigned(op - GET_LAST_REF_0, 4)?)
+        }
+        SET_DATA_REF_U8..=SET_DATA_REF_U32 => Insn::SetDataRef(r.unsigned(op - SET_DATA_REF_U8)?),
+        SET_LAST_REF_0..=SET_LAST_REF_U32 => {
+            Insn::SetLastRef(r.short_unsigned(op - SET_LAST_REF_0, 4)?)
+        }
+        SET_NODE_REF_U8..=SET_NODE_REF_U32 => Insn::SetNodeRef(r.unsigned(op - SET_NODE_REF_U8)?),
+        DROP_LAST_U8..=DROP_LAST_U32 => Insn::DropLast(r.unsigned(op - DROP_LAST_U8)?),
+        J0 => Insn::J0,
+        J1 => Insn::J1,
+        JE0 => Insn::Je0,
+        JE1 => Insn::Je1,
+        JNE0 => Insn::Jne0,
+        JNE1 => Insn::Jne1,
+        INT_TO_FLOAT => Insn::IntToFloat,
+        FLOAT_TO_INT => Insn::FloatToInt,
+        _ => bail!("unknown opcode {op} at {}", *pc),
+    };
+    *pc = r.pc;
+    Ok(insn)
+}
+// instructions of code and their offsets
+pub fn decode_insns(code: &[u8]) -> Result<Vec<(usize, Insn)>> {
+    let mut pc = 0;
+    let mut res = vec![];
+    while pc < code.len() {
+        let offset = pc;
+        res.push((offset, decode_insn(code, &mut pc)?));
+    }
+    Ok(res)
+}
+fn insns(code: &[u8]) -> Result<Vec<Insn>> {
+    Ok(decode_insns(code)?.into_iter().map(|(_, i)| i).collect())
+}
+
+// frame : datasize(u16) data
+// the type of an evaluated expression is not sent to the machine, so it is decoded as Int
+pub fn decode_frame(frame: &[u8]) -> Result<CompiledCode> {
+    let mut r = Reader::new(frame);
+    let len = r.u16()? as usize;
+    if frame.len() != len + 2 {
+        bail!(
+            "datasize is {len}, but the frame has {} bytes",
+            frame.len() - 2
+        )
+    }
+    if r.u8()? != 0 {
+        return Ok(CompiledCode::Eval(Type::Int, insns(&frame[3..])?));
+    }
+    let exp_len = r.u16()? as usize;
+    let upd_len = r.u16()? as usize;
+    let n_last = r.u16()? as usize;
+    let n_node = r.u16()?;
+    let n_func = r.u16()?;
+    let n_new_nodes = r.u16()? as usize;
+    let n_new_func = r.u16()? as usize;
+    let n_new_data = r.u16()? as usize;
+    let defs = |n: u16, r: &mut Reader| -> Result<Vec<(usize, Vec<Insn>)>> {
+        let mut res = vec![];
+        for _ in 0..n {
+            let offset = r.u16()? as usize;
+            let len = r.u16()? as usize;
+            res.push((offset, insns(r.bytes(len)?)?));
+        }
+        Ok(res)
+    };
+    let node = defs(n_node, &mut r)?;
+    let func = defs(n_func, &mut r)?;
+    let update = insns(r.bytes(upd_len)?)?;
+    let init = insns(r.bytes(exp_len)?)?;
+    if r.pc != frame.len() {
+        bail!("{} bytes left after the init code", frame.len() - r.pc)
+    }
+    Ok(CompiledCode::Def(BcDefVar {
+        n_new_nodes,
+        n_new_func,
+        n_new_data,
+        n_last,
+        init,
+        node,
+        func,
+        update,
+    }))
+}
+
+fn signed(name: &str, n: &SignedNum) -> String {
+    let v = n.to_i32();
+    match n {
+        _ if &SignedNum::from_i32(v) == n => format!("{name} {v}"),
+        SignedNum::I8(_) => format!("{name}.i8 {v}"),
+        SignedNum::I16(_) => format!("{name}.i16 {v}"),
+        SignedNum::I32(_) => format!("{name}.i32 {v}"),
+    }
+}
+fn unsigned(name: &str, n: &UnsignedNum) -> String {
+    let v = n.to_u32();
+    match n {
+        _ if &UnsignedNum::from_u32(v) == n => format!("{name} {v}"),
+        UnsignedNum::U8(_) => format!("{name}.u8 {v}"),
+        UnsignedNum::U16(_) => format!("{name}.u16 {v}"),
+        UnsignedNum::U32(_) => format!("{name}.u32 {v}"),
+    }
+}
+pub fn insn_text(insn: &Insn) -> String {
+    let name = insn_name(insn);
+    match insn {
+        Insn::Int(n)
+        | Insn::GetLocal(n)
+        | Insn::SetLocal(n)
+        | Insn::DropLocalObj(n)
+        | Insn::GetLocalRef(n)
+        | Insn::SetLocalRef(n) => signed(name, n),
+        Insn::GetData(n)
+        | Insn::UpdateNode(n)
+        | Insn::UpdateDev(n)
+        | Insn::GetNode(n)
+        | Insn::SetNode(n)
+        | Insn::SetData(n)
+        | Insn::EndUpdateNode(n)
+        | Insn::GetLast(n)
+        | Insn::SetLast(n)
+        | Insn::Pop(n)
+        | Insn::ObjField(n)
+        | Insn::AllocLocal(n)
+        | Insn::OutputAction(n)
+        | Insn::EndUpdateNodeObj(n)
+        | Insn::GetNodeRef(n)
+        | Insn::GetDataRef(n)
+        | Insn::ObjFieldRef(n)
+        | Insn::GetLastRef(n)
+        | Insn::SetLastRef(n)
+        | Insn::SetNodeRef(n)
+        | Insn::SetDataRef(n)
+        | Insn::DropLast(n) => unsigned(name, n),
+        Insn::Call(nargs, f) => {
+            let s = unsigned(name, f);
+            let (name, f) = s.split_once(' ').unwrap();
+            format!("{name} {nargs} {f}")
+        }
+        Insn::AllocObj(max, h) => {
+            let words: Vec<String> = h.words().map(|w| format!("0x{w:08x}")).collect();
+            format!("{} {}", unsigned(name, max), words.join(" "))
+        }
+        Insn::J8(i) | Insn::Je8(i) | Insn::Jne8(i) => format!("{name} {i}"),
+        Insn::J16(i) | Insn::Je16(i) | Insn::Jne16(i) => format!("{name} {i}"),
+        Insn::J32(i) | Insn::Je32(i) | Insn::Jne32(i) => format!("{name} {i}"),
+        Insn::Float(b) => {
+            // bits that do not survive printing, such as NaN payloads, are written in hex
+            let f = f32::from_bits(*b);
+            match format!("{f:?}").parse::<f32>() {
+                Ok(g) if g.to_bits() == *b => format!("{name} {f:?}"),
+                _ => format!("{name} 0x{b:08x}"),
+            }
+        }
+        _ => name.to_string(),
+    }
+}
+fn insn_name(insn: &Insn) -> &'static str {
+    match insn {
+        Insn::None => "None",
+        Insn::Nil => "Nil",
+        Insn::Not => "Not",
+        Insn::Minus => "Minus",
+        Insn::Add => "Add",
+        Insn::Sub => "Sub",
+        Insn::Mul => "Mul",
+        Insn::Div => "Div",
+        Insn::Mod => "Mod",
+        Insn::ShiftL => "ShiftL",
+        Insn::ShiftR => "ShiftR",
+        Insn::Ls => "Ls",
+        Insn::Leq => "Leq",
+        Insn::Gt => "Gt",
+        Insn::Geq => "Geq",
+        Insn::Eq => "Eq",
+        Insn::Neq => "Neq",
+        Insn::BitAnd => "BitAnd",
+        Insn::BitOr => "BitOr",
+        Insn::BitXor => "BitXor",
+        Insn::Return => "Return",
+        Insn::Print => "Print",
+        Insn::PrintObj => "PrintObj",
+        Insn::Halt => "Halt",
+        Insn::Placeholder => "Placeholder",
+        Insn::PushTrue => "PushTrue",
+        Insn::PushFalse => "PushFalse",
+        Insn::Je0 => "Je0",
+        Insn::Je1 => "Je1",
+        Insn::Je8(_) => "Je8",
+        Insn::Je16(_) => "Je16",
+        Insn::Je32(_) => "Je32",
+        Insn::J0 => "J0",
+        Insn::J1 => "J1",
+        Insn::J8(_) => "J8",
+        Insn::J16(_) => "J16",
+        Insn::J32(_) => "J32",
+        Insn::Jne0 => "Jne0",
+        Insn::Jne1 => "Jne1",
+        Insn::Jne8(_) => "Jne8",
+        Insn::Jne16(_) => "Jne16",
+        Insn::Jne32(_) => "Jne32",
+        Insn::Int(_) => "Int",
+        Insn::GetData(_) => "GetData",
+        Insn::GetLocal(_) => "GetLocal",
+        Insn::SetLocal(_) => "SetLocal",
+        Insn::UpdateNode(_) => "UpdateNode",
+        Insn::UpdateDev(_) => "UpdateDev",
+        Insn::GetNode(_) => "GetNode",
+        Insn::SetNode(_) => "SetNode",
+        Insn::SetData(_) => "SetData",
+        Insn::EndUpdateNode(_) => "EndUpdateNode",
+        Insn::GetLast(_) => "GetLast",
+        Insn::SetLast(_) => "SetLast",
+        Insn::Call(_, _) => "Call",
+        Insn::AllocObj(_, _) => "AllocObj",
+        Insn::Peek => "Peek",
+        Insn::Pop(_) => "Pop",
+        Insn::ObjTag => "ObjTag",
+        Insn::ObjField(_) => "ObjField",
+        Insn::AllocLocal(_) => "AllocLocal",
+        Insn::OutputAction(_) => "OutputAction",
+        Insn::EndUpdateNodeObj(_) => "EndUpdateNodeObj",
+        Insn::DropLocalObj(_) => "DropLocalObj",
+        Insn::GetNodeRef(_) => "GetNodeRef",
+        Insn::GetDataRef(_) => "GetDataRef",
+        Insn::GetLocalRef(_) => "GetLocalRef",
+        Insn::SetLocalRef(_) => "SetLocalRef",
+        Insn::ObjFieldRef(_) => "ObjFieldRef",
+        Insn::GetLastRef(_) => "GetLastRef",
+        Insn::SetLastRef(_) => "SetLastRef",
+        Insn::SetNodeRef(_) => "SetNodeRef",
+        Insn::SetDataRef(_) => "SetDataRef",
+        Insn::DropLast(_) => "DropLast",
+        Insn::Abort => "Abort",
+        Insn::Float(_) => "Float",
+        Insn::FMinus => "FMinus",
+        Insn::FAdd => "FAdd",
+        Insn::FSub => "FSub",
+        Insn::FMul => "FMul",
+        Insn::FDiv => "FDiv",
+        Insn::FLs => "FLs",
+        Insn::FLeq => "FLeq",
+        Insn::FGt => "FGt",
+        Insn::FGeq => "FGeq",
+        Insn::FEq => "FEq",
+        Insn::FNeq => "FNeq",
+        Insn::IntToFloat => "IntToFloat",
+        Insn::FloatToInt => "FloatToInt",
+    }
+}
+
+fn write_insns(s: &mut String, insns: &[Insn]) -> std::fmt::Result {
+    let mut pc = 0;
+    for insn in insns {
+        let mut bytes = vec![];
+        insn.push_byte_code(&mut bytes);
+        let next = pc + bytes.len();
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        // jumps are relative to the next instruction
+        let offset = match insn {
+            Insn::J0 | Insn::Je0 | Insn::Jne0 => Some(0),
+            Insn::J1 | Insn::Je1 | Insn::Jne1 => Some(1),
+            Insn::J8(i) | Insn::Je8(i) | Insn::Jne8(i) => Some(*i as i64),
+            Insn::J16(i) | Insn::Je16(i) | Insn::Jne16(i) => Some(*i as i64),
+            Insn::J32(i) | Insn::Je32(i) | Insn::Jne32(i) => Some(*i as i64),
+            _ => None,
+        };
+        write!(s, "  {pc:04}: {:<24}; {}", insn_text(insn), hex.join(" "))?;
+        match offset {
+            Some(i) => writeln!(s, " -> {:04}", next as i64 + i)?,
+            None => writeln!(s)?,
+        }
+        pc = next;
+    }
+    Ok(())
+}
+pub fn listing(code: &CompiledCode) -> String {
+    let mut s = String::new();
+    let _ = write_listing(&mut s, code);
+    s
+}
+pub fn image_listing(image: &Image) -> Result<String> {
+    let mut s = String::new();
+    for (name, typ) in &image.inputs {
+        writeln!(s, "input {name} : {typ:?}")?;
+    }
+    for (name, typ) in &image.outputs {
+        writeln!(s, "output {name} : {typ:?}")?;
+    }
+    for frame in &image.frames {
+        s.push_str(&listing(&decode_frame(frame)?));
+    }
+    Ok(s)
+}
+fn write_listing(s: &mut String, code: &CompiledCode) -> std::fmt::Result {
+    let def = match code {
+        CompiledCode::Eval(_, e) => {
+            writeln!(s, "eval")?;
+            return write_insns(s, e);
+        }
+        CompiledCode::Def(def) => def,
+    };
+    writeln!(s, "def")?;
+    writeln!(s, "new_nodes {}", def.n_new_nodes)?;
+    writeln!(s, "new_funcs {}", def.n_new_func)?;
+    writeln!(s, "new_data {}", def.n_new_data)?;
+    writeln!(s, "num_last {}", def.n_last)?;
+    for (i, insns) in &def.node {
+        writeln!(s, "node {i}")?;
+        write_insns(s, insns)?;
+    }
+    for (i, insns) in &def.func {
+        writeln!(s, "func {i}")?;
+        write_insns(s, insns)?;
+    }
+    writeln!(s, "update")?;
+    write_insns(s, &def.update)?;
+    writeln!(s, "init")?;
+    write_insns(s, &def.init)
+}
+
+const NULLARY: [Insn; 48] = [
+    Insn::None,
+    Insn::Nil,
+    Insn::Not,
+    Insn::Minus,
+    Insn::Add,
+    Insn::Sub,
+    Insn::Mul,
+    Insn::Div,
+    Insn::Mod,
+    Insn::ShiftL,
+    Insn::ShiftR,
+    Insn::Ls,
+    Insn::Leq,
+    Insn::Gt,
+    Insn::Geq,
+    Insn::Eq,
+    Insn::Neq,
+    Insn::BitAnd,
+    Insn::BitOr,
+    Insn::BitXor,
+    Insn::Return,
+    Insn::Print,
+    Insn::PrintObj,
+    Insn::Halt,
+    Insn::Peek,
+    Insn::PushTrue,
+    Insn::PushFalse,
+    Insn::Abort,
+    Insn::ObjTag,
+    Insn::J0,
+    Insn::J1,
+    Insn::Je0,
+    Insn::Je1,
+    Insn::Jne0,
+    Insn::Jne1,
+    Insn::FMinus,
+    Insn::FAdd,
+    Insn::FSub,
+    Insn::FMul,
+    Insn::FDiv,
+    Insn::FLs,
+    Insn::FLeq,
+    Insn::FGt,
+    Insn::FGeq,
+    Insn::FEq,
+    Insn::FNeq,
+    Insn::IntToFloat,
+    Insn::FloatToInt,
+];
+const SIGNED: [fn(SignedNum) -> Insn; 6] = [
+    Insn::Int,
+    Insn::GetLocal,
+    Insn::SetLocal,
+    Insn::DropLocalObj,
+    Insn::GetLocalRef,
+    Insn::SetLocalRef,
+];
+const UNSIGNED: [fn(UnsignedNum) -> Insn; 22] = [
+    Insn::GetData,
+    Insn::UpdateNode,
+    Insn::UpdateDev,
+    Insn::GetNode,
+    Insn::SetNode,
+    Insn::SetData,
+    Insn::EndUpdateNode,
+    Insn::GetLast,
+    Insn::SetLast,
+    Insn::Pop,
+    Insn::ObjField,
+    Insn::AllocLocal,
+    Insn::OutputAction,
+    Insn::EndUpdateNodeObj,
+    Insn::GetNodeRef,
+    Insn::GetDataRef,
+    Insn::ObjFieldRef,
+    Insn::GetLastRef,
+    Insn::SetLastRef,
+    Insn::SetNodeRef,
+    Insn::SetDataRef,
+    Insn::DropLast,
+];
+
+fn parse_int<T: TryFrom<i64>>(s: &str) -> Result<T> {
+    let v = match s.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| anyhow!("invalid number {s}"))?;
+    T::try_from(v).map_err(|_| anyhow!("{s} is out of range"))
+}
+fn signed_num(s: &str, width: Option<&str>) -> Result<SignedNum> {
+    Ok(match width {
+        None => SignedNum::from_i32(parse_int(s)?),
+        Some("i8") => SignedNum::I8(parse_int(s)?),
+        Some("i16") => SignedNum::I16(parse_int(s)?),
+        Some("i32") => SignedNum::I32(parse_int(s)?),
+        Some(w) => bail!("invalid width {w}"),
+    })
+}
+fn unsigned_num(s: &str, width: Option<&str>) -> Result<UnsignedNum> {
+    Ok(match width {
+        None => UnsignedNum::from_u32(parse_int(s)?),
+        Some("u8") => UnsignedNum::U8(parse_int(s)?),
+        Some("u16") => UnsignedNum::U16(parse_int(s)?),
+        Some("u32") => UnsignedNum::U32(parse_int(s)?),
+        Some(w) => bail!("invalid width {w}"),
+    })
+}
+// operands that to_byte_code cannot encode
+fn check_encodable(insn: &Insn) -> Result<()> {
+    match insn {
+        Insn::AllocLocal(UnsignedNum::U8(0)) => bail!("AllocLocal 0 cannot be encoded"),
+        Insn::ObjField(n) | Insn::ObjFieldRef(n) if !matches!(n, UnsignedNum::U8(_)) => {
+            bail!("field index must be u8")
+        }
+        Insn::UpdateDev(n) | Insn::OutputAction(n) | Insn::AllocObj(n, _)
+            if !matches!(n, UnsignedNum::U8(_)) =>
+        {
+            bail!("operand must be u8")
+        }
+        _ => Ok(()),
+    }
+}
+pub fn parse_insn(s: &str) -> Result<Insn> {
+    let mut words = s.split_whitespace();
+    let mnemonic = words.next().ok_or(anyhow!("instruction expected"))?;
+    let args: Vec<&str> = words.collect();
+    let (name, width) = match mnemonic.split_once('.') {
+        Some((name, width)) => (name, Some(width)),
+        None => (mnemonic, None),
+    };
+    let n_args = match name {
+        "Call" | "AllocObj" => 2,
+        _ if NULLARY.iter().any(|i| insn_name(i) == name) => 0,
+        _ => 1,
+    };
+    // the extended header of AllocObj takes more words
+    if args.len() != n_args && !(name == "AllocObj" && args.len() > n_args) {
+        bail!("{name} takes {n_args} operand(s), but {} given", args.len())
+    }
+    if let Some(insn) = NULLARY.iter().find(|i| insn_name(i) == name) {
+        return match width {
+            None => Ok(insn.clone()),
+            Some(_) => bail!("{name} has no operand"),
+        };
+    }
+    let insn = match name {
+        "J8" | "J16" | "J32" | "Je8" | "Je16" | "Je32" | "Jne8" | "Jne16" | "Jne32"
+            if width.is_some() =>
+        {
+            bail!("width of {name} is part of its name")
+        }
+        "J8" => Insn::J8(parse_int(args[0])?),
+        "J16" => Insn::J16(parse_int(args[0])?),
+        "J32" => Insn::J32(parse_int(args[0])?),
+        "Je8" => Insn::Je8(parse_int(args[0])?),
+        "Je16" => Insn::Je16(parse_int(args[0])?),
+        "Je32" => Insn::Je32(parse_int(args[0])?),
+        "Jne8" => Insn::Jne8(parse_int(args[0])?),
+        "Jne16" => Insn::Jne16(parse_int(args[0])?),
+        "Jne32" => Insn::Jne32(parse_int(args[0])?),
+        "Call" => Insn::Call(parse_int(args[0])?, unsigned_num(args[1], width)?),
+        "Float" if width.is_some() => bail!("Float has no width"),
+        "Float" if args[0].starts_with("0x") => Insn::Float(parse_int(args[0])?),
+        "Float" => Insn::Float(
+            args[0]
+                .parse::<f32>()
+                .map_err(|_| anyhow!("invalid number {}", args[0]))?
+                .to_bits(),
+        ),
+        "AllocObj" => {
+            let mut words = args[1..].iter();
+            let header = ObjHeader::read(|| match words.next() {
+                Some(w) => parse_int(w),
+                None => bail!("AllocObj needs the words of its extended header"),
+            })?;
+            if words.next().is_some() {
+                bail!("AllocObj takes {} header word(s)", 1 + header.1.len())
+            }
+            Insn::AllocObj(unsigned_num(args[0], width)?, header)
+        }
+        _ => {
+            if let Some(f) = SIGNED
+                .iter()
+                .find(|f| insn_name(&f(SignedNum::I8(0))) == name)
+            {
+                f(signed_num(args[0], width)?)
+            } else if let Some(f) = UNSIGNED
+                .iter()
+                .find(|f| insn_name(&f(UnsignedNum::U8(0))) == name)
+            {
+                f(unsigned_num(args[0], width)?)
+            } else {
+                bail!("unknown instruction {name}")
+            }
+        }
+    };
+    check_encodable(&insn)?;
+    Ok(insn)
+}
+
+enum Section {
+    Node(usize),
+    Func(usize),
+    Update,
+    Init,
+}
+// reads listings of one or more frames into image.
+// `input` and `output` lines replace the nodes of image
+pub fn assemble(text: &str, image: &mut Image) -> Result<()> {
+    let mut codes: Vec<CompiledCode> = vec![];
+    let mut section = None;
+    let mut io = (vec![], vec![]);
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap().trim();
+        let line = match line.split_once(':') {
+            Some((offset, rest)) if offset.chars().all(|c| c.is_ascii_digit()) => rest.trim(),
+            _ => line,
+        };
+        let res = if let Some(nd) = line.strip_prefix("input ") {
+            parse_node(nd).map(|nd| io.0.push(nd))
+        } else if let Some(nd) = line.strip_prefix("output ") {
+            parse_node(nd).map(|nd| io.1.push(nd))
+        } else if line.is_empty() {
+            Ok(())
+        } else {
+            assemble_line(line, &mut codes, &mut section)
+        };
+        res.with_context(|| format!("line {}", i + 1))?;
+    }
+    if !io.0.is_empty() || !io.1.is_empty() {
+        (image.inputs, image.outputs) = io;
+    }
+    for code in &codes {
+        image.push(code);
+    }
+    Ok(())
+}
+fn assemble_line(
+    line: &str,
+    codes: &mut Vec<CompiledCode>,
+    section: &mut Option<Section>,
+) -> Result<()> {
+    let mut words = line.split_whitespace();
+    let key = words.next().unwrap();
+    let mut arg = || -> Result<usize> {
+        match (words.next(), words.next()) {
+            (Some(n), None) => parse_int(n),
+            _ => bail!("{key} takes a number"),
+        }
+    };
+    match key {
+        "eval" => {
+            codes.push(CompiledCode::Eval(Type::Int, vec![]));
+            *section = None;
+            return Ok(());
+        }
+        "def" => {
+            codes.push(CompiledCode::Def(BcDefVar {
+                n_new_nodes: 0,
+                n_new_func: 0,
+                n_new_data: 0,
+                n_last: 0,
+                init: vec![],
+                node: vec![],
+                func: vec![],
+                update: vec![],
+            }));
+            *section = None;
+            return Ok(());
+        }
+        _ => (),
+    }
+    let Some(code) = codes.last_mut() else {
+        bail!("listing must start with `def` or `eval`")
+    };
+    match code {
+        CompiledCode::Def(def) => match key {
+            "new_nodes" => def.n_new_nodes = arg()?,
+            "new_funcs" => def.n_new_func = arg()?,
+            "new_data" => def.n_new_data = arg()?,
+            "num_last" => def.n_last = arg()?,
+            "node" => {
+                def.node.push((arg()?, vec![]));
+                *section = Some(Section::Node(def.node.len() - 1));
+            }
+            "func" => {
+                def.func.push((arg()?, vec![]));
+                *section = Some(Section::Func(def.func.len() - 1));
+            }
+            "update" => *section = Some(Section::Update),
+            "init" => *section = Some(Section::Init),
+            _ => {
+                let insns = match section {
+                    Some(Section::Node(i)) => &mut def.node[*i].1,
+                    Some(Section::Func(i)) => &mut def.func[*i].1,
+                    Some(Section::Update) => &mut def.update,
+                    Some(Section::Init) => &mut def.init,
+                    _ => bail!("instruction outside of node, func, update or init"),
+                };
+                insns.push(parse_insn(line)?);
+            }
+        },
+        CompiledCode::Eval(_, insns) => insns.push(parse_insn(line)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+// the dependency graph of nodes, written in DOT for Graphviz or in JSON.
+// edges go from a node to the nodes referring to it, i.e. in the direction values flow
+use crate::ast::Id;
+use crate::compile::compile_common::CompileErr;
+use crate::compile::compile_common::Compiler;
+use crate::compile::compile_common::NodeDeps;
+
+struct GraphNode {
+    // input, output or node
+    kind: &'static str,
+    name: String,
+    typ: String,
+    deps: NodeDeps,
+}
+
+fn nodes(cmp: &Compiler) -> Result<Vec<GraphNode>, CompileErr> {
+    let mut res = vec![];
+    for (kind, name, typ) in cmp.var_list() {
+        let kind = match kind {
+            "in" => "input",
+            "out" => "output",
+            "node" => "node",
+            _ => continue,
+        };
+        let deps = cmp.node_deps(&Id(name.clone()))?;
+        res.push(GraphNode {
+            kind,
+            name,
+            typ: typ.to_string(),
+            deps,
+        });
+    }
+    Ok(res)
+}
+
+pub fn quote(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+// input nodes are drawn as inverted houses, output nodes as houses and @last edges dashed
+pub fn to_dot(cmp: &Compiler) -> Result<String, CompileErr> {
+    let nodes = nodes(cmp)?;
+    let mut res = String::from("digraph emfrp {\n    rankdir=LR;\n");
+    for nd in &nodes {
+        let shape = match nd.kind {
+            "input" => "invhouse",
+            "output" => "house",
+            _ => "box",
+        };
+        let label = quote(&format!("{} : {}", nd.name, nd.typ));
+        res.push_str(&format!(
+            "    {} [shape={shape}, label={label}];\n",
+            quote(&nd.name)
+        ));
+    }
+    for nd in &nodes {
+        for prev in &nd.deps.now {
+            res.push_str(&format!("    {} -> {};\n", quote(prev), quote(&nd.name)));
+        }
+        for prev in &nd.deps.last {
+            res.push_str(&format!(
+                "    {} -> {} [style=dashed, label=\"@last\"];\n",
+                quote(prev),
+                quote(&nd.name)
+            ));
+        }
+    }
+    res.push_str("}\n");
+    Ok(res)
+}
+
+// {"nodes": [{"name", "kind", "type", "refers_to", "at_last"}, ..], "update_order": [..]}
+pub fn to_json(cmp: &Compiler) -> Result<String, CompileErr> {
+    let list = |names: &[String]| {
+        let names: Vec<String> = names.iter().map(|s| quote(s)).collect();
+        format!("[{}]", names.join(", "))
+    };
+    let nodes: Vec<String> = nodes(cmp)?
+        .iter()
+        .map(|nd| {
+            format!(
+                "    {{\"name\": {}, \"kind\": \"{}\", \"type\": {}, \"refers_to\": {}, \"at_last\": {}}}",
+                quote(&nd.name),
+                nd.kind,
+                quote(&nd.typ),
+                list(&nd.deps.now),
+                list(&nd.deps.last)
+            )
+        })
+        .collect();
+    let nodes = if nodes.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n  ]", nodes.join(",\n"))
+    };
+    Ok(format!(
+        "{{\n  \"nodes\": {nodes},\n  \"update_order\": {}\n}}\n",
+        list(&cmp.update_order()?)
+    ))
+}
@@ -0,0 +1,150 @@
+// program image that a machine loads at boot with emfrp_load_image (emfrp-machine/esp32/main/machine.c)
+//   magic "EMFP", version(u8)
+//   n_input(u8) n_output(u8), and then for each input and output node: type(u8) name_len(u8) name
+//   n_frame(u16), and then the frames in the same format as the REPL sends : datasize(u16) data
+use std::fmt::Write;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::cli::IoNode;
+use crate::compile::compile_common::CompiledCode;
+use crate::compile::compile_common::Type;
+use crate::insn::to_byte_code;
+
+pub const MAGIC: &[u8; 4] = b"EMFP";
+pub const VERSION: u8 = 1;
+const TYPE_INT: u8 = 0;
+const TYPE_BOOL: u8 = 1;
+const TYPE_FLOAT: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub inputs: Vec<IoNode>,
+    pub outputs: Vec<IoNode>,
+    pub frames: Vec<Vec<u8>>,
+}
+impl Image {
+    pub fn new(inputs: Vec<IoNode>, outputs: Vec<IoNode>) -> Self {
+        Self {
+            inputs,
+            outputs,
+            frames: vec![],
+        }
+    }
+    pub fn push(&mut self, code: &CompiledCode) {
+        self.frames.push(to_byte_code(code))
+    }
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut res = MAGIC.to_vec();
+        res.push(VERSION);
+        for nodes in [&self.inputs, &self.outputs] {
+            if nodes.len() > u8::MAX as usize {
+                bail!("too many input/output nodes")
+            }
+            res.push(nodes.len() as u8);
+        }
+        for (name, typ) in self.inputs.iter().chain(&self.outputs) {
+            res.push(match typ {
+                Type::Int => TYPE_INT,
+                Type::Bool => TYPE_BOOL,
+                Type::Float => TYPE_FLOAT,
+                _ => bail!("type of {name} must be Int, Bool or Float"),
+            });
+            if name.len() > u8::MAX as usize {
+                bail!("name of node {name} is too long")
+            }
+            res.push(name.len() as u8);
+            res.extend(name.as_bytes());
+        }
+        if self.frames.len() > u16::MAX as usize {
+            bail!("too many frames")
+        }
+        res.extend((self.frames.len() as u16).to_le_bytes());
+        for frame in &self.frames {
+            res.extend(frame);
+        }
+        Ok(res)
+    }
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut p = 0;
+        let mut next = |n: usize| -> Result<&[u8]> {
+            let Some(b) = bytes.get(p..p + n) else {
+                bail!("image is truncated")
+            };
+            p += n;
+            Ok(b)
+        };
+        if next(4)? != MAGIC {
+            bail!("not an emfrp image")
+        }
+        let version = next(1)?[0];
+        if version != VERSION {
+            bail!("image version {version} is not supported (expected {VERSION})")
+        }
+        let n_input = next(1)?[0] as usize;
+        let n_output = next(1)?[0] as usize;
+        let mut nodes = vec![];
+        for _ in 0..n_input + n_output {
+            let typ = match next(1)?[0] {
+                TYPE_INT => Type::Int,
+                TYPE_BOOL => Type::Bool,
+                TYPE_FLOAT => Type::Float,
+                t => bail!("unknown node type {t}"),
+            };
+            let len = next(1)?[0] as usize;
+            let name = String::from_utf8(next(len)?.to_vec())?;
+            nodes.push((name, typ));
+        }
+        let outputs = nodes.split_off(n_input);
+        let mut image = Image::new(nodes, outputs);
+        let n_frame = u16::from_le_bytes(next(2)?.try_into().unwrap());
+        for _ in 0..n_frame {
+            let len = next(2)?;
+            let len = u16::from_le_bytes([len[0], len[1]]);
+            let mut frame = len.to_le_bytes().to_vec();
+            frame.extend(next(len as usize)?);
+            image.frames.push(frame);
+        }
+        if p != bytes.len() {
+            bail!("extra bytes after the last frame")
+        }
+        Ok(image)
+    }
+    // header to be included by the firmware
+    //   emfrp_load_image(&em, emfrp_image, sizeof(emfrp_image));
+    pub fn to_c_header(&self, src: &str) -> Result<String> {
+        let bytes = self.to_bytes()?;
+        let node_list = |nodes: &[IoNode]| {
+            let v: Vec<String> = nodes.iter().map(|(n, t)| format!("{n} : {t:?}")).collect();
+            v.join(", ")
+        };
+        let mut s = String::new();
+        writeln!(s, "// generated by emfrp-compiler from {src}. do not edit")?;
+        writeln!(s, "// input nodes : {}", node_list(&self.inputs))?;
+        writeln!(s, "// output nodes : {}", node_list(&self.outputs))?;
+        writeln!(s, "#ifndef EMFRP_IMAGE_H")?;
+        writeln!(s, "#define EMFRP_IMAGE_H")?;
+        writeln!(s, "#include <stdint.h>")?;
+        // keeps the image in flash on AVR, where emfrp_load_image reads it with memcpy_P
+        writeln!(s, "#ifdef __AVR__")?;
+        writeln!(s, "#include <avr/pgmspace.h>")?;
+        writeln!(s, "#define EMFRP_IMAGE_ATTR PROGMEM")?;
+        writeln!(s, "#else")?;
+        writeln!(s, "#define EMFRP_IMAGE_ATTR")?;
+        writeln!(s, "#endif")?;
+        writeln!(s, "#define EMFRP_IMAGE_N_INPUT {}", self.inputs.len())?;
+        writeln!(s, "#define EMFRP_IMAGE_N_OUTPUT {}", self.outputs.len())?;
+        writeln!(
+            s,
+            "static const uint8_t emfrp_image[] EMFRP_IMAGE_ATTR = {{"
+        )?;
+        for line in bytes.chunks(12) {
+            let v: Vec<String> = line.iter().map(|b| format!("0x{b:02x}")).collect();
+            writeln!(s, "    {},", v.join(", "))?;
+        }
+        writeln!(s, "}};")?;
+        writeln!(s, "#endif")?;
+        Ok(s)
+    }
+}
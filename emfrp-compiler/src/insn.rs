@@ -2,7 +2,8 @@ use std::fmt::Debug;
 
 use crate::{
     compile::compile_common::{BcDefVar, CompiledCode},
-    DEBUG,
+    debug,
+    opcode::*,
 };
 #[derive(Clone, Eq, PartialEq)]
 pub enum Insn {
@@ -61,12 +62,12 @@ pub enum Insn {
     GetLast(UnsignedNum),             //0,1,2,3,u8,u16,u32
     SetLast(UnsignedNum),             //0,1,2,3,u8,u16,u32
     Call(NArgs, UnsignedNum),         // FuncOffset: u8,u16,u32
-    AllocObj(UnsignedNum, ObjHeader), // MaxEntry : 0,1,2,3,4,5,6
+    AllocObj(UnsignedNum, ObjHeader), // MaxEntry : 0,1,2,3,4,5,6,u8
     Peek,
 
     Pop(UnsignedNum), //1,2,3,4,5,6,u8,u16,u32
     ObjTag,
-    ObjField(UnsignedNum),     //0,1,2,3,4,5,6
+    ObjField(UnsignedNum),     //0,1,2,3,4,5,6,u8
     AllocLocal(UnsignedNum),   //0,1,2,3,4,5,6,u8,u16,u32
     OutputAction(UnsignedNum), //0,1,2,3,u8
 
@@ -76,13 +77,28 @@ pub enum Insn {
     GetDataRef(UnsignedNum),       //u8, u16, u32
     GetLocalRef(SignedNum),        //0,1,2,3,4,5,6,i8,i16,i32
     SetLocalRef(SignedNum),        //0,1,2,3,4,5,6,i8,i16,i32
-    ObjFieldRef(UnsignedNum),      //0,1,2,3,4,5,6
+    ObjFieldRef(UnsignedNum),      //0,1,2,3,4,5,6,u8
     GetLastRef(UnsignedNum),       //0,1,2,3,u8,u16,u32
     SetLastRef(UnsignedNum),       //0,1,2,3,u8,u16,u32
     SetNodeRef(UnsignedNum),       //u8,u16,u32
     SetDataRef(UnsignedNum),       //u8,u16,u32
     DropLast(UnsignedNum),         // u8,u16,u32
     Abort,
+
+    Float(u32), // bits of f32
+    FMinus,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    FLs,
+    FLeq,
+    FGt,
+    FGeq,
+    FEq,
+    FNeq,
+    IntToFloat,
+    FloatToInt,
 }
 type NArgs = u8;
 
@@ -118,12 +134,12 @@ impl ToByteCode {
         push_u16_le(i, &mut self.bytecode);
         self.dbg_info.push((name, len, len + 2));
     }
-    fn push_byte_code_len(&mut self, insns: &Vec<Insn>, name: Option<&'static str>) {
+    fn push_byte_code_len(&mut self, insns: &[Insn], name: Option<&'static str>) {
         let len = self.bytecode.len();
         self.dbg_info.push((name, len, len + 2));
-        push_u16_le(bytecode_len(&insns) as u16, &mut self.bytecode);
+        push_u16_le(bytecode_len(insns) as u16, &mut self.bytecode);
     }
-    fn push_byte_code(&mut self, insns: &Vec<Insn>, section: Option<&'static str>) {
+    fn push_byte_code(&mut self, insns: &[Insn], section: Option<&'static str>) {
         let i0 = self.bytecode.len();
         for insn in insns {
             insn.push_byte_code(&mut self.bytecode);
@@ -207,7 +223,7 @@ pub fn to_byte_code(code: &CompiledCode) -> Vec<u8> {
     bc.push_byte_code(init, Some("init"));
 
     bc.len_placeholder_stop(datalen_p);
-    if DEBUG {
+    if debug() {
         bc.dbg_bytecode();
     }
     bc.get()
@@ -285,7 +301,7 @@ impl Debug for Insn {
             }
             Self::AllocObj(arg0, arg1) => {
                 let (tag, objbit, entrynum) = arg1.decode();
-                let ObjHeader(i) = arg1;
+                let i = arg1.0;
                 write!(
                     f,
                     "{:>2}:AllocObj(max:{},header:{}[tag:{},objbit:{},entry:{}])",
@@ -316,13 +332,27 @@ impl Debug for Insn {
             Self::GetDataRef(u) => write!(f, "{:>2}:GetDataRef({})", self.op_code(), u.to_u32()),
             Self::GetLocalRef(i) => write!(f, "{:>2}:GetLocalRef({})", self.op_code(), i.to_i32()),
             Self::SetLocalRef(i) => write!(f, "{:>2}:SetLocalRef({})", self.op_code(), i.to_i32()),
-            Self::ObjFieldRef(u) => write!(f, "{:>2}:Pop({})", self.op_code(), u.to_u32()),
+            Self::ObjFieldRef(u) => write!(f, "{:>2}:ObjFieldRef({})", self.op_code(), u.to_u32()),
             Self::GetLastRef(u) => write!(f, "{:>2}:GetLastRef({})", self.op_code(), u.to_u32()),
             Self::SetLastRef(u) => write!(f, "{:>2}:SetLastRef({})", self.op_code(), u.to_u32()),
             Self::SetNodeRef(u) => write!(f, "{:>2}:SetNodeRef({})", self.op_code(), u.to_u32()),
             Self::SetDataRef(u) => write!(f, "{:>2}:SetDataRef({})", self.op_code(), u.to_u32()),
             Self::DropLast(u) => write!(f, "{:>2}:DropLast({})", self.op_code(), u.to_u32()),
             Self::Abort => write!(f, "{:>2}:Abort", self.op_code()),
+            Self::Float(b) => write!(f, "{:>2}:Float({:?})", self.op_code(), f32::from_bits(*b)),
+            Self::FMinus => write!(f, "{:>2}:FMinus", self.op_code()),
+            Self::FAdd => write!(f, "{:>2}:FAdd", self.op_code()),
+            Self::FSub => write!(f, "{:>2}:FSub", self.op_code()),
+            Self::FMul => write!(f, "{:>2}:FMul", self.op_code()),
+            Self::FDiv => write!(f, "{:>2}:FDiv", self.op_code()),
+            Self::FLs => write!(f, "{:>2}:FLs", self.op_code()),
+            Self::FLeq => write!(f, "{:>2}:FLeq", self.op_code()),
+            Self::FGt => write!(f, "{:>2}:FGt", self.op_code()),
+            Self::FGeq => write!(f, "{:>2}:FGeq", self.op_code()),
+            Self::FEq => write!(f, "{:>2}:FEq", self.op_code()),
+            Self::FNeq => write!(f, "{:>2}:FNeq", self.op_code()),
+            Self::IntToFloat => write!(f, "{:>2}:IntToFloat", self.op_code()),
+            Self::FloatToInt => write!(f, "{:>2}:FloatToInt", self.op_code()),
         }
     }
 }
@@ -393,278 +423,294 @@ impl Insn {
     pub fn op_code(&self) -> u8 {
         //opcode, len
         match self {
-            Insn::None => 1,
-            Insn::Nil => 2,
-            Insn::Not => 3,
-            Insn::Minus => 4,
-            Insn::Add => 5,
-            Insn::Sub => 6,
-            Insn::Mul => 7,
-            Insn::Div => 8,
-            Insn::Mod => 9,
-            Insn::ShiftL => 10,
-            Insn::ShiftR => 11,
-            Insn::Ls => 12,
-            Insn::Leq => 13,
-            Insn::Gt => 14,
-            Insn::Geq => 15,
-            Insn::Eq => 16,
-            Insn::Neq => 17,
+            Insn::None => NONE,
+            Insn::Nil => NIL,
+            Insn::Not => NOT,
+            Insn::Minus => MINUS,
+            Insn::Add => ADD,
+            Insn::Sub => SUB,
+            Insn::Mul => MUL,
+            Insn::Div => DIV,
+            Insn::Mod => MOD,
+            Insn::ShiftL => SHIFT_L,
+            Insn::ShiftR => SHIFT_R,
+            Insn::Ls => LS,
+            Insn::Leq => LEQ,
+            Insn::Gt => GT,
+            Insn::Geq => GEQ,
+            Insn::Eq => EQ,
+            Insn::Neq => NEQ,
 
-            Insn::BitAnd => 18,
-            Insn::BitOr => 19,
-            Insn::BitXor => 20,
-            Insn::Return => 21,
-            Insn::Print => 22,
-            Insn::PrintObj => 23,
-            Insn::Halt => 24,
-            Insn::Peek => 25,
+            Insn::BitAnd => BIT_AND,
+            Insn::BitOr => BIT_OR,
+            Insn::BitXor => BIT_XOR,
+            Insn::Return => RETURN,
+            Insn::Print => PRINT,
+            Insn::PrintObj => PRINT_OBJ,
+            Insn::Halt => HALT,
+            Insn::Peek => PEEK,
             Insn::Placeholder => panic!("placeholder must not appear"),
-            Insn::PushTrue => 26,
-            Insn::PushFalse => 27,
-            Insn::Abort => 28,
+            Insn::PushTrue => PUSH_TRUE,
+            Insn::PushFalse => PUSH_FALSE,
+            Insn::Abort => ABORT,
             Insn::Int(n) => match n {
-                SignedNum::I8(0) => 30,
-                SignedNum::I8(1) => 31,
-                SignedNum::I8(2) => 32,
-                SignedNum::I8(3) => 33,
-                SignedNum::I8(4) => 34,
-                SignedNum::I8(5) => 35,
-                SignedNum::I8(6) => 36,
-                SignedNum::I8(_) => 37,
-                SignedNum::I16(_) => 38,
-                SignedNum::I32(_) => 39,
+                SignedNum::I8(0) => INT_0,
+                SignedNum::I8(1) => INT_1,
+                SignedNum::I8(2) => INT_2,
+                SignedNum::I8(3) => INT_3,
+                SignedNum::I8(4) => INT_4,
+                SignedNum::I8(5) => INT_5,
+                SignedNum::I8(6) => INT_6,
+                SignedNum::I8(_) => INT_I8,
+                SignedNum::I16(_) => INT_I16,
+                SignedNum::I32(_) => INT_I32,
             },
             Insn::GetLocal(n) => match n {
-                SignedNum::I8(0) => 40,
-                SignedNum::I8(1) => 41,
-                SignedNum::I8(2) => 42,
-                SignedNum::I8(3) => 43,
-                SignedNum::I8(4) => 44,
-                SignedNum::I8(5) => 45,
-                SignedNum::I8(6) => 46,
-                SignedNum::I8(_) => 47,
-                SignedNum::I16(_) => 48,
-                SignedNum::I32(_) => 49,
+                SignedNum::I8(0) => GET_LOCAL_0,
+                SignedNum::I8(1) => GET_LOCAL_1,
+                SignedNum::I8(2) => GET_LOCAL_2,
+                SignedNum::I8(3) => GET_LOCAL_3,
+                SignedNum::I8(4) => GET_LOCAL_4,
+                SignedNum::I8(5) => GET_LOCAL_5,
+                SignedNum::I8(6) => GET_LOCAL_6,
+                SignedNum::I8(_) => GET_LOCAL_I8,
+                SignedNum::I16(_) => GET_LOCAL_I16,
+                SignedNum::I32(_) => GET_LOCAL_I32,
             },
             Insn::SetLocal(n) => match n {
-                SignedNum::I8(0) => 50,
-                SignedNum::I8(1) => 51,
-                SignedNum::I8(2) => 52,
-                SignedNum::I8(3) => 53,
-                SignedNum::I8(4) => 54,
-                SignedNum::I8(5) => 55,
-                SignedNum::I8(6) => 56,
-                SignedNum::I8(_) => 57,
-                SignedNum::I16(_) => 58,
-                SignedNum::I32(_) => 59,
+                SignedNum::I8(0) => SET_LOCAL_0,
+                SignedNum::I8(1) => SET_LOCAL_1,
+                SignedNum::I8(2) => SET_LOCAL_2,
+                SignedNum::I8(3) => SET_LOCAL_3,
+                SignedNum::I8(4) => SET_LOCAL_4,
+                SignedNum::I8(5) => SET_LOCAL_5,
+                SignedNum::I8(6) => SET_LOCAL_6,
+                SignedNum::I8(_) => SET_LOCAL_I8,
+                SignedNum::I16(_) => SET_LOCAL_I16,
+                SignedNum::I32(_) => SET_LOCAL_I32,
             },
             Insn::AllocLocal(n) => match n {
                 UnsignedNum::U8(0) => panic!("emit_local"),
-                UnsignedNum::U8(1) => 61,
-                UnsignedNum::U8(2) => 62,
-                UnsignedNum::U8(3) => 63,
-                UnsignedNum::U8(4) => 64,
-                UnsignedNum::U8(5) => 65,
-                UnsignedNum::U8(6) => 66,
-                UnsignedNum::U8(_) => 67,
-                UnsignedNum::U16(_) => 68,
-                UnsignedNum::U32(_) => 69,
+                UnsignedNum::U8(1) => ALLOC_LOCAL_1,
+                UnsignedNum::U8(2) => ALLOC_LOCAL_2,
+                UnsignedNum::U8(3) => ALLOC_LOCAL_3,
+                UnsignedNum::U8(4) => ALLOC_LOCAL_4,
+                UnsignedNum::U8(5) => ALLOC_LOCAL_5,
+                UnsignedNum::U8(6) => ALLOC_LOCAL_6,
+                UnsignedNum::U8(_) => ALLOC_LOCAL_U8,
+                UnsignedNum::U16(_) => ALLOC_LOCAL_U16,
+                UnsignedNum::U32(_) => ALLOC_LOCAL_U32,
             },
             Insn::Pop(n) => match n {
-                UnsignedNum::U8(0) => 70,
-                UnsignedNum::U8(1) => 71,
-                UnsignedNum::U8(2) => 72,
-                UnsignedNum::U8(3) => 73,
-                UnsignedNum::U8(4) => 74,
-                UnsignedNum::U8(5) => 75,
-                UnsignedNum::U8(6) => 76,
-                UnsignedNum::U8(_) => 77,
-                UnsignedNum::U16(_) => 78,
-                UnsignedNum::U32(_) => 79,
-            },
-            Insn::Jne8(_) => 80,
-            Insn::Jne16(_) => 81,
-            Insn::Jne32(_) => 82,
-            Insn::Je8(_) => 83,
-            Insn::Je16(_) => 84,
-            Insn::Je32(_) => 85,
-            Insn::J8(_) => 86,
-            Insn::J16(_) => 87,
-            Insn::J32(_) => 88,
+                UnsignedNum::U8(0) => POP_0,
+                UnsignedNum::U8(1) => POP_1,
+                UnsignedNum::U8(2) => POP_2,
+                UnsignedNum::U8(3) => POP_3,
+                UnsignedNum::U8(4) => POP_4,
+                UnsignedNum::U8(5) => POP_5,
+                UnsignedNum::U8(6) => POP_6,
+                UnsignedNum::U8(_) => POP_U8,
+                UnsignedNum::U16(_) => POP_U16,
+                UnsignedNum::U32(_) => POP_U32,
+            },
+            Insn::Jne8(_) => JNE8,
+            Insn::Jne16(_) => JNE16,
+            Insn::Jne32(_) => JNE32,
+            Insn::Je8(_) => JE8,
+            Insn::Je16(_) => JE16,
+            Insn::Je32(_) => JE32,
+            Insn::J8(_) => J8,
+            Insn::J16(_) => J16,
+            Insn::J32(_) => J32,
 
             Insn::GetLast(n) => match n {
-                UnsignedNum::U8(0) => 90,
-                UnsignedNum::U8(1) => 91,
-                UnsignedNum::U8(2) => 92,
-                UnsignedNum::U8(3) => 93,
-                UnsignedNum::U8(_) => 94,
-                UnsignedNum::U16(_) => 95,
-                UnsignedNum::U32(_) => 96,
+                UnsignedNum::U8(0) => GET_LAST_0,
+                UnsignedNum::U8(1) => GET_LAST_1,
+                UnsignedNum::U8(2) => GET_LAST_2,
+                UnsignedNum::U8(3) => GET_LAST_3,
+                UnsignedNum::U8(_) => GET_LAST_U8,
+                UnsignedNum::U16(_) => GET_LAST_U16,
+                UnsignedNum::U32(_) => GET_LAST_U32,
             },
             Insn::SetNode(n) => match n {
-                UnsignedNum::U8(_) => 97,
-                UnsignedNum::U16(_) => 98,
-                UnsignedNum::U32(_) => 99,
+                UnsignedNum::U8(_) => SET_NODE_U8,
+                UnsignedNum::U16(_) => SET_NODE_U16,
+                UnsignedNum::U32(_) => SET_NODE_U32,
             },
             Insn::ObjField(n) => match n {
-                UnsignedNum::U8(0) => 100,
-                UnsignedNum::U8(1) => 101,
-                UnsignedNum::U8(2) => 102,
-                UnsignedNum::U8(3) => 103,
-                UnsignedNum::U8(4) => 104,
-                UnsignedNum::U8(5) => 105,
-                UnsignedNum::U8(6) => 106,
+                UnsignedNum::U8(0) => OBJ_FIELD_0,
+                UnsignedNum::U8(1) => OBJ_FIELD_1,
+                UnsignedNum::U8(2) => OBJ_FIELD_2,
+                UnsignedNum::U8(3) => OBJ_FIELD_3,
+                UnsignedNum::U8(4) => OBJ_FIELD_4,
+                UnsignedNum::U8(5) => OBJ_FIELD_5,
+                UnsignedNum::U8(6) => OBJ_FIELD_6,
+                UnsignedNum::U8(_) => OBJ_FIELD_U8,
                 _ => panic!(),
             },
 
             Insn::UpdateDev(n) => match n {
-                UnsignedNum::U8(0) => 110,
-                UnsignedNum::U8(1) => 111,
-                UnsignedNum::U8(2) => 112,
-                UnsignedNum::U8(3) => 113,
-                UnsignedNum::U8(_) => 114,
+                UnsignedNum::U8(0) => UPD_DEV_0,
+                UnsignedNum::U8(1) => UPD_DEV_1,
+                UnsignedNum::U8(2) => UPD_DEV_2,
+                UnsignedNum::U8(3) => UPD_DEV_3,
+                UnsignedNum::U8(_) => UPD_DEV_U8,
                 UnsignedNum::U16(_) => panic!(),
                 UnsignedNum::U32(_) => panic!(),
             },
             Insn::UpdateNode(n) => match n {
-                UnsignedNum::U8(_) => 117,
-                UnsignedNum::U16(_) => 118,
-                UnsignedNum::U32(_) => 119,
+                UnsignedNum::U8(_) => UPD_NODE_U8,
+                UnsignedNum::U16(_) => UPD_NODE_U16,
+                UnsignedNum::U32(_) => UPD_NODE_U32,
             },
             Insn::OutputAction(n) => match n {
-                UnsignedNum::U8(0) => 120,
-                UnsignedNum::U8(1) => 121,
-                UnsignedNum::U8(2) => 122,
-                UnsignedNum::U8(3) => 123,
-                UnsignedNum::U8(_) => 124,
+                UnsignedNum::U8(0) => O_ACTION_0,
+                UnsignedNum::U8(1) => O_ACTION_1,
+                UnsignedNum::U8(2) => O_ACTION_2,
+                UnsignedNum::U8(3) => O_ACTION_3,
+                UnsignedNum::U8(_) => O_ACTION_U8,
                 UnsignedNum::U16(_) => panic!(),
                 UnsignedNum::U32(_) => panic!(),
             },
 
             Insn::Call(_, n) => match n {
-                UnsignedNum::U8(_) => 127,
-                UnsignedNum::U16(_) => 128,
-                UnsignedNum::U32(_) => 129,
+                UnsignedNum::U8(_) => CALL_U8,
+                UnsignedNum::U16(_) => CALL_U16,
+                UnsignedNum::U32(_) => CALL_U32,
             },
             Insn::GetData(n) => match n {
-                UnsignedNum::U8(_) => 130,
-                UnsignedNum::U16(_) => 131,
-                UnsignedNum::U32(_) => 132,
+                UnsignedNum::U8(_) => GET_DATA_U8,
+                UnsignedNum::U16(_) => GET_DATA_U16,
+                UnsignedNum::U32(_) => GET_DATA_U32,
             },
 
             Insn::GetNode(n) => match n {
-                UnsignedNum::U8(_) => 133,
-                UnsignedNum::U16(_) => 134,
-                UnsignedNum::U32(_) => 135,
+                UnsignedNum::U8(_) => GET_NODE_U8,
+                UnsignedNum::U16(_) => GET_NODE_U16,
+                UnsignedNum::U32(_) => GET_NODE_U32,
             },
 
             Insn::SetData(n) => match n {
-                UnsignedNum::U8(_) => 141,
-                UnsignedNum::U16(_) => 142,
-                UnsignedNum::U32(_) => 143,
+                UnsignedNum::U8(_) => SET_DATA_U8,
+                UnsignedNum::U16(_) => SET_DATA_U16,
+                UnsignedNum::U32(_) => SET_DATA_U32,
             },
-            Insn::ObjTag => 144,
+            Insn::ObjTag => OBJ_TAG,
             Insn::SetLast(n) => match n {
-                UnsignedNum::U8(0) => 150,
-                UnsignedNum::U8(1) => 151,
-                UnsignedNum::U8(2) => 152,
-                UnsignedNum::U8(3) => 153,
-                UnsignedNum::U8(_) => 154,
-                UnsignedNum::U16(_) => 155,
-                UnsignedNum::U32(_) => 156,
+                UnsignedNum::U8(0) => SET_LAST_0,
+                UnsignedNum::U8(1) => SET_LAST_1,
+                UnsignedNum::U8(2) => SET_LAST_2,
+                UnsignedNum::U8(3) => SET_LAST_3,
+                UnsignedNum::U8(_) => SET_LAST_U8,
+                UnsignedNum::U16(_) => SET_LAST_U16,
+                UnsignedNum::U32(_) => SET_LAST_U32,
             },
             Insn::EndUpdateNode(n) => match n {
-                UnsignedNum::U8(_) => 157,
-                UnsignedNum::U16(_) => 158,
-                UnsignedNum::U32(_) => 159,
+                UnsignedNum::U8(_) => END_UPD_U8,
+                UnsignedNum::U16(_) => END_UPD_U16,
+                UnsignedNum::U32(_) => END_UPD_U32,
             },
             Insn::AllocObj(n, _) => match n {
-                UnsignedNum::U8(0) => 160,
-                UnsignedNum::U8(1) => 161,
-                UnsignedNum::U8(2) => 162,
-                UnsignedNum::U8(3) => 163,
-                UnsignedNum::U8(4) => 164,
-                UnsignedNum::U8(5) => 165,
-                UnsignedNum::U8(6) => 166,
-                UnsignedNum::U8(_) => 167,
+                UnsignedNum::U8(0) => ALLOC_OBJ_0,
+                UnsignedNum::U8(1) => ALLOC_OBJ_1,
+                UnsignedNum::U8(2) => ALLOC_OBJ_2,
+                UnsignedNum::U8(3) => ALLOC_OBJ_3,
+                UnsignedNum::U8(4) => ALLOC_OBJ_4,
+                UnsignedNum::U8(5) => ALLOC_OBJ_5,
+                UnsignedNum::U8(6) => ALLOC_OBJ_6,
+                UnsignedNum::U8(_) => ALLOC_OBJ_U8,
                 UnsignedNum::U16(_) => panic!("typecheck"),
                 UnsignedNum::U32(_) => panic!("typecheck"),
             },
             Insn::DropLocalObj(i) => match i {
-                SignedNum::I8(n @ 0..=6) => *n as u8 + 170,
-                SignedNum::I8(_) => 177,
-                SignedNum::I16(_) => 178,
-                SignedNum::I32(_) => 179,
+                SignedNum::I8(n @ 0..=6) => DROP_LOCAL_OBJ_0 + *n as u8,
+                SignedNum::I8(_) => DROP_LOCAL_OBJ_I8,
+                SignedNum::I16(_) => DROP_LOCAL_OBJ_I16,
+                SignedNum::I32(_) => DROP_LOCAL_OBJ_I32,
             },
             Insn::GetLocalRef(i) => match i {
-                SignedNum::I8(n @ 0..=6) => *n as u8 + 180,
-                SignedNum::I8(_) => 187,
-                SignedNum::I16(_) => 188,
-                SignedNum::I32(_) => 189,
+                SignedNum::I8(n @ 0..=6) => GET_LOCAL_REF_0 + *n as u8,
+                SignedNum::I8(_) => GET_LOCAL_REF_I8,
+                SignedNum::I16(_) => GET_LOCAL_REF_I16,
+                SignedNum::I32(_) => GET_LOCAL_REF_I32,
             },
             Insn::SetLocalRef(i) => match i {
-                SignedNum::I8(a @ 0..=6) => *a as u8 + 190,
-                SignedNum::I8(_) => 197,
-                SignedNum::I16(_) => 198,
-                SignedNum::I32(_) => 199,
+                SignedNum::I8(a @ 0..=6) => SET_LOCAL_REF_0 + *a as u8,
+                SignedNum::I8(_) => SET_LOCAL_REF_I8,
+                SignedNum::I16(_) => SET_LOCAL_REF_I16,
+                SignedNum::I32(_) => SET_LOCAL_REF_I32,
             },
             Insn::ObjFieldRef(u) => match u {
-                UnsignedNum::U8(a @ 0..=6) => *a + 200,
+                UnsignedNum::U8(a @ 0..=6) => OBJ_FIELD_REF_0 + *a,
+                UnsignedNum::U8(_) => OBJ_FIELD_REF_U8,
                 _ => panic!(),
             },
             Insn::EndUpdateNodeObj(u) => match u {
-                UnsignedNum::U8(_) => 210,
-                UnsignedNum::U16(_) => 211,
-                UnsignedNum::U32(_) => 212,
+                UnsignedNum::U8(_) => END_UPD_OBJ_U8,
+                UnsignedNum::U16(_) => END_UPD_OBJ_U16,
+                UnsignedNum::U32(_) => END_UPD_OBJ_U32,
             },
 
             Insn::GetNodeRef(u) => match u {
-                UnsignedNum::U8(_) => 213,
-                UnsignedNum::U16(_) => 214,
-                UnsignedNum::U32(_) => 215,
+                UnsignedNum::U8(_) => GET_NODE_REF_U8,
+                UnsignedNum::U16(_) => GET_NODE_REF_U16,
+                UnsignedNum::U32(_) => GET_NODE_REF_U32,
             },
             Insn::GetDataRef(u) => match u {
-                UnsignedNum::U8(_) => 216,
-                UnsignedNum::U16(_) => 217,
-                UnsignedNum::U32(_) => 218,
+                UnsignedNum::U8(_) => GET_DATA_REF_U8,
+                UnsignedNum::U16(_) => GET_DATA_REF_U16,
+                UnsignedNum::U32(_) => GET_DATA_REF_U32,
             },
             Insn::GetLastRef(u) => match u {
-                UnsignedNum::U8(a @ 0..=3) => *a + 220,
-                UnsignedNum::U8(_) => 224,
-                UnsignedNum::U16(_) => 225,
-                UnsignedNum::U32(_) => 226,
+                UnsignedNum::U8(a @ 0..=3) => GET_LAST_REF_0 + *a,
+                UnsignedNum::U8(_) => GET_LAST_REF_U8,
+                UnsignedNum::U16(_) => GET_LAST_REF_U16,
+                UnsignedNum::U32(_) => GET_LAST_REF_U32,
             },
             Insn::SetDataRef(u) => match u {
-                UnsignedNum::U8(_) => 227,
-                UnsignedNum::U16(_) => 228,
-                UnsignedNum::U32(_) => 229,
+                UnsignedNum::U8(_) => SET_DATA_REF_U8,
+                UnsignedNum::U16(_) => SET_DATA_REF_U16,
+                UnsignedNum::U32(_) => SET_DATA_REF_U32,
             },
             Insn::SetLastRef(u) => match u {
-                UnsignedNum::U8(a @ 0..=3) => *a + 230,
-                UnsignedNum::U8(_) => 234,
-                UnsignedNum::U16(_) => 235,
-                UnsignedNum::U32(_) => 236,
+                UnsignedNum::U8(a @ 0..=3) => SET_LAST_REF_0 + *a,
+                UnsignedNum::U8(_) => SET_LAST_REF_U8,
+                UnsignedNum::U16(_) => SET_LAST_REF_U16,
+                UnsignedNum::U32(_) => SET_LAST_REF_U32,
             },
             Insn::SetNodeRef(u) => match u {
-                UnsignedNum::U8(_) => 237,
-                UnsignedNum::U16(_) => 238,
-                UnsignedNum::U32(_) => 239,
+                UnsignedNum::U8(_) => SET_NODE_REF_U8,
+                UnsignedNum::U16(_) => SET_NODE_REF_U16,
+                UnsignedNum::U32(_) => SET_NODE_REF_U32,
             },
             Insn::DropLast(u) => match u {
-                UnsignedNum::U8(_) => 240,
-                UnsignedNum::U16(_) => 241,
-                UnsignedNum::U32(_) => 242,
-            },
-            Insn::J0 => 243,
-            Insn::J1 => 244,
-            Insn::Je0 => 245,
-            Insn::Je1 => 246,
-            Insn::Jne0 => 247,
-            Insn::Jne1 => 248,
+                UnsignedNum::U8(_) => DROP_LAST_U8,
+                UnsignedNum::U16(_) => DROP_LAST_U16,
+                UnsignedNum::U32(_) => DROP_LAST_U32,
+            },
+            Insn::J0 => J0,
+            Insn::J1 => J1,
+            Insn::Je0 => JE0,
+            Insn::Je1 => JE1,
+            Insn::Jne0 => JNE0,
+            Insn::Jne1 => JNE1,
+            Insn::FAdd => F_ADD,
+            Insn::FSub => F_SUB,
+            Insn::FMul => F_MUL,
+            Insn::FDiv => F_DIV,
+            Insn::FMinus => F_MINUS,
+            Insn::FLs => F_LS,
+            Insn::FLeq => F_LEQ,
+            Insn::FGt => F_GT,
+            Insn::FGeq => F_GEQ,
+            Insn::FEq => F_EQ,
+            Insn::FNeq => F_NEQ,
+            Insn::Float(_) => FLOAT,
+            Insn::IntToFloat => INT_TO_FLOAT,
+            Insn::FloatToInt => FLOAT_TO_INT,
         }
     }
-    fn push_byte_code(&self, ret: &mut Vec<u8>) {
+    pub fn push_byte_code(&self, ret: &mut Vec<u8>) {
         ret.push(self.op_code());
         match self {
             // no immediate value
@@ -696,47 +742,60 @@ impl Insn {
             | Insn::Placeholder
             | Insn::Abort
             | Insn::PushTrue
-            | Insn::PushFalse => return,
+            | Insn::PushFalse
+            | Insn::FMinus
+            | Insn::FAdd
+            | Insn::FSub
+            | Insn::FMul
+            | Insn::FDiv
+            | Insn::FLs
+            | Insn::FLeq
+            | Insn::FGt
+            | Insn::FGeq
+            | Insn::FEq
+            | Insn::FNeq
+            | Insn::IntToFloat
+            | Insn::FloatToInt => (),
 
             Insn::Int(n) => match n {
-                SignedNum::I8(0..=6) => return,
+                SignedNum::I8(0..=6) => (),
                 SignedNum::I8(i) => ret.push(i.to_le_bytes()[0]),
                 SignedNum::I16(i) => push_i16_le(*i, ret),
                 SignedNum::I32(i) => push_i32_le(*i, ret),
             },
             Insn::GetLocal(n) => match n {
-                SignedNum::I8(0..=6) => return,
+                SignedNum::I8(0..=6) => (),
                 SignedNum::I8(i) => ret.push(i.to_le_bytes()[0]),
                 SignedNum::I16(i) => push_i16_le(*i, ret),
                 SignedNum::I32(i) => push_i32_le(*i, ret),
             },
             Insn::SetLocal(n) => match n {
-                SignedNum::I8(0..=6) => return,
+                SignedNum::I8(0..=6) => (),
                 SignedNum::I8(i) => ret.push(i.to_le_bytes()[0]),
                 SignedNum::I16(i) => push_i16_le(*i, ret),
                 SignedNum::I32(i) => push_i32_le(*i, ret),
             },
             Insn::AllocLocal(n) => match n {
-                UnsignedNum::U8(0..=6) => return,
+                UnsignedNum::U8(0..=6) => (),
                 UnsignedNum::U8(u) => ret.push(*u),
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
             Insn::Pop(n) => match n {
-                UnsignedNum::U8(0..=6) => return,
+                UnsignedNum::U8(0..=6) => (),
                 UnsignedNum::U8(u) => ret.push(*u),
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
 
             Insn::GetLast(n) => match n {
-                UnsignedNum::U8(0..=3) => return,
+                UnsignedNum::U8(0..=3) => (),
                 UnsignedNum::U8(u) => ret.push(*u),
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
             Insn::SetLast(n) => match n {
-                UnsignedNum::U8(0..=3) => return,
+                UnsignedNum::U8(0..=3) => (),
                 UnsignedNum::U8(u) => ret.push(*u),
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
@@ -747,7 +806,8 @@ impl Insn {
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
             Insn::ObjField(n) => match n {
-                UnsignedNum::U8(0..=6) => return,
+                UnsignedNum::U8(0..=6) => (),
+                UnsignedNum::U8(u) => ret.push(*u),
                 _ => panic!("too many obj field"),
             },
             Insn::EndUpdateNode(n) => match n {
@@ -756,7 +816,7 @@ impl Insn {
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
             Insn::UpdateDev(n) => match n {
-                UnsignedNum::U8(0..=3) => return,
+                UnsignedNum::U8(0..=3) => (),
                 UnsignedNum::U8(u) => ret.push(*u),
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
@@ -767,7 +827,7 @@ impl Insn {
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
             Insn::OutputAction(n) => match n {
-                UnsignedNum::U8(0..=3) => return,
+                UnsignedNum::U8(0..=3) => (),
                 UnsignedNum::U8(u) => ret.push(*u),
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
@@ -805,9 +865,11 @@ impl Insn {
                     UnsignedNum::U16(_) => panic!("typecheck"),
                     UnsignedNum::U32(_) => panic!("typecheck"),
                 }
-                push_u32_le(u2.0, ret);
+                for w in u2.words() {
+                    push_u32_le(w, ret);
+                }
             }
-            Insn::ObjTag => return,
+            Insn::ObjTag => (),
             Insn::Jne8(i) | Insn::Je8(i) | Insn::J8(i) => ret.push(i.to_le_bytes()[0]),
             Insn::Jne16(i) | Insn::Je16(i) | Insn::J16(i) => push_i16_le(*i, ret),
             Insn::Jne32(i) | Insn::Je32(i) | Insn::J32(i) => push_i32_le(*i, ret),
@@ -817,7 +879,7 @@ impl Insn {
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
             Insn::DropLocalObj(i) => match i {
-                SignedNum::I8(0..=6) => return,
+                SignedNum::I8(0..=6) => (),
                 SignedNum::I8(i) => ret.push(i.to_le_bytes()[0]),
                 SignedNum::I16(i) => push_i16_le(*i, ret),
                 SignedNum::I32(i) => push_i32_le(*i, ret),
@@ -843,29 +905,30 @@ impl Insn {
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
             Insn::GetLocalRef(i) => match i {
-                SignedNum::I8(0..=6) => return,
+                SignedNum::I8(0..=6) => (),
                 SignedNum::I8(i) => ret.push(i.to_le_bytes()[0]),
                 SignedNum::I16(i) => push_i16_le(*i, ret),
                 SignedNum::I32(i) => push_i32_le(*i, ret),
             },
             Insn::SetLocalRef(i) => match i {
-                SignedNum::I8(0..=6) => return,
+                SignedNum::I8(0..=6) => (),
                 SignedNum::I8(i) => ret.push(i.to_le_bytes()[0]),
                 SignedNum::I16(i) => push_i16_le(*i, ret),
                 SignedNum::I32(i) => push_i32_le(*i, ret),
             },
             Insn::ObjFieldRef(u) => match u {
-                UnsignedNum::U8(0..=6) => return,
+                UnsignedNum::U8(0..=6) => (),
+                UnsignedNum::U8(u) => ret.push(*u),
                 _ => panic!("too many obj field"),
             },
             Insn::GetLastRef(u) => match u {
-                UnsignedNum::U8(0..=3) => return,
+                UnsignedNum::U8(0..=3) => (),
                 UnsignedNum::U8(u) => ret.push(*u),
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
             Insn::SetLastRef(u) => match u {
-                UnsignedNum::U8(0..=3) => return,
+                UnsignedNum::U8(0..=3) => (),
                 UnsignedNum::U8(u) => ret.push(*u),
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
@@ -875,8 +938,9 @@ impl Insn {
                 UnsignedNum::U16(u) => push_u16_le(*u, ret),
                 UnsignedNum::U32(u) => push_u32_le(*u, ret),
             },
-            Insn::J0 | Insn::J1 | Insn::Je0 | Insn::Je1 | Insn::Jne0 | Insn::Jne1 => return,
-        };
+            Insn::J0 | Insn::J1 | Insn::Je0 | Insn::Je1 | Insn::Jne0 | Insn::Jne1 => (),
+            Insn::Float(b) => push_u32_le(*b, ret),
+        }
     }
 }
 
@@ -910,7 +974,7 @@ impl Insn {
         } else if i8::MIN as i32 <= i && i <= i8::MAX as i32 {
             Insn::J8(i as i8)
         } else {
-            Insn::J32(i as i32)
+            Insn::J32(i)
         }
     }
     pub fn je(i: i32) -> Self {
@@ -921,7 +985,7 @@ impl Insn {
         } else if i8::MIN as i32 <= i && i <= i8::MAX as i32 {
             Insn::Je8(i as i8)
         } else {
-            Insn::Je32(i as i32)
+            Insn::Je32(i)
         }
     }
     pub fn jne(i: i32) -> Self {
@@ -932,7 +996,7 @@ impl Insn {
         } else if i8::MIN as i32 <= i && i <= i8::MAX as i32 {
             Insn::Jne8(i as i8)
         } else {
-            Insn::Jne32(i as i32)
+            Insn::Jne32(i)
         }
     }
 }
@@ -969,7 +1033,20 @@ pub fn bytecode_len(v: &[Insn]) -> usize {
             | Insn::Placeholder
             | Insn::Abort
             | Insn::PushTrue
-            | Insn::PushFalse => 1,
+            | Insn::PushFalse
+            | Insn::FMinus
+            | Insn::FAdd
+            | Insn::FSub
+            | Insn::FMul
+            | Insn::FDiv
+            | Insn::FLs
+            | Insn::FLeq
+            | Insn::FGt
+            | Insn::FGeq
+            | Insn::FEq
+            | Insn::FNeq
+            | Insn::IntToFloat
+            | Insn::FloatToInt => 1,
 
             Insn::Int(n) => match n {
                 SignedNum::I8(0..=6) => 1,
@@ -1070,9 +1147,9 @@ pub fn bytecode_len(v: &[Insn]) -> usize {
                 UnsignedNum::U16(_) => 3,
                 UnsignedNum::U32(_) => 5,
             },
-            Insn::AllocObj(u, _) => match u {
-                UnsignedNum::U8(0..=6) => 5,
-                UnsignedNum::U8(_) => 6,
+            Insn::AllocObj(u, h) => match u {
+                UnsignedNum::U8(0..=6) => 5 + 4 * h.1.len(),
+                UnsignedNum::U8(_) => 6 + 4 * h.1.len(),
                 UnsignedNum::U16(_) => panic!("typecheck"),
                 UnsignedNum::U32(_) => panic!("typecheck"),
             },
@@ -1120,13 +1197,13 @@ pub fn bytecode_len(v: &[Insn]) -> usize {
                 UnsignedNum::U32(_) => 5,
             },
             Insn::GetLastRef(u) => match u {
-                UnsignedNum::U8(0..=6) => 1,
+                UnsignedNum::U8(0..=3) => 1,
                 UnsignedNum::U8(_) => 2,
                 UnsignedNum::U16(_) => 3,
                 UnsignedNum::U32(_) => 5,
             },
             Insn::SetLastRef(u) => match u {
-                UnsignedNum::U8(0..=6) => 1,
+                UnsignedNum::U8(0..=3) => 1,
                 UnsignedNum::U8(_) => 2,
                 UnsignedNum::U16(_) => 3,
                 UnsignedNum::U32(_) => 5,
@@ -1147,15 +1224,30 @@ pub fn bytecode_len(v: &[Insn]) -> usize {
                 UnsignedNum::U32(_) => 5,
             },
             Insn::J0 | Insn::J1 | Insn::Je0 | Insn::Je1 | Insn::Jne0 | Insn::Jne1 => 1,
+            Insn::Float(_) => 5,
         };
     }
     ret
 }
+// the header word and the words of an extended header, in the order they are written in bytecode
 #[derive(Clone, Eq, PartialEq)]
-pub struct ObjHeader(pub u32);
+pub struct ObjHeader(pub u32, pub Vec<u32>);
 impl ObjHeader {
-    // header tag:7bit/ reserved:1bit/ numentry:3bit/ objbit:7bit/ refcnt:14bit
-    pub fn new(tag: u32, objbit: &Vec<bool>, n_entry: u32) -> Self {
+    // header tag:7bit/ carry:1bit/ numentry:3bit/ objbit:7bit/ refcnt:14bit
+    // tag 0 escapes to an extended header for more than 7 fields or a tag above 127, which is
+    // tag:16bit/ numentry:16bit and then an objbit word per 32 fields.
+    // the machine keeps the extended header in the words before the header word
+    pub fn new(tag: u32, objbit: &[bool], n_entry: u32) -> Self {
+        if tag > 0x7f || n_entry > 7 {
+            let mut ext = vec![(tag << 16) | n_entry];
+            ext.resize(1 + (n_entry as usize).div_ceil(32), 0);
+            for (i, b) in objbit.iter().enumerate() {
+                if *b {
+                    ext[1 + i / 32] |= 1 << (i % 32)
+                }
+            }
+            return Self(1, ext);
+        }
         let mut header = 0u32;
         header |= tag << 25;
         for (i, b) in objbit.iter().enumerate() {
@@ -1164,14 +1256,58 @@ impl ObjHeader {
             }
         }
         header |= n_entry << 21;
-        Self(header + 1)
+        Self(header + 1, vec![])
+    }
+    // the header word, and the extended header if the header word escapes to it
+    pub fn read<E>(mut next: impl FnMut() -> Result<u32, E>) -> Result<Self, E> {
+        let header = next()?;
+        if header >> 25 != 0 {
+            return Ok(Self(header, vec![]));
+        }
+        let mut ext = vec![next()?];
+        for _ in 0..(ext[0] & 0xffff).div_ceil(32) {
+            ext.push(next()?);
+        }
+        Ok(Self(header, ext))
+    }
+    pub fn words(&self) -> impl Iterator<Item = u32> + '_ {
+        std::iter::once(self.0).chain(self.1.iter().copied())
+    }
+    pub fn tag(&self) -> u32 {
+        match self.1.first() {
+            Some(ext) => ext >> 16,
+            None => self.0 >> 25,
+        }
+    }
+    pub fn n_entry(&self) -> usize {
+        match self.1.first() {
+            Some(ext) => (ext & 0xffff) as usize,
+            None => ((self.0 & 0xe00000) >> 21) as usize,
+        }
+    }
+    pub fn objbit(&self, i: usize) -> bool {
+        if self.1.is_empty() {
+            self.0 & (1 << (i + 14)) != 0
+        } else {
+            self.1
+                .get(1 + i / 32)
+                .is_some_and(|w| w & (1 << (i % 32)) != 0)
+        }
     }
     pub fn decode(&self) -> (u32, String, u32) {
-        let ObjHeader(i) = self;
+        let objbit: String = (0..self.n_entry())
+            .rev()
+            .map(|i| if self.objbit(i) { '1' } else { '0' })
+            .skip_while(|b| *b == '0')
+            .collect();
         (
-            i >> 25,                          //tag
-            format!("{:b}", (i << 11) >> 25), //objbit
-            (i << 8) >> 29,                   //numentry
+            self.tag(),
+            if objbit.is_empty() {
+                "0".to_string()
+            } else {
+                objbit
+            },
+            self.n_entry() as u32,
         )
     }
 }
@@ -0,0 +1,1263 @@
+// host-side reference implementation of emfrp_exec (emfrp-machine/esp32/main/machine.c)
+// it executes the output of insn::to_byte_code so that the compiler can be run without a device
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::insn::ObjHeader;
+use crate::opcode::*;
+use crate::protocol::QUERY_DATA;
+use crate::protocol::QUERY_NODE;
+use crate::protocol::QUERY_STAGED;
+use crate::protocol::REMOVE_FUNC;
+
+// STACK_SIZE of the esp32, which holds the 255 fields of an object while it is made
+pub const STACK_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EmfrpResult {
+    Ok = 0,
+    RuntimeErr = 1,
+    Panic = 2,
+    Todo = 3,
+    OutOfMemory = 4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Exec,
+    Update,
+    Node(usize),
+    Func(usize),
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ip {
+    seg: Segment,
+    pc: usize,
+}
+// value_t
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    // Int and Bool, and also Float as its bits like `value_t.f`
+    Num(i32),
+    Obj(usize),
+    Ip(Ip),
+}
+// equivalent of `static const value_t ZERO`, which also works as NULL object
+pub const ZERO: Value = Value::Num(0);
+
+pub type DevInput = Box<dyn FnMut(&mut Value)>;
+pub type OutputAction = Box<dyn FnMut(&Value)>;
+
+enum UpdAction {
+    None,
+    Insns(Rc<[u8]>),
+    Dev(DevInput),
+}
+struct Obj {
+    header: ObjHeader,
+    fields: Vec<Value>,
+}
+// definitions received by MSG_DEFINE, which are swapped in by MSG_COMMIT
+struct Staged {
+    num_last: usize,
+    new_data: usize,
+    nodes: Vec<(usize, Rc<[u8]>)>,
+    funcs: Vec<(usize, Rc<[u8]>)>,
+    update: Option<Rc<[u8]>>,
+    init: Rc<[u8]>,
+}
+impl Staged {
+    fn size(&self) -> usize {
+        let bodies = self.nodes.iter().chain(&self.funcs);
+        bodies.map(|(_, b)| b.len()).sum::<usize>()
+            + self.update.as_ref().map_or(0, |u| u.len())
+            + self.init.len()
+    }
+}
+struct Frame {
+    next_ip: Ip,
+    bp: usize,
+    sp: usize,
+}
+pub struct Machine {
+    stack: Vec<Value>,
+    sp: usize,
+    bp: usize,
+    frames: Vec<Frame>,
+    node_last: Vec<Value>,
+    update: Option<Rc<[u8]>>,
+    exec_code: Rc<[u8]>,
+    data: Vec<Value>,
+    func: Vec<Rc<[u8]>>,
+    node_v: Vec<Value>,
+    action: Vec<UpdAction>,
+    output_actions: Vec<OutputAction>,
+    heap: Vec<Option<Obj>>,
+    free_objs: Vec<usize>,
+    // objects whose refcount has overflowed the header, and how many times OBJ_RC_MAX is carried
+    rc_carry: HashMap<usize, u32>,
+    uart: Vec<u8>,
+    // input and output nodes, which are registered before the others
+    n_io: usize,
+    staged: Option<Staged>,
+    // bytes of code the machine can hold, like the heap of the device
+    code_limit: Option<usize>,
+}
+type MResult<T> = Result<T, EmfrpResult>;
+
+// header tag:7bit/ carry:1bit/ numentry:3bit/ objbit:7bit/ refcnt:14bit
+// the refcount is refcnt, plus OBJ_RC_MAX for each carry in rc_carry if the carry bit is set
+const OBJ_RC_MAX: u32 = 0x3fff;
+const OBJ_RC_CARRY: u32 = 1 << 24;
+fn obj_rc(header: u32) -> u32 {
+    header & OBJ_RC_MAX
+}
+
+fn read_u8(code: &[u8], pc: &mut usize) -> MResult<u8> {
+    let b = *code.get(*pc).ok_or(EmfrpResult::Panic)?;
+    *pc += 1;
+    Ok(b)
+}
+fn read_i8(code: &[u8], pc: &mut usize) -> MResult<i32> {
+    Ok(read_u8(code, pc)? as i8 as i32)
+}
+fn read_u16(code: &[u8], pc: &mut usize) -> MResult<usize> {
+    let b = code.get(*pc..*pc + 2).ok_or(EmfrpResult::Panic)?;
+    *pc += 2;
+    Ok(u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+fn read_i16(code: &[u8], pc: &mut usize) -> MResult<i32> {
+    Ok(read_u16(code, pc)? as u16 as i16 as i32)
+}
+fn read_u32(code: &[u8], pc: &mut usize) -> MResult<u32> {
+    let b = code.get(*pc..*pc + 4).ok_or(EmfrpResult::Panic)?;
+    *pc += 4;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+fn read_i32(code: &[u8], pc: &mut usize) -> MResult<i32> {
+    Ok(read_u32(code, pc)? as i32)
+}
+fn jump(pc: usize, offset: i32) -> MResult<usize> {
+    let pc = pc as i64 + offset as i64;
+    if pc < 0 {
+        Err(EmfrpResult::Panic)
+    } else {
+        Ok(pc as usize)
+    }
+}
+fn get<T: Copy>(v: &[T], i: usize) -> MResult<T> {
+    v.get(i).copied().ok_or(EmfrpResult::Panic)
+}
+fn get_mut<T>(v: &mut [T], i: usize) -> MResult<&mut T> {
+    v.get_mut(i).ok_or(EmfrpResult::Panic)
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Machine {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![ZERO; STACK_SIZE],
+            sp: 0,
+            bp: 0,
+            frames: vec![],
+            node_last: vec![],
+            update: None,
+            exec_code: Rc::from(vec![]),
+            data: vec![],
+            func: vec![],
+            node_v: vec![],
+            action: vec![],
+            output_actions: vec![],
+            heap: vec![],
+            free_objs: vec![],
+            rc_carry: HashMap::new(),
+            uart: vec![],
+            n_io: 0,
+            staged: None,
+            code_limit: None,
+        }
+    }
+    pub fn set_code_limit(&mut self, limit: usize) {
+        self.code_limit = Some(limit);
+    }
+    pub fn add_input_node(&mut self, init: Value, driver: DevInput) {
+        self.node_v.push(init);
+        self.action.push(UpdAction::Dev(driver));
+        self.n_io += 1;
+    }
+    pub fn add_output_node(&mut self, init: Value, driver: OutputAction) {
+        self.output_actions.push(driver);
+        self.node_v.push(init);
+        self.action.push(UpdAction::None);
+        self.n_io += 1;
+    }
+    // forgets everything but the input and output nodes, as the machine does after restarting
+    pub fn reset(&mut self) {
+        self.node_v.truncate(self.n_io);
+        self.node_v.fill(ZERO);
+        self.action.truncate(self.n_io);
+        for a in &mut self.action {
+            if let UpdAction::Insns(_) = a {
+                *a = UpdAction::None;
+            }
+        }
+        self.sp = 0;
+        self.bp = 0;
+        self.frames.clear();
+        self.node_last.clear();
+        self.update = None;
+        self.exec_code = Rc::from(vec![]);
+        self.data.clear();
+        self.func.clear();
+        self.heap.clear();
+        self.free_objs.clear();
+        self.rc_carry.clear();
+        self.uart.clear();
+        self.staged = None;
+    }
+    // bytes the machine has written to uart so far
+    pub fn take_uart(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.uart)
+    }
+    pub fn node_value(&self, i: usize) -> Option<Value> {
+        self.node_v.get(i).copied()
+    }
+    pub fn data_value(&self, i: usize) -> Option<Value> {
+        self.data.get(i).copied()
+    }
+    pub fn obj_header(&self, v: Value) -> Option<u32> {
+        match v {
+            Value::Obj(i) => self.heap.get(i)?.as_ref().map(|o| o.header.0),
+            _ => None,
+        }
+    }
+    pub fn obj_fields(&self, v: Value) -> Option<&[Value]> {
+        match v {
+            Value::Obj(i) => self.heap.get(i)?.as_ref().map(|o| &o.fields[..]),
+            _ => None,
+        }
+    }
+    pub fn live_objects(&self) -> usize {
+        self.heap.len() - self.free_objs.len()
+    }
+
+    // evaluates an expression, or stages and commits definitions at once
+    pub fn new_bytecode(&mut self, data: &[u8]) -> EmfrpResult {
+        let res = match self.new_bytecode_inner(data) {
+            Ok(()) => EmfrpResult::Ok,
+            Err(e) => e,
+        };
+        self.uart.push(res as u8);
+        res
+    }
+    // keeps definitions without touching the running program, and writes the status
+    pub fn stage(&mut self, data: &[u8]) -> EmfrpResult {
+        self.staged = None;
+        // the same bytecode as new_bytecode, which is not an expression
+        let staged = match data.split_first() {
+            Some((0, data)) => self.stage_inner(data),
+            _ => Err(EmfrpResult::Panic),
+        };
+        let res = match staged {
+            Ok(staged) => {
+                self.staged = Some(staged);
+                EmfrpResult::Ok
+            }
+            Err(e) => e,
+        };
+        self.uart.push(res as u8);
+        res
+    }
+    // swaps in the staged definitions and runs their initialisation, and writes the status.
+    // as in machine.c, the new definitions stay in place if their initialisation fails
+    pub fn commit(&mut self) -> EmfrpResult {
+        let res = match self.commit_inner() {
+            Ok(()) => EmfrpResult::Ok,
+            Err(e) => e,
+        };
+        self.uart.push(res as u8);
+        res
+    }
+    // drops the staged definitions
+    pub fn abort(&mut self) -> EmfrpResult {
+        self.staged = None;
+        self.uart.push(EmfrpResult::Ok as u8);
+        EmfrpResult::Ok
+    }
+    // writes the value of a node or data in the same format as Print or PrintObj, or whether
+    // definitions are staged, and the status
+    pub fn query(&mut self, kind: u8, is_obj: bool, i: usize) -> EmfrpResult {
+        let res = match self.query_inner(kind, is_obj, i) {
+            Ok(()) => EmfrpResult::Ok,
+            Err(e) => e,
+        };
+        self.uart.push(res as u8);
+        res
+    }
+    // frees the slots of removed definitions after replacing the update code, and writes the status
+    pub fn remove(&mut self, data: &[u8]) -> EmfrpResult {
+        let res = match self.remove_inner(data) {
+            Ok(()) => EmfrpResult::Ok,
+            Err(e) => e,
+        };
+        self.uart.push(res as u8);
+        res
+    }
+    fn remove_inner(&mut self, data: &[u8]) -> MResult<()> {
+        let mut p = 0;
+        let num_last = read_u16(data, &mut p)?;
+        let n = read_u8(data, &mut p)?;
+        let mut slots = vec![];
+        for _ in 0..n {
+            let kind = read_u8(data, &mut p)?;
+            let is_obj = read_u8(data, &mut p)? != 0;
+            let i = read_u16(data, &mut p)?;
+            let valid = match kind {
+                QUERY_NODE => self.n_io <= i && i < self.node_v.len(),
+                QUERY_DATA => i < self.data.len(),
+                REMOVE_FUNC => i < self.func.len(),
+                _ => false,
+            };
+            if !valid {
+                return Err(EmfrpResult::Panic);
+            }
+            slots.push((kind, is_obj, i));
+        }
+        // every slot is checked before any is freed, so that all of them are removed or none is
+        if slots.is_empty() || p == data.len() {
+            return Err(EmfrpResult::Panic);
+        }
+        self.update = Some(Rc::from(&data[p..]));
+        self.node_last = vec![ZERO; num_last];
+        for (kind, is_obj, i) in slots {
+            let v = match kind {
+                QUERY_NODE => {
+                    self.action[i] = UpdAction::None;
+                    std::mem::replace(&mut self.node_v[i], ZERO)
+                }
+                QUERY_DATA => std::mem::replace(&mut self.data[i], ZERO),
+                _ => {
+                    self.func[i] = Rc::from(vec![]);
+                    ZERO
+                }
+            };
+            if is_obj {
+                self.drop_obj(v)?;
+            }
+        }
+        Ok(())
+    }
+    fn query_inner(&mut self, kind: u8, is_obj: bool, i: usize) -> MResult<()> {
+        let v = match kind {
+            QUERY_NODE => get(&self.node_v, i)?,
+            QUERY_DATA => get(&self.data, i)?,
+            QUERY_STAGED => {
+                self.uart.push(self.staged.is_some() as u8);
+                return Ok(());
+            }
+            _ => return Err(EmfrpResult::Panic),
+        };
+        if is_obj {
+            let size = self.object_size(v)?;
+            self.uart.extend(size.to_le_bytes());
+            self.uart_write_object(v)
+        } else {
+            match v {
+                Value::Num(n) => {
+                    self.uart.extend(4u16.to_le_bytes());
+                    self.uart.extend(n.to_le_bytes());
+                    Ok(())
+                }
+                _ => Err(EmfrpResult::Panic),
+            }
+        }
+    }
+    fn new_bytecode_inner(&mut self, data: &[u8]) -> MResult<()> {
+        let mut p = 0;
+        let is_eval = read_u8(data, &mut p)?;
+        if is_eval != 0 {
+            self.exec_code = Rc::from(&data[p..]);
+            return self.exec(Segment::Exec);
+        }
+        let staged = self.stage_inner(&data[p..])?;
+        self.staged = Some(staged);
+        self.commit_inner()
+    }
+    fn stage_inner(&self, data: &[u8]) -> MResult<Staged> {
+        let mut p = 0;
+        let exp_len = read_u16(data, &mut p)?;
+        let upd_len = read_u16(data, &mut p)?;
+        let num_last = read_u16(data, &mut p)?;
+        let n_node = read_u16(data, &mut p)?;
+        let n_func = read_u16(data, &mut p)?;
+        let _new_nodes = read_u16(data, &mut p)?;
+        let _new_func = read_u16(data, &mut p)?;
+        let new_data = read_u16(data, &mut p)?;
+        // a body is placed in a slot, or right after the last one
+        let mut n = self.node_v.len();
+        let mut nodes = vec![];
+        for _ in 0..n_node {
+            let offset = read_u16(data, &mut p)?;
+            let body = Self::read_body(data, &mut p)?;
+            if offset > n {
+                return Err(EmfrpResult::Panic);
+            }
+            n += (offset == n) as usize;
+            nodes.push((offset, body));
+        }
+        let mut n = self.func.len();
+        let mut funcs = vec![];
+        for _ in 0..n_func {
+            let offset = read_u16(data, &mut p)?;
+            let body = Self::read_body(data, &mut p)?;
+            if offset > n {
+                return Err(EmfrpResult::Panic);
+            }
+            n += (offset == n) as usize;
+            funcs.push((offset, body));
+        }
+        let update = if upd_len > 0 {
+            let update = data.get(p..p + upd_len).ok_or(EmfrpResult::Panic)?;
+            p += upd_len;
+            Some(Rc::from(update))
+        } else {
+            None
+        };
+        let init = data.get(p..p + exp_len).ok_or(EmfrpResult::Panic)?;
+        let staged = Staged {
+            num_last,
+            new_data,
+            nodes,
+            funcs,
+            update,
+            init: Rc::from(init),
+        };
+        // the running program is kept until the commit, so both have to fit
+        match self.code_limit {
+            Some(limit) if self.code_size() + staged.size() > limit => {
+                Err(EmfrpResult::OutOfMemory)
+            }
+            _ => Ok(staged),
+        }
+    }
+    fn commit_inner(&mut self) -> MResult<()> {
+        let staged = self.staged.take().ok_or(EmfrpResult::Panic)?;
+        self.node_last = vec![ZERO; staged.num_last];
+        self.data.extend(std::iter::repeat_n(ZERO, staged.new_data));
+        for (offset, body) in staged.nodes {
+            if offset < self.node_v.len() {
+                self.action[offset] = UpdAction::Insns(body);
+            } else {
+                self.node_v.push(ZERO);
+                self.action.push(UpdAction::Insns(body));
+            }
+        }
+        for (offset, body) in staged.funcs {
+            if offset < self.func.len() {
+                self.func[offset] = body;
+            } else {
+                self.func.push(body);
+            }
+        }
+        if staged.update.is_some() {
+            self.update = staged.update;
+        }
+        if staged.init.is_empty() {
+            Ok(())
+        } else {
+            self.exec_code = staged.init;
+            self.exec(Segment::Exec)
+        }
+    }
+    fn code_size(&self) -> usize {
+        let nodes = self.action.iter().map(|a| match a {
+            UpdAction::Insns(insns) => insns.len(),
+            _ => 0,
+        });
+        nodes.sum::<usize>()
+            + self.func.iter().map(|f| f.len()).sum::<usize>()
+            + self.update.as_ref().map_or(0, |u| u.len())
+    }
+    fn read_body(data: &[u8], p: &mut usize) -> MResult<Rc<[u8]>> {
+        let len = read_u16(data, p)?;
+        let body = data.get(*p..*p + len).ok_or(EmfrpResult::Panic)?;
+        *p += len;
+        Ok(Rc::from(body))
+    }
+    pub fn update(&mut self) -> EmfrpResult {
+        if self.update.is_none() {
+            return EmfrpResult::Ok;
+        }
+        match self.exec(Segment::Update) {
+            Ok(()) => EmfrpResult::Ok,
+            Err(e) => e,
+        }
+    }
+
+    fn code(&self, seg: Segment) -> MResult<Rc<[u8]>> {
+        match seg {
+            Segment::Exec => Ok(self.exec_code.clone()),
+            Segment::Update => self.update.clone().ok_or(EmfrpResult::Panic),
+            Segment::Node(i) => match self.action.get(i) {
+                Some(UpdAction::Insns(insns)) => Ok(insns.clone()),
+                _ => Err(EmfrpResult::Panic),
+            },
+            Segment::Func(i) => self.func.get(i).cloned().ok_or(EmfrpResult::Panic),
+        }
+    }
+    fn push(&mut self, v: Value) -> MResult<()> {
+        *get_mut(&mut self.stack, self.sp)? = v;
+        self.sp += 1;
+        Ok(())
+    }
+    fn pop(&mut self) -> MResult<Value> {
+        if self.sp == 0 {
+            return Err(EmfrpResult::Panic);
+        }
+        self.sp -= 1;
+        Ok(self.stack[self.sp])
+    }
+    fn pop_num(&mut self) -> MResult<i32> {
+        match self.pop()? {
+            Value::Num(i) => Ok(i),
+            _ => Err(EmfrpResult::Panic),
+        }
+    }
+    fn push_num(&mut self, i: i32) -> MResult<()> {
+        self.push(Value::Num(i))
+    }
+    fn local(&self, i: i32) -> MResult<usize> {
+        let i = self.bp as i64 + i as i64;
+        if i < 0 || i >= STACK_SIZE as i64 {
+            Err(EmfrpResult::Panic)
+        } else {
+            Ok(i as usize)
+        }
+    }
+    fn obj(&self, v: Value) -> MResult<&Obj> {
+        match v {
+            Value::Obj(i) => match self.heap.get(i) {
+                Some(Some(o)) => Ok(o),
+                _ => Err(EmfrpResult::Panic),
+            },
+            _ => Err(EmfrpResult::Panic),
+        }
+    }
+    fn obj_mut(&mut self, v: Value) -> MResult<&mut Obj> {
+        match v {
+            Value::Obj(i) => match self.heap.get_mut(i) {
+                Some(Some(o)) => Ok(o),
+                _ => Err(EmfrpResult::Panic),
+            },
+            _ => Err(EmfrpResult::Panic),
+        }
+    }
+    fn alloc_obj(&mut self, header: ObjHeader, fields: Vec<Value>) -> Value {
+        let obj = Obj { header, fields };
+        match self.free_objs.pop() {
+            Some(i) => {
+                self.heap[i] = Some(obj);
+                Value::Obj(i)
+            }
+            None => {
+                self.heap.push(Some(obj));
+                Value::Obj(self.heap.len() - 1)
+            }
+        }
+    }
+    fn inc_rc(&mut self, v: Value) -> MResult<()> {
+        let o = self.obj_mut(v)?;
+        if obj_rc(o.header.0) != OBJ_RC_MAX {
+            o.header.0 += 1;
+            return Ok(());
+        }
+        // the header counts from 1 again
+        o.header.0 = (o.header.0 - OBJ_RC_MAX + 1) | OBJ_RC_CARRY;
+        if let Value::Obj(i) = v {
+            *self.rc_carry.entry(i).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+    fn drop_obj(&mut self, v: Value) -> MResult<()> {
+        if v == ZERO {
+            return Ok(());
+        }
+        let o = self.obj_mut(v)?;
+        if obj_rc(o.header.0) == 1 && o.header.0 & OBJ_RC_CARRY != 0 {
+            o.header.0 += OBJ_RC_MAX - 1;
+            if let Value::Obj(i) = v {
+                let carry = self.rc_carry.get_mut(&i).ok_or(EmfrpResult::Panic)?;
+                *carry -= 1;
+                if *carry == 0 {
+                    self.rc_carry.remove(&i);
+                    self.obj_mut(v)?.header.0 &= !OBJ_RC_CARRY;
+                }
+            }
+            return Ok(());
+        }
+        o.header.0 = o.header.0.wrapping_sub(1);
+        if obj_rc(o.header.0) != 0 {
+            return Ok(());
+        }
+        let header = o.header.clone();
+        let fields = std::mem::take(&mut o.fields);
+        if let Value::Obj(i) = v {
+            self.heap[i] = None;
+            self.free_objs.push(i);
+        }
+        for (i, f) in fields.into_iter().enumerate().take(header.n_entry()) {
+            if header.objbit(i) {
+                self.drop_obj(f)?;
+            }
+        }
+        Ok(())
+    }
+    fn object_size(&self, v: Value) -> MResult<u16> {
+        let o = self.obj(v)?;
+        let mut ret = 4 * (1 + o.header.1.len() as u16);
+        for i in 0..o.header.n_entry() {
+            if o.header.objbit(i) {
+                ret = ret.wrapping_add(self.object_size(get(&o.fields, i)?)?);
+            } else {
+                ret = ret.wrapping_add(4);
+            }
+        }
+        Ok(ret)
+    }
+    fn uart_write_object(&mut self, v: Value) -> MResult<()> {
+        let o = self.obj(v)?;
+        let header = o.header.clone();
+        let fields = o.fields.clone();
+        for w in header.words() {
+            self.uart.extend(w.to_le_bytes());
+        }
+        for i in 0..header.n_entry() {
+            let f = get(&fields, i)?;
+            if header.objbit(i) {
+                self.uart_write_object(f)?;
+            } else {
+                match f {
+                    Value::Num(n) => self.uart.extend(n.to_le_bytes()),
+                    _ => return Err(EmfrpResult::Panic),
+                }
+            }
+        }
+        Ok(())
+    }
+    fn binop(&mut self, f: impl Fn(i32, i32) -> MResult<i32>) -> MResult<()> {
+        let b = self.pop_num()?;
+        let a = self.pop_num()?;
+        self.push_num(f(a, b)?)
+    }
+    fn pop_float(&mut self) -> MResult<f32> {
+        Ok(f32::from_bits(self.pop_num()? as u32))
+    }
+    fn push_float(&mut self, f: f32) -> MResult<()> {
+        self.push_num(f.to_bits() as i32)
+    }
+    fn float_binop(&mut self, f: impl Fn(f32, f32) -> f32) -> MResult<()> {
+        let b = self.pop_float()?;
+        let a = self.pop_float()?;
+        self.push_float(f(a, b))
+    }
+    fn float_cmp(&mut self, f: impl Fn(f32, f32) -> bool) -> MResult<()> {
+        let b = self.pop_float()?;
+        let a = self.pop_float()?;
+        self.push_num(f(a, b) as i32)
+    }
+    fn call(&mut self, nargs: u8, f: usize, next_ip: Ip) -> MResult<Ip> {
+        let sp = self
+            .sp
+            .checked_sub(nargs as usize)
+            .ok_or(EmfrpResult::Panic)?;
+        self.frames.push(Frame {
+            next_ip,
+            bp: self.bp,
+            sp,
+        });
+        self.bp = sp;
+        if f >= self.func.len() {
+            return Err(EmfrpResult::Panic);
+        }
+        Ok(Ip {
+            seg: Segment::Func(f),
+            pc: 0,
+        })
+    }
+    fn update_node(&mut self, i: usize, next_ip: Ip) -> MResult<Ip> {
+        self.push(Value::Ip(next_ip))?;
+        Ok(Ip {
+            seg: Segment::Node(i),
+            pc: 0,
+        })
+    }
+    fn end_update_node(&mut self, i: usize, is_obj: bool) -> MResult<Ip> {
+        if is_obj {
+            let old = get(&self.node_v, i)?;
+            self.drop_obj(old)?;
+        }
+        let v = self.pop()?;
+        *get_mut(&mut self.node_v, i)? = v;
+        match self.pop()? {
+            Value::Ip(ip) => Ok(ip),
+            _ => Err(EmfrpResult::Panic),
+        }
+    }
+    fn alloc_obj_insn(&mut self, max: usize, header: ObjHeader) -> MResult<()> {
+        let n = header.n_entry();
+        if n > max {
+            return Err(EmfrpResult::Panic);
+        }
+        let mut fields = vec![ZERO; max];
+        for i in 0..n {
+            fields[n - 1 - i] = self.pop()?;
+        }
+        let v = self.alloc_obj(header, fields);
+        self.push(v)
+    }
+    fn obj_field(&mut self, i: usize, is_ref: bool) -> MResult<()> {
+        let o = self.pop()?;
+        let v = get(&self.obj(o)?.fields, i)?;
+        if is_ref {
+            self.inc_rc(v)?;
+        }
+        self.push(v)
+    }
+    fn get_ref(&mut self, v: Value) -> MResult<()> {
+        self.inc_rc(v)?;
+        self.push(v)
+    }
+    fn set_local(&mut self, i: i32, is_ref: bool) -> MResult<()> {
+        let i = self.local(i)?;
+        if is_ref {
+            self.drop_obj(self.stack[i])?;
+        }
+        self.stack[i] = self.pop()?;
+        Ok(())
+    }
+    fn set_last(&mut self, i: usize, is_ref: bool) -> MResult<()> {
+        if is_ref {
+            self.drop_obj(get(&self.node_last, i)?)?;
+        }
+        let v = self.pop()?;
+        *get_mut(&mut self.node_last, i)? = v;
+        Ok(())
+    }
+    fn set_node(&mut self, i: usize, is_ref: bool) -> MResult<()> {
+        if is_ref {
+            self.drop_obj(get(&self.node_v, i)?)?;
+        }
+        let v = self.pop()?;
+        *get_mut(&mut self.node_v, i)? = v;
+        Ok(())
+    }
+    fn set_data(&mut self, i: usize, is_ref: bool) -> MResult<()> {
+        if is_ref {
+            self.drop_obj(get(&self.data, i)?)?;
+        }
+        let v = self.pop()?;
+        *get_mut(&mut self.data, i)? = v;
+        Ok(())
+    }
+    fn update_dev(&mut self, i: usize) -> MResult<()> {
+        let v = get_mut(&mut self.node_v, i)?;
+        match self.action.get_mut(i) {
+            Some(UpdAction::Dev(driver)) => {
+                driver(v);
+                Ok(())
+            }
+            _ => Err(EmfrpResult::Panic),
+        }
+    }
+    fn output_action(&mut self, i: usize) -> MResult<()> {
+        let v = get(
+            &self.stack,
+            self.sp.checked_sub(1).ok_or(EmfrpResult::Panic)?,
+        )?;
+        let action = get_mut(&mut self.output_actions, i)?;
+        action(&v);
+        Ok(())
+    }
+
+    fn exec(&mut self, seg: Segment) -> MResult<()> {
+        self.sp = 0;
+        self.bp = 0;
+        self.frames.clear();
+        let mut seg = seg;
+        let mut code = self.code(seg)?;
+        let mut pc = 0;
+        loop {
+            let op = read_u8(&code, &mut pc)?;
+            // ip after the immediate values of insns that jump into other segments
+            let next: Option<Ip> = match op {
+                NIL => {
+                    self.push(ZERO)?;
+                    None
+                }
+                NOT => {
+                    let i = self.pop_num()?;
+                    self.push_num((i == 0) as i32)?;
+                    None
+                }
+                MINUS => {
+                    let i = self.pop_num()?;
+                    self.push_num(i.wrapping_neg())?;
+                    None
+                }
+                ADD => {
+                    self.binop(|a, b| Ok(a.wrapping_add(b)))?;
+                    None
+                }
+                SUB => {
+                    self.binop(|a, b| Ok(a.wrapping_sub(b)))?;
+                    None
+                }
+                MUL => {
+                    self.binop(|a, b| Ok(a.wrapping_mul(b)))?;
+                    None
+                }
+                DIV => {
+                    self.binop(|a, b| a.checked_div(b).ok_or(EmfrpResult::Panic))?;
+                    None
+                }
+                MOD => {
+                    self.binop(|a, b| a.checked_rem(b).ok_or(EmfrpResult::Panic))?;
+                    None
+                }
+                SHIFT_L => {
+                    self.binop(|a, b| Ok(a.wrapping_shl(b as u32)))?;
+                    None
+                }
+                SHIFT_R => {
+                    self.binop(|a, b| Ok(a.wrapping_shr(b as u32)))?;
+                    None
+                }
+                LS => {
+                    self.binop(|a, b| Ok((a < b) as i32))?;
+                    None
+                }
+                LEQ => {
+                    self.binop(|a, b| Ok((a <= b) as i32))?;
+                    None
+                }
+                GT => {
+                    self.binop(|a, b| Ok((a > b) as i32))?;
+                    None
+                }
+                GEQ => {
+                    self.binop(|a, b| Ok((a >= b) as i32))?;
+                    None
+                }
+                EQ => {
+                    self.binop(|a, b| Ok((a == b) as i32))?;
+                    None
+                }
+                NEQ => {
+                    self.binop(|a, b| Ok((a != b) as i32))?;
+                    None
+                }
+                BIT_AND => {
+                    self.binop(|a, b| Ok(a & b))?;
+                    None
+                }
+                BIT_OR => {
+                    self.binop(|a, b| Ok(a | b))?;
+                    None
+                }
+                BIT_XOR => {
+                    self.binop(|a, b| Ok(a ^ b))?;
+                    None
+                }
+                RETURN => {
+                    let v = self.pop()?;
+                    let frame = self.frames.pop().ok_or(EmfrpResult::Panic)?;
+                    self.bp = frame.bp;
+                    self.sp = frame.sp;
+                    self.push(v)?;
+                    Some(frame.next_ip)
+                }
+                PRINT => {
+                    let i = self.pop_num()?;
+                    self.uart.extend(4u16.to_le_bytes());
+                    self.uart.extend(i.to_le_bytes());
+                    None
+                }
+                PRINT_OBJ => {
+                    let v = self.pop()?;
+                    let size = self.object_size(v)?;
+                    self.uart.extend(size.to_le_bytes());
+                    self.uart_write_object(v)?;
+                    self.drop_obj(v)?;
+                    None
+                }
+                HALT => {
+                    if self.sp != 0 {
+                        return Err(EmfrpResult::Panic);
+                    }
+                    return Ok(());
+                }
+                PEEK => {
+                    let v = get(
+                        &self.stack,
+                        self.sp.checked_sub(1).ok_or(EmfrpResult::Panic)?,
+                    )?;
+                    self.push(v)?;
+                    None
+                }
+                PUSH_TRUE => {
+                    self.push_num(1)?;
+                    None
+                }
+                PUSH_FALSE => {
+                    self.push_num(0)?;
+                    None
+                }
+                ABORT => return Err(EmfrpResult::RuntimeErr),
+                INT_0..=INT_6 => {
+                    self.push_num((op - INT_0) as i32)?;
+                    None
+                }
+                INT_I8 => {
+                    let i = read_i8(&code, &mut pc)?;
+                    self.push_num(i)?;
+                    None
+                }
+                INT_I16 => {
+                    let i = read_i16(&code, &mut pc)?;
+                    self.push_num(i)?;
+                    None
+                }
+                INT_I32 => {
+                    let i = read_i32(&code, &mut pc)?;
+                    self.push_num(i)?;
+                    None
+                }
+                GET_LOCAL_0..=GET_LOCAL_I32 => {
+                    let i = self.local_operand(op - GET_LOCAL_0, &code, &mut pc)?;
+                    let v = self.stack[self.local(i)?];
+                    self.push(v)?;
+                    None
+                }
+                SET_LOCAL_0..=SET_LOCAL_I32 => {
+                    let i = self.local_operand(op - SET_LOCAL_0, &code, &mut pc)?;
+                    self.set_local(i, false)?;
+                    None
+                }
+                ALLOC_LOCAL_1..=ALLOC_LOCAL_U32 => {
+                    let n = match op {
+                        ALLOC_LOCAL_1..=ALLOC_LOCAL_6 => (op + 1 - ALLOC_LOCAL_1) as usize,
+                        ALLOC_LOCAL_U8 => read_u8(&code, &mut pc)? as usize,
+                        ALLOC_LOCAL_U16 => read_u16(&code, &mut pc)?,
+                        _ => read_u32(&code, &mut pc)? as usize,
+                    };
+                    if self.sp + n > STACK_SIZE {
+                        return Err(EmfrpResult::Panic);
+                    }
+                    // uninitialized in machine.c; cleared here so that the result is deterministic
+                    for v in &mut self.stack[self.sp..self.sp + n] {
+                        *v = ZERO;
+                    }
+                    self.sp += n;
+                    None
+                }
+                POP_0..=POP_U32 => {
+                    let n = match op {
+                        POP_0..=POP_6 => (op - POP_0) as usize,
+                        POP_U8 => read_u8(&code, &mut pc)? as usize,
+                        POP_U16 => read_u16(&code, &mut pc)?,
+                        _ => read_u32(&code, &mut pc)? as usize,
+                    };
+                    self.sp = self.sp.checked_sub(n).ok_or(EmfrpResult::Panic)?;
+                    None
+                }
+                JNE8..=J32 => {
+                    let offset = match op {
+                        JNE8 | JE8 | J8 => read_i8(&code, &mut pc)?,
+                        JNE16 | JE16 | J16 => read_i16(&code, &mut pc)?,
+                        _ => read_i32(&code, &mut pc)?,
+                    };
+                    let cond = match op {
+                        JNE8..=JNE32 => self.pop_num()? == 0,
+                        JE8..=JE32 => self.pop_num()? != 0,
+                        _ => true,
+                    };
+                    if cond {
+                        pc = jump(pc, offset)?;
+                    }
+                    None
+                }
+                GET_LAST_0..=GET_LAST_U32 => {
+                    let i = self.last_operand(op - GET_LAST_0, &code, &mut pc)?;
+                    let v = get(&self.node_last, i)?;
+                    self.push(v)?;
+                    None
+                }
+                SET_NODE_U8..=SET_NODE_U32 => {
+                    let i = self.unsigned_operand(op - SET_NODE_U8, &code, &mut pc)?;
+                    self.set_node(i, false)?;
+                    None
+                }
+                OBJ_FIELD_0..=OBJ_FIELD_U8 => {
+                    let i = match op {
+                        OBJ_FIELD_0..=OBJ_FIELD_6 => (op - OBJ_FIELD_0) as usize,
+                        _ => read_u8(&code, &mut pc)? as usize,
+                    };
+                    self.obj_field(i, false)?;
+                    None
+                }
+                UPD_DEV_0..=UPD_DEV_U8 => {
+                    let i = match op {
+                        UPD_DEV_0..=UPD_DEV_3 => (op - UPD_DEV_0) as usize,
+                        _ => read_u8(&code, &mut pc)? as usize,
+                    };
+                    self.update_dev(i)?;
+                    None
+                }
+                UPD_NODE_U8..=UPD_NODE_U32 => {
+                    let i = self.unsigned_operand(op - UPD_NODE_U8, &code, &mut pc)?;
+                    Some(self.update_node(i, Ip { seg, pc })?)
+                }
+                O_ACTION_0..=O_ACTION_U8 => {
+                    let i = match op {
+                        O_ACTION_0..=O_ACTION_3 => (op - O_ACTION_0) as usize,
+                        _ => read_u8(&code, &mut pc)? as usize,
+                    };
+                    self.output_action(i)?;
+                    None
+                }
+                CALL_U8..=CALL_U32 => {
+                    let nargs = read_u8(&code, &mut pc)?;
+                    let f = self.unsigned_operand(op - CALL_U8, &code, &mut pc)?;
+                    Some(self.call(nargs, f, Ip { seg, pc })?)
+                }
+                GET_DATA_U8..=GET_DATA_U32 => {
+                    let i = self.unsigned_operand(op - GET_DATA_U8, &code, &mut pc)?;
+                    let v = get(&self.data, i)?;
+                    self.push(v)?;
+                    None
+                }
+                GET_NODE_U8..=GET_NODE_U32 => {
+                    let i = self.unsigned_operand(op - GET_NODE_U8, &code, &mut pc)?;
+                    let v = get(&self.node_v, i)?;
+                    self.push(v)?;
+                    None
+                }
+                F_ADD => {
+                    self.float_binop(|a, b| a + b)?;
+                    None
+                }
+                F_SUB => {
+                    self.float_binop(|a, b| a - b)?;
+                    None
+                }
+                F_MUL => {
+                    self.float_binop(|a, b| a * b)?;
+                    None
+                }
+                F_DIV => {
+                    self.float_binop(|a, b| a / b)?;
+                    None
+                }
+                F_MINUS => {
+                    let f = self.pop_float()?;
+                    self.push_float(-f)?;
+                    None
+                }
+                SET_DATA_U8..=SET_DATA_U32 => {
+                    let i = self.unsigned_operand(op - SET_DATA_U8, &code, &mut pc)?;
+                    self.set_data(i, false)?;
+                    None
+                }
+                OBJ_TAG => {
+                    let o = self.pop()?;
+                    let tag = self.obj(o)?.header.tag();
+                    self.push_num(tag as i32)?;
+                    None
+                }
+                F_LS => {
+                    self.float_cmp(|a, b| a < b)?;
+                    None
+                }
+                F_LEQ => {
+                    self.float_cmp(|a, b| a <= b)?;
+                    None
+                }
+                F_GT => {
+                    self.float_cmp(|a, b| a > b)?;
+                    None
+                }
+                F_GEQ => {
+                    self.float_cmp(|a, b| a >= b)?;
+                    None
+                }
+                F_EQ => {
+                    self.float_cmp(|a, b| a == b)?;
+                    None
+                }
+                SET_LAST_0..=SET_LAST_U32 => {
+                    let i = self.last_operand(op - SET_LAST_0, &code, &mut pc)?;
+                    self.set_last(i, false)?;
+                    None
+                }
+                END_UPD_U8..=END_UPD_U32 => {
+                    let i = self.unsigned_operand(op - END_UPD_U8, &code, &mut pc)?;
+                    Some(self.end_update_node(i, false)?)
+                }
+                ALLOC_OBJ_0..=ALLOC_OBJ_U8 => {
+                    let max = match op {
+                        ALLOC_OBJ_0..=ALLOC_OBJ_6 => (op - ALLOC_OBJ_0) as usize,
+                        _ => read_u8(&code, &mut pc)? as usize,
+                    };
+                    let header = ObjHeader::read(|| read_u32(&code, &mut pc))?;
+                    self.alloc_obj_insn(max, header)?;
+                    None
+                }
+                F_NEQ => {
+                    self.float_cmp(|a, b| a != b)?;
+                    None
+                }
+                FLOAT => {
+                    let bits = read_u32(&code, &mut pc)?;
+                    self.push_num(bits as i32)?;
+                    None
+                }
+                DROP_LOCAL_OBJ_0..=DROP_LOCAL_OBJ_I32 => {
+                    let i = self.local_operand(op - DROP_LOCAL_OBJ_0, &code, &mut pc)?;
+                    let v = self.stack[self.local(i)?];
+                    self.drop_obj(v)?;
+                    None
+                }
+                GET_LOCAL_REF_0..=GET_LOCAL_REF_I32 => {
+                    let i = self.local_operand(op - GET_LOCAL_REF_0, &code, &mut pc)?;
+                    let v = self.stack[self.local(i)?];
+                    self.get_ref(v)?;
+                    None
+                }
+                SET_LOCAL_REF_0..=SET_LOCAL_REF_I32 => {
+                    let i = self.local_operand(op - SET_LOCAL_REF_0, &code, &mut pc)?;
+                    self.set_local(i, true)?;
+                    None
+                }
+                OBJ_FIELD_REF_0..=OBJ_FIELD_REF_U8 => {
+                    let i = match op {
+                        OBJ_FIELD_REF_0..=OBJ_FIELD_REF_6 => (op - OBJ_FIELD_REF_0) as usize,
+                        _ => read_u8(&code, &mut pc)? as usize,
+                    };
+                    self.obj_field(i, true)?;
+                    None
+                }
+                END_UPD_OBJ_U8..=END_UPD_OBJ_U32 => {
+                    let i = self.unsigned_operand(op - END_UPD_OBJ_U8, &code, &mut pc)?;
+                    Some(self.end_update_node(i, true)?)
+                }
+                GET_NODE_REF_U8..=GET_NODE_REF_U32 => {
+                    let i = self.unsigned_operand(op - GET_NODE_REF_U8, &code, &mut pc)?;
+                    self.get_ref(get(&self.node_v, i)?)?;
+                    None
+                }
+                GET_DATA_REF_U8..=GET_DATA_REF_U32 => {
+                    let i = self.unsigned_operand(op - GET_DATA_REF_U8, &code, &mut pc)?;
+                    self.get_ref(get(&self.data, i)?)?;
+                    None
+                }
+                GET_LAST_REF_0..=GET_LAST_REF_U32 => {
+                    let i = self.last_operand(op - GET_LAST_REF_0, &code, &mut pc)?;
+                    self.get_ref(get(&self.node_last, i)?)?;
+                    None
+                }
+                SET_DATA_REF_U8..=SET_DATA_REF_U32 => {
+                    let i = self.unsigned_operand(op - SET_DATA_REF_U8, &code, &mut pc)?;
+                    self.set_data(i, true)?;
+                    None
+                }
+                SET_LAST_REF_0..=SET_LAST_REF_U32 => {
+                    let i = self.last_operand(op - SET_LAST_REF_0, &code, &mut pc)?;
+                    self.set_last(i, true)?;
+                    None
+                }
+                SET_NODE_REF_U8..=SET_NODE_REF_U32 => {
+                    let i = self.unsigned_operand(op - SET_NODE_REF_U8, &code, &mut pc)?;
+                    self.set_node(i, true)?;
+                    None
+                }
+                DROP_LAST_U8..=DROP_LAST_U32 => {
+                    let i = self.unsigned_operand(op - DROP_LAST_U8, &code, &mut pc)?;
+                    self.drop_obj(get(&self.node_last, i)?)?;
+                    None
+                }
+                J0 | JE0 | JNE0 => {
+                    if op != J0 {
+                        self.pop_num()?;
+                    }
+                    None
+                }
+                J1 => {
+                    pc += 1;
+                    None
+                }
+                JE1 => {
+                    if self.pop_num()? != 0 {
+                        pc += 1;
+                    }
+                    None
+                }
+                JNE1 => {
+                    if self.pop_num()? == 0 {
+                        pc += 1;
+                    }
+                    None
+                }
+                INT_TO_FLOAT => {
+                    let i = self.pop_num()?;
+                    self.push_float(i as f32)?;
+                    None
+                }
+                FLOAT_TO_INT => {
+                    // truncated toward zero. out of range values are saturated and NaN is 0
+                    let f = self.pop_float()?;
+                    self.push_num(f as i32)?;
+                    None
+                }
+                _ => return Err(EmfrpResult::Todo),
+            };
+            if let Some(ip) = next {
+                if ip.seg != seg {
+                    code = self.code(ip.seg)?;
+                    seg = ip.seg;
+                }
+                pc = ip.pc;
+            }
+        }
+    }
+    // operand of insns encoded as 0,1,2,3,4,5,6,i8,i16,i32
+    fn local_operand(&self, k: u8, code: &[u8], pc: &mut usize) -> MResult<i32> {
+        match k {
+            0..=6 => Ok(k as i32),
+            7 => read_i8(code, pc),
+            8 => read_i16(code, pc),
+            _ => read_i32(code, pc),
+        }
+    }
+    // operand of insns encoded as 0,1,2,3,u8,u16,u32
+    fn last_operand(&self, k: u8, code: &[u8], pc: &mut usize) -> MResult<usize> {
+        match k {
+            0..=3 => Ok(k as usize),
+            4 => Ok(read_u8(code, pc)? as usize),
+            5 => read_u16(code, pc),
+            _ => Ok(read_u32(code, pc)? as usize),
+        }
+    }
+    // operand of insns encoded as u8,u16,u32
+    fn unsigned_operand(&self, k: u8, code: &[u8], pc: &mut usize) -> MResult<usize> {
+        match k {
+            0 => Ok(read_u8(code, pc)? as usize),
+            1 => read_u16(code, pc),
+            _ => Ok(read_u32(code, pc)? as usize),
+        }
+    }
+}
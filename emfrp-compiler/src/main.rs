@@ -1,40 +1,213 @@
 mod ast;
 mod parser;
+pub mod cli;
 pub mod compile {
+    #[allow(clippy::module_inception)]
     pub mod compile;
     pub mod compile_common;
     mod dependency;
     mod emit;
+    mod exhaustive;
+    mod generic;
+    mod info;
     mod typecheck;
     mod typed_ast;
     mod typeinfer;
 }
+pub mod diagnostic;
+pub mod disasm;
+pub mod graph;
+pub mod image;
 pub mod insn;
+pub mod machine;
+pub mod opcode;
+pub mod protocol;
 pub mod repl;
-lalrpop_mod!(grammer);
-use compile::compile_common::Type;
+pub mod sim;
+#[cfg(test)]
+mod tests;
+pub mod transport;
+lalrpop_mod!(#[allow(clippy::all)] grammer);
+use std::fs;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use cli::Command;
+use cli::Options;
+use compile::compile_common::CompiledCode;
+use compile::compile_common::Compiler;
+use image::Image;
 use lalrpop_util::lalrpop_mod;
+use parser::ParserWrapper;
 
 use crate::repl::*;
 
 use std::io::*;
-const UART_FILE: &str = "/dev/cu.usbserial-0001";
-//const UART_FILE: &str = "/dev/cu.usbmodem142101";
-const BAUD_RATE: u32 = 115200;
-const DEBUG: bool = true;
+static DEBUG: AtomicBool = AtomicBool::new(false);
+pub fn debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
 
 fn main() {
-    let mut repl = match Repl::new() {
-        Ok(r) => r,
-        Err(s) => {
-            println!("{s}");
-            return;
+    let opts = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            println!("{e}");
+            println!("{}", cli::USAGE);
+            std::process::exit(2);
         }
     };
+    DEBUG.store(opts.debug, Ordering::Relaxed);
+    if let Err(e) = run_command(&opts) {
+        println!("{:?}", e);
+        std::process::exit(1);
+    }
+}
 
-    repl.add_input_node("gpio16", Type::Bool);
-    repl.add_output_node("gpio5", Type::Bool);
-    //repl.add_output_node("led", Type::Bool);
+fn run_command(opts: &Options) -> Result<()> {
+    match &opts.command {
+        Command::Help => println!("{}", cli::USAGE),
+        Command::Repl => open_repl(opts)?.run(),
+        Command::Run(file) => {
+            let mut repl = open_repl(opts)?;
+            let res = if file.ends_with(".bin") {
+                repl.load_image(file)?
+            } else {
+                repl.load(file)?
+            };
+            println!("{res}");
+        }
+        Command::Compile(file) => {
+            let (cmp, code) = compile_file(opts, file)?;
+            let io = cmp.io_nodes();
+            let mut image = Image::new(io.inputs, io.outputs);
+            image.push(&code);
+            let base = file.strip_suffix(".mfrp").unwrap_or(file);
+            let out = match &opts.out {
+                Some(out) => out.clone(),
+                None => format!("{base}.bin"),
+            };
+            let header = match &opts.header {
+                Some(header) => header.clone(),
+                None => format!("{}.h", out.strip_suffix(".bin").unwrap_or(base)),
+            };
+            let bytes = image.to_bytes()?;
+            fs::write(&out, &bytes).with_context(|| format!("could not write {out}"))?;
+            fs::write(&header, image.to_c_header(file)?)
+                .with_context(|| format!("could not write {header}"))?;
+            println!("{out} : {}bytes", bytes.len());
+            println!("{header}");
+        }
+        Command::Disasm(file) if file.ends_with(".bin") => {
+            let bytes = fs::read(file).with_context(|| format!("could not read {file}"))?;
+            let image = Image::decode(&bytes).with_context(|| format!("could not load {file}"))?;
+            print!("{}", disasm::image_listing(&image)?);
+        }
+        Command::Disasm(file) => {
+            let (_, code) = compile_file(opts, file)?;
+            print!("{}", disasm::listing(&code));
+        }
+        Command::Graph(file) => {
+            let (cmp, _) = compile_file(opts, file)?;
+            let graph = if opts.json {
+                graph::to_json(&cmp)
+            } else {
+                graph::to_dot(&cmp)
+            };
+            let graph = graph.map_err(|e| anyhow!("error: {:?}", e))?;
+            match &opts.out {
+                Some(out) => {
+                    fs::write(out, graph).with_context(|| format!("could not write {out}"))?
+                }
+                None => print!("{graph}"),
+            }
+        }
+        Command::Sim(file) => {
+            let (cmp, code) = compile_file(opts, file)?;
+            let trace = match &opts.trace {
+                Some(trace) => sim::Trace::load(trace)?,
+                None => sim::Trace::default(),
+            };
+            let ticks = match (opts.ticks, &opts.trace) {
+                (Some(ticks), _) => ticks,
+                (None, Some(_)) => trace.rows.len(),
+                (None, None) => return Err(anyhow!("sim requires --trace or --ticks")),
+            };
+            let record = sim::simulate(&cmp, &code, &trace, ticks)?;
+            let text = if opts.json {
+                record.to_json()
+            } else {
+                record.to_csv()
+            };
+            match &opts.out {
+                Some(out) => {
+                    fs::write(out, text).with_context(|| format!("could not write {out}"))?
+                }
+                None => print!("{text}"),
+            }
+            for f in &record.failures {
+                println!(
+                    "tick {} : {} was expected to be {}, but was {}",
+                    f.tick, f.node, f.expected, f.actual
+                );
+            }
+            if !record.failures.is_empty() {
+                let n = record.failures.len();
+                return Err(anyhow!("{n} of the expected values did not match"));
+            }
+        }
+        Command::Asm(file) => {
+            let text =
+                fs::read_to_string(file).with_context(|| format!("could not read {file}"))?;
+            let mut image = Image::new(opts.inputs.clone(), opts.outputs.clone());
+            disasm::assemble(&text, &mut image)
+                .with_context(|| format!("could not assemble {file}"))?;
+            let out = match &opts.out {
+                Some(out) => out.clone(),
+                None => format!("{}.bin", file.strip_suffix(".asm").unwrap_or(file)),
+            };
+            let bytes = image.to_bytes()?;
+            fs::write(&out, &bytes).with_context(|| format!("could not write {out}"))?;
+            println!("{out} : {}bytes", bytes.len());
+        }
+    }
+    Ok(())
+}
+
+fn open_repl(opts: &Options) -> Result<Repl> {
+    let port = transport::open(&opts.port, opts.baud_rate)?;
+    let mut repl = Repl::new(port);
+    repl.set_timeout(opts.timeout);
+    // the machine registers input nodes before output nodes
+    for (name, typ) in &opts.inputs {
+        repl.add_input_node(name, typ.clone());
+    }
+    for (name, typ) in &opts.outputs {
+        repl.add_output_node(name, typ.clone());
+    }
+    Ok(repl)
+}
 
-    repl.run();
+fn compile_file(opts: &Options, file: &str) -> Result<(Compiler, CompiledCode)> {
+    let mut cmp = Compiler::new();
+    for (name, typ) in &opts.inputs {
+        cmp.add_input_node(name, typ.clone());
+    }
+    for (name, typ) in &opts.outputs {
+        cmp.add_output_node(name, typ.clone());
+    }
+    let mut parser = ParserWrapper::new();
+    let prog = parser.load_program(file, cmp.imported_modules())?;
+    let code = cmp
+        .compile(prog)
+        .map_err(|e| anyhow!(parser.sources().compile_error(&e)))?
+        .ok_or(anyhow!("{file} only contains type definitions"))?;
+    // stdout may be the output of the command
+    for (span, msg) in cmp.take_warnings() {
+        eprintln!("{}", parser.sources().warning(span, &msg));
+    }
+    Ok((cmp, code))
 }
@@ -0,0 +1,240 @@
+// opcodes of the machine, the same as `bytecode` in machine.h of the firmware
+// insn.rs encodes, disasm.rs decodes and machine.rs executes instructions with these names
+pub const NONE: u8 = 1;
+pub const NIL: u8 = 2;
+pub const NOT: u8 = 3;
+pub const MINUS: u8 = 4;
+pub const ADD: u8 = 5;
+pub const SUB: u8 = 6;
+pub const MUL: u8 = 7;
+pub const DIV: u8 = 8;
+pub const MOD: u8 = 9;
+pub const SHIFT_L: u8 = 10;
+pub const SHIFT_R: u8 = 11;
+pub const LS: u8 = 12;
+pub const LEQ: u8 = 13;
+pub const GT: u8 = 14;
+pub const GEQ: u8 = 15;
+pub const EQ: u8 = 16;
+pub const NEQ: u8 = 17;
+pub const BIT_AND: u8 = 18;
+pub const BIT_OR: u8 = 19;
+pub const BIT_XOR: u8 = 20;
+pub const RETURN: u8 = 21;
+pub const PRINT: u8 = 22;
+pub const PRINT_OBJ: u8 = 23;
+pub const HALT: u8 = 24;
+pub const PEEK: u8 = 25;
+pub const PUSH_TRUE: u8 = 26;
+pub const PUSH_FALSE: u8 = 27;
+pub const ABORT: u8 = 28;
+pub const INT_0: u8 = 30;
+pub const INT_1: u8 = 31;
+pub const INT_2: u8 = 32;
+pub const INT_3: u8 = 33;
+pub const INT_4: u8 = 34;
+pub const INT_5: u8 = 35;
+pub const INT_6: u8 = 36;
+pub const INT_I8: u8 = 37;
+pub const INT_I16: u8 = 38;
+pub const INT_I32: u8 = 39;
+pub const GET_LOCAL_0: u8 = 40;
+pub const GET_LOCAL_1: u8 = 41;
+pub const GET_LOCAL_2: u8 = 42;
+pub const GET_LOCAL_3: u8 = 43;
+pub const GET_LOCAL_4: u8 = 44;
+pub const GET_LOCAL_5: u8 = 45;
+pub const GET_LOCAL_6: u8 = 46;
+pub const GET_LOCAL_I8: u8 = 47;
+pub const GET_LOCAL_I16: u8 = 48;
+pub const GET_LOCAL_I32: u8 = 49;
+pub const SET_LOCAL_0: u8 = 50;
+pub const SET_LOCAL_1: u8 = 51;
+pub const SET_LOCAL_2: u8 = 52;
+pub const SET_LOCAL_3: u8 = 53;
+pub const SET_LOCAL_4: u8 = 54;
+pub const SET_LOCAL_5: u8 = 55;
+pub const SET_LOCAL_6: u8 = 56;
+pub const SET_LOCAL_I8: u8 = 57;
+pub const SET_LOCAL_I16: u8 = 58;
+pub const SET_LOCAL_I32: u8 = 59;
+pub const ALLOC_LOCAL_1: u8 = 61;
+pub const ALLOC_LOCAL_2: u8 = 62;
+pub const ALLOC_LOCAL_3: u8 = 63;
+pub const ALLOC_LOCAL_4: u8 = 64;
+pub const ALLOC_LOCAL_5: u8 = 65;
+pub const ALLOC_LOCAL_6: u8 = 66;
+pub const ALLOC_LOCAL_U8: u8 = 67;
+pub const ALLOC_LOCAL_U16: u8 = 68;
+pub const ALLOC_LOCAL_U32: u8 = 69;
+pub const POP_0: u8 = 70;
+pub const POP_1: u8 = 71;
+pub const POP_2: u8 = 72;
+pub const POP_3: u8 = 73;
+pub const POP_4: u8 = 74;
+pub const POP_5: u8 = 75;
+pub const POP_6: u8 = 76;
+pub const POP_U8: u8 = 77;
+pub const POP_U16: u8 = 78;
+pub const POP_U32: u8 = 79;
+pub const JNE8: u8 = 80;
+pub const JNE16: u8 = 81;
+pub const JNE32: u8 = 82;
+pub const JE8: u8 = 83;
+pub const JE16: u8 = 84;
+pub const JE32: u8 = 85;
+pub const J8: u8 = 86;
+pub const J16: u8 = 87;
+pub const J32: u8 = 88;
+pub const GET_LAST_0: u8 = 90;
+pub const GET_LAST_1: u8 = 91;
+pub const GET_LAST_2: u8 = 92;
+pub const GET_LAST_3: u8 = 93;
+pub const GET_LAST_U8: u8 = 94;
+pub const GET_LAST_U16: u8 = 95;
+pub const GET_LAST_U32: u8 = 96;
+pub const SET_NODE_U8: u8 = 97;
+pub const SET_NODE_U16: u8 = 98;
+pub const SET_NODE_U32: u8 = 99;
+pub const OBJ_FIELD_0: u8 = 100;
+pub const OBJ_FIELD_1: u8 = 101;
+pub const OBJ_FIELD_2: u8 = 102;
+pub const OBJ_FIELD_3: u8 = 103;
+pub const OBJ_FIELD_4: u8 = 104;
+pub const OBJ_FIELD_5: u8 = 105;
+pub const OBJ_FIELD_6: u8 = 106;
+pub const OBJ_FIELD_U8: u8 = 107;
+pub const UPD_DEV_0: u8 = 110;
+pub const UPD_DEV_1: u8 = 111;
+pub const UPD_DEV_2: u8 = 112;
+pub const UPD_DEV_3: u8 = 113;
+pub const UPD_DEV_U8: u8 = 114;
+pub const UPD_NODE_U8: u8 = 117;
+pub const UPD_NODE_U16: u8 = 118;
+pub const UPD_NODE_U32: u8 = 119;
+pub const O_ACTION_0: u8 = 120;
+pub const O_ACTION_1: u8 = 121;
+pub const O_ACTION_2: u8 = 122;
+pub const O_ACTION_3: u8 = 123;
+pub const O_ACTION_U8: u8 = 124;
+pub const CALL_U8: u8 = 127;
+pub const CALL_U16: u8 = 128;
+pub const CALL_U32: u8 = 129;
+pub const GET_DATA_U8: u8 = 130;
+pub const GET_DATA_U16: u8 = 131;
+pub const GET_DATA_U32: u8 = 132;
+pub const GET_NODE_U8: u8 = 133;
+pub const GET_NODE_U16: u8 = 134;
+pub const GET_NODE_U32: u8 = 135;
+pub const SET_DATA_U8: u8 = 141;
+pub const SET_DATA_U16: u8 = 142;
+pub const SET_DATA_U32: u8 = 143;
+pub const OBJ_TAG: u8 = 144;
+pub const SET_LAST_0: u8 = 150;
+pub const SET_LAST_1: u8 = 151;
+pub const SET_LAST_2: u8 = 152;
+pub const SET_LAST_3: u8 = 153;
+pub const SET_LAST_U8: u8 = 154;
+pub const SET_LAST_U16: u8 = 155;
+pub const SET_LAST_U32: u8 = 156;
+pub const END_UPD_U8: u8 = 157;
+pub const END_UPD_U16: u8 = 158;
+pub const END_UPD_U32: u8 = 159;
+pub const ALLOC_OBJ_0: u8 = 160;
+pub const ALLOC_OBJ_1: u8 = 161;
+pub const ALLOC_OBJ_2: u8 = 162;
+pub const ALLOC_OBJ_3: u8 = 163;
+pub const ALLOC_OBJ_4: u8 = 164;
+pub const ALLOC_OBJ_5: u8 = 165;
+pub const ALLOC_OBJ_6: u8 = 166;
+pub const ALLOC_OBJ_U8: u8 = 167;
+pub const DROP_LOCAL_OBJ_0: u8 = 170;
+pub const DROP_LOCAL_OBJ_1: u8 = 171;
+pub const DROP_LOCAL_OBJ_2: u8 = 172;
+pub const DROP_LOCAL_OBJ_3: u8 = 173;
+pub const DROP_LOCAL_OBJ_4: u8 = 174;
+pub const DROP_LOCAL_OBJ_5: u8 = 175;
+pub const DROP_LOCAL_OBJ_6: u8 = 176;
+pub const DROP_LOCAL_OBJ_I8: u8 = 177;
+pub const DROP_LOCAL_OBJ_I16: u8 = 178;
+pub const DROP_LOCAL_OBJ_I32: u8 = 179;
+pub const GET_LOCAL_REF_0: u8 = 180;
+pub const GET_LOCAL_REF_1: u8 = 181;
+pub const GET_LOCAL_REF_2: u8 = 182;
+pub const GET_LOCAL_REF_3: u8 = 183;
+pub const GET_LOCAL_REF_4: u8 = 184;
+pub const GET_LOCAL_REF_5: u8 = 185;
+pub const GET_LOCAL_REF_6: u8 = 186;
+pub const GET_LOCAL_REF_I8: u8 = 187;
+pub const GET_LOCAL_REF_I16: u8 = 188;
+pub const GET_LOCAL_REF_I32: u8 = 189;
+pub const SET_LOCAL_REF_0: u8 = 190;
+pub const SET_LOCAL_REF_1: u8 = 191;
+pub const SET_LOCAL_REF_2: u8 = 192;
+pub const SET_LOCAL_REF_3: u8 = 193;
+pub const SET_LOCAL_REF_4: u8 = 194;
+pub const SET_LOCAL_REF_5: u8 = 195;
+pub const SET_LOCAL_REF_6: u8 = 196;
+pub const SET_LOCAL_REF_I8: u8 = 197;
+pub const SET_LOCAL_REF_I16: u8 = 198;
+pub const SET_LOCAL_REF_I32: u8 = 199;
+pub const OBJ_FIELD_REF_0: u8 = 200;
+pub const OBJ_FIELD_REF_1: u8 = 201;
+pub const OBJ_FIELD_REF_2: u8 = 202;
+pub const OBJ_FIELD_REF_3: u8 = 203;
+pub const OBJ_FIELD_REF_4: u8 = 204;
+pub const OBJ_FIELD_REF_5: u8 = 205;
+pub const OBJ_FIELD_REF_6: u8 = 206;
+pub const OBJ_FIELD_REF_U8: u8 = 207;
+pub const END_UPD_OBJ_U8: u8 = 210;
+pub const END_UPD_OBJ_U16: u8 = 211;
+pub const END_UPD_OBJ_U32: u8 = 212;
+pub const GET_NODE_REF_U8: u8 = 213;
+pub const GET_NODE_REF_U16: u8 = 214;
+pub const GET_NODE_REF_U32: u8 = 215;
+pub const GET_DATA_REF_U8: u8 = 216;
+pub const GET_DATA_REF_U16: u8 = 217;
+pub const GET_DATA_REF_U32: u8 = 218;
+pub const GET_LAST_REF_0: u8 = 220;
+pub const GET_LAST_REF_1: u8 = 221;
+pub const GET_LAST_REF_2: u8 = 222;
+pub const GET_LAST_REF_3: u8 = 223;
+pub const GET_LAST_REF_U8: u8 = 224;
+pub const GET_LAST_REF_U16: u8 = 225;
+pub const GET_LAST_REF_U32: u8 = 226;
+pub const SET_DATA_REF_U8: u8 = 227;
+pub const SET_DATA_REF_U16: u8 = 228;
+pub const SET_DATA_REF_U32: u8 = 229;
+pub const SET_LAST_REF_0: u8 = 230;
+pub const SET_LAST_REF_1: u8 = 231;
+pub const SET_LAST_REF_2: u8 = 232;
+pub const SET_LAST_REF_3: u8 = 233;
+pub const SET_LAST_REF_U8: u8 = 234;
+pub const SET_LAST_REF_U16: u8 = 235;
+pub const SET_LAST_REF_U32: u8 = 236;
+pub const SET_NODE_REF_U8: u8 = 237;
+pub const SET_NODE_REF_U16: u8 = 238;
+pub const SET_NODE_REF_U32: u8 = 239;
+pub const DROP_LAST_U8: u8 = 240;
+pub const DROP_LAST_U16: u8 = 241;
+pub const DROP_LAST_U32: u8 = 242;
+pub const J0: u8 = 243;
+pub const J1: u8 = 244;
+pub const JE0: u8 = 245;
+pub const JE1: u8 = 246;
+pub const JNE0: u8 = 247;
+pub const JNE1: u8 = 248;
+pub const F_ADD: u8 = 136;
+pub const F_SUB: u8 = 137;
+pub const F_MUL: u8 = 138;
+pub const F_DIV: u8 = 139;
+pub const F_MINUS: u8 = 140;
+pub const F_LS: u8 = 145;
+pub const F_LEQ: u8 = 146;
+pub const F_GT: u8 = 147;
+pub const F_GEQ: u8 = 148;
+pub const F_EQ: u8 = 149;
+pub const F_NEQ: u8 = 168;
+pub const FLOAT: u8 = 169;
+pub const INT_TO_FLOAT: u8 = 249;
+pub const FLOAT_TO_INT: u8 = 250;
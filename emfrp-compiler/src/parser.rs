@@ -1,13 +1,29 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::anyhow;
 use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
+use lalrpop_util::lexer::Token;
+use lalrpop_util::ParseError;
 
+use crate::diagnostic::SourceMap;
 use crate::{ast::*, grammer::*};
+
+type ParseErr<'a> = ParseError<usize, Token<'a>, LitError>;
+
+// modules that can be used without a file
+const BUILTIN_MODULES: [(&str, &str); 1] = [("Std", include_str!("../lib/Std.mfrp"))];
+
 pub struct ParserWrapper {
     p_type: TypeDefParser,
     p_var: VarDefParser,
     p_exp: ExpParser,
     p_prog: ProgramParser,
     res: Vec<Def>,
+    sources: SourceMap,
 }
 impl ParserWrapper {
     pub fn new() -> Self {
@@ -17,42 +33,191 @@ impl ParserWrapper {
             p_exp: ExpParser::new(),
             p_prog: ProgramParser::new(),
             res: vec![],
+            sources: SourceMap::new(),
         }
     }
+    // every source parsed so far. spans in the ast point into it
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
     pub fn parse_line(&mut self, input: &str) -> Result<Program> {
-        if let Ok(res) = self.p_exp.parse(input) {
-            Ok(Program::Exp(res))
-        } else if let Ok(res) = self.p_var.parse(input) {
-            Ok(Program::Def(vec![Def::Var(res)]))
-        } else if let Ok(res) = self.p_type.parse(input) {
-            Ok(Program::Def(vec![Def::Type(res)]))
-        } else {
-            bail!("parse err")
+        let file = self.sources.add("<repl>", input);
+        let e_exp = match self.p_exp.parse(file, input) {
+            Ok(res) => return Ok(Program::Exp(res)),
+            Err(e) => e,
+        };
+        let e_var = match self.p_var.parse(file, input) {
+            Ok(res) => return Ok(Program::Def(vec![Def::Var(res)])),
+            Err(e) => e,
+        };
+        let e_type = match self.p_type.parse(file, input) {
+            Ok(res) => return Ok(Program::Def(vec![Def::Type(res)])),
+            Err(e) => e,
+        };
+        // the parser that went furthest is most likely the one the user meant
+        let e = [e_exp, e_var, e_type]
+            .into_iter()
+            .rev()
+            .max_by_key(error_location)
+            .unwrap();
+        bail!(self.parse_error(file, input, &e))
+    }
+    // true if input has no tokens, such as a line with only a comment
+    pub fn is_blank(&self, input: &str) -> bool {
+        matches!(
+            self.p_exp.parse(0, input),
+            Err(ParseError::UnrecognizedEof { location: 0, .. })
+        )
+    }
+    // true if input is the beginning of an expression or a definition which continues on the next line
+    pub fn is_incomplete(&self, input: &str) -> bool {
+        if unclosed_comment(input).is_some() {
+            return true;
+        }
+        let errs = match (
+            self.p_exp.parse(0, input),
+            self.p_var.parse(0, input),
+            self.p_type.parse(0, input),
+        ) {
+            (Err(e1), Err(e2), Err(e3)) => [e1, e2, e3],
+            _ => return false,
+        };
+        errs.iter()
+            .any(|e| matches!(e, ParseError::UnrecognizedEof { location, .. } if *location > 0))
+    }
+    pub fn parse_program(&mut self, name: &str, input: &str) -> Result<Program> {
+        let file = self.sources.add(name, input);
+        self.p_prog
+            .parse(file, input)
+            .map_err(|e| anyhow!(self.parse_error(file, input, &e)))
+    }
+    // parses `fname` and inlines the definitions of the modules it uses.
+    // a used module is looked up as <Name>.mfrp in the directory of the file using it,
+    // and then in the built-in modules. modules in `imported` are skipped
+    pub fn load_program(&mut self, fname: &str, imported: &HashSet<String>) -> Result<Program> {
+        let src = fs::read_to_string(fname).with_context(|| format!("could not read {fname}"))?;
+        match self.parse_program(fname, &src)? {
+            Program::Module(mut m, defs) => {
+                let dir = Path::new(fname).parent().unwrap_or(Path::new(""));
+                let mut used = vec![];
+                let mut loading = vec![m.name.clone()];
+                let mut all_defs = vec![];
+                self.load_uses(&m, dir, imported, &mut used, &mut loading, &mut all_defs)?;
+                all_defs.extend(defs);
+                m.uses = used;
+                Ok(Program::Module(m, all_defs))
+            }
+            prog => Ok(prog),
         }
     }
-    pub fn parse_program(&self, input: &str) -> Result<Program> {
-        match self.p_prog.parse(input) {
-            Ok(res) => Ok(res),
-            Err(e) => {
-                println!("{e}");
-                bail!("parse error")
+    fn load_uses(
+        &mut self,
+        m: &Module,
+        dir: &Path,
+        imported: &HashSet<String>,
+        used: &mut Vec<String>,
+        loading: &mut Vec<String>,
+        defs: &mut Vec<Def>,
+    ) -> Result<()> {
+        for name in &m.uses {
+            if imported.contains(name) || used.contains(name) {
+                continue;
             }
+            if loading.contains(name) {
+                bail!("circular use of module {name}")
+            }
+            let path = dir.join(format!("{name}.mfrp"));
+            let (src_name, src) = if path.is_file() {
+                let src = fs::read_to_string(&path)
+                    .with_context(|| format!("could not read {}", path.display()))?;
+                (path.display().to_string(), src)
+            } else {
+                match BUILTIN_MODULES.iter().find(|(n, _)| n == name) {
+                    Some((_, src)) => (format!("<{name}>"), src.to_string()),
+                    None => bail!("module {name} not found"),
+                }
+            };
+            let (used_m, used_defs) = match self.parse_program(&src_name, &src)? {
+                Program::Module(used_m, used_defs) if &used_m.name == name => (used_m, used_defs),
+                _ => bail!("{} does not define module {name}", path.display()),
+            };
+            if !used_m.inputs.is_empty() || !used_m.outputs.is_empty() {
+                bail!("module {name} declares input/output nodes and cannot be used")
+            }
+            loading.push(name.clone());
+            let used_dir = path.parent().unwrap_or(dir);
+            self.load_uses(&used_m, used_dir, imported, used, loading, defs)?;
+            loading.pop();
+            defs.extend(used_defs);
+            used.push(name.clone());
         }
+        Ok(())
     }
     pub fn parse_one_of_lines(&mut self, input: &str) -> Result<()> {
-        if let Ok(res) = self.p_var.parse(input) {
-            self.res.push(Def::Var(res));
-            Ok(())
-        } else if let Ok(res) = self.p_type.parse(input) {
-            self.res.push(Def::Type(res));
-            Ok(())
-        } else if let Ok(_) = self.p_exp.parse(input) {
-            bail!("expression is not allowed here")
-        } else {
-            bail!("parse error")
+        let file = self.sources.add("<repl>", input);
+        let e_var = match self.p_var.parse(file, input) {
+            Ok(res) => {
+                self.res.push(Def::Var(res));
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        let e_type = match self.p_type.parse(file, input) {
+            Ok(res) => {
+                self.res.push(Def::Type(res));
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        if let Ok(e) = self.p_exp.parse(file, input) {
+            bail!(self
+                .sources
+                .render(e.span(), "expression is not allowed here"))
         }
+        let e = [e_var, e_type]
+            .into_iter()
+            .rev()
+            .max_by_key(error_location)
+            .unwrap();
+        bail!(self.parse_error(file, input, &e))
     }
     pub fn get_result(&mut self) -> Program {
         Program::Def(std::mem::take(&mut self.res))
     }
+    // an unclosed block comment is reported instead of the tokens the lexer made of it
+    fn parse_error(&self, file: usize, input: &str, e: &ParseErr) -> String {
+        match unclosed_comment(input) {
+            Some(pos) => self
+                .sources
+                .render(Span::new(file, pos, pos + 2), "unterminated block comment"),
+            None => self.sources.parse_error(file, e),
+        }
+    }
+}
+// the position of a `/*` which is never closed, outside of other comments and char literals
+fn unclosed_comment(input: &str) -> Option<usize> {
+    let b = input.as_bytes();
+    let mut i = 0;
+    while i < b.len() {
+        match b[i..] {
+            [b'#', ..] | [b'/', b'/', ..] => i += input[i..].find('\n')?,
+            [b'/', b'*', ..] => match input[i + 2..].find("*/") {
+                Some(n) => i += n + 4,
+                None => return Some(i),
+            },
+            [b'\'', b'\\', _, b'\'', ..] => i += 4,
+            [b'\'', _, b'\'', ..] => i += 3,
+            _ => i += 1,
+        }
+    }
+    None
+}
+fn error_location(e: &ParseErr) -> usize {
+    match e {
+        ParseError::InvalidToken { location } => *location,
+        ParseError::UnrecognizedEof { location, .. } => *location,
+        ParseError::UnrecognizedToken { token, .. } => token.0,
+        ParseError::ExtraToken { token } => token.0,
+        ParseError::User { error } => error.span.start,
+    }
 }
@@ -0,0 +1,500 @@
+// framed protocol between the host and a machine (emfrp-machine/esp32/main/protocol.c)
+//   frame : sync(0xaa 0x55) type(u8) seq(u8) len(u16) hcrc(u8) payload crc(u16)
+//   crc is CRC-16/CCITT-FALSE of type, seq, len, hcrc and payload, and hcrc is the low byte of the
+//   crc of type, seq and len, so that a broken len is found before waiting for the payload
+// every request is answered with a frame of type `request | REPLY` and the same seq, or with NAK.
+// the machine keeps the reply to the last request, so a request sent again because its reply was
+// lost is answered with the same reply instead of being executed twice.
+// MSG_TELEMETRY is the only frame the machine sends without a request
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::machine::EmfrpResult;
+use crate::machine::Machine;
+use crate::transport::Transport;
+
+pub const VERSION: u8 = 6;
+pub const SYNC: [u8; 2] = [0xaa, 0x55];
+const HEADER_LEN: usize = 7;
+const CRC_LEN: usize = 2;
+
+// payload : version. reply : version max_payload(u16) telemetry_size(u16)
+pub const MSG_HELLO: u8 = 1;
+pub const MSG_PING: u8 = 2;
+// payload : bytecode made by to_byte_code without its length. the definitions are staged, and the
+// running program is kept until MSG_COMMIT. reply : status
+pub const MSG_DEFINE: u8 = 3;
+// payload : bytecode. reply : size(u16) value status, or status if the expression is not evaluated
+pub const MSG_EVAL: u8 = 4;
+// payload : kind(u8, QUERY_NODE or QUERY_DATA) is_obj(u8) index(u16). reply : same as MSG_EVAL.
+// with QUERY_STAGED, reply : whether definitions are staged(u8) status
+pub const MSG_QUERY: u8 = 5;
+// reply is sent before the machine restarts
+pub const MSG_RESET: u8 = 6;
+// payload : num_last(u16) n(u8), kind(u8, QUERY_NODE, QUERY_DATA or REMOVE_FUNC) is_obj(u8) index(u16)
+// of each of the n slots, and update. the update code is replaced and then all the slots are freed,
+// or nothing is done if one of them is invalid. reply : status
+pub const MSG_REMOVE: u8 = 7;
+// swaps in the staged definitions and runs their initialisation. reply : status
+pub const MSG_COMMIT: u8 = 8;
+// drops the staged definitions. reply : status
+pub const MSG_ABORT: u8 = 9;
+// payload : every(u16), and then is_obj(u8) index(u16) of each node, at most MAX_SUBSCRIBE of them.
+// the machine sends MSG_TELEMETRY after every `every`th update, and no nodes stop it. reply : status
+pub const MSG_SUBSCRIBE: u8 = 10;
+// seq counts telemetry frames. payload : status of the update, and then len(u16) and the same bytes
+// as the reply to MSG_QUERY for each node. only the status is sent if the payload is longer than
+// telemetry_size of the reply to MSG_HELLO
+pub const MSG_TELEMETRY: u8 = 11;
+// payload : NAK_*
+pub const MSG_NAK: u8 = 0x7f;
+pub const REPLY: u8 = 0x80;
+
+pub const QUERY_NODE: u8 = 0;
+pub const QUERY_DATA: u8 = 1;
+pub const REMOVE_FUNC: u8 = 2;
+pub const QUERY_STAGED: u8 = 3;
+
+pub const MAX_SUBSCRIBE: usize = 8;
+
+pub const NAK_CRC: u8 = 1;
+pub const NAK_TOO_LONG: u8 = 2;
+pub const NAK_UNKNOWN: u8 = 3;
+pub const NAK_MALFORMED: u8 = 4;
+
+// a request is sent this many times before giving up
+const RETRIES: usize = 5;
+// telemetry frames kept until they are read, and the older ones are dropped
+const TELEMETRY_BACKLOG: usize = 1024;
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub typ: u8,
+    pub seq: u8,
+    pub payload: Vec<u8>,
+}
+impl Frame {
+    pub fn new(typ: u8, seq: u8, payload: &[u8]) -> Self {
+        Self {
+            typ,
+            seq,
+            payload: payload.to_vec(),
+        }
+    }
+    pub fn encode(&self) -> Vec<u8> {
+        let mut res = SYNC.to_vec();
+        res.push(self.typ);
+        res.push(self.seq);
+        res.extend((self.payload.len() as u16).to_le_bytes());
+        res.push(crc16(&res[SYNC.len()..]) as u8);
+        res.extend(&self.payload);
+        let crc = crc16(&res[SYNC.len()..]);
+        res.extend(crc.to_le_bytes());
+        res
+    }
+}
+
+// a frame that could not be read. the seq may be wrong if the header is broken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BadFrame {
+    pub seq: u8,
+    pub reason: u8,
+}
+
+// splits a byte stream into frames. bytes before a sync are skipped
+pub struct FrameParser {
+    buf: Vec<u8>,
+    max_payload: usize,
+}
+impl FrameParser {
+    pub fn new(max_payload: usize) -> Self {
+        Self {
+            buf: vec![],
+            max_payload,
+        }
+    }
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<Frame, BadFrame>> {
+        self.buf.extend(bytes);
+        let mut res = vec![];
+        loop {
+            match self.buf.windows(2).position(|w| w == SYNC) {
+                Some(i) => {
+                    self.buf.drain(..i);
+                }
+                None => {
+                    // keeps the first byte of a sync split between two reads
+                    let keep = self.buf.last() == Some(&SYNC[0]);
+                    self.buf.drain(..self.buf.len() - keep as usize);
+                    break;
+                }
+            }
+            if self.buf.len() < HEADER_LEN {
+                break;
+            }
+            let seq = self.buf[3];
+            let len = u16::from_le_bytes([self.buf[4], self.buf[5]]) as usize;
+            if crc16(&self.buf[SYNC.len()..HEADER_LEN - 1]) as u8 != self.buf[HEADER_LEN - 1] {
+                res.push(Err(BadFrame {
+                    seq,
+                    reason: NAK_CRC,
+                }));
+                self.buf.drain(..SYNC.len());
+                continue;
+            }
+            if len > self.max_payload {
+                res.push(Err(BadFrame {
+                    seq,
+                    reason: NAK_TOO_LONG,
+                }));
+                self.buf.drain(..SYNC.len());
+                continue;
+            }
+            let end = HEADER_LEN + len + CRC_LEN;
+            if self.buf.len() < end {
+                break;
+            }
+            let crc = u16::from_le_bytes([self.buf[end - 2], self.buf[end - 1]]);
+            if crc16(&self.buf[SYNC.len()..end - CRC_LEN]) == crc {
+                res.push(Ok(Frame::new(
+                    self.buf[2],
+                    seq,
+                    &self.buf[HEADER_LEN..end - CRC_LEN],
+                )));
+                self.buf.drain(..end);
+            } else {
+                // the sync may have been a byte of a broken frame, so the rest is searched again
+                res.push(Err(BadFrame {
+                    seq,
+                    reason: NAK_CRC,
+                }));
+                self.buf.drain(..SYNC.len());
+            }
+        }
+        res
+    }
+    // drops a frame received partway
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+}
+
+// host side of the protocol
+pub struct Link {
+    port: Box<dyn Transport>,
+    parser: FrameParser,
+    seq: u8,
+    timeout: Duration,
+    // payload size the machine accepts and the longest payload of MSG_TELEMETRY, known after the
+    // handshake
+    max_payload: Option<usize>,
+    telemetry_size: usize,
+    telemetry: VecDeque<Frame>,
+}
+impl Link {
+    pub fn new(port: Box<dyn Transport>, timeout: Duration) -> Self {
+        Self {
+            port,
+            parser: FrameParser::new(u16::MAX as usize),
+            seq: 0,
+            timeout,
+            max_payload: None,
+            telemetry_size: 0,
+            telemetry: VecDeque::new(),
+        }
+    }
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    pub fn machine(&mut self) -> Option<&mut Machine> {
+        self.port.machine()
+    }
+    // checks the version of the machine. called before the first request
+    pub fn handshake(&mut self) -> Result<()> {
+        self.port.clear()?;
+        self.parser.clear();
+        let reply = self.send(MSG_HELLO, &[VERSION])?;
+        // the version is checked first, since a machine of another version replies differently
+        if reply.first().is_some_and(|&v| v != VERSION) {
+            bail!("the machine speaks protocol version {}, but the compiler speaks {VERSION}. Please flash the machine again", reply[0])
+        }
+        let [_, lo, hi, t_lo, t_hi] = reply[..] else {
+            bail!("UART error : invalid reply to the handshake")
+        };
+        self.max_payload = Some(u16::from_le_bytes([lo, hi]) as usize);
+        self.telemetry_size = u16::from_le_bytes([t_lo, t_hi]) as usize;
+        Ok(())
+    }
+    // the longest payload of MSG_TELEMETRY the machine sends
+    pub fn telemetry_size(&mut self) -> Result<usize> {
+        if self.max_payload.is_none() {
+            self.handshake()?;
+        }
+        Ok(self.telemetry_size)
+    }
+    // sends a request and returns the payload of its reply
+    pub fn request(&mut self, typ: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        let max_payload = match self.max_payload {
+            Some(n) => n,
+            None => {
+                self.handshake()?;
+                self.max_payload.unwrap()
+            }
+        };
+        if payload.len() > max_payload {
+            bail!(
+                "code is {}bytes, but the machine accepts at most {max_payload}bytes at once",
+                payload.len()
+            )
+        }
+        let reply = self.send(typ, payload)?;
+        if typ == MSG_RESET {
+            self.max_payload = None;
+        }
+        Ok(reply)
+    }
+    // sends the last request again with the same seq, which the machine answers with the reply it
+    // sent before if it has executed the request, instead of executing it again
+    pub fn request_again(&mut self, typ: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        self.transmit(typ, payload)
+    }
+    fn send(&mut self, typ: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        self.seq = self.seq.wrapping_add(1);
+        self.transmit(typ, payload)
+    }
+    fn transmit(&mut self, typ: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        let frame = Frame::new(typ, self.seq, payload).encode();
+        let mut last_err = "no reply";
+        for _ in 0..RETRIES {
+            self.port.send(&frame).context("fail to send a frame")?;
+            match self.wait_reply(typ)? {
+                Ok(payload) => return Ok(payload),
+                Err(e) => last_err = e,
+            }
+        }
+        bail!("UART error : {last_err} from the machine after {RETRIES} attempts")
+    }
+    // Err is returned when the request should be sent again
+    fn wait_reply(&mut self, typ: u8) -> Result<Result<Vec<u8>, &'static str>> {
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0; 256];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(Err("no reply"));
+            }
+            let n = self.port.recv(&mut buf, deadline - now)?;
+            let mut retry = None;
+            for frame in self.parser.push(&buf[..n]) {
+                match frame {
+                    Ok(f) if f.typ == MSG_TELEMETRY => self.push_telemetry(f),
+                    // replies to older requests are ignored
+                    Ok(f) if f.seq != self.seq => continue,
+                    Ok(f) if f.typ == typ | REPLY => return Ok(Ok(f.payload)),
+                    Ok(f) if f.typ == MSG_NAK => {
+                        retry = Some(match f.payload.first() {
+                            Some(&NAK_CRC) => "broken request",
+                            Some(&NAK_TOO_LONG) => "too long request",
+                            Some(&NAK_UNKNOWN) => "unknown request",
+                            _ => "malformed request",
+                        })
+                    }
+                    Ok(_) => continue,
+                    Err(_) => retry = Some("broken reply"),
+                }
+            }
+            if let Some(e) = retry {
+                return Ok(Err(e));
+            }
+        }
+    }
+    fn push_telemetry(&mut self, f: Frame) {
+        if self.telemetry.len() == TELEMETRY_BACKLOG {
+            self.telemetry.pop_front();
+        }
+        self.telemetry.push_back(f);
+    }
+    // the oldest telemetry frame not read yet, waiting at most `timeout` for one
+    pub fn recv_telemetry(&mut self, timeout: Duration) -> Result<Option<Frame>> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0; 256];
+        while self.telemetry.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            let n = self.port.recv(&mut buf, deadline - now)?;
+            for f in self.parser.push(&buf[..n]).into_iter().flatten() {
+                if f.typ == MSG_TELEMETRY {
+                    self.push_telemetry(f);
+                }
+            }
+        }
+        Ok(self.telemetry.pop_front())
+    }
+    pub fn clear_telemetry(&mut self) {
+        self.telemetry.clear();
+    }
+}
+
+// machine side of the protocol, which protocol.c also implements
+pub struct Device {
+    parser: FrameParser,
+    // seq and type of the last request and its reply
+    last: Option<(u8, u8, Vec<u8>)>,
+    max_payload: usize,
+    // REPLY_BUF_SIZE of the firmware
+    reply_size: usize,
+    telemetry_size: usize,
+    // is_obj and index of the nodes sent by telemetry
+    subscribed: Vec<(bool, usize)>,
+    every: usize,
+    n_update: usize,
+    telemetry_seq: u8,
+}
+impl Device {
+    pub fn new(max_payload: usize, reply_size: usize, telemetry_size: usize) -> Self {
+        Self {
+            parser: FrameParser::new(max_payload),
+            last: None,
+            max_payload,
+            reply_size,
+            telemetry_size,
+            subscribed: vec![],
+            every: 1,
+            n_update: 0,
+            telemetry_seq: 0,
+        }
+    }
+    // handles the bytes read from uart and returns the bytes to write
+    pub fn receive(&mut self, vm: &mut Machine, bytes: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        for frame in self.parser.push(bytes) {
+            let reply = match frame {
+                Ok(f) => self.handle(vm, f),
+                Err(e) => Frame::new(MSG_NAK, e.seq, &[e.reason]),
+            };
+            out.extend(reply.encode());
+        }
+        out
+    }
+    fn handle(&mut self, vm: &mut Machine, f: Frame) -> Frame {
+        if f.typ == MSG_HELLO {
+            // a new host does not know what the previous one subscribed
+            self.last = None;
+            self.subscribed.clear();
+            let mut payload = vec![VERSION];
+            payload.extend((self.max_payload as u16).to_le_bytes());
+            payload.extend((self.telemetry_size as u16).to_le_bytes());
+            return Frame::new(f.typ | REPLY, f.seq, &payload);
+        }
+        if let Some((seq, typ, reply)) = &self.last {
+            if *seq == f.seq && *typ == f.typ {
+                return Frame::new(f.typ | REPLY, f.seq, reply);
+            }
+        }
+        vm.take_uart();
+        let res = match f.typ {
+            MSG_PING => EmfrpResult::Ok,
+            MSG_DEFINE => vm.stage(&f.payload),
+            MSG_EVAL => vm.new_bytecode(&f.payload),
+            MSG_COMMIT => vm.commit(),
+            MSG_ABORT => vm.abort(),
+            MSG_QUERY => match f.payload[..] {
+                [kind, is_obj, lo, hi] => {
+                    vm.query(kind, is_obj != 0, u16::from_le_bytes([lo, hi]) as usize)
+                }
+                _ => return Frame::new(MSG_NAK, f.seq, &[NAK_MALFORMED]),
+            },
+            MSG_REMOVE => vm.remove(&f.payload),
+            MSG_SUBSCRIBE => {
+                let status = self.subscribe(vm, &f.payload) as u8;
+                self.last = Some((f.seq, f.typ, vec![status]));
+                return Frame::new(f.typ | REPLY, f.seq, &[status]);
+            }
+            MSG_RESET => {
+                // the machine forgets the last request as it does after restarting
+                vm.reset();
+                self.last = None;
+                self.subscribed.clear();
+                return Frame::new(f.typ | REPLY, f.seq, &[]);
+            }
+            _ => return Frame::new(MSG_NAK, f.seq, &[NAK_UNKNOWN]),
+        };
+        let mut reply = vm.take_uart();
+        // a reply which does not fit is replaced by the result, and the machine goes on
+        if reply.len() > self.reply_size {
+            let status = match res {
+                EmfrpResult::Ok => EmfrpResult::OutOfMemory,
+                e => e,
+            };
+            reply = vec![status as u8];
+        }
+        let frame = Frame::new(f.typ | REPLY, f.seq, &reply);
+        self.last = Some((f.seq, f.typ, reply));
+        frame
+    }
+    fn subscribe(&mut self, vm: &Machine, payload: &[u8]) -> EmfrpResult {
+        let Some((every, nodes)) = payload.split_first_chunk::<2>() else {
+            return EmfrpResult::Panic;
+        };
+        let every = u16::from_le_bytes(*every) as usize;
+        if every == 0 || nodes.len() % 3 != 0 || nodes.len() / 3 > MAX_SUBSCRIBE {
+            return EmfrpResult::Panic;
+        }
+        let mut subscribed = vec![];
+        for nd in nodes.chunks(3) {
+            let i = u16::from_le_bytes([nd[1], nd[2]]) as usize;
+            if vm.node_value(i).is_none() {
+                return EmfrpResult::Panic;
+            }
+            subscribed.push((nd[0] != 0, i));
+        }
+        self.subscribed = subscribed;
+        self.every = every;
+        self.n_update = 0;
+        EmfrpResult::Ok
+    }
+    // the telemetry frame to send after an update which ended with status, if it is time to send one
+    pub fn telemetry(&mut self, vm: &mut Machine, status: EmfrpResult) -> Option<Frame> {
+        if self.subscribed.is_empty() {
+            return None;
+        }
+        self.n_update += 1;
+        if self.n_update < self.every {
+            return None;
+        }
+        self.n_update = 0;
+        let mut payload = vec![status as u8];
+        for &(is_obj, i) in &self.subscribed {
+            vm.query(QUERY_NODE, is_obj, i);
+            let value = vm.take_uart();
+            payload.extend((value.len() as u16).to_le_bytes());
+            payload.extend(value);
+        }
+        if payload.len() > self.telemetry_size {
+            payload = vec![EmfrpResult::OutOfMemory as u8];
+        }
+        self.telemetry_seq = self.telemetry_seq.wrapping_add(1);
+        Some(Frame::new(MSG_TELEMETRY, self.telemetry_seq, &payload))
+    }
+}
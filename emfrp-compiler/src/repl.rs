@@ -1,64 +1,35 @@
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::Read;
+use std::fmt;
+use std::fs;
 use std::io::Write;
-use std::iter;
+use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
+use crate::ast::Id;
+use crate::ast::Program;
 use crate::compile::compile_common::*;
+use crate::image::Image;
 use crate::insn::to_byte_code;
 use crate::insn::ObjHeader;
+use crate::machine::ZERO;
 use crate::parser::*;
+use crate::protocol::*;
+use crate::sim::csv_field;
+use crate::transport::Transport;
 use crate::*;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 
-use serial2::*;
 const CONSOLE: &str = " > ";
 const CONSOLE2: &str = "...";
-pub const BUF_SIZE: usize = 1024;
-const DEBUG_COMPILER: bool = false;
-const DEBUG_FILE: &str = "machine.txt";
-enum Out {
-    UART(SerialPort),
-    FILE(String), //for debug
-}
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+// how often `:watch` asks the machine for the value, and how long `:stream` waits for a value
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+// columns `:plot` uses
+const PLOT_WIDTH: usize = 60;
 const RES_TABLE: [&str; 5] = ["OK", "RUNTIME ERROR", "PANIC", "TODO", "OUT OF MEMORY"];
-impl Out {
-    pub fn truncate(&mut self) {
-        match self {
-            Out::UART(port) => port.discard_buffers().unwrap(),
-            Out::FILE(f) => {
-                OpenOptions::new()
-                    .write(true)
-                    .truncate(true)
-                    .open(&f)
-                    .unwrap();
-            }
-        };
-    }
-    fn write(&mut self, v: &Vec<u8>) -> Result<()> {
-        match self {
-            Out::UART(port) => port.write_all(v).context("could not write to the port"),
-            Out::FILE(f) => match OpenOptions::new().write(true).truncate(true).open(&f) {
-                Err(_) => bail!("could not open file"),
-                Ok(mut fd) => fd.write_all(v).context("could not write to the file"),
-            },
-        }
-    }
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        match self {
-            Out::UART(port) => port.read(buf).context("could not read port"),
-            Out::FILE(f) => match OpenOptions::new().read(true).open(&f) {
-                Err(_) => bail!("could not open file"),
-                Ok(mut fd) => fd.read(buf).context("could not read file"),
-            },
-        }
-    }
-}
 fn read_u32(v: &[u8]) -> Result<u32> {
     if v.len() < 4 {
         bail!("UART error");
@@ -73,6 +44,14 @@ fn read_i32(v: &[u8]) -> Result<i32> {
     let v: [u8; 4] = [v[0], v[1], v[2], v[3]];
     Ok(i32::from_le_bytes(v))
 }
+// the header of an object written by the machine, which is extended for large objects
+fn obj_header(v: &[u8], i: &mut usize) -> Result<ObjHeader> {
+    ObjHeader::read(|| {
+        let w = read_u32(v.get(*i..).unwrap_or_default())?;
+        *i += 4;
+        Ok(w)
+    })
+}
 fn u8_to_value(t: &Type, v: &[u8], res: &mut String) -> Result<usize> {
     match t {
         Type::Int => {
@@ -84,18 +63,23 @@ fn u8_to_value(t: &Type, v: &[u8], res: &mut String) -> Result<usize> {
             res.push_str(if i == 0 { "false" } else { "true" });
             Ok(4)
         }
-        Type::User(_, vars) => {
-            let header = ObjHeader(read_u32(v)?);
-            let (tag, _, _) = header.decode();
+        Type::Float => {
+            let f = f32::from_bits(read_u32(v)?);
+            res.push_str(&format!("{f:?}"));
+            Ok(4)
+        }
+        Type::User(_, _, vars) => {
+            let mut i = 0;
+            let header = obj_header(v, &mut i)?;
+            let tag = header.tag();
             if tag > vars.len() as u32 || tag == 0 {
                 bail!("UART error");
             }
-            let &(ref vname, ref vargs) = &vars[tag as usize - 1];
+            let (vname, vargs) = &vars[tag as usize - 1];
             res.push_str(&vname.0);
-            if vargs.len() == 0 {
-                return Ok(4);
+            if vargs.is_empty() {
+                return Ok(i);
             }
-            let mut i = 4;
             res.push('(');
             for (x, t) in vargs.iter().enumerate() {
                 if t.is_obj_type() {
@@ -114,10 +98,11 @@ fn u8_to_value(t: &Type, v: &[u8], res: &mut String) -> Result<usize> {
             Ok(i)
         }
         Type::Tuple(types) => {
-            if types.len() == 0 {
-                return Ok(4);
+            let mut i = 0;
+            obj_header(v, &mut i)?;
+            if types.is_empty() {
+                return Ok(i);
             }
-            let mut i = 4;
             res.push('(');
             for (x, t) in types.iter().enumerate() {
                 if t.is_obj_type() {
@@ -137,194 +122,672 @@ fn u8_to_value(t: &Type, v: &[u8], res: &mut String) -> Result<usize> {
         }
     }
 }
+// reply to a request which only returns the status
+pub fn status_reply(reply: &[u8]) -> Result<String> {
+    let [status] = reply[..] else {
+        bail!("UART error")
+    };
+    if status >= RES_TABLE.len() as u8 {
+        bail!("UART error")
+    } else if status != 0 {
+        bail!("{}", RES_TABLE[status as usize])
+    } else {
+        Ok(RES_TABLE[status as usize].to_string())
+    }
+}
+// size(u16) value status, or status only if the value was not written
+fn value_reply(t: &Type, reply: &[u8]) -> Result<String> {
+    Ok(format!("[OK] {}", reply_value(t, reply)?))
+}
+pub fn reply_value(t: &Type, reply: &[u8]) -> Result<String> {
+    if reply.len() == 1 {
+        status_reply(reply)?;
+        bail!("UART error")
+    }
+    let [lo, hi, ..] = reply[..] else {
+        bail!("UART error")
+    };
+    let size = u16::from_le_bytes([lo, hi]) as usize;
+    if reply.len() != size + 3 {
+        bail!("UART error")
+    }
+    status_reply(&reply[size + 2..])?;
+    let mut s = String::new();
+    u8_to_value(t, &reply[2..], &mut s)?;
+    Ok(s)
+}
+// an Id of grammer.lalrpop
+fn is_var_name(s: &str) -> bool {
+    const KEYWORDS: [&str; 20] = [
+        "data", "else", "false", "func", "if", "in", "init", "module", "node", "of", "out",
+        "record", "then", "toFloat", "toInt", "true", "type", "use", "when", "with",
+    ];
+    s.starts_with(|c: char| c.is_ascii_lowercase())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&s)
+}
+// the definitions are in place on the machine, but their initialisation failed
+#[derive(Debug)]
+struct InitFailed(String);
+impl fmt::Display for InitFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} while initialising the definitions", self.0)
+    }
+}
+impl std::error::Error for InitFailed {}
+// no reply to MSG_COMMIT arrived, and the machine could not be asked whether it swapped the definitions in
+#[derive(Debug)]
+struct CommitLost(anyhow::Error);
+impl fmt::Display for CommitLost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} while committing the definitions", self.0)
+    }
+}
+impl std::error::Error for CommitLost {}
 pub struct Repl {
     pub cmp: Compiler,
     parser: ParserWrapper,
-    port: Out,
+    link: Link,
+    // types of the nodes subscribed to
+    streamed: Vec<Type>,
+}
+// values of subscribed nodes after an update, or the error of the update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub seq: u8,
+    pub values: Result<Vec<String>, String>,
+}
+// nodes, every and the CSV file of `:stream <node>... [every <n>] [> <file>]`
+fn stream_args(arg: &str) -> Result<(Vec<&str>, u16, Option<&str>)> {
+    let (arg, file) = match arg.split_once('>') {
+        Some((arg, file)) if !file.trim().is_empty() => (arg, Some(file.trim())),
+        Some(_) => bail!("usage : :stream <node>... [every <n>] [> <file>]"),
+        None => (arg, None),
+    };
+    let mut names: Vec<&str> = arg.split_whitespace().collect();
+    let mut every = 1;
+    if let [.., "every", n] = names[..] {
+        every = match n.parse() {
+            Ok(n) if n > 0 => n,
+            _ => bail!("every must be a number from 1 to {}", u16::MAX),
+        };
+        names.truncate(names.len() - 2);
+    }
+    if names.is_empty() {
+        bail!("usage : :stream <node>... [every <n>] [> <file>]")
+    }
+    Ok((names, every, file))
+}
+// None at the end of input
+fn read_line(prompt: &str) -> Option<String> {
+    print!("{prompt}");
+    stdout().flush().unwrap();
+    let mut input = String::new();
+    match stdin().read_line(&mut input).unwrap() {
+        0 => None,
+        _ => Some(input),
+    }
 }
 impl Repl {
     pub fn run(mut self) {
-        for _ in 0.. {
-            stdout().flush().unwrap();
-            print!("{CONSOLE}");
-            stdout().flush().unwrap();
-            let mut input = String::new();
-            stdin().read_line(&mut input).unwrap();
-            let cmp_clone = self.cmp.clone();
+        while let Some(mut input) = read_line(CONSOLE) {
+            // a definition spanning lines is read until it is complete or an empty line is given
+            while input.trim() != "{" && self.parser.is_incomplete(&input) {
+                match read_line(CONSOLE2) {
+                    Some(line) if !line.trim().is_empty() => input.push_str(&line),
+                    _ => break,
+                }
+            }
             match self.interpret(&input) {
                 Ok(res) => println!("{res}"),
-                Err(msg) => {
-                    self.cmp = cmp_clone;
-                    println!("{:?}", msg);
-                }
+                Err(msg) => println!("{:?}", msg),
             }
         }
     }
-    pub fn new() -> Result<Self, String> {
-        if DEBUG_COMPILER {
-            return Ok(Self::new_with_file());
-        }
-        let mut port = SerialPort::open(UART_FILE, BAUD_RATE).map_err(|e| format!("{:?}", e))?;
-        let mut settings = port.get_configuration().map_err(|e| format!("{:?}", e))?;
-
-        settings.set_stop_bits(StopBits::One);
-        settings.set_flow_control(FlowControl::None);
-        settings.set_char_size(CharSize::Bits8);
-        port.set_configuration(&settings)
-            .map_err(|e| format!("{:?}", e))?;
-        port.set_read_timeout(Duration::from_secs(10))
-            .map_err(|e| format!("{:?}", e))?;
-        Ok(Self {
-            cmp: Compiler::new(),
-            parser: ParserWrapper::new(),
-            port: Out::UART(port),
-        })
-    }
-    pub fn new_with_file() -> Self {
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(DEBUG_FILE)
-            .unwrap();
+    pub fn new(port: Box<dyn Transport>) -> Self {
         Self {
             cmp: Compiler::new(),
             parser: ParserWrapper::new(),
-            port: Out::FILE(DEBUG_FILE.to_string()),
+            link: Link::new(port, REPLY_TIMEOUT),
+            streamed: vec![],
         }
     }
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.link.set_timeout(timeout);
+    }
+    pub fn link(&mut self) -> &mut Link {
+        &mut self.link
+    }
     //output gpio5
-    pub fn add_input_node(&mut self, name: &'static str, typ: Type) {
+    pub fn add_input_node(&mut self, name: &str, typ: Type) {
         self.cmp.add_input_node(name, typ);
+        if let Some(vm) = self.link.machine() {
+            vm.add_input_node(ZERO, Box::new(|_| ()));
+        }
     }
-    pub fn add_output_node(&mut self, name: &'static str, typ: Type) {
+    pub fn add_output_node(&mut self, name: &str, typ: Type) {
         self.cmp.add_output_node(name, typ);
+        if let Some(vm) = self.link.machine() {
+            vm.add_output_node(ZERO, Box::new(|_| ()));
+        }
     }
 }
-fn load_file(fname: &str) -> Result<String> {
-    let mut f = File::open(fname).context("file not found")?;
-
-    let mut contents = String::new();
-    f.read_to_string(&mut contents)
-        .expect("something went wrong reading the file");
-    Ok(contents)
-}
 impl Repl {
     pub fn interpret(&mut self, input: &str) -> Result<String> {
         let prog = match input.trim() {
-            "{" => loop {
-                let mut input2 = String::new();
-                stdout().flush().unwrap();
-                print!("{CONSOLE2}");
-                stdout().flush().unwrap();
-                stdin().read_line(&mut input2).unwrap();
-                if let "}" = input2.trim() {
-                    break self.parser.get_result();
-                } else {
-                    self.parser
-                        .parse_one_of_lines(&input2)
-                        .map_err(|s| anyhow!("{s}"))?;
+            // definitions until `}`, each of which may span lines
+            "{" => {
+                let mut def = String::new();
+                loop {
+                    let line = read_line(CONSOLE2).unwrap_or("}".to_string());
+                    if self.parser.is_blank(&def) && line.trim() == "}" {
+                        break self.parser.get_result();
+                    }
+                    def.push_str(&line);
+                    if !self.parser.is_blank(&def) && !self.parser.is_incomplete(&def) {
+                        let res = self.parser.parse_one_of_lines(&def);
+                        def.clear();
+                        res.map_err(|s| anyhow!("{s}"))?;
+                    }
                 }
-            },
+            }
+            input if self.parser.is_blank(input) => return Ok(String::new()),
             input => {
                 let mut iter = input.split_whitespace();
                 match iter.next() {
                     None => return Ok("".to_string()),
                     Some("load") => match iter.next() {
-                        Some(f) => {
-                            let f_input = load_file(f)?;
-
-                            self.parser
-                                .parse_program(&f_input)
-                                .map_err(|s| anyhow!("{s}"))?
-                        }
+                        Some(f) => return self.load(f),
                         None => bail!("usage : load <filename>"),
                     },
+                    Some("reset") if iter.next().is_none() => return self.reset(),
+                    Some("remove") => match (iter.next(), iter.next()) {
+                        (Some(name), None) => return self.remove(name),
+                        _ => bail!("usage : remove <name>"),
+                    },
+                    Some("rename") => match (iter.next(), iter.next(), iter.next()) {
+                        (Some(name), Some(new), None) => return self.rename(name, new),
+                        _ => bail!("usage : rename <name> <new name>"),
+                    },
+                    Some(cmd) if cmd.starts_with(':') => return self.command(input.trim()),
                     _ => self.parser.parse_line(input).map_err(|s| anyhow!("{s}"))?,
                 }
             }
         };
-        let res = self.cmp.compile(prog).map_err(|msg| anyhow!("{:?}", msg))?;
+        self.exec_program(prog)
+    }
+    pub fn load(&mut self, fname: &str) -> Result<String> {
+        let prog = self
+            .parser
+            .load_program(fname, self.cmp.imported_modules())?;
+        self.exec_program(prog)
+    }
+    // the compiler is restored if the program is not accepted by the machine
+    fn exec_program(&mut self, prog: Program) -> Result<String> {
+        if let Program::Exp(e) = &prog {
+            // generic funcs are compiled for the types used in e before evaluating it
+            let cmp_clone = self.cmp.clone();
+            let res = self
+                .cmp
+                .compile_instances(e)
+                .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)));
+            if let Err(e) = res.and_then(|code| match code {
+                Some(code) => self.send_code(code),
+                None => Ok(String::new()),
+            }) {
+                self.cmp = cmp_clone;
+                return Err(e);
+            }
+        }
+        let cmp_clone = self.cmp.clone();
+        let res = self.compile_and_send(prog);
+        if let Err(e) = &res {
+            if e.downcast_ref::<InitFailed>().is_none() {
+                self.cmp = cmp_clone;
+            }
+            if e.downcast_ref::<CommitLost>().is_some() {
+                // neither the old nor the new definitions are known to be on the machine
+                bail!("{e}. The machine may run the new definitions, so type `reset` before defining anything")
+            }
+        }
+        res
+    }
+    fn compile_and_send(&mut self, prog: Program) -> Result<String> {
+        let new_nodes = match &prog {
+            Program::Module(m, _) => self
+                .cmp
+                .declare_module(m)
+                .map_err(|e| anyhow!(self.parser.sources().compile_error(&e.at(m.span))))?,
+            _ => NewIoNodes::default(),
+        };
+        let res = self
+            .cmp
+            .compile(prog)
+            .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+        for (span, msg) in self.cmp.take_warnings() {
+            println!("{}", self.parser.sources().warning(span, &msg));
+        }
+        // io nodes declared by the module header also exist on the host VM
+        if let Some(vm) = self.link.machine() {
+            for _ in new_nodes.inputs {
+                vm.add_input_node(ZERO, Box::new(|_| ()));
+            }
+            for _ in new_nodes.outputs {
+                vm.add_output_node(ZERO, Box::new(|_| ()));
+            }
+        }
         match res {
-            Some(code) => {
-                if DEBUG {
-                    println!("{:?}", code);
-                }
+            Some(code) => self.send_code(code),
+            None => Ok("defined successfully".to_string()),
+        }
+    }
+    fn send_code(&mut self, code: CompiledCode) -> Result<String> {
+        if debug() {
+            println!("{:?}", code);
+        }
 
-                let bc = to_byte_code(&code);
-                if DEBUG {
-                    println!("");
-                    println!("size : {}bytes", bc.len());
-                    println!("{:?}", bc);
-                }
-                self.port.truncate();
-                self.port.write(&bc).context("fail to write file")?;
-                if DEBUG_COMPILER {
-                    return Ok("ok".to_string());
+        let bc = to_byte_code(&code);
+        if debug() {
+            println!();
+            println!("size : {}bytes", bc.len());
+            println!("{:?}", bc);
+        }
+        // the payload is the bytecode without its length
+        match code {
+            CompiledCode::Eval(t, _) => {
+                let reply = self.link.request(MSG_EVAL, &bc[2..])?;
+                if debug() {
+                    println!("{:?}", reply);
                 }
-
-                match code {
-                    CompiledCode::Eval(t, _) => {
-                        let mut read_len = 0;
-                        let num_timeout = 10;
-                        let mut buf: Vec<u8> = iter::repeat(0).take(BUF_SIZE).collect();
-                        for _ in 0..num_timeout {
-                            read_len += self.port.read(&mut buf[read_len..])?;
-                            if read_len > 0 {
-                                let ret_val_size = buf[0] as usize;
-                                for _ in 0..num_timeout {
-                                    // datasize(u8) data status(u8)
-                                    if read_len >= ret_val_size + 2 {
-                                        if DEBUG {
-                                            println!("{:?}", &buf[0..read_len]);
-                                        }
-                                        let status = buf[ret_val_size + 1];
-                                        if status >= RES_TABLE.len() as u8 {
-                                            bail!("UART error")
-                                        } else {
-                                            if status != 0 {
-                                                bail!("{}", RES_TABLE[status as usize])
-                                            } else {
-                                                let mut s = String::new();
-                                                u8_to_value(&t, &buf[1..], &mut s)?;
-                                                return Ok(format!("[OK] {s}"));
-                                            }
-                                        }
-                                    }
-                                    read_len += self.port.read(&mut buf[read_len..])?;
-                                    thread::sleep(Duration::from_millis(10));
-                                }
-                                bail!("UART timeout. Plese try again");
-                            }
-                            thread::sleep(Duration::from_millis(10));
-                        }
-                        bail!("UART timeout. Plese try again");
-                    }
-                    CompiledCode::Def(_) => {
-                        let num_timeout = 10;
-                        let mut read_len = 0;
-                        let mut buf: Vec<u8> = iter::repeat(0).take(BUF_SIZE).collect();
-                        for _ in 0..num_timeout {
-                            read_len += self.port.read(&mut buf[read_len..])?;
-                            if read_len > 0 {
-                                let status = buf[0];
-                                if status >= RES_TABLE.len() as u8 {
-                                    bail!("UART error")
-                                } else {
-                                    if status != 0 {
-                                        bail!("{}", RES_TABLE[status as usize])
-                                    } else {
-                                        return Ok(RES_TABLE[status as usize].to_string());
-                                    }
-                                }
-                            }
-                            thread::sleep(Duration::from_millis(10));
-                        }
-                        bail!("UART timeout. Plese try again");
+                value_reply(&t, &reply)
+            }
+            CompiledCode::Def(_) => self.define(&bc[2..]),
+        }
+    }
+    // stages the definitions on the machine and then swaps them in, so that the running program is
+    // kept if they are not accepted
+    fn define(&mut self, payload: &[u8]) -> Result<String> {
+        let staged = self
+            .link
+            .request(MSG_DEFINE, payload)
+            .and_then(|reply| status_reply(&reply));
+        if let Err(e) = staged {
+            // the reply may be lost after staging
+            let _ = self.link.request(MSG_ABORT, &[]);
+            return Err(e);
+        }
+        let reply = match self.link.request(MSG_COMMIT, &[]) {
+            Ok(reply) => reply,
+            Err(e) => self.recover_commit(e)?,
+        };
+        status_reply(&reply).map_err(|e| InitFailed(e.to_string()).into())
+    }
+    // the reply to MSG_COMMIT is lost. the commit is sent again with the same seq, which the machine
+    // answers with its last reply if it has committed. if no reply arrives again, the machine is
+    // asked whether it still keeps the staged definitions
+    fn recover_commit(&mut self, lost: anyhow::Error) -> Result<Vec<u8>> {
+        if let Ok(reply) = self.link.request_again(MSG_COMMIT, &[]) {
+            return Ok(reply);
+        }
+        let staged = self.link.request(MSG_QUERY, &[QUERY_STAGED, 0, 0, 0]);
+        match staged.as_deref() {
+            // the commit did not arrive, so it is sent as a new request
+            Ok([1, 0]) => self
+                .link
+                .request(MSG_COMMIT, &[])
+                .map_err(|e| CommitLost(e).into()),
+            // the definitions are in place, but whether their initialisation failed is not known
+            Ok([0, 0]) => Err(InitFailed("no reply arrived".to_string()).into()),
+            _ => Err(CommitLost(lost).into()),
+        }
+    }
+    // commands starting with `:`, which show what is defined without changing it
+    fn command(&mut self, input: &str) -> Result<String> {
+        let (cmd, arg) = match input.split_once(char::is_whitespace) {
+            Some((cmd, arg)) => (cmd, arg.trim()),
+            None => (input, ""),
+        };
+        let is_name = !arg.is_empty() && !arg.contains(char::is_whitespace);
+        match cmd {
+            ":nodes" if arg.is_empty() => {
+                let vars: Vec<String> = self.cmp.var_list().into_iter()
+                    .map(|(kind, name, t)| format!("{kind} {name}: {t}"))
+                    .collect();
+                Ok(vars.join("\n"))
+            }
+            ":funcs" if arg.is_empty() => Ok(self.cmp.func_list().join("\n")),
+            ":types" if arg.is_empty() => Ok(self.cmp.type_list().join("\n")),
+            ":type" if !arg.is_empty() => {
+                let Program::Exp(e) = self.parser.parse_line(arg).map_err(|s| anyhow!("{s}"))?
+                else {
+                    bail!("usage : :type <expression>")
+                };
+                let t = self
+                    .cmp
+                    .type_of(&e)
+                    .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+                Ok(t.to_string())
+            }
+            ":deps" if is_name => {
+                let deps = self
+                    .cmp
+                    .node_deps(&Id(arg.to_string()))
+                    .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+                let list = |names: Vec<String>| {
+                    if names.is_empty() {
+                        "-".to_string()
+                    } else {
+                        names.join(", ")
                     }
+                };
+                Ok(format!(
+                    "refers to : {}\nat last   : {}\nused by   : {}",
+                    list(deps.now),
+                    list(deps.last),
+                    list(deps.used_by)
+                ))
+            }
+            ":order" if arg.is_empty() => {
+                let order = self
+                    .cmp
+                    .update_order()
+                    .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+                Ok(order.join(", "))
+            }
+            ":watch" if is_name => self.watch(arg),
+            ":stream" => self.stream(arg),
+            ":plot" => self.plot(arg),
+            ":graph" if matches!(arg, "" | "dot" | "json") => {
+                let graph = if arg == "json" {
+                    graph::to_json(&self.cmp)
+                } else {
+                    graph::to_dot(&self.cmp)
+                };
+                let graph = graph.map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+                Ok(graph.trim_end().to_string())
+            }
+            _ => bail!(
+                "usage : :nodes, :funcs, :types, :type <expression>, :deps <node>, :order, :watch <node>, :stream <node>... [every <n>] [> <file>], :plot <node> [every <n>] or :graph [dot|json]"
+            ),
+        }
+    }
+    // the current value of a node on the machine
+    pub fn node_value(&mut self, name: &str) -> Result<String> {
+        let (i, t) = self
+            .cmp
+            .node_slot(&Id(name.to_string()))
+            .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+        let mut payload = vec![QUERY_NODE, t.is_obj_type() as u8];
+        payload.extend((i as u16).to_le_bytes());
+        let reply = self.link.request(MSG_QUERY, &payload)?;
+        reply_value(&t, &reply)
+    }
+    // prints the value of a node whenever it changes, until Enter is pressed
+    fn watch(&mut self, name: &str) -> Result<String> {
+        self.node_value(name)?;
+        println!("watching {name}. press Enter to stop");
+        let mut last = None;
+        self.until_enter(|repl| {
+            // the machine may be busy, so errors are shown as values and watching goes on
+            let v = repl.node_value(name).unwrap_or_else(|e| e.to_string());
+            if last.as_ref() != Some(&v) {
+                println!("{name} = {v}");
+                last = Some(v);
+            }
+            thread::sleep(WATCH_INTERVAL);
+            Ok(())
+        })
+    }
+    // calls f until Enter is pressed. if f fails, the error is returned after Enter is pressed,
+    // so that the line is not read as the next input
+    fn until_enter(&mut self, mut f: impl FnMut(&mut Self) -> Result<()>) -> Result<String> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = stdin().read_line(&mut String::new());
+            let _ = tx.send(());
+        });
+        while rx.try_recv().is_err() {
+            if let Err(e) = f(self) {
+                println!("{e}. press Enter");
+                let _ = rx.recv();
+                return Err(e);
+            }
+        }
+        Ok(String::new())
+    }
+    // asks the machine to send the values of nodes after every `every`th update.
+    // no nodes stop it
+    pub fn subscribe(&mut self, names: &[&str], every: u16) -> Result<()> {
+        if names.len() > MAX_SUBSCRIBE {
+            bail!("at most {MAX_SUBSCRIBE} nodes can be streamed at once")
+        }
+        // status, and len(u16) size(u16) a word and status for each node at least
+        let size = self.link.telemetry_size()?;
+        if 1 + names.len() * 9 > size {
+            bail!(
+                "the values of {} nodes do not fit in the {size} bytes the machine sends after an update",
+                names.len()
+            )
+        }
+        let mut payload = every.to_le_bytes().to_vec();
+        let mut types = vec![];
+        for name in names {
+            let (i, t) = self
+                .cmp
+                .node_slot(&Id(name.to_string()))
+                .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+            payload.push(t.is_obj_type() as u8);
+            payload.extend((i as u16).to_le_bytes());
+            types.push(t);
+        }
+        let reply = self.link.request(MSG_SUBSCRIBE, &payload)?;
+        status_reply(&reply)?;
+        self.link.clear_telemetry();
+        self.streamed = types;
+        Ok(())
+    }
+    // values sent by the machine after an update, waiting at most `timeout` for them
+    pub fn next_sample(&mut self, timeout: Duration) -> Result<Option<Sample>> {
+        let Some(f) = self.link.recv_telemetry(timeout)? else {
+            return Ok(None);
+        };
+        let Some((&status, mut rest)) = f.payload.split_first() else {
+            bail!("UART error")
+        };
+        if let Err(e) = status_reply(&[status]) {
+            return Ok(Some(Sample {
+                seq: f.seq,
+                values: Err(e.to_string()),
+            }));
+        }
+        let mut values = vec![];
+        for t in &self.streamed {
+            let Some((len, value)) = rest.split_first_chunk::<2>() else {
+                bail!("UART error")
+            };
+            let len = u16::from_le_bytes(*len) as usize;
+            if value.len() < len {
+                bail!("UART error")
+            }
+            values.push(reply_value(t, &value[..len])?);
+            rest = &value[len..];
+        }
+        Ok(Some(Sample {
+            seq: f.seq,
+            values: Ok(values),
+        }))
+    }
+    // prints the values of nodes after updates, or writes them to a CSV file
+    //   :stream <node>... [every <n>] [> <file>]
+    fn stream(&mut self, arg: &str) -> Result<String> {
+        let (names, every, file) = stream_args(arg)?;
+        let mut csv = match file {
+            Some(file) => {
+                let mut csv =
+                    fs::File::create(file).with_context(|| format!("could not write {file}"))?;
+                writeln!(csv, "seq,{}", names.join(","))?;
+                Some(csv)
+            }
+            None => None,
+        };
+        self.subscribe(&names, every)?;
+        println!("streaming. press Enter to stop");
+        let res = self.until_enter(|repl| {
+            let Some(sample) = repl.next_sample(WATCH_INTERVAL)? else {
+                return Ok(());
+            };
+            match (&mut csv, sample.values) {
+                (Some(csv), Ok(values)) => {
+                    let values: Vec<String> = values.iter().map(|v| csv_field(v)).collect();
+                    writeln!(csv, "{},{}", sample.seq, values.join(","))?;
+                }
+                (None, Ok(values)) => {
+                    let values: Vec<String> = (names.iter().zip(values))
+                        .map(|(name, v)| format!("{name} = {v}"))
+                        .collect();
+                    println!("{}", values.join(", "));
                 }
+                (_, Err(e)) => println!("{e}"),
+            }
+            Ok(())
+        });
+        self.subscribe(&[], 1)?;
+        res
+    }
+    // draws the value of an Int, Float or Bool node after updates as the position of `*`
+    //   :plot <node> [every <n>]
+    fn plot(&mut self, arg: &str) -> Result<String> {
+        let (names, every, file) = stream_args(arg)?;
+        let ([name], None) = (&names[..], file) else {
+            bail!("usage : :plot <node> [every <n>]")
+        };
+        self.subscribe(&[name], every)?;
+        if self.streamed[0].is_obj_type() {
+            self.subscribe(&[], 1)?;
+            bail!("only a node of Int, Float or Bool can be plotted")
+        }
+        println!("plotting {name}. press Enter to stop");
+        // the scale grows to the smallest and largest values so far
+        let (mut lo, mut hi) = (f64::MAX, f64::MIN);
+        let res = self.until_enter(|repl| {
+            let Some(sample) = repl.next_sample(WATCH_INTERVAL)? else {
+                return Ok(());
+            };
+            let v = match sample.values {
+                Ok(values) => values[0].clone(),
+                Err(e) => {
+                    println!("{e}");
+                    return Ok(());
+                }
+            };
+            let x = match v.as_str() {
+                "true" => 1.0,
+                "false" => 0.0,
+                v => v.parse::<f64>()?,
+            };
+            lo = lo.min(x);
+            hi = hi.max(x);
+            let pos = if hi > lo {
+                ((x - lo) / (hi - lo) * PLOT_WIDTH as f64).round() as usize
+            } else {
+                0
+            };
+            println!("{v:>12} |{}*", " ".repeat(pos));
+            Ok(())
+        });
+        self.subscribe(&[], 1)?;
+        res
+    }
+    // removes a node, data or func, which nothing else may refer to, from the compiler and the machine
+    pub fn remove(&mut self, name: &str) -> Result<String> {
+        let cmp_clone = self.cmp.clone();
+        let res = self.remove_and_send(name);
+        if res.is_err() {
+            self.cmp = cmp_clone;
+        }
+        res
+    }
+    fn remove_and_send(&mut self, name: &str) -> Result<String> {
+        let code = self
+            .cmp
+            .remove(&Id(name.to_string()))
+            .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+        // the instances of a generic func are removed in one request, so that the machine does not
+        // keep some of them if a request fails
+        let mut payload = (code.n_last as u16).to_le_bytes().to_vec();
+        payload.push(code.slots.len() as u8);
+        for &(kind, is_obj, i) in &code.slots {
+            let kind = match kind {
+                DefKind::Node => QUERY_NODE,
+                DefKind::Data => QUERY_DATA,
+                DefKind::Func => REMOVE_FUNC,
+            };
+            payload.extend([kind, is_obj as u8]);
+            payload.extend((i as u16).to_le_bytes());
+        }
+        for insn in &code.update {
+            insn.push_byte_code(&mut payload);
+        }
+        let reply = self.link.request(MSG_REMOVE, &payload)?;
+        status_reply(&reply)?;
+        Ok(format!("{name} is removed"))
+    }
+    // gives a node, data or func another name. nothing is sent, as the machine knows it by its slot
+    pub fn rename(&mut self, name: &str, new: &str) -> Result<String> {
+        if !is_var_name(new) {
+            bail!("{new} can not be the name of a node, data or func")
+        }
+        self.cmp
+            .rename(&Id(name.to_string()), &Id(new.to_string()))
+            .map_err(|e| anyhow!(self.parser.sources().compile_error(&e)))?;
+        Ok(format!("{name} is renamed to {new}"))
+    }
+    // restarts the machine, which forgets everything but the input and output nodes
+    pub fn reset(&mut self) -> Result<String> {
+        let io = self.cmp.io_nodes();
+        let res = self.link.request(MSG_RESET, &[]);
+        // what the compiler knows about the machine is forgotten even if the machine is not reset,
+        // so that `reset` can be typed again once it is connected
+        self.cmp = Compiler::new();
+        for (name, typ) in io.inputs {
+            self.cmp.add_input_node(&name, typ);
+        }
+        for (name, typ) in io.outputs {
+            self.cmp.add_output_node(&name, typ);
+        }
+        res?;
+        Ok("machine is reset".to_string())
+    }
+    // sends the frames of an image made by `compile`, which must be made for the nodes of this machine
+    pub fn load_image(&mut self, fname: &str) -> Result<String> {
+        let bytes = fs::read(fname).with_context(|| format!("could not read {fname}"))?;
+        let image = Image::decode(&bytes).with_context(|| format!("could not load {fname}"))?;
+        let mut io = self.cmp.io_nodes();
+        // the host VM has no devices, so it registers the nodes of the image
+        if io.inputs.is_empty() && io.outputs.is_empty() && self.link.machine().is_some() {
+            for (name, typ) in &image.inputs {
+                self.add_input_node(name, typ.clone());
+            }
+            for (name, typ) in &image.outputs {
+                self.add_output_node(name, typ.clone());
+            }
+            io = self.cmp.io_nodes();
+        }
+        if image.inputs != io.inputs || image.outputs != io.outputs {
+            bail!(
+                "{fname} is made for input nodes {:?} and output nodes {:?}",
+                image.inputs,
+                image.outputs
+            )
+        }
+        let mut res = String::new();
+        for frame in &image.frames {
+            // the reply to an eval frame depends on the type of the expression, which is not in the image
+            if frame.get(2) != Some(&0) {
+                bail!("{fname} contains an eval frame, which can only be loaded at boot")
             }
-            None => return Ok("defined successfully".to_string()),
+            res = self.define(&frame[2..])?;
         }
+        Ok(res)
     }
 }
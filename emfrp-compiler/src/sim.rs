@@ -0,0 +1,379 @@
+// deterministic simulation of a program on the host VM, one update per tick.
+// input nodes take their values from a trace instead of devices, and the values of all nodes are
+// recorded after each update. a column of the trace which is not an input node is the value the
+// node is expected to have after the update of that tick
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::ast::Id;
+use crate::compile::compile_common::CompiledCode;
+use crate::compile::compile_common::Compiler;
+use crate::compile::compile_common::Type;
+use crate::graph::quote;
+use crate::insn::to_byte_code;
+use crate::machine::Machine;
+use crate::machine::Value;
+use crate::machine::ZERO;
+use crate::protocol::QUERY_NODE;
+use crate::repl::reply_value;
+use crate::repl::status_reply;
+
+// values of named nodes per tick. an empty cell keeps the previous value of an input node and
+// expects nothing of other nodes
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Trace {
+    pub names: Vec<String>,
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub tick: usize,
+    pub node: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+// values of input, output and other nodes after each update, in the order of `names`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub names: Vec<String>,
+    pub types: Vec<Type>,
+    pub rows: Vec<Vec<String>>,
+    pub failures: Vec<Failure>,
+}
+
+// fields of a line, where a field in "" may contain commas and "" stands for "
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => res.push(std::mem::take(&mut field).trim().to_string()),
+            c => field.push(c),
+        }
+    }
+    res.push(field.trim().to_string());
+    res
+}
+
+pub fn csv_field(v: &str) -> String {
+    if v.contains([',', '"']) {
+        format!("\"{}\"", v.replace('"', "\"\""))
+    } else {
+        v.to_string()
+    }
+}
+
+impl Trace {
+    // a header of node names and a row per tick. lines starting with # are skipped
+    pub fn from_csv(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with('#'));
+        let Some((_, header)) = lines.next() else {
+            bail!("the trace has no header")
+        };
+        let names = csv_fields(header);
+        let mut rows = vec![];
+        for (i, line) in lines {
+            let row = csv_fields(line);
+            if row.len() != names.len() {
+                bail!(
+                    "line {} has {} fields, but the header has {}",
+                    i + 1,
+                    row.len(),
+                    names.len()
+                )
+            }
+            rows.push(
+                row.into_iter()
+                    .map(|v| (!v.is_empty()).then_some(v))
+                    .collect(),
+            );
+        }
+        Ok(Self { names, rows })
+    }
+    // an array with an object per tick, e.g. [{"gpio16": true}, {"gpio16": false, "gpio5": true}]
+    pub fn from_json(text: &str) -> Result<Self> {
+        let mut p = JsonParser {
+            s: text.as_bytes(),
+            i: 0,
+        };
+        let mut res = Self::default();
+        p.expect(b'[')?;
+        while !p.eat(b']') {
+            if !res.rows.is_empty() {
+                p.expect(b',')?;
+            }
+            let mut row = vec![None; res.names.len()];
+            p.expect(b'{')?;
+            let mut first = true;
+            while !p.eat(b'}') {
+                if !first {
+                    p.expect(b',')?;
+                }
+                first = false;
+                let name = p.string()?;
+                p.expect(b':')?;
+                let v = p.scalar()?;
+                let i = match res.names.iter().position(|n| *n == name) {
+                    Some(i) => i,
+                    None => {
+                        res.names.push(name);
+                        row.push(None);
+                        res.names.len() - 1
+                    }
+                };
+                row[i] = v;
+            }
+            res.rows.push(row);
+        }
+        if p.skip_ws() < p.s.len() {
+            bail!("unexpected text after the trace at byte {}", p.i)
+        }
+        // nodes named later are not given in the earlier rows
+        for row in &mut res.rows {
+            row.resize(res.names.len(), None);
+        }
+        Ok(res)
+    }
+    // json if the file name ends with .json, and csv otherwise
+    pub fn load(file: &str) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(file).map_err(|e| anyhow!("could not read {file}: {e}"))?;
+        let trace = if file.ends_with(".json") {
+            Self::from_json(&text)
+        } else {
+            Self::from_csv(&text)
+        };
+        trace.map_err(|e| anyhow!("{file} : {e}"))
+    }
+}
+
+// the flat subset of JSON a trace is written in
+struct JsonParser<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+impl JsonParser<'_> {
+    fn skip_ws(&mut self) -> usize {
+        while self.i < self.s.len() && self.s[self.i].is_ascii_whitespace() {
+            self.i += 1;
+        }
+        self.i
+    }
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_ws();
+        if self.s.get(self.i) == Some(&c) {
+            self.i += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn expect(&mut self, c: u8) -> Result<()> {
+        if !self.eat(c) {
+            bail!("expected `{}` at byte {}", c as char, self.i)
+        }
+        Ok(())
+    }
+    fn string(&mut self) -> Result<String> {
+        self.expect(b'"')?;
+        let mut res = vec![];
+        loop {
+            match self.s.get(self.i) {
+                None => bail!("unterminated string"),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.i += 1;
+                    match self.s.get(self.i) {
+                        Some(b'n') => res.push(b'\n'),
+                        Some(&c @ (b'"' | b'\\' | b'/')) => res.push(c),
+                        _ => bail!("unsupported escape at byte {}", self.i),
+                    }
+                }
+                Some(&c) => res.push(c),
+            }
+            self.i += 1;
+        }
+        self.i += 1;
+        Ok(String::from_utf8(res)?)
+    }
+    // a number, true, false or a string as it is written, and null as no value
+    fn scalar(&mut self) -> Result<Option<String>> {
+        self.skip_ws();
+        if self.s.get(self.i) == Some(&b'"') {
+            return Ok(Some(self.string()?));
+        }
+        let start = self.i;
+        while self.i < self.s.len() && !b",}] \t\r\n".contains(&self.s[self.i]) {
+            self.i += 1;
+        }
+        match std::str::from_utf8(&self.s[start..self.i])? {
+            "" => bail!("expected a value at byte {start}"),
+            "null" => Ok(None),
+            v => Ok(Some(v.to_string())),
+        }
+    }
+}
+
+fn input_value(t: &Type, v: &str) -> Result<Value> {
+    let res = match (t, v) {
+        (Type::Bool, "true" | "1") => Some(1),
+        (Type::Bool, "false" | "0") => Some(0),
+        (Type::Int, v) => v.parse().ok(),
+        (Type::Float, v) => v.parse::<f32>().ok().map(|f| f.to_bits() as i32),
+        _ => None,
+    };
+    res.map(Value::Num)
+        .ok_or(anyhow!("{v} is not a value of {t}"))
+}
+// an expected value written as it is printed, where a Float may be written without `.0`
+fn same_value(t: &Type, expected: &str, actual: &str) -> bool {
+    match t {
+        Type::Float => expected.parse::<f32>().ok() == actual.parse::<f32>().ok(),
+        Type::Bool if expected == "1" => actual == "true",
+        Type::Bool if expected == "0" => actual == "false",
+        _ => expected.replace(' ', "") == actual,
+    }
+}
+
+pub fn simulate(
+    cmp: &Compiler,
+    code: &CompiledCode,
+    trace: &Trace,
+    ticks: usize,
+) -> Result<Record> {
+    let mut vm = Machine::new();
+    // the machine registers input nodes before output nodes, as the devices do
+    let io = cmp.io_nodes();
+    let inputs = Rc::new(RefCell::new(vec![ZERO; io.inputs.len()]));
+    for k in 0..io.inputs.len() {
+        let inputs = inputs.clone();
+        vm.add_input_node(ZERO, Box::new(move |v| *v = inputs.borrow()[k]));
+    }
+    for _ in &io.outputs {
+        vm.add_output_node(ZERO, Box::new(|_| ()));
+    }
+    let bc = to_byte_code(code);
+    vm.new_bytecode(&bc[2..]);
+    status_reply(&vm.take_uart()).map_err(|e| anyhow!("{e} while initialising the program"))?;
+
+    let mut nodes = vec![];
+    for (kind, name, t) in cmp.var_list() {
+        if kind != "data" {
+            let (i, _) = cmp
+                .node_slot(&Id(name.clone()))
+                .map_err(|e| anyhow!("error: {:?}", e))?;
+            nodes.push((name, i, t));
+        }
+    }
+    // the column of the trace each input node takes its values from
+    let mut columns = vec![];
+    for (name, t) in &io.inputs {
+        columns.push((trace.names.iter().position(|n| n == name), t));
+    }
+    for name in &trace.names {
+        if !nodes.iter().any(|(n, _, _)| n == name) {
+            bail!("{name} of the trace is not a node")
+        }
+    }
+
+    let mut res = Record {
+        names: nodes.iter().map(|(n, _, _)| n.clone()).collect(),
+        types: nodes.iter().map(|(_, _, t)| t.clone()).collect(),
+        rows: vec![],
+        failures: vec![],
+    };
+    for tick in 1..=ticks {
+        let row = trace.rows.get(tick - 1);
+        for (k, (col, t)) in columns.iter().enumerate() {
+            if let Some(Some(v)) = col.and_then(|c| row.map(|r| &r[c])) {
+                inputs.borrow_mut()[k] =
+                    input_value(t, v).map_err(|e| anyhow!("tick {tick} : {e}"))?;
+            }
+        }
+        let status = vm.update();
+        status_reply(&[status as u8]).map_err(|e| anyhow!("tick {tick} : {e}"))?;
+        let mut values = vec![];
+        for (_, i, t) in &nodes {
+            vm.query(QUERY_NODE, t.is_obj_type(), *i);
+            values.push(reply_value(t, &vm.take_uart()).map_err(|e| anyhow!("tick {tick} : {e}"))?);
+        }
+        if let Some(row) = row {
+            for (name, expected) in trace.names.iter().zip(row) {
+                let Some(expected) = expected else {
+                    continue;
+                };
+                if io.inputs.iter().any(|(n, _)| n == name) {
+                    continue;
+                }
+                let j = res.names.iter().position(|n| n == name).unwrap();
+                if !same_value(&res.types[j], expected, &values[j]) {
+                    res.failures.push(Failure {
+                        tick,
+                        node: name.clone(),
+                        expected: expected.clone(),
+                        actual: values[j].clone(),
+                    });
+                }
+            }
+        }
+        res.rows.push(values);
+    }
+    Ok(res)
+}
+
+impl Record {
+    pub fn to_csv(&self) -> String {
+        let mut res = format!("tick,{}\n", self.names.join(","));
+        for (tick, row) in self.rows.iter().enumerate() {
+            let row: Vec<String> = row.iter().map(|v| csv_field(v)).collect();
+            res.push_str(&format!("{},{}\n", tick + 1, row.join(",")));
+        }
+        res
+    }
+    // numbers and booleans are written as they are, NaN and infinities as null, and values of
+    // other types as strings
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(tick, row)| {
+                let mut fields = vec![format!("\"tick\": {}", tick + 1)];
+                for ((name, t), v) in self.names.iter().zip(&self.types).zip(row) {
+                    let v = match t {
+                        Type::Int | Type::Bool => v.clone(),
+                        // JSON has no NaN or infinity
+                        Type::Float => match v.parse::<f32>() {
+                            Ok(f) if f.is_finite() => v.clone(),
+                            _ => "null".to_string(),
+                        },
+                        _ => quote(v),
+                    };
+                    fields.push(format!("{}: {v}", quote(name)));
+                }
+                format!("  {{{}}}", fields.join(", "))
+            })
+            .collect();
+        if rows.is_empty() {
+            "[]\n".to_string()
+        } else {
+            format!("[\n{}\n]\n", rows.join(",\n"))
+        }
+    }
+}
@@ -0,0 +1,82 @@
+mod cli;
+mod errors;
+mod exhaustive;
+mod golden;
+mod infer;
+mod object;
+mod parse;
+mod prop;
+mod protocol;
+mod record;
+mod repl;
+mod sim;
+mod vm;
+
+use crate::compile::compile_common::CompiledCode;
+use crate::disasm::decode_frame;
+use crate::disasm::decode_insns;
+use crate::disasm::listing;
+use crate::insn::*;
+use crate::repl::Repl;
+use crate::transport::LoopbackTransport;
+
+// a REPL on the host VM, with the definitions interpreted in order
+pub fn repl(defs: &[&str]) -> Repl {
+    let mut repl = Repl::new(Box::new(LoopbackTransport::new()));
+    for def in defs {
+        repl.interpret(def).unwrap();
+    }
+    repl
+}
+
+// checks what the machine relies on in every section of code:
+// lengths sent in the frame match the emitted bytes, the bytes decode into the same instructions
+// and every jump lands on an instruction
+pub fn check_code(code: &CompiledCode) {
+    match code {
+        CompiledCode::Eval(_, e) => check_insns(e),
+        CompiledCode::Def(def) => {
+            for (_, insns) in def.node.iter().chain(&def.func) {
+                check_insns(insns);
+            }
+            check_insns(&def.update);
+            check_insns(&def.init);
+        }
+    }
+    let decoded = decode_frame(&to_byte_code(code)).unwrap();
+    assert_eq!(listing(&decoded), listing(code));
+}
+fn check_insns(insns: &[Insn]) {
+    let mut bytes = vec![];
+    for insn in insns {
+        let start = bytes.len();
+        insn.push_byte_code(&mut bytes);
+        assert_eq!(
+            bytecode_len(std::slice::from_ref(insn)),
+            bytes.len() - start,
+            "length of {:?}",
+            insn
+        );
+    }
+    assert_eq!(bytecode_len(insns), bytes.len());
+    let decoded = decode_insns(&bytes).unwrap();
+    let offsets: Vec<usize> = decoded.iter().map(|(i, _)| *i).collect();
+    for (k, (offset, insn)) in decoded.iter().enumerate() {
+        assert_eq!(insn, &insns[k]);
+        let jump = match insn {
+            Insn::J0 | Insn::Je0 | Insn::Jne0 => 0,
+            Insn::J1 | Insn::Je1 | Insn::Jne1 => 1,
+            Insn::J8(i) | Insn::Je8(i) | Insn::Jne8(i) => *i as i64,
+            Insn::J16(i) | Insn::Je16(i) | Insn::Jne16(i) => *i as i64,
+            Insn::J32(i) | Insn::Je32(i) | Insn::Jne32(i) => *i as i64,
+            _ => continue,
+        };
+        let next = offset + bytecode_len(std::slice::from_ref(insn));
+        let target = next as i64 + jump;
+        assert!(
+            target == bytes.len() as i64 || offsets.contains(&(target as usize)),
+            "{:?} at {offset} jumps to {target}, which is not an instruction",
+            insn
+        );
+    }
+}
@@ -0,0 +1,103 @@
+// command-line arguments
+use std::time::Duration;
+
+use crate::cli::*;
+
+// arguments separated by spaces
+fn parse(args: &str) -> anyhow::Result<Options> {
+    parse_args(args.split_whitespace().map(|s| s.to_string()))
+}
+fn err(args: &str) -> String {
+    parse(args).unwrap_err().to_string()
+}
+fn names(nodes: &[IoNode]) -> Vec<String> {
+    nodes.iter().map(|(n, t)| format!("{n}:{t:?}")).collect()
+}
+
+#[test]
+fn board_defaults() {
+    let o = parse("").unwrap();
+    assert_eq!((o.command, o.board), (Command::Repl, Board::Esp32));
+    assert_eq!(o.port, "/dev/cu.usbserial-0001");
+    assert_eq!((o.baud_rate, o.timeout), (115200, Duration::from_secs(2)));
+    assert_eq!(names(&o.inputs), ["gpio16:Bool"]);
+    assert_eq!(names(&o.outputs), ["gpio5:Bool"]);
+    assert!(!o.json && !o.debug && o.out.is_none() && o.header.is_none());
+    assert!(o.trace.is_none() && o.ticks.is_none());
+
+    let o = parse("--board uno").unwrap();
+    assert_eq!(
+        (o.board, o.port.as_str()),
+        (Board::Uno, "/dev/cu.usbmodem142101")
+    );
+    assert!(o.inputs.is_empty());
+    assert_eq!(names(&o.outputs), ["led:Bool"]);
+
+    let o = parse("--board microbit").unwrap();
+    assert_eq!(
+        (o.board, o.port.as_str()),
+        (Board::Microbit, "/dev/cu.usbmodem142101")
+    );
+    assert_eq!(names(&o.inputs), ["gpio2:Bool"]);
+    assert_eq!(names(&o.outputs), ["gpio1:Bool"]);
+
+    let o = parse("--board host").unwrap();
+    assert_eq!((o.board, o.port.as_str()), (Board::Host, "loopback"));
+    assert!(o.inputs.is_empty() && o.outputs.is_empty());
+}
+
+#[test]
+fn options() {
+    let o = parse(
+        "compile a.mfrp --board uno -p pipe:/tmp/a -b 9600 -t 500 --input btn:Int -d -o a.bin --header a.h",
+    )
+    .unwrap();
+    assert_eq!(o.command, Command::Compile("a.mfrp".to_string()));
+    assert_eq!((o.port.as_str(), o.baud_rate), ("pipe:/tmp/a", 9600));
+    assert_eq!(o.timeout, Duration::from_millis(500));
+    // nodes given on the command line replace all the nodes of the board
+    assert_eq!(names(&o.inputs), ["btn:Int"]);
+    assert!(o.outputs.is_empty());
+    assert!(o.debug);
+    assert_eq!(o.out.as_deref(), Some("a.bin"));
+    assert_eq!(o.header.as_deref(), Some("a.h"));
+    assert_eq!(
+        parse("asm a.asm").unwrap().command,
+        Command::Asm("a.asm".to_string())
+    );
+    let o = parse("graph a.mfrp --json -o a.json").unwrap();
+    assert_eq!(o.command, Command::Graph("a.mfrp".to_string()));
+    assert!(o.json);
+    assert_eq!(o.out.as_deref(), Some("a.json"));
+    let o = parse("sim a.mfrp --trace in.csv --ticks 3").unwrap();
+    assert_eq!(o.command, Command::Sim("a.mfrp".to_string()));
+    assert_eq!((o.trace.as_deref(), o.ticks), (Some("in.csv"), Some(3)));
+    assert_eq!(parse("run -h").unwrap().command, Command::Help);
+}
+
+#[test]
+fn errors() {
+    for (args, e) in [
+        ("--frobnicate", "unknown option --frobnicate"),
+        ("-p", "-p requires a value"),
+        ("repl --port", "--port requires a value"),
+        ("--board", "--board requires a value"),
+        ("--board pico", "unknown board pico"),
+        ("-b fast", "invalid baud rate fast"),
+        ("-t 1s", "invalid timeout 1s"),
+        ("--ticks -1", "invalid number of ticks -1"),
+        (
+            "--input btn:String",
+            "type of an input/output node must be Int, Bool or Float, but String was given",
+        ),
+        ("--output led", "expected <name:Type>, but led was given"),
+        ("compile", "usage: compile <file>"),
+        ("asm", "usage: asm <file>"),
+        ("graph", "usage: graph <file>"),
+        ("sim", "usage: sim <file>"),
+        ("flash", "unknown command flash"),
+        ("run a.mfrp b.mfrp", "unexpected argument b.mfrp"),
+    ] {
+        assert_eq!(err(args), e, "{args:?}");
+    }
+}
@@ -0,0 +1,54 @@
+// compiles every tests/golden/errors/<name>.mfrp and compares the rendered error with
+// tests/golden/errors/<name>.err. UPDATE_GOLDEN=1 cargo test rewrites the errors
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::compile::compile_common::Compiler;
+use crate::parser::ParserWrapper;
+
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/errors");
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "mfrp"))
+        .collect();
+    files.sort();
+    files
+}
+
+// the rendered error, with the file name only so that it does not depend on the directory
+fn diagnose(path: &Path) -> String {
+    let name = path.file_name().unwrap().to_str().unwrap();
+    let src = fs::read_to_string(path).unwrap();
+    let mut parser = ParserWrapper::new();
+    let prog = match parser.parse_program(name, &src) {
+        Ok(prog) => prog,
+        Err(e) => return format!("{e}\n"),
+    };
+    match Compiler::new().compile(prog) {
+        Ok(_) => panic!("{name} is compiled without errors"),
+        Err(e) => format!("{}\n", parser.sources().compile_error(&e)),
+    }
+}
+
+#[test]
+fn errors() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failed = vec![];
+    for path in corpus() {
+        let actual = diagnose(&path);
+        let golden = path.with_extension("err");
+        if update {
+            fs::write(&golden, &actual).unwrap();
+        } else if fs::read_to_string(&golden).unwrap_or_default() != actual {
+            failed.push(format!("{}:\n{actual}", golden.display()));
+        }
+    }
+    assert!(
+        failed.is_empty(),
+        "errors differ (run with UPDATE_GOLDEN=1 to accept them)\n{}",
+        failed.join("\n")
+    );
+}
@@ -0,0 +1,145 @@
+// exhaustiveness and redundancy of `of:`, and nested patterns on the machine
+use super::repl;
+use crate::compile::compile_common::Compiler;
+use crate::parser::ParserWrapper;
+
+const TYPES: &str = "type Shape = Circle(Int) | Rect(Int, Int) | Empty\n";
+
+// the error, or the patterns of the branches which are never taken
+fn check(def: &str) -> Result<Vec<String>, String> {
+    let src = format!("{TYPES}{def}\n");
+    let mut cmp = Compiler::new();
+    let mut parser = ParserWrapper::new();
+    let prog = parser.parse_program("prog.mfrp", &src).unwrap();
+    match cmp.compile(prog) {
+        Ok(_) => Ok(cmp
+            .take_warnings()
+            .into_iter()
+            .map(|(span, _)| src[span.start..span.end].to_string())
+            .collect()),
+        Err(e) => Err(format!("{e:?}")),
+    }
+}
+
+#[test]
+fn missing_patterns() {
+    for (def, missing) in [
+        ("func f(x: Int) -> Int = x of: 0 -> { 1 } 1 -> { 2 }", "2"),
+        ("func f(x: Bool) -> Int = x of: true -> { 1 }", "false"),
+        (
+            "func f(s: Shape) -> Int = s of: Circle(r) -> { r } Empty -> { 0 }",
+            "Rect(_, _)",
+        ),
+        (
+            "func f(x: Bool, y: Bool) -> Int = (x, y) of: (true, _) -> { 1 } (false, true) -> { 2 }",
+            "(false, false)",
+        ),
+        (
+            "func f(s: Shape) -> Int = s of: Circle(0) -> { 0 } Rect(_, _) -> { 1 } Empty -> { 2 }",
+            "Circle(1)",
+        ),
+        (
+            "func f(s: (Shape, Int)) -> Int = s of: (Empty, _) -> { 0 } (_, 3) -> { 1 }",
+            "(Circle(_), 0)",
+        ),
+        // a guarded branch covers nothing
+        (
+            "func f(x: Bool) -> Int = x of: true -> { 1 } b when !b -> { 0 }",
+            "false",
+        ),
+    ] {
+        assert_eq!(
+            check(def),
+            Err(format!("Non-exhaustive match: {missing} is not covered")),
+            "{def}"
+        );
+    }
+}
+
+#[test]
+fn unreachable_branches() {
+    assert_eq!(
+        check("func f(x: Int) -> Int = x of: n -> { n } 0 -> { 1 }"),
+        Ok(vec!["0".to_string()])
+    );
+    assert_eq!(
+        check("func f(x: Bool) -> Int = x of: true -> { 1 } false -> { 0 } _ -> { 2 }"),
+        Ok(vec!["_".to_string()])
+    );
+    assert_eq!(
+        check(
+            "func f(s: Shape) -> Int = s of: Circle(r) -> { r } Rect(w, h) -> { w * h } Empty -> { 0 }"
+        ),
+        Ok(vec![])
+    );
+    assert_eq!(
+        check(
+            "func f(x: Int) -> Int = x of: n when n > 0 -> { n } 0 when true -> { 1 } _ -> { 0 }"
+        ),
+        Ok(vec![])
+    );
+    assert_eq!(
+        check("func f(x: Bool) -> Int = x of: _ -> { 1 } b when b -> { 0 }"),
+        Ok(vec!["b".to_string()])
+    );
+}
+
+#[test]
+fn nested_patterns() {
+    let mut repl = repl(&[
+        TYPES.trim(),
+        "type Maybe<a> = Just(a) | Nothing",
+        "func f(m: Maybe<(Shape, Bool)>) -> Int = m of: Just((Circle(0), _)) -> { 0 } Just((Circle(r), true)) -> { r } Just((Rect(w, h), false)) -> { w * h } Just((s, _)) -> { s of: Empty -> { 1 } _ -> { 2 } } Nothing -> { 3 }",
+        "func g(p: (Maybe<Int>, Shape)) -> Maybe<Shape> = p of: (Just(1), s) -> { Just(s) } (_, Rect(w, _)) -> { Just(Circle(w)) } _ -> { Nothing }",
+    ]);
+    for (e, v) in [
+        ("f(Just((Circle(0), true)))", "0"),
+        ("f(Just((Circle(5), true)))", "5"),
+        ("f(Just((Circle(5), false)))", "2"),
+        ("f(Just((Rect(2, 3), false)))", "6"),
+        ("f(Just((Rect(2, 3), true)))", "2"),
+        ("f(Just((Empty, true)))", "1"),
+        ("f(Nothing)", "3"),
+        (
+            "g((Just(1), Rect(4, 5))) of: Just(Rect(w, h)) -> { w * h } _ -> { 0 }",
+            "20",
+        ),
+        (
+            "g((Just(2), Rect(4, 5))) of: Just(Circle(r)) -> { r } _ -> { 0 }",
+            "4",
+        ),
+        ("g((Nothing, Empty)) of: Nothing -> { 7 } _ -> { 0 }", "7"),
+    ] {
+        assert_eq!(repl.interpret(e).unwrap(), format!("[OK] {v}"), "{e}");
+    }
+    // fields bound by the patterns are not left behind
+    assert_eq!(repl.link().machine().unwrap().live_objects(), 0);
+}
+
+#[test]
+fn guards() {
+    let mut repl = repl(&[
+        TYPES.trim(),
+        "func f(s: Shape) -> Int = s of: Circle(r) when r > 10 -> { 100 } Rect(w, h) when w == h -> { w } Circle(r) -> { r } Rect(_, h) when h < 0 -> { -1 } _ -> { 0 }",
+        "func sign(x: Int) -> Int = x of: n when n < 0 -> { -1 } 0 -> { 0 } n when (n of: 1 -> { true } _ -> { false }) -> { 1 } _ -> { 2 }",
+    ]);
+    for (e, v) in [
+        ("f(Circle(11))", "100"),
+        ("f(Circle(3))", "3"),
+        ("f(Rect(4, 4))", "4"),
+        ("f(Rect(4, -2))", "-1"),
+        ("f(Rect(4, 5))", "0"),
+        ("f(Empty)", "0"),
+        ("sign(-5)", "-1"),
+        ("sign(0)", "0"),
+        ("sign(1)", "1"),
+        ("sign(9)", "2"),
+    ] {
+        assert_eq!(repl.interpret(e).unwrap(), format!("[OK] {v}"), "{e}");
+    }
+    assert_eq!(repl.link().machine().unwrap().live_objects(), 0);
+    let err = repl
+        .interpret("1 of: n when n -> { 0 } _ -> { 1 }")
+        .unwrap_err();
+    assert!(err.to_string().contains("Bool"), "{err}");
+}
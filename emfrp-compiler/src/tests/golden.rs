@@ -0,0 +1,67 @@
+// compiles every tests/golden/<name>.mfrp and compares the listing with tests/golden/<name>.asm.
+// UPDATE_GOLDEN=1 cargo test rewrites the listings
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::check_code;
+use crate::compile::compile_common::Compiler;
+use crate::disasm::assemble;
+use crate::disasm::image_listing;
+use crate::image::Image;
+use crate::parser::ParserWrapper;
+
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "mfrp"))
+        .collect();
+    files.sort();
+    files
+}
+fn compile(path: &Path) -> Image {
+    let mut cmp = Compiler::new();
+    let mut parser = ParserWrapper::new();
+    let fname = path.to_str().unwrap();
+    let prog = parser.load_program(fname, cmp.imported_modules()).unwrap();
+    let code = match cmp.compile(prog) {
+        Ok(code) => code.unwrap(),
+        Err(e) => panic!("{}", parser.sources().compile_error(&e)),
+    };
+    check_code(&code);
+    let io = cmp.io_nodes();
+    let mut image = Image::new(io.inputs, io.outputs);
+    image.push(&code);
+    image
+}
+
+#[test]
+fn golden() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failed = vec![];
+    for path in corpus() {
+        let image = compile(&path);
+        let actual = image_listing(&image).unwrap();
+        let golden = path.with_extension("asm");
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_default();
+        if actual != expected {
+            failed.push(format!("{}:\n{actual}", golden.display()));
+            continue;
+        }
+        // the listing is assembled into the same image
+        let mut assembled = Image::new(vec![], vec![]);
+        assemble(&expected, &mut assembled).unwrap();
+        assert_eq!(assembled, image, "{}", golden.display());
+    }
+    assert!(
+        failed.is_empty(),
+        "listings differ (run with UPDATE_GOLDEN=1 to accept them)\n{}",
+        failed.join("\n")
+    );
+}
@@ -0,0 +1,112 @@
+// programs whose types can not be inferred, and generic funcs used at several types
+use super::repl;
+use crate::repl::Repl;
+
+// the first line of the error, without the source it points to
+fn err(repl: &mut Repl, src: &str) -> String {
+    let e = repl.interpret(src).unwrap_err().to_string();
+    e.lines().next().unwrap().to_string()
+}
+
+#[test]
+fn mismatch() {
+    let mut repl = repl(&["type Maybe<a> = Just(a) | Nothing"]);
+    for (src, e) in [
+        (
+            "node a = 1 + true",
+            "type mismatch: expected Int, found Bool",
+        ),
+        (
+            "func h(x) = if x then x else 1",
+            "type mismatch: expected Bool, found Int",
+        ),
+        (
+            "func g(x) = (x, x) == x",
+            "type mismatch: expected Int, found ('t0, 't0)",
+        ),
+        (
+            "data j = if true then Just(1) else Just(false)",
+            "type mismatch: expected Maybe<Int>, found Maybe<Bool>",
+        ),
+        (
+            "func bad(x: a) -> Int = x + 1",
+            "type mismatch: expected Int, found a",
+        ),
+        (
+            "func one(x: a) -> a = 1",
+            "type mismatch: expected a, found Int",
+        ),
+        ("func f(x) = x(1)", "\"x\" is not a func"),
+    ] {
+        assert_eq!(err(&mut repl, src), format!("error: {e}"), "{src}");
+    }
+    // a failed definition is not defined
+    assert!(err(&mut repl, "h(true)").contains("h not found"));
+}
+
+#[test]
+fn occurs_check() {
+    let mut repl = repl(&["type Maybe<a> = Just(a) | Nothing"]);
+    for (src, e) in [
+        (
+            "func loop(x) = loop((x, x))",
+            "('t0, 't0) would have to contain itself to match 't0",
+        ),
+        (
+            "func deep(m) = if true then m else Just(m)",
+            "Maybe<'t0> would have to contain itself to match 't0",
+        ),
+    ] {
+        assert_eq!(err(&mut repl, src), format!("error: type {e}"), "{src}");
+    }
+}
+
+#[test]
+fn ambiguous() {
+    let mut repl = repl(&[
+        "type Maybe<a> = Just(a) | Nothing",
+        "func id(x) = x",
+        "func len(m: Maybe<a>) -> Int = m of: Just(_) -> { 1 } Nothing -> { 0 }",
+    ]);
+    for (src, what) in [
+        ("data n = Nothing", "n"),
+        ("data k = id(Nothing)", "k"),
+        ("func first(p) = p.0", "the tuple of .0"),
+        ("len(Just(true)) + len(Nothing)", "type args of len"),
+    ] {
+        let e = format!("error: cannot infer the type of {what}, add a type annotation");
+        assert_eq!(err(&mut repl, src), e, "{src}");
+    }
+    // an annotation decides the type
+    repl.interpret("data n: Maybe<Bool> = Nothing").unwrap();
+    assert_eq!(repl.interpret("len(n) + len(Just(n))").unwrap(), "[OK] 1");
+}
+
+#[test]
+fn generic_instances() {
+    let mut repl = repl(&[
+        "type Maybe<a> = Just(a) | Nothing",
+        "func id(x) = x",
+        "func fromMaybe(m: Maybe<a>, d: a) -> a = m of: Just(x) -> { x } Nothing -> { d }",
+        "data pair = (id(1), id(true), id((2, false)))",
+        "data m = (fromMaybe(Just(3), 0), fromMaybe(Nothing, true), fromMaybe(Just((1, 2)), (0, 0)))",
+    ]);
+    assert_eq!(repl.interpret("pair").unwrap(), "[OK] (1,true,(2,false))");
+    assert_eq!(repl.interpret("m").unwrap(), "[OK] (3,true,(1,2))");
+    // each type a generic func is called with is compiled separately
+    let funcs = repl.interpret(":funcs").unwrap();
+    for f in [
+        "func id<Int>(x: Int) -> Int",
+        "func id<Bool>(x: Bool) -> Bool",
+        "func id<(Int, Bool)>(x: (Int, Bool)) -> (Int, Bool)",
+        "func fromMaybe<Bool>(m: Maybe<Bool>, d: Bool) -> Bool",
+        "func fromMaybe<(Int, Int)>(m: Maybe<(Int, Int)>, d: (Int, Int)) -> (Int, Int)",
+    ] {
+        assert!(funcs.lines().any(|l| l == f), "{f} in\n{funcs}");
+    }
+    // the type variable is the same in all the parameters of one call
+    assert_eq!(
+        err(&mut repl, "fromMaybe(Just(1), false)"),
+        "error: type mismatch: expected Int, found Bool"
+    );
+}
@@ -0,0 +1,107 @@
+// objects whose header does not fit in a word, and refcounts above what the header holds
+use super::repl;
+use crate::disasm::insn_text;
+use crate::disasm::parse_insn;
+use crate::insn::Insn;
+use crate::insn::ObjHeader;
+use crate::insn::UnsignedNum;
+use crate::repl::Repl;
+
+#[test]
+fn extended_header() {
+    let objbit: Vec<bool> = (0..40).map(|i| i % 3 == 0).collect();
+    let header = ObjHeader::new(200, &objbit, 40);
+    assert_eq!(header.words().count(), 4);
+    assert_eq!((header.tag(), header.n_entry()), (200, 40));
+    assert!(header.objbit(33) && !header.objbit(34));
+    // the compact header is kept for small objects
+    let header = ObjHeader::new(3, &[false, true], 2);
+    assert_eq!(header.decode(), (3, "10".to_string(), 2));
+    assert!(header.1.is_empty());
+
+    for insn in [
+        Insn::AllocObj(UnsignedNum::U8(40), ObjHeader::new(200, &objbit, 40)),
+        Insn::ObjField(UnsignedNum::U8(39)),
+        Insn::ObjFieldRef(UnsignedNum::U8(7)),
+    ] {
+        assert_eq!(parse_insn(&insn_text(&insn)).unwrap(), insn);
+    }
+    assert!(parse_insn("AllocObj 9 0x00000001").is_err());
+}
+
+#[test]
+fn large_objects() {
+    let variants: Vec<String> = (0..130).map(|i| format!("V{i}")).collect();
+    let many = format!(
+        "type Many = {} | Last(Int, (Int, Int))",
+        variants.join(" | ")
+    );
+    let mut repl = repl(&[
+        &many,
+        "record Big = { a: Int, b: Int, c: Int, d: Int, e: Int, f: Int, g: Int, h: (Int, Bool), i: Many }",
+        "data big = Big(1, 2, 3, 4, 5, 6, 7, (8, true), Last(9, (10, 11)))",
+        "func tail(m) = m of: Last(x, (_, y)) -> { x + y } V129 -> { -1 } _ -> { 0 }",
+    ]);
+    let live = repl.link().machine().unwrap().live_objects();
+    for (e, v) in [
+        ("big", "Big(1,2,3,4,5,6,7,(8,true),Last(9,(10,11)))"),
+        ("big.h.0 + tail(big.i) + tail(V129) + tail(V3)", "27"),
+        ("{ big with i = V128 }.i", "V128"),
+        ("(1, 2, 3, 4, 5, 6, 7, 8, (9, 10)).8.1", "10"),
+        (
+            "big of: Big(_, _, _, _, _, _, 7, (x, true), Last(y, _)) -> { x * y } _ -> { 0 }",
+            "72",
+        ),
+    ] {
+        assert_eq!(repl.interpret(e).unwrap(), format!("[OK] {v}"), "{e}");
+    }
+    assert_eq!(repl.link().machine().unwrap().live_objects(), live);
+}
+
+#[test]
+fn wide_values() {
+    // more than 255 bytes are written for these values
+    let ns: Vec<String> = (0..255).map(|i| i.to_string()).collect();
+    let fields: Vec<String> = (0..255).map(|i| format!("f{i}: Int")).collect();
+    let mut repl = repl(&[
+        &format!("record Wide = {{ {} }}", fields.join(", ")),
+        &format!("node t = ({})", ns.join(", ")),
+    ]);
+    let tuple = format!("({})", ns.join(","));
+    assert_eq!(repl.interpret("t").unwrap(), format!("[OK] {tuple}"));
+    // with t inside, as long as the reply fits in the 2048 bytes of the esp32
+    assert_eq!(
+        repl.interpret(&format!("({}, t)", ns[..100].join(", ")))
+            .unwrap(),
+        format!("[OK] ({},{tuple})", ns[..100].join(","))
+    );
+    assert_eq!(
+        repl.interpret(&format!("Wide({})", ns.join(", "))).unwrap(),
+        format!("[OK] Wide{tuple}")
+    );
+}
+
+#[test]
+fn refcount_carry() {
+    // 5 * 64 * 64 references to d, which the 14 bits of the header can not count
+    let xs = vec!["x"; 64].join(", ");
+    let fs = vec!["f(d)"; 64].join(", ");
+    let f = format!("func f(x) = ({xs})");
+    let es: Vec<String> = (0..5).map(|k| format!("data e{k} = ({fs})")).collect();
+    let mut defs = vec!["data d = (1, 2)", &f];
+    defs.extend(es.iter().map(String::as_str));
+    let mut repl = repl(&defs);
+    let rc = |repl: &mut Repl| {
+        let vm = repl.link().machine().unwrap();
+        let d = vm.data_value(0).unwrap();
+        vm.obj_header(d).unwrap() & 0x1003fff
+    };
+    assert_eq!(rc(&mut repl), (1 << 24) | ((5 * 64 * 64 + 1) % 0x3fff));
+    assert_eq!(repl.interpret("d.1 + e4.63.63.0").unwrap(), "[OK] 3");
+    for k in 0..5 {
+        repl.interpret(&format!("remove e{k}")).unwrap();
+    }
+    assert_eq!(rc(&mut repl), 1);
+    repl.interpret("remove d").unwrap();
+    assert_eq!(repl.link().machine().unwrap().live_objects(), 0);
+}
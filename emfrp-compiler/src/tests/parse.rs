@@ -0,0 +1,83 @@
+use crate::ast::*;
+use crate::parser::ParserWrapper;
+
+fn int_of(src: &str) -> i32 {
+    match ParserWrapper::new().parse_line(src) {
+        Ok(Program::Exp(Exp::Term(Logical::BitWise(BitWise::Comp(Comp::Comp2(
+            Comp2::Shift(s),
+        )))))) => match *s {
+            Shift::Add(Add::Factor(Factor::Unary(Unary::Primary(Primary::Int(i, _))))) => i,
+            _ => panic!("{src} is not a literal"),
+        },
+        res => panic!("{src} is not a literal: {res:?}"),
+    }
+}
+
+#[test]
+fn int_literals() {
+    assert_eq!(int_of("1_000"), 1000);
+    assert_eq!(int_of("0x7f"), 127);
+    assert_eq!(int_of("0xFFFF_FFFF"), -1);
+    assert_eq!(int_of("0b1010"), 10);
+    assert_eq!(int_of("'A'"), 65);
+    assert_eq!(int_of("'\\n'"), 10);
+    assert_eq!(int_of("-2147483648"), i32::MIN);
+    assert_eq!(int_of("-0x8000_0000"), i32::MIN);
+}
+
+#[test]
+fn int_overflow() {
+    for src in [
+        "2147483648",
+        "-2147483649",
+        "0x1_0000_0000",
+        "99999999999999999999",
+        "x of 2147483648 -> { 1 } _ -> { 0 }",
+    ] {
+        let err = ParserWrapper::new()
+            .parse_line(src)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("out of range for Int"), "{src}: {err}");
+        assert!(err.contains("^^^^^^^^^^"), "{src}: {err}");
+    }
+}
+
+#[test]
+fn comments() {
+    let src = "# a\nnode a = { // b\n  x = 1; /* c\n d */ x } # e\n// f";
+    let prog = ParserWrapper::new().parse_program("test", src);
+    assert!(matches!(prog, Ok(Program::Def(defs)) if defs.len() == 1));
+    assert_eq!(int_of("/* 1 */ 2 // 3"), 2);
+    assert_eq!(int_of("4 # 5"), 4);
+}
+
+#[test]
+fn incomplete_input() {
+    let p = ParserWrapper::new();
+    for src in [
+        "node a =",
+        "node a = 1 +\n",
+        "func f(x) = {\n x = 1;",
+        "type T = A |",
+        "(1",
+        "node a = 1 /* b",
+        "/* a */ node a = /* b\n",
+    ] {
+        assert!(p.is_incomplete(src), "{src}");
+    }
+    for src in [
+        "node a = 1",
+        "1 + 2 // +",
+        "1 )",
+        "",
+        "# comment",
+        "node a = 1 1",
+        "1 // /*",
+        "'/' + '*'",
+    ] {
+        assert!(!p.is_incomplete(src), "{src}");
+    }
+    assert!(p.is_blank(" /* a */ # b\n"));
+    assert!(!p.is_blank("a"));
+}
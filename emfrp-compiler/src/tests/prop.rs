@@ -0,0 +1,301 @@
+// random well-typed expressions are compiled, checked with check_code and evaluated on the host VM.
+// the result must be the value computed here
+use super::check_code;
+use super::repl;
+use crate::compile::compile_common::CompiledCode;
+use crate::compile::compile_common::Compiler;
+use crate::compile::compile_common::Type;
+use crate::disasm::decode_insn;
+use crate::insn::*;
+use crate::parser::ParserWrapper;
+
+const CASES: u64 = 300;
+const MAX_DEPTH: u32 = 4;
+
+// xorshift64
+struct Rng(u64);
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+struct Gen {
+    rng: Rng,
+    n_locals: usize,
+}
+impl Gen {
+    fn int(&mut self, depth: u32) -> (String, i32) {
+        let n_kinds = if depth == 0 { 1 } else { 16 };
+        match self.rng.below(n_kinds) {
+            0 => self.int_literal(),
+            1 => {
+                let (a, va) = self.int(depth - 1);
+                (format!("-({a})"), va.wrapping_neg())
+            }
+            k @ 2..=7 => {
+                let (a, va) = self.int(depth - 1);
+                let (b, vb) = self.int(depth - 1);
+                let (op, v) = match k {
+                    2 => ("+", va.wrapping_add(vb)),
+                    3 => ("-", va.wrapping_sub(vb)),
+                    4 => ("*", va.wrapping_mul(vb)),
+                    5 => ("&", va & vb),
+                    6 => ("|", va | vb),
+                    _ => ("^", va ^ vb),
+                };
+                (format!("({a} {op} {b})"), v)
+            }
+            k @ 8..=11 => {
+                let (a, va) = self.int(depth - 1);
+                let (op, b, v) = match k {
+                    8 => {
+                        let b = self.rng.below(9) as i32 + 1;
+                        ("/", b, va.wrapping_div(b))
+                    }
+                    9 => {
+                        let b = self.rng.below(9) as i32 + 1;
+                        ("%", b, va.wrapping_rem(b))
+                    }
+                    10 => {
+                        let b = self.rng.below(32) as i32;
+                        ("<<", b, va.wrapping_shl(b as u32))
+                    }
+                    _ => {
+                        let b = self.rng.below(32) as i32;
+                        (">>", b, va.wrapping_shr(b as u32))
+                    }
+                };
+                (format!("({a} {op} {b})"), v)
+            }
+            12 | 13 => {
+                let (c, vc) = self.bool(depth - 1);
+                let (a, va) = self.int(depth - 1);
+                let (b, vb) = self.int(depth - 1);
+                (
+                    format!("(if {c} then {a} else {b})"),
+                    if vc { va } else { vb },
+                )
+            }
+            14 => {
+                let x = format!("v{}", self.n_locals);
+                self.n_locals += 1;
+                let (a, va) = self.int(depth - 1);
+                let (b, vb) = self.int(depth - 1);
+                (format!("({{ {x} = {a}; {x} - {b} }})"), va.wrapping_sub(vb))
+            }
+            _ => {
+                let (a, va) = self.float(depth - 1);
+                (format!("toInt({a})"), va as i32)
+            }
+        }
+    }
+    // decimal, negative, hex, binary or char literal
+    fn int_literal(&mut self) -> (String, i32) {
+        let i = match self.rng.below(3) {
+            0 => self.rng.below(1000) as i32,
+            1 => self.rng.next() as i32,
+            _ => [i32::MIN, i32::MAX, -1][self.rng.below(3) as usize],
+        };
+        let src = match self.rng.below(5) {
+            0 if i >= 0 => format!("{i}"),
+            0 | 1 => format!("({i})"),
+            2 => format!("0x{:X}", i as u32),
+            3 => format!("0b{:b}", i as u32),
+            _ => {
+                let c = b"az09_' "[self.rng.below(7) as usize] as char;
+                let src = if c == '\'' {
+                    "'\\''".to_string()
+                } else {
+                    format!("'{c}'")
+                };
+                return (src, c as i32);
+            }
+        };
+        (src, i)
+    }
+    fn float(&mut self, depth: u32) -> (String, f32) {
+        let n_kinds = if depth == 0 { 1 } else { 8 };
+        match self.rng.below(n_kinds) {
+            0 => {
+                let f = self.rng.below(4000) as f32 / 8.0;
+                (format!("{f:?}"), f)
+            }
+            1 => {
+                let (a, va) = self.float(depth - 1);
+                (format!("-({a})"), -va)
+            }
+            k @ 2..=4 => {
+                let (a, va) = self.float(depth - 1);
+                let (b, vb) = self.float(depth - 1);
+                let (op, v) = match k {
+                    2 => ("+", va + vb),
+                    3 => ("-", va - vb),
+                    _ => ("*", va * vb),
+                };
+                (format!("({a} {op} {b})"), v)
+            }
+            5 => {
+                let (a, va) = self.float(depth - 1);
+                let b = self.rng.below(9) as f32 + 1.0;
+                (format!("({a} / {b:?})"), va / b)
+            }
+            6 => {
+                let (a, va) = self.int(depth - 1);
+                (format!("toFloat({a})"), va as f32)
+            }
+            _ => {
+                let (c, vc) = self.bool(depth - 1);
+                let (a, va) = self.float(depth - 1);
+                let (b, vb) = self.float(depth - 1);
+                (
+                    format!("(if {c} then {a} else {b})"),
+                    if vc { va } else { vb },
+                )
+            }
+        }
+    }
+    fn bool(&mut self, depth: u32) -> (String, bool) {
+        let n_kinds = if depth == 0 { 1 } else { 13 };
+        match self.rng.below(n_kinds) {
+            0 => {
+                let b = self.rng.below(2) == 0;
+                (b.to_string(), b)
+            }
+            1 => {
+                let (a, va) = self.bool(depth - 1);
+                (format!("!({a})"), !va)
+            }
+            k @ 2..=7 => {
+                let (a, va) = self.int(depth - 1);
+                let (b, vb) = self.int(depth - 1);
+                let (op, v) = match k {
+                    2 => ("==", va == vb),
+                    3 => ("!=", va != vb),
+                    4 => ("<", va < vb),
+                    5 => ("<=", va <= vb),
+                    6 => (">", va > vb),
+                    _ => (">=", va >= vb),
+                };
+                (format!("({a} {op} {b})"), v)
+            }
+            k @ 8..=9 => {
+                let (a, va) = self.bool(depth - 1);
+                let (b, vb) = self.bool(depth - 1);
+                let (op, v) = if k == 8 {
+                    ("&&", va && vb)
+                } else {
+                    ("||", va || vb)
+                };
+                (format!("({a} {op} {b})"), v)
+            }
+            10 | 11 => {
+                let (c, vc) = self.bool(depth - 1);
+                let (a, va) = self.bool(depth - 1);
+                let (b, vb) = self.bool(depth - 1);
+                (
+                    format!("(if {c} then {a} else {b})"),
+                    if vc { va } else { vb },
+                )
+            }
+            _ => {
+                let (a, va) = self.float(depth - 1);
+                let (b, vb) = self.float(depth - 1);
+                let (op, v) = match self.rng.below(6) {
+                    0 => ("==", va == vb),
+                    1 => ("!=", va != vb),
+                    2 => ("<", va < vb),
+                    3 => ("<=", va <= vb),
+                    4 => (">", va > vb),
+                    _ => (">=", va >= vb),
+                };
+                (format!("({a} {op} {b})"), v)
+            }
+        }
+    }
+}
+
+#[test]
+fn random_expressions() {
+    let mut repl = repl(&[]);
+    for seed in 0..CASES {
+        let mut gen = Gen {
+            rng: Rng::new(seed),
+            n_locals: 0,
+        };
+        let depth = gen.rng.below(MAX_DEPTH as u64 + 1) as u32;
+        let (src, expected, typ) = match gen.rng.below(3) {
+            0 => {
+                let (src, v) = gen.int(depth);
+                (src, v.to_string(), Type::Int)
+            }
+            1 => {
+                let (src, v) = gen.bool(depth);
+                (src, v.to_string(), Type::Bool)
+            }
+            _ => {
+                let (src, v) = gen.float(depth);
+                (src, format!("{v:?}"), Type::Float)
+            }
+        };
+
+        let mut parser = ParserWrapper::new();
+        let prog = parser.parse_line(&src).unwrap();
+        let code = match Compiler::new().compile(prog) {
+            Ok(code) => code.unwrap(),
+            Err(e) => panic!("{src}\n{}", parser.sources().compile_error(&e)),
+        };
+        match &code {
+            CompiledCode::Eval(t, _) => assert_eq!(t, &typ, "{src}"),
+            CompiledCode::Def(_) => panic!("{src} is compiled as a definition"),
+        }
+        check_code(&code);
+
+        let res = repl.interpret(&src).unwrap();
+        assert_eq!(res, format!("[OK] {expected}"), "{src}");
+    }
+}
+
+// every instruction the decoder accepts is encoded back into bytecode_len bytes that decode into
+// the same instruction. the encoding is the one read unless that was longer than necessary
+#[test]
+fn encode_decode() {
+    let mut rng = Rng::new(0);
+    for op in 0..=u8::MAX {
+        for _ in 0..16 {
+            let mut bytes = vec![op];
+            bytes.extend((0..6).map(|_| rng.next() as u8));
+            let mut pc = 0;
+            let Ok(insn) = decode_insn(&bytes, &mut pc) else {
+                continue;
+            };
+            let mut encoded = vec![];
+            insn.push_byte_code(&mut encoded);
+            assert_eq!(
+                bytecode_len(std::slice::from_ref(&insn)),
+                encoded.len(),
+                "{:?}",
+                insn
+            );
+            assert!(
+                encoded == bytes[..pc] || encoded.len() < pc,
+                "{:?} is read from {:?} but encoded as {:?}",
+                insn,
+                &bytes[..pc],
+                encoded
+            );
+            let mut pc = 0;
+            assert_eq!(decode_insn(&encoded, &mut pc).unwrap(), insn);
+            assert_eq!(pc, encoded.len(), "{:?}", insn);
+        }
+    }
+}
@@ -0,0 +1,487 @@
+// the host VM behind a transport which breaks some of the frames
+use std::time::Duration;
+
+use anyhow::Result;
+
+use super::repl;
+use crate::machine::EmfrpResult;
+use crate::machine::Machine;
+use crate::machine::Value;
+use crate::opcode::HALT;
+use crate::protocol::*;
+use crate::repl::Repl;
+use crate::transport::LoopbackTransport;
+use crate::transport::Transport;
+
+const TIMEOUT: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Copy)]
+enum Fault {
+    None,
+    // flips a byte of the request
+    Corrupt,
+    // drops a byte of the request
+    Drop,
+    // the machine executes the request, but its reply is lost
+    LoseReply,
+}
+
+// the fault of the ith frame sent
+type FaultAt = fn(usize) -> Fault;
+
+struct Faulty {
+    inner: LoopbackTransport,
+    n_send: usize,
+    fault: FaultAt,
+}
+impl Faulty {
+    fn new(fault: FaultAt) -> Self {
+        Self {
+            inner: LoopbackTransport::new(),
+            n_send: 0,
+            fault,
+        }
+    }
+}
+impl Transport for Faulty {
+    fn send(&mut self, frame: &[u8]) -> Result<()> {
+        let fault = (self.fault)(self.n_send);
+        self.n_send += 1;
+        let mut frame = frame.to_vec();
+        let i = frame.len() / 2;
+        match fault {
+            Fault::None => (),
+            Fault::Corrupt => frame[i] ^= 0x10,
+            Fault::Drop => {
+                frame.remove(i);
+            }
+            Fault::LoseReply => {
+                self.inner.send(&frame)?;
+                return self.inner.clear();
+            }
+        }
+        self.inner.send(&frame)
+    }
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.inner.recv(buf, timeout)
+    }
+    fn machine(&mut self) -> Option<&mut Machine> {
+        self.inner.machine()
+    }
+}
+
+fn faulty_repl(fault: FaultAt) -> Repl {
+    let mut repl = Repl::new(Box::new(Faulty::new(fault)));
+    repl.set_timeout(TIMEOUT);
+    repl
+}
+
+#[test]
+fn crc() {
+    assert_eq!(crc16(b"123456789"), 0x29b1);
+}
+
+#[test]
+fn parser_resyncs() {
+    let frame = Frame::new(MSG_EVAL, 7, &[1, 0xaa, 0x55, 3]);
+    let mut bytes = vec![0x55, 0xaa, 0x00];
+    bytes.extend(frame.encode());
+    let mut broken = frame.encode();
+    broken[7] ^= 1;
+    bytes.extend(broken);
+    bytes.extend(frame.encode());
+
+    // fed one byte at a time, as bytes arrive from uart
+    let mut parser = FrameParser::new(64);
+    let mut res = vec![];
+    for b in bytes {
+        res.extend(parser.push(&[b]));
+    }
+    // the sync in the payload of the broken frame is also reported
+    let ok: Vec<&Frame> = res.iter().filter_map(|r| r.as_ref().ok()).collect();
+    assert_eq!(ok, [&frame, &frame]);
+    assert_eq!(
+        res[1],
+        Err(BadFrame {
+            seq: 7,
+            reason: NAK_CRC
+        })
+    );
+}
+
+#[test]
+fn too_long() {
+    let mut device = Device::new(4, 4, 4);
+    let reply = device.receive(
+        &mut Machine::new(),
+        &Frame::new(MSG_PING, 1, &[0; 5]).encode(),
+    );
+    assert_eq!(reply, Frame::new(MSG_NAK, 1, &[NAK_TOO_LONG]).encode());
+}
+
+#[test]
+fn retransmission() {
+    for fault in [Fault::Corrupt, Fault::Drop, Fault::LoseReply] {
+        // the handshake and every define and eval fail once
+        let mut repl = faulty_repl(match fault {
+            Fault::Corrupt => |i| {
+                if i % 2 == 0 {
+                    Fault::Corrupt
+                } else {
+                    Fault::None
+                }
+            },
+            Fault::Drop => |i| if i % 2 == 0 { Fault::Drop } else { Fault::None },
+            _ => |i| {
+                if i % 2 == 0 {
+                    Fault::LoseReply
+                } else {
+                    Fault::None
+                }
+            },
+        });
+        assert_eq!(repl.interpret("data x = 20").unwrap(), "OK");
+        assert_eq!(repl.interpret("data y = x + 1").unwrap(), "OK");
+        assert_eq!(repl.interpret("x * 2 + y").unwrap(), "[OK] 61");
+        // a request sent again is not executed twice
+        let vm = repl.link().machine().unwrap();
+        assert_eq!(vm.data_value(1), Some(Value::Num(21)));
+        assert_eq!(vm.data_value(2), None);
+    }
+}
+
+#[test]
+fn gives_up() {
+    let mut repl = faulty_repl(|_| Fault::Corrupt);
+    let err = repl.interpret("1").unwrap_err().to_string();
+    assert!(err.contains("broken request"), "{err}");
+}
+
+#[test]
+fn query_and_reset() {
+    let mut repl = repl(&["data d = (1, 2)", "node n = 3"]);
+    let q = |kind, is_obj, i: u16| {
+        let mut payload = vec![kind, is_obj];
+        payload.extend(i.to_le_bytes());
+        payload
+    };
+    let reply = repl
+        .link()
+        .request(MSG_QUERY, &q(QUERY_NODE, 0, 0))
+        .unwrap();
+    assert_eq!(reply, [4, 0, 3, 0, 0, 0, 0]);
+    let reply = repl
+        .link()
+        .request(MSG_QUERY, &q(QUERY_DATA, 1, 0))
+        .unwrap();
+    assert_eq!(reply[..2], [12, 0]);
+    assert_eq!(&reply[6..14], [1, 0, 0, 0, 2, 0, 0, 0]);
+    // no such node
+    let reply = repl
+        .link()
+        .request(MSG_QUERY, &q(QUERY_NODE, 0, 1))
+        .unwrap();
+    assert_eq!(reply, [2]);
+
+    assert_eq!(repl.link().request(MSG_PING, &[]).unwrap(), []);
+    assert_eq!(repl.interpret("reset").unwrap(), "machine is reset");
+    assert!(repl.interpret("n").is_err());
+    assert_eq!(repl.link().machine().unwrap().data_value(0), None);
+}
+
+#[test]
+fn remove() {
+    let mut repl = repl(&[
+        "data d = (1, 2)",
+        "node a init[0] = a@last + 1",
+        "node b = (a, a@last)",
+        "func f(x) = x + 1",
+        "node c = f(a)",
+    ]);
+    let err = repl.interpret("remove a").unwrap_err().to_string();
+    assert!(err.contains(r#"["b", "c"]"#), "{err}");
+    let err = repl.interpret("remove f").unwrap_err().to_string();
+    assert!(err.contains(r#"["c"]"#), "{err}");
+    for name in ["b", "c", "f", "d"] {
+        assert_eq!(
+            repl.interpret(&format!("remove {name}")).unwrap(),
+            format!("{name} is removed")
+        );
+    }
+    assert!(repl.interpret("b").is_err());
+    assert_eq!(repl.link().machine().unwrap().live_objects(), 0);
+    // the slots of b and d are reused
+    repl.interpret("node e = a * 10").unwrap();
+    repl.interpret("data g = 5").unwrap();
+    let vm = repl.link().machine().unwrap();
+    assert_eq!(vm.node_value(3), None);
+    assert_eq!(vm.data_value(1), None);
+    assert_eq!(vm.data_value(0), Some(Value::Num(5)));
+    assert_eq!(repl.interpret("e == a * 10").unwrap(), "[OK] true");
+}
+
+#[test]
+fn remove_all_or_none() {
+    let mut repl = repl(&["func id(x) = x"]);
+    assert_eq!(
+        repl.interpret("(id(1), id(true))").unwrap(),
+        "[OK] (1,true)"
+    );
+    // the second slot does not exist, so the first one is not freed either
+    let payload = [0, 0, 2, REMOVE_FUNC, 0, 0, 0, REMOVE_FUNC, 0, 99, 0, HALT];
+    let reply = repl.link().request(MSG_REMOVE, &payload).unwrap();
+    assert_eq!(reply, [EmfrpResult::Panic as u8]);
+    assert_eq!(repl.interpret("id(5)").unwrap(), "[OK] 5");
+    // both instances are removed in one request
+    assert_eq!(repl.interpret("remove id").unwrap(), "id is removed");
+    assert!(repl.interpret("id(5)").is_err());
+}
+
+#[test]
+fn rename() {
+    let mut repl = repl(&[
+        "node a init[0] = a@last + 1",
+        "data d = (1, 2)",
+        "func f(x) = x + d.0",
+        "node b = f(a)",
+        "func id(x) = x",
+        "node c = id(b)",
+    ]);
+    let vm = repl.link().machine().unwrap();
+    let (nodes, data) = (vm.node_value(1), vm.data_value(0));
+    for (name, new) in [("a", "cnt"), ("d", "offset"), ("f", "g"), ("id", "same")] {
+        assert_eq!(
+            repl.interpret(&format!("rename {name} {new}")).unwrap(),
+            format!("{name} is renamed to {new}")
+        );
+        assert!(repl.interpret(name).is_err());
+    }
+    // the slots are kept, so the machine runs as before
+    let vm = repl.link().machine().unwrap();
+    assert_eq!((vm.node_value(1), vm.data_value(0)), (nodes, data));
+    vm.update();
+    assert_eq!(repl.interpret("g(cnt) == b").unwrap(), "[OK] true");
+    assert_eq!(repl.interpret("same(offset)").unwrap(), "[OK] (1,2)");
+    // the definitions referring to a renamed one are found by its new name
+    let err = repl.interpret("remove g").unwrap_err().to_string();
+    assert!(err.contains(r#"["b"]"#), "{err}");
+    repl.interpret("remove c").unwrap();
+    repl.interpret("remove same").unwrap();
+    assert!(repl.interpret("same(1)").is_err());
+
+    for (cmd, msg) in [
+        ("rename cnt b", "b is already defined"),
+        ("rename cnt If", "can not be the name"),
+        ("rename cnt then", "can not be the name"),
+        ("rename x y", "Identifier x not found"),
+        ("rename cnt", "usage"),
+    ] {
+        let err = repl.interpret(cmd).unwrap_err().to_string();
+        assert!(err.contains(msg), "{cmd} : {err}");
+    }
+    repl.interpret("func twice(x) = g(g(x))").unwrap();
+    repl.interpret("func k(x) = (x, offset)").unwrap();
+    let err = repl.interpret("rename offset o").unwrap_err().to_string();
+    assert!(err.contains(r#"["k"]"#), "{err}");
+    assert_eq!(repl.interpret("twice(1)").unwrap(), "[OK] 3");
+}
+
+#[test]
+fn staged_define() {
+    let mut repl = repl(&["data x = 1", "node n = x + 1"]);
+    repl.link().machine().unwrap().set_code_limit(0);
+    // nothing is swapped in if the machine can not hold both programs
+    for def in ["node n = x * 100", "data y = 2"] {
+        let err = repl.interpret(def).unwrap_err().to_string();
+        assert!(err.contains("OUT OF MEMORY"), "{err}");
+    }
+    repl.link().machine().unwrap().update();
+    assert_eq!(repl.interpret("n").unwrap(), "[OK] 2");
+    assert!(repl.interpret("y").is_err());
+    // nothing is left to commit
+    assert_eq!(repl.link().request(MSG_ABORT, &[]).unwrap(), [0]);
+    assert_eq!(repl.link().request(MSG_COMMIT, &[]).unwrap(), [2]);
+
+    repl.link().machine().unwrap().set_code_limit(1000);
+    // the definitions are kept if their initialisation fails
+    let err = repl
+        .interpret("data z = 1 / (x - 1)")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("while initialising"), "{err}");
+    assert_eq!(repl.interpret("z").unwrap(), "[OK] 0");
+    // and a node replaced by one whose initialisation fails is updated with its new body
+    let err = repl
+        .interpret("node n init[1 / (x - 1)] = x * 100")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("while initialising"), "{err}");
+    repl.link().machine().unwrap().update();
+    assert_eq!(repl.interpret("n").unwrap(), "[OK] 100");
+}
+
+#[test]
+fn commit_lost() {
+    // the handshake and the define arrive, and the first replies to the commit are lost
+    let cases: [(FaultAt, &str); 4] = [
+        // the commit sent again with the same seq is answered with the lost reply
+        (
+            |i| match i {
+                2..=6 => Fault::LoseReply,
+                _ => Fault::None,
+            },
+            "OK",
+        ),
+        // the machine tells that it has no staged definitions, so it has committed them
+        (
+            |i| match i {
+                2..=11 => Fault::LoseReply,
+                _ => Fault::None,
+            },
+            "no reply arrived while initialising the definitions",
+        ),
+        // the machine still keeps the staged definitions, so the commit is sent as a new request
+        (
+            |i| match i {
+                2..=11 => Fault::Drop,
+                _ => Fault::None,
+            },
+            "OK",
+        ),
+        (
+            |i| match i {
+                2.. => Fault::LoseReply,
+                _ => Fault::None,
+            },
+            "type `reset` before defining anything",
+        ),
+    ];
+    for (k, (fault, e)) in cases.into_iter().enumerate() {
+        let mut repl = faulty_repl(fault);
+        match repl.interpret("data x = 1") {
+            Ok(res) => assert_eq!(res, e),
+            Err(err) => assert!(err.to_string().contains(e), "{err}"),
+        }
+        if k < 3 {
+            assert_eq!(
+                repl.link().machine().unwrap().data_value(0),
+                Some(Value::Num(1))
+            );
+            assert_eq!(repl.interpret("x").unwrap(), "[OK] 1");
+        } else {
+            // the compiler does not know which definitions the machine runs
+            assert!(repl.cmp.var_list().is_empty());
+        }
+    }
+}
+
+#[test]
+fn telemetry() {
+    let mut repl = repl(&["node a init[0] = a@last + 1", "node b = (a, true)"]);
+    repl.subscribe(&["a", "b"], 2).unwrap();
+    let mut samples = vec![];
+    while samples.len() < 3 {
+        if let Some(s) = repl.next_sample(TIMEOUT).unwrap() {
+            samples.push(s);
+        }
+        if samples.len() == 1 {
+            // values sent while waiting for a reply are kept
+            assert_eq!(repl.interpret("1").unwrap(), "[OK] 1");
+        }
+    }
+    let values: Vec<Vec<String>> = samples
+        .iter()
+        .map(|s| s.values.clone().unwrap())
+        .collect();
+    let a: i32 = values[0][0].parse().unwrap();
+    assert_eq!(values[0][1], format!("({a},true)"));
+    assert_eq!(samples[1].seq, samples[0].seq.wrapping_add(1));
+    assert!(values[1][0].parse::<i32>().unwrap() > a);
+    assert_eq!(
+        values[2][0].parse::<i32>().unwrap(),
+        values[1][0].parse::<i32>().unwrap() + 2
+    );
+
+    let err = repl.subscribe(&["a"; 9], 1).unwrap_err().to_string();
+    assert!(err.contains("at most"), "{err}");
+    repl.subscribe(&[], 1).unwrap();
+    assert_eq!(repl.next_sample(TIMEOUT).unwrap(), None);
+}
+
+// a machine whose telemetry frames are as short as those of the Arduino Uno
+struct Small {
+    vm: Machine,
+    device: Device,
+    reply: Vec<u8>,
+}
+impl Transport for Small {
+    fn send(&mut self, frame: &[u8]) -> Result<()> {
+        let reply = self.device.receive(&mut self.vm, frame);
+        self.reply.extend(reply);
+        Ok(())
+    }
+    fn recv(&mut self, buf: &mut [u8], _: Duration) -> Result<usize> {
+        if self.reply.is_empty() {
+            let status = self.vm.update();
+            if let Some(f) = self.device.telemetry(&mut self.vm, status) {
+                self.reply.extend(f.encode());
+            }
+        }
+        let n = self.reply.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.reply[..n]);
+        self.reply.drain(..n);
+        Ok(n)
+    }
+    fn machine(&mut self) -> Option<&mut Machine> {
+        Some(&mut self.vm)
+    }
+}
+
+#[test]
+fn telemetry_size() {
+    let mut device = Device::new(55, 64, 32);
+    let reply = device.receive(
+        &mut Machine::new(),
+        &Frame::new(MSG_HELLO, 1, &[VERSION]).encode(),
+    );
+    let hello = [VERSION, 55, 0, 32, 0];
+    assert_eq!(reply, Frame::new(MSG_HELLO | REPLY, 1, &hello).encode());
+
+    let mut repl = Repl::new(Box::new(Small {
+        vm: Machine::new(),
+        device,
+        reply: vec![],
+    }));
+    repl.interpret("node a init[0] = a@last + 1").unwrap();
+    repl.interpret("node b = (a, a, a, a, a, a)").unwrap();
+    assert_eq!(repl.link().telemetry_size().unwrap(), 32);
+    let err = repl.subscribe(&["a"; 4], 1).unwrap_err().to_string();
+    assert!(err.contains("do not fit in the 32 bytes"), "{err}");
+    repl.subscribe(&["a"; 3], 1).unwrap();
+    let sample = repl.next_sample(TIMEOUT).unwrap().unwrap();
+    assert_eq!(sample.values.unwrap().len(), 3);
+    // the size of an object is known only when it is sent
+    repl.subscribe(&["b"], 1).unwrap();
+    let sample = repl.next_sample(TIMEOUT).unwrap().unwrap();
+    assert_eq!(sample.values, Err("OUT OF MEMORY".to_string()));
+}
+
+#[test]
+fn reply_size() {
+    let mut repl = Repl::new(Box::new(Small {
+        vm: Machine::new(),
+        device: Device::new(1015, 64, 32),
+        reply: vec![],
+    }));
+    let fields = vec!["1"; 20].join(", ");
+    repl.interpret(&format!("node big = ({fields})")).unwrap();
+    repl.interpret("node n = 3").unwrap();
+    // nodes have values after the first update
+    repl.link().machine().unwrap().update();
+    // the value of big does not fit in the 64 bytes of the reply
+    let err = repl.node_value("big").unwrap_err().to_string();
+    assert!(err.contains("OUT OF MEMORY"), "{err}");
+    // the machine goes on
+    assert_eq!(repl.node_value("n").unwrap(), "3");
+    assert_eq!(repl.interpret("n + 1").unwrap(), "[OK] 4");
+}
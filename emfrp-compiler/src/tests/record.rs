@@ -0,0 +1,66 @@
+// records, field access and update, and projection of tuples
+use super::repl;
+
+#[test]
+fn records() {
+    let mut repl = repl(&[
+        "record Reading = { x: Int, y: Int, ok: Bool }",
+        "record Pair<a> = { fst: a, snd: a }",
+        "func norm(r) = r.x * r.x + r.y * r.y",
+        "func swap(p) = { p with fst = p.snd, snd = p.fst }",
+        "data r = Reading { ok = true, y = 4, x = 3 }",
+        "data p = Pair { fst = r, snd = { r with x = 0, ok = false } }",
+    ]);
+    let live = repl.link().machine().unwrap().live_objects();
+    for (e, v) in [
+        ("norm(r)", "25"),
+        ("r", "Reading(3,4,true)"),
+        ("{ r with y = r.x }.y", "3"),
+        ("p.snd", "Reading(0,4,false)"),
+        ("p.snd.ok || p.fst.x == 3", "true"),
+        ("{ p with fst = p.snd }.fst.x", "0"),
+        ("swap(p).fst.x + swap(Pair { fst = 1, snd = 2 }).fst", "2"),
+        // a record is also a variant named after it
+        (
+            "Reading(1, 2, false) of: Reading(a, _, false) -> { a } _ -> { 0 }",
+            "1",
+        ),
+        ("((1, (2, 3)), 4).0.1.1 + (5, 6).1", "9"),
+    ] {
+        assert_eq!(repl.interpret(e).unwrap(), format!("[OK] {v}"), "{e}");
+    }
+    // fields taken and records updated are not left behind
+    assert_eq!(repl.link().machine().unwrap().live_objects(), live);
+    assert_eq!(
+        repl.interpret(":types").unwrap(),
+        "record Pair<a> = { fst: a, snd: a }\nrecord Reading = { x: Int, y: Int, ok: Bool }"
+    );
+}
+
+#[test]
+fn field_errors() {
+    let mut repl = repl(&[
+        "record Reading = { x: Int, y: Int }",
+        "data r = Reading(1, 2)",
+    ]);
+    for (e, err) in [
+        ("r.z", "Reading has no field z"),
+        ("(1, 2).2", "(Int, Int) has no field 2"),
+        ("Reading { x = 1 }", "Field y of Reading is not given"),
+        (
+            "Reading { x = 1, y = 2, x = 3 }",
+            "Field x appears more than once",
+        ),
+        ("{ r with x = true }", "expected Int"),
+        ("{ (1, 2) with x = 1 }", "has no field x"),
+    ] {
+        let res = repl.interpret(e).unwrap_err().to_string();
+        assert!(res.contains(err), "{e} : {res}");
+    }
+    let res = repl
+        .interpret("record Bad = { a: Int, a: Int }")
+        .unwrap_err()
+        .to_string();
+    assert!(res.contains("Field a appears more than once"), "{res}");
+    assert!(repl.interpret("Bad(1, 2)").is_err());
+}
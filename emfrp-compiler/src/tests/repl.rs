@@ -0,0 +1,88 @@
+// commands of the REPL which show what is defined
+use super::repl;
+use crate::compile::compile_common::Type;
+use crate::repl::Repl;
+
+#[test]
+fn introspection() {
+    let mut repl = repl(&[]);
+    repl.add_input_node("btn", Type::Bool);
+    for def in [
+        "type Maybe<a> = Just(a) | Nothing",
+        "data x = 1",
+        "node a init[0] = a@last + x",
+        "func id(z) = z",
+        "node b = (id(a), a@last)",
+        "node c = if btn then a@last else x",
+    ] {
+        repl.interpret(def).unwrap();
+    }
+    let run = |repl: &mut Repl, cmd: &str| repl.interpret(cmd).unwrap();
+    assert_eq!(
+        run(&mut repl, ":nodes"),
+        "in btn: Bool\nnode a: Int\nnode b: (Int, Int)\nnode c: Int\ndata x: Int"
+    );
+    assert_eq!(
+        run(&mut repl, ":funcs"),
+        "func id<Int>(z: Int) -> Int\nfunc id(z: a) -> a"
+    );
+    assert_eq!(
+        run(&mut repl, ":types"),
+        "type Maybe<a> = Just(a) | Nothing"
+    );
+    assert_eq!(
+        run(&mut repl, ":type Just((a, 1.5))"),
+        "Maybe<(Int, Float)>"
+    );
+    // the expression is not compiled into the program
+    assert_eq!(run(&mut repl, ":funcs").lines().count(), 2);
+    assert_eq!(
+        run(&mut repl, ":deps a"),
+        "refers to : -\nat last   : -\nused by   : b, c"
+    );
+    assert_eq!(
+        run(&mut repl, ":deps c"),
+        "refers to : btn\nat last   : a\nused by   : -"
+    );
+    assert_eq!(run(&mut repl, ":order"), "btn, a, c, b");
+    assert!(repl.interpret(":deps x").is_err());
+    assert!(repl.interpret(":order a").is_err());
+
+    // every request lets the host VM update the nodes once
+    let v = repl.node_value("a").unwrap().parse::<i32>().unwrap();
+    assert!(v > 0);
+    assert_eq!(repl.node_value("b").unwrap(), format!("({},{})", v + 1, v));
+}
+
+#[test]
+fn graph() {
+    let mut repl = repl(&[]);
+    repl.add_input_node("btn", Type::Bool);
+    repl.add_output_node("led", Type::Bool);
+    for def in [
+        "node cnt init[0] = if btn then cnt@last + 1 else cnt@last",
+        "node prev = cnt@last",
+        "node led = cnt > prev",
+    ] {
+        repl.interpret(def).unwrap();
+    }
+    assert_eq!(
+        repl.interpret(":graph").unwrap(),
+        r#"digraph emfrp {
+    rankdir=LR;
+    "btn" [shape=invhouse, label="btn : Bool"];
+    "led" [shape=house, label="led : Bool"];
+    "cnt" [shape=box, label="cnt : Int"];
+    "prev" [shape=box, label="prev : Int"];
+    "cnt" -> "led";
+    "prev" -> "led";
+    "btn" -> "cnt";
+    "cnt" -> "prev" [style=dashed, label="@last"];
+}"#
+    );
+    let json = repl.interpret(":graph json").unwrap();
+    assert!(json.contains(
+        r#"{"name": "prev", "kind": "node", "type": "Int", "refers_to": [], "at_last": ["cnt"]}"#
+    ));
+    assert!(json.contains(r#""update_order": ["btn", "prev", "cnt", "led"]"#));
+}
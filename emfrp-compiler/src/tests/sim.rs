@@ -0,0 +1,96 @@
+// programs run on the host VM with traces of input nodes
+use crate::compile::compile_common::Compiler;
+use crate::compile::compile_common::Type;
+use crate::parser::ParserWrapper;
+use crate::sim::*;
+
+const PROG: &str = "
+node cnt init[0] = if btn && !btn@last then cnt@last + 1 else cnt@last
+node pressed = btn && !btn@last
+node led = cnt % 2 == 1
+node pair = (cnt, pressed)
+";
+
+fn run(trace: &Trace, ticks: usize) -> anyhow::Result<Record> {
+    let mut cmp = Compiler::new();
+    cmp.add_input_node("btn", Type::Bool);
+    cmp.add_output_node("led", Type::Bool);
+    let mut parser = ParserWrapper::new();
+    let prog = parser.parse_program("prog.mfrp", PROG).unwrap();
+    let code = cmp.compile(prog).unwrap().unwrap();
+    simulate(&cmp, &code, trace, ticks)
+}
+
+#[test]
+fn csv_trace() {
+    let trace = Trace::from_csv(
+        "# btn is held for two ticks
+btn, cnt, led
+true, 1, true
+, 1,
+false, , false
+1, 2, false
+",
+    )
+    .unwrap();
+    let record = run(&trace, 5).unwrap();
+    assert_eq!(
+        record.to_csv(),
+        "tick,btn,led,cnt,pressed,pair
+1,true,true,1,true,\"(1,true)\"
+2,true,true,1,false,\"(1,false)\"
+3,false,true,1,false,\"(1,false)\"
+4,true,false,2,true,\"(2,true)\"
+5,true,false,2,false,\"(2,false)\"
+"
+    );
+    // led is still on at tick 3
+    assert_eq!(
+        record.failures,
+        [Failure {
+            tick: 3,
+            node: "led".to_string(),
+            expected: "false".to_string(),
+            actual: "true".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn json_trace() {
+    let trace = Trace::from_json(
+        r#"[{"btn": true}, {"btn": false, "pair": "(1, false)"}, {"btn": null, "cnt": 1}]"#,
+    )
+    .unwrap();
+    assert_eq!(trace.names, ["btn", "pair", "cnt"]);
+    let record = run(&trace, trace.rows.len()).unwrap();
+    assert_eq!(record.failures, []);
+    assert!(record.to_json().contains(
+        r#"{"tick": 3, "btn": false, "led": true, "cnt": 1, "pressed": false, "pair": "(1,false)"}"#
+    ));
+
+    let trace = Trace::from_csv("btn,nothing\ntrue,1\n").unwrap();
+    let err = run(&trace, 1).unwrap_err().to_string();
+    assert!(err.contains("nothing"), "{err}");
+    let trace = Trace::from_csv("btn\n2\n").unwrap();
+    let err = run(&trace, 1).unwrap_err().to_string();
+    assert!(err.contains("tick 1"), "{err}");
+}
+
+#[test]
+fn json_floats() {
+    let mut parser = ParserWrapper::new();
+    let prog = parser
+        .parse_program(
+            "floats.mfrp",
+            "node z = 0.0\nnode nan = z / z\nnode inf = 1.0 / z\nnode half = 1.0 / 2.0",
+        )
+        .unwrap();
+    let mut cmp = Compiler::new();
+    let code = cmp.compile(prog).unwrap().unwrap();
+    let record = simulate(&cmp, &code, &Trace::from_csv("z\n").unwrap(), 1).unwrap();
+    assert_eq!(
+        record.to_json(),
+        "[\n  {\"tick\": 1, \"z\": 0.0, \"nan\": null, \"inf\": null, \"half\": 0.5}\n]\n"
+    );
+}
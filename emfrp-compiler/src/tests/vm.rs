@@ -0,0 +1,87 @@
+// programs compiled and run on the host VM
+use crate::compile::compile_common::Compiler;
+use crate::insn::to_byte_code;
+use crate::machine::*;
+use crate::parser::ParserWrapper;
+
+struct Vm {
+    cmp: Compiler,
+    parser: ParserWrapper,
+    machine: Machine,
+}
+impl Vm {
+    fn new() -> Self {
+        Self {
+            cmp: Compiler::new(),
+            parser: ParserWrapper::new(),
+            machine: Machine::new(),
+        }
+    }
+    // compiles the definitions and sends them to the machine in order
+    fn define(&mut self, defs: &[&str]) {
+        for def in defs {
+            let prog = self.parser.parse_line(def).unwrap();
+            let code = self.cmp.compile(prog).unwrap().unwrap();
+            // the bytecode without the datasize in front of it
+            let bc = to_byte_code(&code);
+            assert_eq!(
+                self.machine.new_bytecode(&bc[2..]),
+                EmfrpResult::Ok,
+                "{def}"
+            );
+        }
+    }
+    // data are numbered in the order they are defined
+    fn data(&self, i: usize) -> Value {
+        self.machine.data_value(i).unwrap()
+    }
+}
+
+#[test]
+fn integers() {
+    let mut vm = Vm::new();
+    vm.define(&[
+        "data a : Int = 6",
+        "data b : Int = 100",
+        "data c : Int = -1000",
+        "data d : Int = 100000",
+        "data e : Int = a + b + c + d",
+    ]);
+    for (i, n) in [6, 100, -1000, 100000, 99106].into_iter().enumerate() {
+        assert_eq!(vm.data(i), Value::Num(n));
+    }
+}
+
+#[test]
+fn long_jumps() {
+    // the branches are too long for a jump with an 8-bit offset
+    let long = vec!["1000"; 40].join(" + ");
+    let mut vm = Vm::new();
+    vm.define(&[
+        &format!("func pick(c : Bool) -> Int = if c then {long} else 2"),
+        &format!("func skip(c : Bool) -> Int = if c then 2 else {long}"),
+        "data t : (Int, Int, Int, Int) = (pick(true), pick(false), skip(true), skip(false))",
+    ]);
+    let t = vm.data(0);
+    let fields = vm.machine.obj_fields(t).unwrap();
+    let expected = [40000, 2, 2, 40000].map(Value::Num);
+    assert_eq!(fields, expected);
+}
+
+#[test]
+fn block_locals() {
+    // only the locals holding objects are dropped at the end of a block
+    let mut vm = Vm::new();
+    vm.define(&[
+        "func f(n : Int) -> Int = { x : Int = n + 1; p : (Int, Int) = (x, n); b : Bool = x > 2; q : ((Int, Int), Bool) = (p, b); q of: (r, _) -> { r of: (y, z) -> { y * 10 + z } } }",
+        "data d : (Int, Int) = { k : Int = 7; t : (Int, Int) = (k, k); t }",
+    ]);
+    let live = vm.machine.live_objects();
+    for i in 1..=3 {
+        vm.define(&[&format!(
+            "data r{i} : Int = f(3) + (d of: (_, k) -> {{ k }})"
+        )]);
+        assert_eq!(vm.data(i), Value::Num(50));
+        assert_eq!(vm.machine.live_objects(), live);
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serial2::*;
+
+use crate::machine::Machine;
+use crate::protocol::Device;
+
+// connection between the host and a machine, which carries the frames of protocol.rs
+pub trait Transport {
+    fn send(&mut self, frame: &[u8]) -> Result<()>;
+    // waits at most `timeout` for a reply and returns the number of bytes read.
+    // Ok(0) means nothing arrived in time
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize>;
+    // discard bytes left over from the previous exchange
+    fn clear(&mut self) -> Result<()> {
+        Ok(())
+    }
+    // the machine behind this transport, if it runs in the same process
+    fn machine(&mut self) -> Option<&mut Machine> {
+        None
+    }
+}
+
+// transport spec:
+//   serial:<device>   serial port (a spec without prefix is treated as a serial device)
+//   tcp:<host:port>   emulator listening on a TCP socket
+//   pipe:<path>       pty or fifo used for both directions
+//   pipe:<tx>,<rx>    separate fifos for sending frames and receiving replies
+//   loopback          host VM in this process
+pub fn open(spec: &str, baud_rate: u32) -> Result<Box<dyn Transport>> {
+    if spec == "loopback" {
+        return Ok(Box::new(LoopbackTransport::new()));
+    }
+    match spec.split_once(':') {
+        Some(("serial", dev)) => Ok(Box::new(SerialTransport::open(dev, baud_rate)?)),
+        Some(("tcp", addr)) => Ok(Box::new(TcpTransport::connect(addr)?)),
+        Some(("pipe", paths)) => match paths.split_once(',') {
+            Some((tx, rx)) => Ok(Box::new(PipeTransport::open(tx, rx)?)),
+            None => Ok(Box::new(PipeTransport::open(paths, paths)?)),
+        },
+        _ => Ok(Box::new(SerialTransport::open(spec, baud_rate)?)),
+    }
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+}
+
+pub struct SerialTransport {
+    port: SerialPort,
+}
+impl SerialTransport {
+    pub fn open(dev: &str, baud_rate: u32) -> Result<Self> {
+        let mut port =
+            SerialPort::open(dev, baud_rate).with_context(|| format!("could not open {dev}"))?;
+        let mut settings = port.get_configuration()?;
+        settings.set_stop_bits(StopBits::One);
+        settings.set_flow_control(FlowControl::None);
+        settings.set_char_size(CharSize::Bits8);
+        port.set_configuration(&settings)?;
+        Ok(Self { port })
+    }
+}
+impl Transport for SerialTransport {
+    fn send(&mut self, frame: &[u8]) -> Result<()> {
+        self.port
+            .write_all(frame)
+            .context("could not write to the port")
+    }
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.port.set_read_timeout(timeout)?;
+        match self.port.read(buf) {
+            Ok(n) => Ok(n),
+            Err(e) if is_timeout(&e) => Ok(0),
+            Err(e) => Err(e).context("could not read port"),
+        }
+    }
+    fn clear(&mut self) -> Result<()> {
+        self.port
+            .discard_buffers()
+            .context("could not discard buffers")
+    }
+}
+
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+impl TcpTransport {
+    pub fn connect(addr: &str) -> Result<Self> {
+        let stream =
+            TcpStream::connect(addr).with_context(|| format!("could not connect to {addr}"))?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream })
+    }
+}
+impl Transport for TcpTransport {
+    fn send(&mut self, frame: &[u8]) -> Result<()> {
+        self.stream
+            .write_all(frame)
+            .context("could not write to the socket")
+    }
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        match self.stream.read(buf) {
+            Ok(0) => bail!("connection closed"),
+            Ok(n) => Ok(n),
+            Err(e) if is_timeout(&e) => Ok(0),
+            Err(e) => Err(e).context("could not read socket"),
+        }
+    }
+    fn clear(&mut self) -> Result<()> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0; 256];
+        let res = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => continue,
+                Err(e) if is_timeout(&e) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        res.context("could not read socket")
+    }
+}
+
+// files have no read timeout, so replies are read on another thread
+pub struct PipeTransport {
+    tx: File,
+    rx: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+impl PipeTransport {
+    pub fn open(tx: &str, rx: &str) -> Result<Self> {
+        // opening a fifo blocks until the other side opens it, so the order matters
+        let writer = OpenOptions::new()
+            .write(true)
+            .open(tx)
+            .with_context(|| format!("could not open {tx}"))?;
+        let mut reader = File::open(rx).with_context(|| format!("could not open {rx}"))?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0; 256];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if sender.send(buf[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            tx: writer,
+            rx: receiver,
+            pending: vec![],
+        })
+    }
+}
+impl Transport for PipeTransport {
+    fn send(&mut self, frame: &[u8]) -> Result<()> {
+        self.tx
+            .write_all(frame)
+            .context("could not write to the pipe")?;
+        self.tx.flush().context("could not write to the pipe")
+    }
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        if self.pending.is_empty() {
+            match self.rx.recv_timeout(timeout) {
+                Ok(v) => self.pending = v,
+                Err(RecvTimeoutError::Timeout) => return Ok(0),
+                Err(RecvTimeoutError::Disconnected) => bail!("pipe closed"),
+            }
+        }
+        let n = self.pending.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+    fn clear(&mut self) -> Result<()> {
+        self.pending.clear();
+        while self.rx.try_recv().is_ok() {}
+        Ok(())
+    }
+}
+
+// payload size the host VM accepts : UART_BUF_SIZE of the machines minus the 9 bytes a frame adds
+const LOOPBACK_BUF_SIZE: usize = 1015;
+// reply size of the host VM, which is REPLY_BUF_SIZE of the esp32
+const LOOPBACK_REPLY_SIZE: usize = 2048;
+// payload size of telemetry frames of the host VM, which is TELEMETRY_BUF_SIZE of the esp32
+const LOOPBACK_TELEMETRY_SIZE: usize = 256;
+// the host VM updates nodes once in this time while the host waits for it
+const LOOPBACK_PERIOD: Duration = Duration::from_millis(10);
+
+pub struct LoopbackTransport {
+    vm: Machine,
+    device: Device,
+    reply: Vec<u8>,
+}
+impl Default for LoopbackTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl LoopbackTransport {
+    pub fn new() -> Self {
+        Self {
+            vm: Machine::new(),
+            device: Device::new(
+                LOOPBACK_BUF_SIZE,
+                LOOPBACK_REPLY_SIZE,
+                LOOPBACK_TELEMETRY_SIZE,
+            ),
+            reply: vec![],
+        }
+    }
+    fn update(&mut self) {
+        let status = self.vm.update();
+        if let Some(f) = self.device.telemetry(&mut self.vm, status) {
+            self.reply.extend(f.encode());
+        }
+    }
+}
+impl Transport for LoopbackTransport {
+    fn send(&mut self, frame: &[u8]) -> Result<()> {
+        // the device keeps updating nodes between frames
+        self.update();
+        let reply = self.device.receive(&mut self.vm, frame);
+        self.reply.extend(reply);
+        Ok(())
+    }
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        if self.reply.is_empty() {
+            thread::sleep(timeout.min(LOOPBACK_PERIOD));
+            self.update();
+        }
+        let n = self.reply.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.reply[..n]);
+        self.reply.drain(..n);
+        Ok(n)
+    }
+    fn clear(&mut self) -> Result<()> {
+        self.reply.clear();
+        Ok(())
+    }
+    fn machine(&mut self) -> Option<&mut Machine> {
+        Some(&mut self.vm)
+    }
+}
@@ -0,0 +1,64 @@
+def
+new_nodes 0
+new_funcs 0
+new_data 8
+num_last 0
+update
+init
+  0000: Int 7                   ; 25 07
+  0002: SetData 0               ; 8d 00
+  0004: Int 3                   ; 21
+  0005: SetData 1               ; 8d 01
+  0007: GetData 0               ; 82 00
+  0009: GetData 1               ; 82 01
+  0011: Neq                     ; 11
+  0012: SetData 2               ; 8d 02
+  0014: GetData 0               ; 82 00
+  0016: GetData 1               ; 82 01
+  0018: Eq                      ; 10
+  0019: SetData 3               ; 8d 03
+  0021: GetData 0               ; 82 00
+  0023: GetData 1               ; 82 01
+  0025: Ls                      ; 0c
+  0026: Je8 6                   ; 53 06 -> 0034
+  0028: GetData 0               ; 82 00
+  0030: GetData 1               ; 82 01
+  0032: Geq                     ; 0f
+  0033: J1                      ; f4 -> 0035
+  0034: PushTrue                ; 1a
+  0035: Jne8 7                  ; 50 07 -> 0044
+  0037: GetData 0               ; 82 00
+  0039: GetData 1               ; 82 01
+  0041: Leq                     ; 0d
+  0042: Not                     ; 03
+  0043: J1                      ; f4 -> 0045
+  0044: PushFalse               ; 1b
+  0045: SetData 4               ; 8d 04
+  0047: GetData 0               ; 82 00
+  0049: GetData 1               ; 82 01
+  0051: BitAnd                  ; 12
+  0052: GetData 0               ; 82 00
+  0054: GetData 1               ; 82 01
+  0056: BitXor                  ; 14
+  0057: BitOr                   ; 13
+  0058: SetData 5               ; 8d 05
+  0060: GetData 0               ; 82 00
+  0062: Int 2                   ; 20
+  0063: ShiftL                  ; 0a
+  0064: GetData 0               ; 82 00
+  0066: Int 1                   ; 1f
+  0067: ShiftR                  ; 0b
+  0068: Add                     ; 05
+  0069: SetData 6               ; 8d 06
+  0071: GetData 0               ; 82 00
+  0073: Minus                   ; 04
+  0074: GetData 1               ; 82 01
+  0076: Mul                     ; 07
+  0077: Int 2                   ; 20
+  0078: Div                     ; 08
+  0079: Int 5                   ; 23
+  0080: Mod                     ; 09
+  0081: Int 1                   ; 1f
+  0082: Sub                     ; 06
+  0083: SetData 7               ; 8d 07
+  0085: Halt                    ; 18
@@ -0,0 +1,46 @@
+def
+new_nodes 2
+new_funcs 0
+new_data 2
+num_last 0
+node 0
+  0000: GetNode 0               ; 85 00
+  0002: Int 1                   ; 1f
+  0003: Add                     ; 05
+  0004: EndUpdateNode 0         ; 9d 00
+node 1
+  0000: GetNode 0               ; 85 00
+  0002: Int 2                   ; 20
+  0003: Mod                     ; 09
+  0004: Int 0                   ; 1e
+  0005: Eq                      ; 10
+  0006: EndUpdateNode 1         ; 9d 01
+update
+  0000: UpdateNode 0            ; 75 00
+  0002: UpdateNode 1            ; 75 01
+  0004: Halt                    ; 18
+init
+  0000: Int 5                   ; 23
+  0001: SetData 0               ; 8d 00
+  0003: AllocLocal 3            ; 3f
+  0004: GetData 0               ; 82 00
+  0006: Int 3                   ; 21
+  0007: Gt                      ; 0e
+  0008: Je8 3                   ; 53 03 -> 0013
+  0010: Int 0                   ; 1e
+  0011: J8 10                   ; 56 0a -> 0023
+  0013: GetData 0               ; 82 00
+  0015: Int 2                   ; 20
+  0016: Mul                     ; 07
+  0017: SetLocal 1              ; 33
+  0018: GetLocal 1              ; 29
+  0019: Int 1                   ; 1f
+  0020: Add                     ; 05
+  0021: SetLocal 2              ; 34
+  0022: GetLocal 2              ; 2a
+  0023: SetLocal 0              ; 32
+  0024: Pop 2                   ; 48
+  0025: SetData 1               ; 8d 01
+  0027: Int 0                   ; 1e
+  0028: SetNode 0               ; 61 00
+  0030: Halt                    ; 18
@@ -0,0 +1,45 @@
+def
+new_nodes 3
+new_funcs 1
+new_data 2
+num_last 0
+node 0
+  0000: GetNode 0               ; 85 00
+  0002: GetData 1               ; 82 01
+  0004: IntToFloat              ; f9
+  0005: GetNode 0               ; 85 00
+  0007: FSub                    ; 89
+  0008: GetData 0               ; 82 00
+  0010: FMul                    ; 8a
+  0011: FAdd                    ; 88
+  0012: EndUpdateNode 0         ; 9d 00
+node 1
+  0000: GetNode 0               ; 85 00
+  0002: Float 10.0              ; a9 00 00 20 41
+  0007: FMul                    ; 8a
+  0008: FloatToInt              ; fa
+  0009: EndUpdateNode 1         ; 9d 01
+node 2
+  0000: GetNode 0               ; 85 00
+  0002: Float 3.5               ; a9 00 00 60 40
+  0007: FGeq                    ; 94
+  0008: EndUpdateNode 2         ; 9d 02
+func 0
+  0000: GetLocal 0              ; 28
+  0001: FMinus                  ; 8c
+  0002: GetLocal 1              ; 29
+  0003: FDiv                    ; 8b
+  0004: Return                  ; 15
+update
+  0000: UpdateNode 0            ; 75 00
+  0002: UpdateNode 1            ; 75 01
+  0004: UpdateNode 2            ; 75 02
+  0006: Halt                    ; 18
+init
+  0000: Float 0.25              ; a9 00 00 80 3e
+  0005: SetData 0               ; 8d 00
+  0007: Int 7                   ; 25 07
+  0009: SetData 1               ; 8d 01
+  0011: Float 0.0               ; a9 00 00 00 00
+  0016: SetNode 0               ; 61 00
+  0018: Halt                    ; 18
@@ -0,0 +1,32 @@
+def
+new_nodes 0
+new_funcs 2
+new_data 1
+num_last 0
+func 0
+  0000: GetLocal 0              ; 28
+  0001: Int 1                   ; 1f
+  0002: Leq                     ; 0d
+  0003: Je8 9                   ; 53 09 -> 0014
+  0005: GetLocal 0              ; 28
+  0006: GetLocal 0              ; 28
+  0007: Int 1                   ; 1f
+  0008: Sub                     ; 06
+  0009: Call 1 0                ; 7f 01 00
+  0012: Mul                     ; 07
+  0013: J1                      ; f4 -> 0015
+  0014: Int 1                   ; 1f
+  0015: Return                  ; 15
+func 1
+  0000: GetLocal 0              ; 28
+  0001: GetLocal 1              ; 29
+  0002: Add                     ; 05
+  0003: Return                  ; 15
+update
+init
+  0000: Int 5                   ; 23
+  0001: Call 1 0                ; 7f 01 00
+  0004: Int 1                   ; 1f
+  0005: Call 2 1                ; 7f 02 01
+  0008: SetData 0               ; 8d 00
+  0010: Halt                    ; 18
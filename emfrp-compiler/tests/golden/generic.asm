@@ -0,0 +1,59 @@
+def
+new_nodes 0
+new_funcs 2
+new_data 2
+num_last 0
+func 0
+  0000: AllocLocal 3            ; 3f
+  0001: GetLocalRef 0           ; b4
+  0002: SetLocal 3              ; 35
+  0003: GetLocal 3              ; 2b
+  0004: ObjTag                  ; 90
+  0005: Int 1                   ; 1f
+  0006: Eq                      ; 10
+  0007: Jne8 9                  ; 50 09 -> 0018
+  0009: GetLocal 3              ; 2b
+  0010: ObjField 0              ; 64
+  0011: SetLocal 4              ; 36
+  0012: GetLocal 4              ; 2c
+  0013: J32 6                   ; 58 06 00 00 00 -> 0024
+  0018: GetLocal 1              ; 29
+  0019: J32 0                   ; 58 00 00 00 00 -> 0024
+  0024: DropLocalObj 3          ; ad
+  0025: SetLocal 2              ; 34
+  0026: Pop 2                   ; 48
+  0027: DropLocalObj 0          ; aa
+  0028: Return                  ; 15
+func 1
+  0000: AllocLocal 3            ; 3f
+  0001: GetLocalRef 0           ; b4
+  0002: SetLocal 3              ; 35
+  0003: GetLocal 3              ; 2b
+  0004: ObjTag                  ; 90
+  0005: Int 1                   ; 1f
+  0006: Eq                      ; 10
+  0007: Jne8 9                  ; 50 09 -> 0018
+  0009: GetLocal 3              ; 2b
+  0010: ObjField 0              ; 64
+  0011: SetLocal 4              ; 36
+  0012: GetLocal 4              ; 2c
+  0013: J32 6                   ; 58 06 00 00 00 -> 0024
+  0018: GetLocal 1              ; 29
+  0019: J32 0                   ; 58 00 00 00 00 -> 0024
+  0024: DropLocalObj 3          ; ad
+  0025: SetLocal 2              ; 34
+  0026: Pop 2                   ; 48
+  0027: DropLocalObj 0          ; aa
+  0028: Return                  ; 15
+update
+init
+  0000: Int 3                   ; 21
+  0001: AllocObj 1 0x02200001   ; a1 01 00 20 02
+  0006: Int 0                   ; 1e
+  0007: Call 2 1                ; 7f 02 01
+  0010: SetData 0               ; 8d 00
+  0012: AllocObj 1 0x04000001   ; a1 01 00 00 04
+  0017: PushTrue                ; 1a
+  0018: Call 2 0                ; 7f 02 00
+  0021: SetData 1               ; 8d 01
+  0023: Halt                    ; 18
@@ -0,0 +1,49 @@
+def
+new_nodes 2
+new_funcs 0
+new_data 4
+num_last 0
+node 0
+  0000: GetNode 0               ; 85 00
+  0002: Int 1                   ; 1f
+  0003: Add                     ; 05
+  0004: EndUpdateNode 0         ; 9d 00
+node 1
+  0000: AllocLocal 2            ; 3e
+  0001: GetNode 0               ; 85 00
+  0003: SetLocal 2              ; 34
+  0004: GetLocal 2              ; 2a
+  0005: Int -1                  ; 25 ff
+  0007: Eq                      ; 10
+  0008: Jne8 7                  ; 50 07 -> 0017
+  0010: Int 110                 ; 25 6e
+  0012: J32 19                  ; 58 13 00 00 00 -> 0036
+  0017: GetLocal 2              ; 2a
+  0018: Int 0                   ; 1e
+  0019: Eq                      ; 10
+  0020: Jne8 7                  ; 50 07 -> 0029
+  0022: Int 122                 ; 25 7a
+  0024: J32 7                   ; 58 07 00 00 00 -> 0036
+  0029: Int 112                 ; 25 70
+  0031: J32 0                   ; 58 00 00 00 00 -> 0036
+  0036: SetLocal 1              ; 33
+  0037: Pop 1                   ; 47
+  0038: EndUpdateNode 1         ; 9d 01
+update
+  0000: UpdateNode 0            ; 75 00
+  0002: UpdateNode 1            ; 75 01
+  0004: Halt                    ; 18
+init
+  0000: Int -16711936           ; 27 00 ff 00 ff
+  0005: SetData 0               ; 8d 00
+  0007: Int 10                  ; 25 0a
+  0009: Int 5                   ; 23
+  0010: BitOr                   ; 13
+  0011: SetData 1               ; 8d 01
+  0013: Int -2147483648         ; 27 00 00 00 80
+  0018: SetData 2               ; 8d 02
+  0020: Int 10                  ; 25 0a
+  0022: SetData 3               ; 8d 03
+  0024: Int 0                   ; 1e
+  0025: SetNode 0               ; 61 00
+  0027: Halt                    ; 18
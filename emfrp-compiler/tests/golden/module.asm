@@ -0,0 +1,69 @@
+input btn : Bool
+output led : Bool
+def
+new_nodes 1
+new_funcs 4
+new_data 0
+num_last 0
+node 2
+  0000: GetNode 0               ; 85 00
+  0002: Je8 4                   ; 53 04 -> 0008
+  0004: GetNode 2               ; 85 02
+  0006: J8 4                    ; 56 04 -> 0012
+  0008: GetNode 2               ; 85 02
+  0010: Int 1                   ; 1f
+  0011: Add                     ; 05
+  0012: EndUpdateNode 2         ; 9d 02
+node 1
+  0000: GetNode 2               ; 85 02
+  0002: Int 2                   ; 20
+  0003: Mod                     ; 09
+  0004: Int 0                   ; 1e
+  0005: Eq                      ; 10
+  0006: Call 1 0                ; 7f 01 00
+  0009: OutputAction 0          ; 78
+  0010: EndUpdateNode 1         ; 9d 01
+func 0
+  0000: GetLocal 0              ; 28
+  0001: Je8 2                   ; 53 02 -> 0005
+  0003: PushTrue                ; 1a
+  0004: J1                      ; f4 -> 0006
+  0005: PushFalse               ; 1b
+  0006: Return                  ; 15
+func 1
+  0000: GetLocal 0              ; 28
+  0001: Int 0                   ; 1e
+  0002: Ls                      ; 0c
+  0003: Je8 3                   ; 53 03 -> 0008
+  0005: GetLocal 0              ; 28
+  0006: J8 2                    ; 56 02 -> 0010
+  0008: GetLocal 0              ; 28
+  0009: Minus                   ; 04
+  0010: Return                  ; 15
+func 2
+  0000: GetLocal 0              ; 28
+  0001: GetLocal 1              ; 29
+  0002: Ls                      ; 0c
+  0003: Je8 2                   ; 53 02 -> 0007
+  0005: GetLocal 0              ; 28
+  0006: J1                      ; f4 -> 0008
+  0007: GetLocal 1              ; 29
+  0008: Return                  ; 15
+func 3
+  0000: GetLocal 0              ; 28
+  0001: GetLocal 1              ; 29
+  0002: Ls                      ; 0c
+  0003: Je8 2                   ; 53 02 -> 0007
+  0005: GetLocal 1              ; 29
+  0006: J1                      ; f4 -> 0008
+  0007: GetLocal 0              ; 28
+  0008: Return                  ; 15
+update
+  0000: UpdateDev 0             ; 6e
+  0001: UpdateNode 2            ; 75 02
+  0003: UpdateNode 1            ; 75 01
+  0005: Halt                    ; 18
+init
+  0000: Int 0                   ; 1e
+  0001: SetNode 2               ; 61 02
+  0003: Halt                    ; 18
@@ -0,0 +1,94 @@
+def
+new_nodes 0
+new_funcs 1
+new_data 1
+num_last 0
+func 0
+  0000: AllocLocal 4            ; 40
+  0001: GetLocalRef 0           ; b4
+  0002: SetLocal 2              ; 34
+  0003: GetLocal 2              ; 2a
+  0004: ObjTag                  ; 90
+  0005: Int 1                   ; 1f
+  0006: Eq                      ; 10
+  0007: Jne8 74                 ; 50 4a -> 0083
+  0009: GetLocal 2              ; 2a
+  0010: ObjField 0              ; 64
+  0011: ObjField 0              ; 64
+  0012: ObjTag                  ; 90
+  0013: Int 1                   ; 1f
+  0014: Eq                      ; 10
+  0015: Jne8 35                 ; 50 23 -> 0052
+  0017: GetLocal 2              ; 2a
+  0018: ObjField 0              ; 64
+  0019: ObjField 0              ; 64
+  0020: ObjField 0              ; 64
+  0021: Int 0                   ; 1e
+  0022: Eq                      ; 10
+  0023: Jne8 6                  ; 50 06 -> 0031
+  0025: Int 0                   ; 1e
+  0026: J32 64                  ; 58 40 00 00 00 -> 0095
+  0031: GetLocal 2              ; 2a
+  0032: ObjField 0              ; 64
+  0033: ObjField 1              ; 65
+  0034: Jne8 11                 ; 50 0b -> 0047
+  0036: GetLocal 2              ; 2a
+  0037: ObjField 0              ; 64
+  0038: ObjField 0              ; 64
+  0039: ObjField 0              ; 64
+  0040: SetLocal 3              ; 35
+  0041: GetLocal 3              ; 2b
+  0042: J32 48                  ; 58 30 00 00 00 -> 0095
+  0047: J32 37                  ; 58 25 00 00 00 -> 0089
+  0052: GetLocal 2              ; 2a
+  0053: ObjField 0              ; 64
+  0054: ObjField 0              ; 64
+  0055: ObjTag                  ; 90
+  0056: Int 2                   ; 20
+  0057: Eq                      ; 10
+  0058: Jne8 18                 ; 50 12 -> 0078
+  0060: GetLocal 2              ; 2a
+  0061: ObjField 0              ; 64
+  0062: ObjField 0              ; 64
+  0063: ObjField 0              ; 64
+  0064: SetLocal 3              ; 35
+  0065: GetLocal 2              ; 2a
+  0066: ObjField 0              ; 64
+  0067: ObjField 0              ; 64
+  0068: ObjField 1              ; 65
+  0069: SetLocal 4              ; 36
+  0070: GetLocal 3              ; 2b
+  0071: GetLocal 4              ; 2c
+  0072: Mul                     ; 07
+  0073: J32 17                  ; 58 11 00 00 00 -> 0095
+  0078: J32 6                   ; 58 06 00 00 00 -> 0089
+  0083: Int 2                   ; 20
+  0084: J32 6                   ; 58 06 00 00 00 -> 0095
+  0089: Int 1                   ; 1f
+  0090: J32 0                   ; 58 00 00 00 00 -> 0095
+  0095: DropLocalObj 2          ; ac
+  0096: SetLocal 1              ; 33
+  0097: Pop 3                   ; 49
+  0098: DropLocalObj 0          ; aa
+  0099: Return                  ; 15
+update
+init
+  0000: Int 3                   ; 21
+  0001: AllocObj 2 0x02200001   ; a2 01 00 20 02
+  0006: PushTrue                ; 1a
+  0007: AllocObj 2 0x02404001   ; a2 01 40 40 02
+  0012: AllocObj 1 0x02204001   ; a1 01 40 20 02
+  0017: Call 1 0                ; 7f 01 00
+  0020: Int 2                   ; 20
+  0021: Int 5                   ; 23
+  0022: AllocObj 2 0x04400001   ; a2 01 00 40 04
+  0027: PushFalse               ; 1b
+  0028: AllocObj 2 0x02404001   ; a2 01 40 40 02
+  0033: AllocObj 1 0x02204001   ; a1 01 40 20 02
+  0038: Call 1 0                ; 7f 01 00
+  0041: Add                     ; 05
+  0042: AllocObj 1 0x04000001   ; a1 01 00 00 04
+  0047: Call 1 0                ; 7f 01 00
+  0050: Add                     ; 05
+  0051: SetData 0               ; 8d 00
+  0053: Halt                    ; 18
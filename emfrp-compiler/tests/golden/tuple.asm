@@ -0,0 +1,39 @@
+def
+new_nodes 0
+new_funcs 0
+new_data 2
+num_last 0
+update
+init
+  0000: Int 1                   ; 1f
+  0001: PushTrue                ; 1a
+  0002: Int 3                   ; 21
+  0003: AllocObj 3 0x02600001   ; a3 01 00 60 02
+  0008: SetDataRef 0            ; e3 00
+  0010: AllocLocal 5            ; 41
+  0011: GetDataRef 0            ; d8 00
+  0013: SetLocal 1              ; 33
+  0014: GetLocal 1              ; 29
+  0015: ObjField 0              ; 64
+  0016: SetLocal 2              ; 34
+  0017: GetLocal 1              ; 29
+  0018: ObjField 1              ; 65
+  0019: SetLocal 3              ; 35
+  0020: GetLocal 1              ; 29
+  0021: ObjField 2              ; 66
+  0022: SetLocal 4              ; 36
+  0023: GetLocal 3              ; 2b
+  0024: Je8 5                   ; 53 05 -> 0031
+  0026: GetLocal 2              ; 2a
+  0027: GetLocal 4              ; 2c
+  0028: Sub                     ; 06
+  0029: J8 3                    ; 56 03 -> 0034
+  0031: GetLocal 2              ; 2a
+  0032: GetLocal 4              ; 2c
+  0033: Add                     ; 05
+  0034: J32 0                   ; 58 00 00 00 00 -> 0039
+  0039: DropLocalObj 1          ; ab
+  0040: SetLocal 0              ; 32
+  0041: Pop 4                   ; 4a
+  0042: SetData 1               ; 8d 01
+  0044: Halt                    ; 18
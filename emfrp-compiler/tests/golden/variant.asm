@@ -0,0 +1,60 @@
+def
+new_nodes 0
+new_funcs 1
+new_data 1
+num_last 0
+func 0
+  0000: AllocLocal 4            ; 40
+  0001: GetLocalRef 0           ; b4
+  0002: SetLocal 2              ; 34
+  0003: GetLocal 2              ; 2a
+  0004: ObjTag                  ; 90
+  0005: Int 1                   ; 1f
+  0006: Eq                      ; 10
+  0007: Jne8 13                 ; 50 0d -> 0022
+  0009: GetLocal 2              ; 2a
+  0010: ObjField 0              ; 64
+  0011: SetLocal 3              ; 35
+  0012: Int 3                   ; 21
+  0013: GetLocal 3              ; 2b
+  0014: Mul                     ; 07
+  0015: GetLocal 3              ; 2b
+  0016: Mul                     ; 07
+  0017: J32 26                  ; 58 1a 00 00 00 -> 0048
+  0022: GetLocal 2              ; 2a
+  0023: ObjTag                  ; 90
+  0024: Int 2                   ; 20
+  0025: Eq                      ; 10
+  0026: Jne8 14                 ; 50 0e -> 0042
+  0028: GetLocal 2              ; 2a
+  0029: ObjField 0              ; 64
+  0030: SetLocal 3              ; 35
+  0031: GetLocal 2              ; 2a
+  0032: ObjField 1              ; 65
+  0033: SetLocal 4              ; 36
+  0034: GetLocal 3              ; 2b
+  0035: GetLocal 4              ; 2c
+  0036: Mul                     ; 07
+  0037: J32 6                   ; 58 06 00 00 00 -> 0048
+  0042: Int 0                   ; 1e
+  0043: J32 0                   ; 58 00 00 00 00 -> 0048
+  0048: DropLocalObj 2          ; ac
+  0049: SetLocal 1              ; 33
+  0050: Pop 3                   ; 49
+  0051: DropLocalObj 0          ; aa
+  0052: Return                  ; 15
+update
+init
+  0000: Int 2                   ; 20
+  0001: AllocObj 2 0x02200001   ; a2 01 00 20 02
+  0006: Call 1 0                ; 7f 01 00
+  0009: Int 3                   ; 21
+  0010: Int 4                   ; 22
+  0011: AllocObj 2 0x04400001   ; a2 01 00 40 04
+  0016: Call 1 0                ; 7f 01 00
+  0019: Add                     ; 05
+  0020: AllocObj 2 0x06000001   ; a2 01 00 00 06
+  0025: Call 1 0                ; 7f 01 00
+  0028: Add                     ; 05
+  0029: SetData 0               ; 8d 00
+  0031: Halt                    ; 18
@@ -1,8 +1,11 @@
 #define __ARDUINO
+// #define EMFRP_LOAD_IMAGE // loads include/emfrp_image.h made by `emfrp-compiler compile` at boot
 // #define EMFRP_MEASURE_HEAP
 //    #define EMFRP_MEASURE_LATENCY
 // #define EMFRP_DEBUG
 //   #define EMFRP_DEBUG_OBJCNT
 // #define EMFRP_DEBUG_LEVEL2
 #define UART_BUF_SIZE 1024
+#define REPLY_BUF_SIZE 64
+#define TELEMETRY_BUF_SIZE 32
 #define STACK_SIZE 128
\ No newline at end of file
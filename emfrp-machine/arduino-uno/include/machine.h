@@ -14,11 +14,14 @@
 typedef union value_t
 {
     int32_t num;
+    float f;
     uint32_t obj_header;
     uint8_t *ip;
     union value_t *bp;
     union value_t *obj;
 } value_t;
+// version of the image loaded by emfrp_load_image
+#define EMFRP_IMAGE_VERSION 1
 typedef void (*dev_input_t)(value_t *);
 typedef struct call_frame
 {
@@ -72,6 +75,23 @@ typedef struct
     int cap;
 } node_list_t;
 typedef struct
+{
+    int offset;
+    uint8_t *body;
+} staged_body_t;
+// definitions kept by emfrp_stage until emfrp_commit swaps them in
+typedef struct
+{
+    int active;
+    int n_node, n_func, n_new_data;
+    staged_body_t *nodes, *funcs;
+    // NULL if the update code is kept
+    uint8_t *update;
+    value_t *node_last;
+    // NULL if there is no init code
+    uint8_t *init;
+} staged_t;
+typedef struct
 {
     value_t *v_stack, *node_last;
     uint8_t *update;
@@ -80,6 +100,7 @@ typedef struct
     node_list_t node_list;
     output_action_t *output_actions;
     int output_nd_len;
+    staged_t staged;
 } emfrp_machine_t;
 
 typedef enum
@@ -151,6 +172,7 @@ typedef enum
     BC_ALLOC_LOCAL_U8 = 67,
     BC_ALLOC_LOCAL_U16 = 68,
     BC_ALLOC_LOCAL_U32 = 69,
+    BC_POP_0 = 70,
     BC_POP_1 = 71,
     BC_POP_2 = 72,
     BC_POP_3 = 73,
@@ -188,6 +210,7 @@ typedef enum
     BC_OBJ_FIELD_4 = 104,
     BC_OBJ_FIELD_5 = 105,
     BC_OBJ_FIELD_6 = 106,
+    BC_OBJ_FIELD_U8 = 107,
 
     BC_UPD_DEV_0 = 110,
     BC_UPD_DEV_1 = 111,
@@ -270,6 +293,7 @@ typedef enum
     BC_OBJ_FIELD_REF_4 = 204,
     BC_OBJ_FIELD_REF_5 = 205,
     BC_OBJ_FIELD_REF_6 = 206,
+    BC_OBJ_FIELD_REF_U8 = 207,
     BC_END_UPD_OBJ_U8 = 210,
     BC_END_UPD_OBJ_U16 = 211,
     BC_END_UPD_OBJ_U32 = 212,
@@ -308,6 +332,20 @@ typedef enum
     BC_Je1 = 246,
     BC_Jne0 = 247,
     BC_Jne1 = 248,
+    BC_FAdd = 136,
+    BC_FSub = 137,
+    BC_FMul = 138,
+    BC_FDiv = 139,
+    BC_FMinus = 140,
+    BC_FLs = 145,
+    BC_FLeq = 146,
+    BC_FGt = 147,
+    BC_FGeq = 148,
+    BC_FEq = 149,
+    BC_FNeq = 168,
+    BC_FLOAT = 169,
+    BC_IntToFloat = 249,
+    BC_FloatToInt = 250,
 
 } bytecode;
 emfrp_result_t emfrp_init(emfrp_machine_t *em, int n_input_node, int n_output_node);
@@ -315,6 +353,12 @@ void emfrp_add_input_node(emfrp_machine_t *em, value_t init, dev_input_t driver)
 void emfrp_add_output_node(emfrp_machine_t *em, value_t init, output_action_t driver);
 emfrp_result_t emfrp_update(emfrp_machine_t *em);
 emfrp_result_t emfrp_new_bytecode(emfrp_machine_t *em, int data_len, uint8_t *data);
+emfrp_result_t emfrp_stage(emfrp_machine_t *em, int data_len, uint8_t *data);
+emfrp_result_t emfrp_commit(emfrp_machine_t *em);
+void emfrp_abort(emfrp_machine_t *em);
+emfrp_result_t emfrp_load_image(emfrp_machine_t *em, const uint8_t *image, int len);
+emfrp_result_t emfrp_query(emfrp_machine_t *em, int kind, int is_obj, int index);
+emfrp_result_t emfrp_remove(emfrp_machine_t *em, int data_len, uint8_t *data);
 value_t emfrp_int(int32_t i);
 value_t emfrp_true();
 value_t emfrp_false();
@@ -7,12 +7,17 @@
 #define ECHO_TEST_CTS (UART_PIN_NO_CHANGE)
 #define ECHO_UART_PORT_NUM 0
 #define ECHO_UART_BAUD_RATE 115200
+#include "esp_system.h"
+#include "esp_timer.h"
 #ifdef EMFRP_DEBUG
 #include "esp_log.h"
 #endif
 #endif
 #ifdef __ARDUINO
 #include "Arduino.h"
+#ifdef __AVR__
+#include <avr/wdt.h>
+#endif
 #endif
 void setup_uart_with_bufsize(int buf_size)
 {
@@ -60,6 +65,32 @@ int uart_read(char *buf, int max_len)
 #endif
     return -1;
 }
+uint32_t uptime_ms(void)
+{
+#ifdef __ESP
+    return (uint32_t)(esp_timer_get_time() / 1000);
+#endif
+#ifdef __ARDUINO
+    return millis();
+#endif
+    return 0;
+}
+void restart(void)
+{
+#ifdef __ESP
+    esp_restart();
+#endif
+#ifdef __ARDUINO
+#ifdef __AVR__
+    // the watchdog resets the board
+    wdt_enable(WDTO_15MS);
+    while (1)
+        ;
+#else
+    NVIC_SystemReset();
+#endif
+#endif
+}
 #ifdef EMFRP_DEBUG
 void dbg_int(const char *info, int n)
 {
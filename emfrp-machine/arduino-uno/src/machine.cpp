@@ -7,6 +7,7 @@
 #define INCLUDE_IO
 #include "io.h"
 #endif
+#include "protocol.h"
 #define READ_U8() (*ip++)
 #define READ_I8() (int8_t)(*ip++)
 #define READ_I32(dest)                                                                               \
@@ -21,20 +22,44 @@
 #define READ_U32(dest)                                                                                   \
     dest = (uint32_t)ip[0] + ((uint32_t)ip[1] << 8) + ((uint32_t)ip[2] << 16) + ((uint32_t)ip[3] << 24); \
     ip += 4;
+#ifdef __AVR__
+#include <avr/pgmspace.h>
+// images are kept in flash with PROGMEM
+#define IMAGE_BYTE(p) pgm_read_byte(p)
+#define IMAGE_COPY(dst, src, n) memcpy_P(dst, src, n)
+#else
+#define IMAGE_BYTE(p) (*(p))
+#define IMAGE_COPY(dst, src, n) memcpy(dst, src, n)
+#endif
 #define PUSH_PTR(val) sp++->ptr = (val)
 #define PUSH_NUM(val) sp++->num = (val)
 #define POP_NUM() ((--sp)->num)
+#define PUSH_FLOAT(val) sp++->f = (val)
+#define POP_FLOAT() ((--sp)->f)
 #define PUSH(val) *sp++ = (val)
 #define POP() (*--sp)
-// header tag:7bit/ reserved:1bit/ numentry:3bit/ objbit:7bit/ refcnt:14bit
+// header tag:7bit/ carry:1bit/ numentry:3bit/ objbit:7bit/ refcnt:14bit
+// tag 0 escapes to an extended header in the words before the header:
+// tag:16bit/ numentry:16bit at obj[-1], and objbit words of 32 fields each from obj[-2] down
 #define OBJ_HEADER(vobj) ((vobj).obj->obj_header)
-#define OBJ_TAG(vobj) (OBJ_HEADER(vobj) >> 25)
-#define OBJ_ENTRY_NUM(vobj) ((OBJ_HEADER(vobj) & 0xe00000) >> 21)
-#define OBJBIT_ITH(vobj, i) ((OBJ_HEADER(vobj) & ((uint32_t)1 << (i + 14))) != 0)
+#define OBJ_EXTENDED(vobj) ((OBJ_HEADER(vobj) >> 25) == 0)
+#define OBJ_EXT(vobj) ((vobj).obj[-1].obj_header)
+#define OBJ_TAG(vobj) (OBJ_EXTENDED(vobj) ? OBJ_EXT(vobj) >> 16 : OBJ_HEADER(vobj) >> 25)
+#define OBJ_ENTRY_NUM(vobj) \
+    (OBJ_EXTENDED(vobj) ? OBJ_EXT(vobj) & 0xffff : (OBJ_HEADER(vobj) & 0xe00000) >> 21)
+#define OBJBIT_ITH(vobj, i)                                                            \
+    (OBJ_EXTENDED(vobj) ? (((vobj).obj[-2 - (i) / 32].obj_header >> ((i) % 32)) & 1) \
+                        : (OBJ_HEADER(vobj) & ((uint32_t)1 << (i + 14))) != 0)
+// words of the extended header
+#define OBJ_EXT_LEN(vobj) (OBJ_EXTENDED(vobj) ? 1 + (OBJ_ENTRY_NUM(vobj) + 31) / 32 : 0)
 #define OBJ_ITH(vobj, i) ((vobj).obj[(i) + 1])
-#define OBJ_INC_RC(vobj) OBJ_HEADER(vobj) += 1
-#define OBJ_DEC_RC_SHALLOW(vobj) OBJ_HEADER(vobj) -= 1
-#define OBJ_GET_RC(vobj) (OBJ_HEADER(vobj) & 0x3fff)
+#define OBJ_INC_RC(vobj)              \
+    if (obj_inc_rc(vobj) != EMFRP_OK) \
+    return EMFRP_OUTOF_MEMORY
+// the refcount is refcnt, plus OBJ_RC_MAX for each carry in rc_carry if the carry bit is set
+#define OBJ_RC_MAX 0x3fff
+#define OBJ_RC_CARRY ((uint32_t)1 << 24)
+#define OBJ_GET_RC(vobj) (OBJ_HEADER(vobj) & OBJ_RC_MAX)
 #define INITIAL_VARIABLE_CAPACITY 3
 #define CHECK_NULL(v) \
     if (v == NULL)    \
@@ -77,9 +102,9 @@ static void dbg_u8array(char *name, uint8_t *v, int len)
     printf("]\n");
 }
 #endif
-static uint8_t object_size(value_t obj)
+static uint16_t object_size(value_t obj)
 {
-    uint8_t ret = 4;
+    uint16_t ret = 4 * (1 + OBJ_EXT_LEN(obj));
     for (uint8_t i = 0, j = OBJ_ENTRY_NUM(obj); i < j; ++i)
     {
 
@@ -94,30 +119,91 @@ static uint8_t object_size(value_t obj)
     }
     return ret;
 }
-static void uart_write_object(value_t obj)
+static void reply_write_object(value_t obj)
 {
-    uart_write((char *)&OBJ_HEADER(obj), 4);
+    reply_write((char *)&OBJ_HEADER(obj), 4);
+    for (int i = 1, j = OBJ_EXT_LEN(obj); i <= j; ++i)
+    {
+        reply_write((char *)&obj.obj[-i].obj_header, 4);
+    }
     for (int i = 0, j = OBJ_ENTRY_NUM(obj); i < j; ++i)
     {
 
         if (OBJBIT_ITH(obj, i))
         {
-            uart_write_object(OBJ_ITH(obj, i));
+            reply_write_object(OBJ_ITH(obj, i));
         }
         else
         {
-            uart_write((char *)&(OBJ_ITH(obj, i).num), 4);
+            reply_write((char *)&(OBJ_ITH(obj, i).num), 4);
         }
     }
 }
 
+typedef struct
+{
+    value_t *obj;
+    uint32_t n;
+} rc_carry_t;
+// objects whose refcount has overflowed the header, and how many times OBJ_RC_MAX is carried
+static rc_carry_t *rc_carry;
+static int rc_carry_len;
+
+static rc_carry_t *find_rc_carry(value_t obj)
+{
+    for (int i = 0; i < rc_carry_len; ++i)
+    {
+        if (rc_carry[i].obj == obj.obj)
+            return &rc_carry[i];
+    }
+    return NULL;
+}
+static emfrp_result_t obj_inc_rc(value_t obj)
+{
+    if (OBJ_GET_RC(obj) != OBJ_RC_MAX)
+    {
+        OBJ_HEADER(obj) += 1;
+        return EMFRP_OK;
+    }
+    rc_carry_t *c = (OBJ_HEADER(obj) & OBJ_RC_CARRY) ? find_rc_carry(obj) : NULL;
+    if (c == NULL)
+    {
+        c = (rc_carry_t *)realloc(rc_carry, sizeof(rc_carry_t) * (rc_carry_len + 1));
+        CHECK_NULL(c);
+        rc_carry = c;
+        c = &rc_carry[rc_carry_len++];
+        c->obj = obj.obj;
+        c->n = 0;
+    }
+    ++c->n;
+    // the header counts from 1 again
+    OBJ_HEADER(obj) = (OBJ_HEADER(obj) - OBJ_RC_MAX + 1) | OBJ_RC_CARRY;
+    return EMFRP_OK;
+}
+// decrements the refcount, and returns whether it is 0
+static int obj_dec_rc(value_t obj)
+{
+    if (OBJ_GET_RC(obj) == 1 && (OBJ_HEADER(obj) & OBJ_RC_CARRY))
+    {
+        rc_carry_t *c = find_rc_carry(obj);
+        OBJ_HEADER(obj) += OBJ_RC_MAX - 1;
+        if (--c->n == 0)
+        {
+            *c = rc_carry[--rc_carry_len];
+            OBJ_HEADER(obj) &= ~OBJ_RC_CARRY;
+        }
+        return 0;
+    }
+    OBJ_HEADER(obj) -= 1;
+    return !OBJ_GET_RC(obj);
+}
+
 static void drop(value_t obj)
 {
 
     if (obj.obj == NULL)
         return;
-    OBJ_DEC_RC_SHALLOW(obj);
-    if (!OBJ_GET_RC(obj))
+    if (obj_dec_rc(obj))
     {
         for (int i = 0, j = OBJ_ENTRY_NUM(obj); i < j; ++i)
         {
@@ -129,7 +215,7 @@ static void drop(value_t obj)
 #ifdef EMFRP_DEBUG_OBJCNT
         --obj_cnt;
 #endif
-        free(obj.obj);
+        free(obj.obj - OBJ_EXT_LEN(obj));
     }
 }
 
@@ -149,6 +235,33 @@ static inline value_t *alloc_obj(emfrp_machine_t *em, int len)
 #endif
 }
 
+// allocates an object of max entries behind its extended header, with the header of AllocObj
+static value_t *alloc_obj_insn(emfrp_machine_t *em, uint8_t **p, int max)
+{
+    uint8_t *ip = *p;
+    uint32_t header, ext = 0;
+    int n_ext = 0;
+    READ_U32(header);
+    if (header >> 25 == 0)
+    {
+        READ_U32(ext);
+        n_ext = 1 + ((ext & 0xffff) + 31) / 32;
+    }
+    value_t *obj = alloc_obj(em, n_ext + 1 + max);
+    if (obj == NULL)
+        return NULL;
+    obj += n_ext;
+    obj->obj_header = header;
+    if (n_ext)
+        obj[-1].obj_header = ext;
+    for (int i = 2; i <= n_ext; ++i)
+    {
+        READ_U32(obj[-i].obj_header);
+    }
+    *p = ip;
+    return obj;
+}
+
 static inline int next_word(uint8_t **p)
 { // little endian
     int ret = (int)(**p) + (((int)(p[0][1])) << 8);
@@ -214,23 +327,21 @@ emfrp_result_t extend_node_list(node_list_t *lst, const int new_cap)
 {
     if (lst->cap < new_cap)
     {
+        // both are allocated first, so that the list is kept if either fails
         value_t *vs = (value_t *)malloc(sizeof(value_t) * new_cap);
-        CHECK_NULL(vs);
-        memcpy(vs, lst->values, lst->len * sizeof(value_t));
-#ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("extend node list");
-#endif
-        free(lst->values);
-#ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("free node list");
-#endif
         upd_action_t *upds = (upd_action_t *)malloc(sizeof(upd_action_t) * new_cap);
-        CHECK_NULL(upds);
+        if (vs == NULL || upds == NULL)
+        {
+            free(vs);
+            free(upds);
+            return EMFRP_OUTOF_MEMORY;
+        }
+        memcpy(vs, lst->values, lst->len * sizeof(value_t));
         memcpy(upds, lst->action, lst->len * sizeof(upd_action_t));
-
 #ifdef EMFRP_MEASURE_HEAP
         update_min_free_heap_size("extend node list");
 #endif
+        free(lst->values);
         free(lst->action);
 #ifdef EMFRP_MEASURE_HEAP
         update_min_free_heap_size("free node list");
@@ -302,6 +413,7 @@ emfrp_result_t emfrp_init(emfrp_machine_t *em, int n_input_node, int n_output_no
 
     em->update = NULL;
     em->node_last = NULL;
+    memset(&em->staged, 0, sizeof(staged_t));
     if (n_output_node != 0)
     {
         em->output_actions = (output_action_t *)malloc(n_output_node * sizeof(output_action_t));
@@ -354,7 +466,9 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
     call_frame *frame_prev = NULL, *frame_tmp;
     value_t tmp_v;
     uint8_t tmp_byte;
+    uint16_t tmp_u16;
     uint32_t tmp_int;
+    float tmp_float;
 
     while (1)
     {
@@ -469,6 +583,79 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             PUSH_NUM(tmp_int);
             break;
 
+        case BC_FLOAT:
+            READ_U32(tmp_v.obj_header);
+            PUSH(tmp_v);
+            break;
+        case BC_FMinus:
+            sp[-1].f = -sp[-1].f;
+            break;
+        case BC_FAdd:
+            tmp_float = POP_FLOAT();
+            tmp_float = POP_FLOAT() + tmp_float;
+            PUSH_FLOAT(tmp_float);
+            break;
+        case BC_FSub:
+            tmp_float = POP_FLOAT();
+            tmp_float = POP_FLOAT() - tmp_float;
+            PUSH_FLOAT(tmp_float);
+            break;
+        case BC_FMul:
+            tmp_float = POP_FLOAT();
+            tmp_float = POP_FLOAT() * tmp_float;
+            PUSH_FLOAT(tmp_float);
+            break;
+        case BC_FDiv:
+            tmp_float = POP_FLOAT();
+            tmp_float = POP_FLOAT() / tmp_float;
+            PUSH_FLOAT(tmp_float);
+            break;
+        case BC_FLs:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() < tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FLeq:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() <= tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FGt:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() > tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FGeq:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() >= tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FEq:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() == tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FNeq:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() != tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_IntToFloat:
+            sp[-1].f = (float)sp[-1].num;
+            break;
+        case BC_FloatToInt:
+            // truncated toward zero. out of range values are saturated and NaN is 0, as the compiler's VM does
+            tmp_float = sp[-1].f;
+            if (tmp_float != tmp_float)
+                sp[-1].num = 0;
+            else if (tmp_float >= 2147483648.0f)
+                sp[-1].num = INT32_MAX;
+            else if (tmp_float <= -2147483648.0f)
+                sp[-1].num = INT32_MIN;
+            else
+                sp[-1].num = (int32_t)tmp_float;
+            break;
+
         case BC_INT_0:
             PUSH_NUM(0);
             break;
@@ -487,6 +674,9 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
         case BC_INT_5:
             PUSH_NUM(5);
             break;
+        case BC_INT_6:
+            PUSH_NUM(6);
+            break;
         case BC_INT_I8:
             PUSH_NUM((int)READ_I8());
             break;
@@ -537,7 +727,7 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             }
             else
             {
-                ip += 1;
+                ip += 2;
             }
             break;
         case BC_Je32:
@@ -549,7 +739,7 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             }
             else
             {
-                ip += 1;
+                ip += 4;
             }
             break;
         case BC_Jne8:
@@ -572,7 +762,7 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             }
             else
             {
-                ip += 1;
+                ip += 2;
             }
             break;
         case BC_Jne32:
@@ -763,7 +953,7 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             frame_prev = frame_tmp;
             bp = sp - tmp_byte;
 
-            READ_U32(tmp_int);
+            READ_U16(tmp_int);
             frame_tmp->next_ip = ip;
             ip = func[tmp_int];
             break;
@@ -807,7 +997,7 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             node_v[READ_U8()] = POP();
             break;
         case BC_SET_NODE_U16:
-            READ_U32(tmp_int);
+            READ_U16(tmp_int);
             node_v[tmp_int] = POP();
             break;
         case BC_SET_NODE_U32:
@@ -866,9 +1056,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             ip = sp->ip;
             break;
         case BC_ALLOC_OBJ_0:
-            tmp_v.obj = alloc_obj(em, 1);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 0);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -877,9 +1066,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_1:
-            tmp_v.obj = alloc_obj(em, 2);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 1);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -888,10 +1076,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_2:
-            tmp_v.obj = alloc_obj(em, 3);
-
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 2);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -905,9 +1091,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
 
             break;
         case BC_ALLOC_OBJ_3:
-            tmp_v.obj = alloc_obj(em, 4);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 3);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -916,9 +1101,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_4:
-            tmp_v.obj = alloc_obj(em, 5);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 4);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -927,9 +1111,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_5:
-            tmp_v.obj = alloc_obj(em, 6);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 5);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -938,9 +1121,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_6:
-            tmp_v.obj = alloc_obj(em, 7);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 6);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -949,9 +1131,9 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_U8:
-            tmp_v.obj = alloc_obj(em, (int)READ_U8() + 1);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_byte = READ_U8();
+            tmp_v.obj = alloc_obj_insn(em, &ip, tmp_byte);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -963,6 +1145,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             *sp = sp[-1];
             ++sp;
             break;
+        case BC_POP_0:
+            break;
         case BC_POP_1:
             --sp;
             break;
@@ -993,7 +1177,8 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             sp -= tmp_int;
             break;
         case BC_OBJ_TAG:
-            tmp_int = OBJ_TAG(POP());
+            tmp_v = POP();
+            tmp_int = OBJ_TAG(tmp_v);
             sp->obj_header = (uint32_t)tmp_int;
             ++sp;
             break;
@@ -1025,6 +1210,11 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             tmp_v = POP().obj[7];
             PUSH(tmp_v);
             break;
+        case BC_OBJ_FIELD_U8:
+            tmp_byte = READ_U8();
+            tmp_v = POP().obj[tmp_byte + 1];
+            PUSH(tmp_v);
+            break;
         case BC_ALLOC_LOCAL_1:
             sp += 1;
             break;
@@ -1215,6 +1405,12 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             OBJ_INC_RC(tmp_v);
             PUSH(tmp_v);
             break;
+        case BC_OBJ_FIELD_REF_U8:
+            tmp_byte = READ_U8();
+            tmp_v = POP().obj[tmp_byte + 1];
+            OBJ_INC_RC(tmp_v);
+            PUSH(tmp_v);
+            break;
         case BC_END_UPD_OBJ_U8:
             tmp_byte = READ_U8();
             drop(node_v[tmp_byte]);
@@ -1347,17 +1543,15 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
             return EMFRP_RUNTIME_ERR;
         case BC_Print:
             tmp_int = POP().num;
-            tmp_byte = 4;
-            uart_write((const char *)&tmp_byte, 1);
-            uart_write((const char *)&tmp_int, 4);
-            uart_flush_();
+            tmp_u16 = 4;
+            reply_write((const char *)&tmp_u16, 2);
+            reply_write((const char *)&tmp_int, 4);
             break;
         case BC_PRINTOBJ:
             tmp_v = POP();
-            tmp_byte = object_size(tmp_v);
-            uart_write((char *)&tmp_byte, 1);
-            uart_write_object(tmp_v);
-            uart_flush_();
+            tmp_u16 = object_size(tmp_v);
+            reply_write((char *)&tmp_u16, 2);
+            reply_write_object(tmp_v);
             drop(tmp_v);
             break;
 
@@ -1382,144 +1576,358 @@ emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
     }
 }
 
-emfrp_result_t emfrp_init_vars(emfrp_machine_t *em, int n_node, int n_func, uint8_t **data)
+emfrp_result_t emfrp_new_bytecode(emfrp_machine_t *em, int data_len, uint8_t *data)
 {
+    emfrp_result_t res;
+    if (data_len > 0 && data[0] != 0)
+    {
+        res = emfrp_exec(em, data + 1);
+        reply_write((char *)&res, 1);
+        return res;
+    }
+    res = emfrp_stage(em, data_len, data);
+    if (res == EMFRP_OK)
+        res = emfrp_commit(em);
+    reply_write((char *)&res, 1);
+    return res;
+}
+
+static uint8_t *copy_bytes(uint8_t **data, int len)
+{
+    uint8_t *p = (uint8_t *)malloc(len);
+    if (p != NULL)
+        memcpy(p, *data, len);
+    *data += len;
+#ifdef EMFRP_MEASURE_HEAP
+    update_min_free_heap_size("stage");
+#endif
+    return p;
+}
 
-    for (int i = 0; i < n_node; ++i)
+// keeps the definitions in a define request apart from the running program, which is left as it is
+// if they do not fit in memory
+emfrp_result_t emfrp_stage(emfrp_machine_t *em, int data_len, uint8_t *data)
+{
+    staged_t *st = &em->staged;
+    emfrp_result_t res = EMFRP_OUTOF_MEMORY;
+    int exp_len, upd_len, num_last, n;
+    emfrp_abort(em);
+    // is_eval(u8) and 8 words
+    if (data_len < 17 || next_byte(&data) != 0)
+        return EMFRP_PANIC;
+    exp_len = next_word(&data);
+    upd_len = next_word(&data);
+    num_last = next_word(&data);
+    st->n_node = next_word(&data);
+    st->n_func = next_word(&data);
+    // the numbers of new nodes and funcs are counted from the offsets
+    next_word(&data);
+    next_word(&data);
+    st->n_new_data = next_word(&data);
+    st->active = true;
+    st->nodes = (staged_body_t *)calloc(st->n_node + 1, sizeof(staged_body_t));
+    st->funcs = (staged_body_t *)calloc(st->n_func + 1, sizeof(staged_body_t));
+    if (st->nodes == NULL || st->funcs == NULL)
+        goto fail;
+    // a body is placed in a slot, or right after the last one
+    n = em->node_list.len;
+    for (int i = 0; i < st->n_node; ++i)
     {
-        int offset = next_word(data);
-        int body_len = next_word(data);
-        if (offset < em->node_list.len)
+        int offset = next_word(&data);
+        int body_len = next_word(&data);
+        if (offset > n)
         {
-            uint8_t *prev_body = em->node_list.action[offset].insns;
-            free(prev_body);
+            res = EMFRP_PANIC;
+            goto fail;
         }
+        n += offset == n;
+        st->nodes[i].offset = offset;
+        st->nodes[i].body = copy_bytes(&data, body_len);
+        if (st->nodes[i].body == NULL)
+            goto fail;
+    }
+    // the lists are extended now, so that the commit does not allocate them
+    res = extend_node_list(&em->node_list, n);
+    if (res != EMFRP_OK)
+        goto fail;
+    res = EMFRP_OUTOF_MEMORY;
+    n = em->func_list.len;
+    for (int i = 0; i < st->n_func; ++i)
+    {
+        int offset = next_word(&data);
+        int body_len = next_word(&data);
+        if (offset > n)
+        {
+            res = EMFRP_PANIC;
+            goto fail;
+        }
+        n += offset == n;
+        st->funcs[i].offset = offset;
+        st->funcs[i].body = copy_bytes(&data, body_len);
+        if (st->funcs[i].body == NULL)
+            goto fail;
+    }
+    res = extend_func_list(&em->func_list, n);
+    if (res != EMFRP_OK)
+        goto fail;
+    res = extend_data_list(&em->data_list, em->data_list.len + st->n_new_data);
+    if (res != EMFRP_OK)
+        goto fail;
+    res = EMFRP_OUTOF_MEMORY;
+    if (upd_len > 0 && (st->update = copy_bytes(&data, upd_len)) == NULL)
+        goto fail;
+    if (num_last > 0 && (st->node_last = (value_t *)malloc(num_last * sizeof(value_t))) == NULL)
+        goto fail;
+    if (exp_len > 0 && (st->init = copy_bytes(&data, exp_len)) == NULL)
+        goto fail;
+    return EMFRP_OK;
+fail:
+    emfrp_abort(em);
+    return res;
+}
 
-        uint8_t *body = (uint8_t *)malloc(body_len);
-        CHECK_NULL(body);
-
-        memcpy(body, *data, body_len);
-        *data += body_len;
-#ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("init node");
-#endif
+// swaps in the definitions kept by emfrp_stage, and runs their init code.
+// the replaced bodies are freed before init runs, so the new definitions stay in place if it fails
+emfrp_result_t emfrp_commit(emfrp_machine_t *em)
+{
+    staged_t *st = &em->staged;
+    emfrp_result_t res = EMFRP_OK;
+    if (!st->active)
+        return EMFRP_PANIC;
+    for (int i = 0; i < st->n_new_data; ++i)
+    {
+        push_data_uncheck(&em->data_list, ZERO);
+    }
+    for (int i = 0; i < st->n_node; ++i)
+    {
+        int offset = st->nodes[i].offset;
         if (offset < em->node_list.len)
         {
-            em->node_list.action[offset].insns = body;
+            free(em->node_list.action[offset].insns);
+            em->node_list.action[offset].insns = st->nodes[i].body;
         }
         else
         {
-            push_node_usr_uncheck(&em->node_list, ZERO, body);
+            push_node_usr_uncheck(&em->node_list, ZERO, st->nodes[i].body);
         }
     }
-    for (int i = 0; i < n_func; ++i)
+    for (int i = 0; i < st->n_func; ++i)
     {
-        int offset = next_word(data);
-        int body_len = next_word(data);
+        int offset = st->funcs[i].offset;
         if (offset < em->func_list.len)
         {
             free(em->func_list.lst[offset]);
-        }
-        uint8_t *body = (uint8_t *)malloc(body_len);
-        CHECK_NULL(body);
-#ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("init func");
-
-#endif
-        memcpy(body, *data, body_len);
-        *data += body_len;
-        if (offset < em->func_list.len)
-        {
-            em->func_list.lst[offset] = body;
+            em->func_list.lst[offset] = st->funcs[i].body;
         }
         else
         {
-            push_func_uncheck(&em->func_list, body);
+            push_func_uncheck(&em->func_list, st->funcs[i].body);
         }
     }
-    return EMFRP_OK;
+    if (st->update != NULL)
+    {
+        free(em->update);
+        em->update = st->update;
+    }
+    free(em->node_last);
+    em->node_last = st->node_last;
+    if (st->init != NULL)
+    {
+#ifdef EMFRP_MEASURE_HEAP
+        stack_max_usage = 0;
+#endif
+        res = emfrp_exec(em, st->init);
+#ifdef EMFRP_MEASURE_HEAP
+        dbg_int("max stack usage in init", stack_max_usage);
+#endif
+        free(st->init);
+    }
+    free(st->nodes);
+    free(st->funcs);
+    memset(st, 0, sizeof(staged_t));
+    return res;
 }
 
-emfrp_result_t emfrp_new_bytecode(emfrp_machine_t *em, int data_len, uint8_t *data)
+// frees the definitions kept by emfrp_stage
+void emfrp_abort(emfrp_machine_t *em)
 {
-    int is_eval = next_byte(&data);
-    emfrp_result_t res;
-    if (is_eval)
+    staged_t *st = &em->staged;
+    if (!st->active)
+        return;
+    for (int i = 0; st->nodes != NULL && i < st->n_node; ++i)
     {
-        res = emfrp_exec(em, data);
-        uart_write((char *)&res, 1);
-        return res;
+        free(st->nodes[i].body);
     }
-    int exp_len = next_word(&data);
-    int upd_len = next_word(&data);
-    int num_last = next_word(&data);
-    int n_node = next_word(&data);
-    int n_func = next_word(&data);
-    int tmp;
-
-    free(em->node_last);
-    if (num_last != 0)
+    for (int i = 0; st->funcs != NULL && i < st->n_func; ++i)
     {
-        em->node_last = (value_t *)malloc(num_last * sizeof(value_t));
-        CHECK_NULL(em->node_last);
+        free(st->funcs[i].body);
     }
+    free(st->nodes);
+    free(st->funcs);
+    free(st->update);
+    free(st->node_last);
+    free(st->init);
+    memset(st, 0, sizeof(staged_t));
+}
 
-    tmp = next_word(&data);
-    if (tmp > 0)
+// writes the value of a node (PROTO_QUERY_NODE) or data (PROTO_QUERY_DATA) in the same format as
+// BC_Print or BC_PRINTOBJ, or 1 if definitions are staged and 0 if not (PROTO_QUERY_STAGED), and the result
+emfrp_result_t emfrp_query(emfrp_machine_t *em, int kind, int is_obj, int index)
+{
+    emfrp_result_t res = EMFRP_PANIC;
+    value_t v;
+    uint16_t size;
+    uint8_t active;
+    if (kind == PROTO_QUERY_STAGED)
     {
-        res = extend_node_list(&em->node_list, tmp + em->node_list.len);
-        CHECK_ERR(res);
+        active = em->staged.active != 0;
+        reply_write((char *)&active, 1);
+        res = EMFRP_OK;
+        goto end;
     }
-    tmp = next_word(&data);
-    if (tmp > 0)
+    if (kind == PROTO_QUERY_NODE && index < em->node_list.len)
+        v = em->node_list.values[index];
+    else if (kind == PROTO_QUERY_DATA && index < em->data_list.len)
+        v = em->data_list.lst[index];
+    else
+        goto end;
+    if (is_obj)
     {
-        res = extend_func_list(&em->func_list, tmp + em->func_list.len);
-        CHECK_ERR(res);
+        if (v.obj == NULL)
+            goto end;
+        size = object_size(v);
+        reply_write((char *)&size, 2);
+        reply_write_object(v);
+    }
+    else
+    {
+        size = 4;
+        reply_write((char *)&size, 2);
+        reply_write((char *)&v.num, 4);
     }
-    tmp = next_word(&data);
+    res = EMFRP_OK;
+end:
+    reply_write((char *)&res, 1);
+    return res;
+}
 
-    if (tmp > 0)
+// frees the slots of removed nodes (PROTO_QUERY_NODE), data (PROTO_QUERY_DATA) and funcs (PROTO_REMOVE_FUNC).
+// every slot is checked before the update code is replaced, so that all of them are freed or none is
+emfrp_result_t emfrp_remove(emfrp_machine_t *em, int data_len, uint8_t *data)
+{
+    emfrp_result_t res = EMFRP_PANIC;
+    int kind, is_obj, index, num_last, n, upd_len;
+    uint8_t *slots, *update;
+    value_t *node_last = NULL;
+    value_t v;
+    if (data_len < 3)
+        goto end;
+    num_last = next_word(&data);
+    n = next_byte(&data);
+    upd_len = data_len - 3 - 4 * n;
+    if (n == 0 || upd_len <= 0)
+        goto end;
+    slots = data;
+    for (int i = 0; i < n; ++i)
     {
-        res = extend_data_list(&em->data_list, tmp + em->data_list.len);
-        CHECK_ERR(res);
-        for (int i = 0; i < tmp; ++i)
-        {
-            push_data_uncheck(&em->data_list, ZERO);
-        }
+        kind = next_byte(&data);
+        next_byte(&data);
+        index = next_word(&data);
+        if (!(kind == PROTO_QUERY_NODE && index < em->node_list.len) &&
+            !(kind == PROTO_QUERY_DATA && index < em->data_list.len) &&
+            !(kind == PROTO_REMOVE_FUNC && index < em->func_list.len))
+            goto end;
     }
-    res = emfrp_init_vars(em, n_node, n_func, &data);
-    CHECK_ERR(res);
-    if (upd_len > 0)
+    update = (uint8_t *)malloc(upd_len);
+    if (num_last != 0)
+        node_last = (value_t *)malloc(num_last * sizeof(value_t));
+    if (update == NULL || (num_last != 0 && node_last == NULL))
     {
-        if (em->update != NULL)
-            free(em->update);
-        uint8_t *update = (uint8_t *)malloc(upd_len);
-        CHECK_NULL(update);
-#ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("init copy update");
-#endif
-        memcpy(update, data, upd_len);
-        data += upd_len;
-        em->update = update;
+        free(update);
+        free(node_last);
+        res = EMFRP_OUTOF_MEMORY;
+        goto end;
+    }
+    memcpy(update, data, upd_len);
+    free(em->update);
+    em->update = update;
+    free(em->node_last);
+    em->node_last = node_last;
+    for (int i = 0; i < n; ++i)
+    {
+        kind = next_byte(&slots);
+        is_obj = next_byte(&slots);
+        index = next_word(&slots);
+        v = ZERO;
+        if (kind == PROTO_QUERY_NODE)
+        {
+            free(em->node_list.action[index].insns);
+            em->node_list.action[index].insns = NULL;
+            v = em->node_list.values[index];
+            em->node_list.values[index] = ZERO;
+        }
+        else if (kind == PROTO_QUERY_DATA)
+        {
+            v = em->data_list.lst[index];
+            em->data_list.lst[index] = ZERO;
+        }
+        else
+        {
+            free(em->func_list.lst[index]);
+            em->func_list.lst[index] = NULL;
+        }
+        if (is_obj)
+            drop(v);
     }
-    if (exp_len == 0)
+    res = EMFRP_OK;
+end:
+    reply_write((char *)&res, 1);
+    return res;
+}
+
+// loads an image made by `emfrp-compiler compile` (see emfrp-compiler/src/image.rs)
+// input and output nodes of the image must be registered beforehand
+emfrp_result_t emfrp_load_image(emfrp_machine_t *em, const uint8_t *image, int len)
+{
+    uint8_t head[7];
+    if (len < 7)
+        return EMFRP_PANIC;
+    IMAGE_COPY(head, image, 7);
+    if (memcmp(head, "EMFP", 4) != 0 || head[4] != EMFRP_IMAGE_VERSION)
+        return EMFRP_PANIC;
+    int n_io = head[5] + head[6];
+    if (n_io != em->node_list.len || head[6] != em->output_nd_len)
+        return EMFRP_PANIC;
+    int p = 7;
+    // node table: type(u8) name_len(u8) name
+    for (int i = 0; i < n_io; ++i)
     {
-        res = EMFRP_OK;
-        uart_write((char *)&res, 1);
-        return EMFRP_OK;
+        if (p + 2 > len)
+            return EMFRP_PANIC;
+        p += 2 + IMAGE_BYTE(image + p + 1);
     }
-    else
+    if (p + 2 > len)
+        return EMFRP_PANIC;
+    int n_frame = IMAGE_BYTE(image + p) + ((int)IMAGE_BYTE(image + p + 1) << 8);
+    p += 2;
+    for (int i = 0; i < n_frame; ++i)
     {
-#ifdef EMFRP_MEASURE_HEAP
-        stack_max_usage = 0;
-#endif
-        res = emfrp_exec(em, data);
-#ifdef EMFRP_MEASURE_HEAP
-        dbg_int("max stack usage in init", stack_max_usage);
-#endif
-        uart_write((char *)&res, 1);
-        return res;
+        if (p + 2 > len)
+            return EMFRP_PANIC;
+        int data_len = IMAGE_BYTE(image + p) + ((int)IMAGE_BYTE(image + p + 1) << 8);
+        p += 2;
+        if (p + data_len > len)
+            return EMFRP_PANIC;
+        // the frame is read with next_word, so it is copied to RAM
+        uint8_t *data = (uint8_t *)malloc(data_len);
+        CHECK_NULL(data);
+        IMAGE_COPY(data, image + p, data_len);
+        emfrp_result_t res = emfrp_new_bytecode(em, data_len, data);
+        free(data);
+        CHECK_ERR(res);
+        p += data_len;
     }
+    return EMFRP_OK;
 }
 
 emfrp_result_t emfrp_update(emfrp_machine_t *em)
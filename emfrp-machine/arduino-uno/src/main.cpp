@@ -1,7 +1,13 @@
 #include "config.h"
+#ifndef INCLUDE_MACHINE
+#define INCLUDE_MACHINE
 #include "machine.h"
-static uint8_t buf[UART_BUF_SIZE];
-static int read_len = 0;
+#endif
+#include "protocol.h"
+#ifdef EMFRP_LOAD_IMAGE
+#include "emfrp_image.h"
+#endif
+static uint8_t buf[64];
 static emfrp_machine_t em;
 
 void gpio2_input(value_t *v)
@@ -27,25 +33,22 @@ void setup()
     emfrp_init(&em, 0, 1);
     // emfrp_add_input_node(&em, emfrp_int(0), gpio2_input);
     emfrp_add_output_node(&em, emfrp_int(0), gpio_output);
+#ifdef EMFRP_LOAD_IMAGE
+    emfrp_load_image(&em, emfrp_image, sizeof(emfrp_image));
+#endif
 }
 
 void loop()
 {
-    emfrp_result_t res;
-    read_len += Serial.readBytes(&buf[read_len], UART_BUF_SIZE - read_len);
-    if (read_len >= 2)
+    int n = Serial.available();
+    if (n > 0)
     {
-        int data_len = (int)buf[0] + ((int)buf[1] << 8);
-        while (read_len < data_len + 2)
-        {
-            read_len += Serial.readBytes(&buf[read_len], UART_BUF_SIZE - read_len);
-        }
-        read_len = 0;
-        res = emfrp_new_bytecode(&em, data_len, &buf[2]);
-        Serial.write((char *)&res, 1);
+        n = Serial.readBytes(buf, n < (int)sizeof(buf) ? n : (int)sizeof(buf));
+        proto_receive(&em, buf, n);
     }
     else
     {
-        emfrp_update(&em);
+        proto_poll();
+        proto_telemetry(&em, emfrp_update(&em));
     }
 }
\ No newline at end of file
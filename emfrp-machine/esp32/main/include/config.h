@@ -1,8 +1,11 @@
 #define __ESP
+// #define EMFRP_LOAD_IMAGE // loads include/emfrp_image.h made by `emfrp-compiler compile` at boot
 // #define EMFRP_MEASURE_HEAP
 //      #define EMFRP_MEASURE_LATENCY
 // #define EMFRP_DEBUG
 // #define EMFRP_DEBUG_OBJCNT
 //    #define EMFRP_DEBUG_LEVEL2
 #define UART_BUF_SIZE 1024
-#define STACK_SIZE 128
\ No newline at end of file
+#define REPLY_BUF_SIZE 2048
+#define TELEMETRY_BUF_SIZE 256
+#define STACK_SIZE 512
\ No newline at end of file
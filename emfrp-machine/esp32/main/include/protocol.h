@@ -0,0 +1,53 @@
+#include "config.h"
+#ifndef INCLUDE_MACHINE
+#define INCLUDE_MACHINE
+#include "machine.h"
+#endif
+// framed protocol between the compiler and the machine (see emfrp-compiler/src/protocol.rs)
+//   frame : sync(0xaa 0x55) type(u8) seq(u8) len(u16) hcrc(u8) payload crc(u16)
+#define PROTO_VERSION 6
+#define PROTO_SYNC0 0xaa
+#define PROTO_SYNC1 0x55
+#define PROTO_HEADER_LEN 7
+#define PROTO_CRC_LEN 2
+// largest payload that fits in UART_BUF_SIZE
+#define PROTO_MAX_PAYLOAD (UART_BUF_SIZE - PROTO_HEADER_LEN - PROTO_CRC_LEN)
+// a frame whose next byte does not arrive in this time is dropped
+#define PROTO_TIMEOUT_MS 200
+
+#define PROTO_MSG_HELLO 1
+#define PROTO_MSG_PING 2
+#define PROTO_MSG_DEFINE 3
+#define PROTO_MSG_EVAL 4
+#define PROTO_MSG_QUERY 5
+#define PROTO_MSG_RESET 6
+#define PROTO_MSG_REMOVE 7
+#define PROTO_MSG_COMMIT 8
+#define PROTO_MSG_ABORT 9
+#define PROTO_MSG_SUBSCRIBE 10
+// sent by the machine after updates, with values of the subscribed nodes
+#define PROTO_MSG_TELEMETRY 11
+#define PROTO_MSG_NAK 0x7f
+#define PROTO_REPLY 0x80
+
+#define PROTO_QUERY_NODE 0
+#define PROTO_QUERY_DATA 1
+#define PROTO_REMOVE_FUNC 2
+// whether definitions are staged, asked when the reply to PROTO_MSG_COMMIT is lost
+#define PROTO_QUERY_STAGED 3
+
+#define PROTO_MAX_SUBSCRIBE 8
+
+#define PROTO_NAK_CRC 1
+#define PROTO_NAK_TOO_LONG 2
+#define PROTO_NAK_UNKNOWN 3
+#define PROTO_NAK_MALFORMED 4
+
+// bytes written while a request is handled, which are sent as the payload of its reply
+void reply_write(const char *buf, int len);
+// handles bytes read from uart, and returns EMFRP_OUTOF_MEMORY if the machine can not continue
+emfrp_result_t proto_receive(emfrp_machine_t *em, const uint8_t *data, int len);
+// sends the values of the subscribed nodes after an update which ended with res
+void proto_telemetry(emfrp_machine_t *em, emfrp_result_t res);
+// drops a frame received partway if the rest does not arrive
+void proto_poll(void);
@@ -7,18 +7,22 @@
 #define INCLUDE_IO
 #include "io.h"
 #endif
+#include "protocol.h"
 #include "freertos/task.h"
 #include "driver/gpio.h"
 #include "driver/gptimer.h"
 #include "sdkconfig.h"
 #include <unistd.h>
 #include "esp_task_wdt.h"
+#ifdef EMFRP_LOAD_IMAGE
+#include "emfrp_image.h"
+#endif
 
 #define START() gpio_set_level(2, 1)
 #define END() gpio_set_level(2, 0)
 
 static volatile bool update_flag = false;
-uint8_t buf[UART_BUF_SIZE];
+uint8_t buf[64];
 
 void gpio16_input(value_t *v)
 {
@@ -80,7 +84,6 @@ void app_main(void)
 {
     emfrp_result_t res;
     emfrp_machine_t em = {0};
-    int data_len;
     gptimer_handle_t gptimer = NULL;
 #ifdef EMFRP_MEASURE_HEAP
     const uint32_t initial_heap_size = esp_get_free_heap_size(); // 300556
@@ -106,10 +109,14 @@ void app_main(void)
 #endif
     emfrp_add_input_node(&em, emfrp_false(), gpio16_input);
     emfrp_add_output_node(&em, emfrp_false(), gpio5_output);
+#ifdef EMFRP_LOAD_IMAGE
+    if (emfrp_load_image(&em, emfrp_image, sizeof(emfrp_image)) == EMFRP_OUTOF_MEMORY)
+        goto err;
+#endif
 #ifdef EMFRP_MEASURE_HEAP
     const uint32_t initial_heap_size2 = esp_get_free_heap_size();
 #endif
-    int read_len = 0, tmp;
+    int tmp;
 
 #ifdef EMFRP_MEASURE_HEAP
     dbg_int("initial", initial_heap_size);
@@ -133,6 +140,7 @@ void app_main(void)
             START();
             res = emfrp_update(&em);
             END();
+            proto_telemetry(&em, res);
             if (res == EMFRP_OUTOF_MEMORY)
                 goto err;
 #ifdef EMFRP_MEASURE_HEAP
@@ -141,31 +149,15 @@ void app_main(void)
 
             gptimer_start(gptimer);
         }
-        tmp = uart_read((char *)&buf[read_len], UART_BUF_SIZE - read_len);
-        if (tmp == -1)
-            goto begin_loop;
-        read_len += tmp;
-        if (read_len >= 2)
+        tmp = uart_read((char *)buf, sizeof(buf));
+        if (tmp > 0)
         {
-            data_len = buf[0] + ((int)buf[1] << 8);
-            while (read_len < data_len + 2)
-            {
-                tmp = uart_read((char *)&buf[read_len], UART_BUF_SIZE - read_len);
-                if (tmp == -1)
-                    goto begin_loop;
-                read_len += tmp;
-            }
-            read_len = 0;
-            res = emfrp_new_bytecode(&em, data_len, buf + 2);
+            res = proto_receive(&em, buf, tmp);
             if (res == EMFRP_OUTOF_MEMORY)
-            {
                 goto err;
-            }
-            else
-            {
-                goto begin_loop;
-            }
+            goto begin_loop;
         }
+        proto_poll();
         vTaskDelay(pdMS_TO_TICKS(100));
     }
 err:
@@ -0,0 +1,286 @@
+#include "protocol.h"
+#include <string.h>
+#ifndef INCLUDE_IO
+#define INCLUDE_IO
+#include "io.h"
+#endif
+
+static uint8_t rx[UART_BUF_SIZE];
+static int rx_len = 0;
+static uint32_t rx_time;
+// reply to the last request, which is sent again when the request is retransmitted
+static uint8_t reply[REPLY_BUF_SIZE];
+static int reply_len = 0;
+static int reply_overflow = false;
+static int last_seq = -1;
+static uint8_t last_type;
+// values of the subscribed nodes, which are written here instead of the reply while they are queried
+static uint8_t telemetry[TELEMETRY_BUF_SIZE];
+static int telemetry_len = 0;
+static int in_telemetry = false;
+static uint8_t sub_obj[PROTO_MAX_SUBSCRIBE];
+static uint16_t sub_index[PROTO_MAX_SUBSCRIBE];
+static int n_sub = 0;
+static uint16_t every, n_update;
+static uint8_t telemetry_seq = 0;
+
+// CRC-16/CCITT-FALSE
+static uint16_t crc16(uint16_t crc, const uint8_t *p, int len)
+{
+    while (len-- > 0)
+    {
+        crc ^= (uint16_t)*p++ << 8;
+        for (int i = 0; i < 8; ++i)
+            crc = (crc & 0x8000) ? (crc << 1) ^ 0x1021 : crc << 1;
+    }
+    return crc;
+}
+
+void reply_write(const char *buf, int len)
+{
+    if (in_telemetry)
+    {
+        if (telemetry_len + len > TELEMETRY_BUF_SIZE)
+        {
+            reply_overflow = true;
+            return;
+        }
+        memcpy(telemetry + telemetry_len, buf, len);
+        telemetry_len += len;
+        return;
+    }
+    if (reply_len + len > REPLY_BUF_SIZE)
+    {
+        reply_overflow = true;
+        return;
+    }
+    memcpy(reply + reply_len, buf, len);
+    reply_len += len;
+}
+
+static void send_frame(uint8_t type, uint8_t seq, const uint8_t *payload, int len)
+{
+    uint8_t head[PROTO_HEADER_LEN] = {PROTO_SYNC0, PROTO_SYNC1, type, seq, (uint8_t)len, (uint8_t)(len >> 8)};
+    uint16_t crc = crc16(0xffff, head + 2, 4);
+    head[6] = (uint8_t)crc;
+    crc = crc16(crc16(0xffff, head + 2, 5), payload, len);
+    uint8_t tail[PROTO_CRC_LEN] = {(uint8_t)crc, (uint8_t)(crc >> 8)};
+    uart_write((const char *)head, PROTO_HEADER_LEN);
+    uart_write((const char *)payload, len);
+    uart_write((const char *)tail, PROTO_CRC_LEN);
+    uart_flush_();
+}
+
+static void send_nak(uint8_t seq, uint8_t reason)
+{
+    send_frame(PROTO_MSG_NAK, seq, &reason, 1);
+}
+
+// payload : every(u16), and then is_obj(u8) index(u16) of each node
+static emfrp_result_t subscribe(emfrp_machine_t *em, const uint8_t *payload, int len)
+{
+    if (len < 2 || (len - 2) % 3 != 0 || (len - 2) / 3 > PROTO_MAX_SUBSCRIBE)
+        return EMFRP_PANIC;
+    uint16_t e = payload[0] + ((uint16_t)payload[1] << 8);
+    if (e == 0)
+        return EMFRP_PANIC;
+    int n = (len - 2) / 3;
+    for (int i = 0; i < n; ++i)
+    {
+        const uint8_t *nd = payload + 2 + 3 * i;
+        if (nd[1] + ((int)nd[2] << 8) >= em->node_list.len)
+            return EMFRP_PANIC;
+    }
+    for (int i = 0; i < n; ++i)
+    {
+        const uint8_t *nd = payload + 2 + 3 * i;
+        sub_obj[i] = nd[0];
+        sub_index[i] = nd[1] + ((uint16_t)nd[2] << 8);
+    }
+    n_sub = n;
+    every = e;
+    n_update = 0;
+    return EMFRP_OK;
+}
+
+static emfrp_result_t handle(emfrp_machine_t *em, uint8_t type, uint8_t seq, uint8_t *payload, int len)
+{
+    emfrp_result_t res = EMFRP_OK, status;
+    if (type == PROTO_MSG_HELLO)
+    {
+        uint8_t hello[5] = {PROTO_VERSION, (uint8_t)PROTO_MAX_PAYLOAD, (uint8_t)(PROTO_MAX_PAYLOAD >> 8),
+                            (uint8_t)TELEMETRY_BUF_SIZE, (uint8_t)(TELEMETRY_BUF_SIZE >> 8)};
+        last_seq = -1;
+        n_sub = 0;
+        send_frame(type | PROTO_REPLY, seq, hello, 5);
+        return EMFRP_OK;
+    }
+    if (seq == last_seq && type == last_type)
+    {
+        send_frame(type | PROTO_REPLY, seq, reply, reply_len);
+        return EMFRP_OK;
+    }
+    reply_len = 0;
+    reply_overflow = false;
+    switch (type)
+    {
+    case PROTO_MSG_PING:
+        break;
+    case PROTO_MSG_DEFINE:
+        // the running program is kept if the definitions do not fit, so the machine goes on
+        status = emfrp_stage(em, len, payload);
+        reply_write((char *)&status, 1);
+        break;
+    case PROTO_MSG_EVAL:
+        res = emfrp_new_bytecode(em, len, payload);
+        break;
+    case PROTO_MSG_COMMIT:
+        res = emfrp_commit(em);
+        reply_write((char *)&res, 1);
+        break;
+    case PROTO_MSG_ABORT:
+        emfrp_abort(em);
+        reply_write((char *)&res, 1);
+        break;
+    case PROTO_MSG_QUERY:
+        if (len != 4)
+        {
+            send_nak(seq, PROTO_NAK_MALFORMED);
+            return EMFRP_OK;
+        }
+        res = emfrp_query(em, payload[0], payload[1], payload[2] + ((int)payload[3] << 8));
+        break;
+    case PROTO_MSG_REMOVE:
+        res = emfrp_remove(em, len, payload);
+        break;
+    case PROTO_MSG_SUBSCRIBE:
+        status = subscribe(em, payload, len);
+        reply_write((char *)&status, 1);
+        break;
+    case PROTO_MSG_RESET:
+        send_frame(type | PROTO_REPLY, seq, reply, 0);
+        restart();
+        return EMFRP_OK;
+    default:
+        send_nak(seq, PROTO_NAK_UNKNOWN);
+        return EMFRP_OK;
+    }
+    // a reply which does not fit is replaced by the result. only the reply ran out of space,
+    // so the machine goes on and the caller is not told that it is out of memory
+    if (reply_overflow || (reply_len == 0 && type != PROTO_MSG_PING))
+    {
+        reply_len = 0;
+        reply[reply_len++] = reply_overflow && res == EMFRP_OK ? EMFRP_OUTOF_MEMORY : res;
+    }
+    last_seq = seq;
+    last_type = type;
+    send_frame(type | PROTO_REPLY, seq, reply, reply_len);
+    return res;
+}
+
+// rx[0..rx_len] starts with a sync. returns the number of bytes used, or 0 if more bytes are needed
+static int parse(emfrp_machine_t *em, emfrp_result_t *res)
+{
+    if (rx_len < PROTO_HEADER_LEN)
+        return 0;
+    uint8_t seq = rx[3];
+    int len = rx[4] + ((int)rx[5] << 8);
+    if ((uint8_t)crc16(0xffff, rx + 2, 4) != rx[6])
+    {
+        send_nak(seq, PROTO_NAK_CRC);
+        return 2;
+    }
+    if (len > PROTO_MAX_PAYLOAD)
+    {
+        send_nak(seq, PROTO_NAK_TOO_LONG);
+        return 2;
+    }
+    int end = PROTO_HEADER_LEN + len + PROTO_CRC_LEN;
+    if (rx_len < end)
+        return 0;
+    uint16_t crc = rx[end - 2] + ((uint16_t)rx[end - 1] << 8);
+    if (crc16(0xffff, rx + 2, end - 2 - PROTO_CRC_LEN) != crc)
+    {
+        // the sync may have been a byte of a broken frame, so the rest is searched again
+        send_nak(seq, PROTO_NAK_CRC);
+        return 2;
+    }
+    *res = handle(em, rx[2], seq, rx + PROTO_HEADER_LEN, len);
+    return end;
+}
+
+emfrp_result_t proto_receive(emfrp_machine_t *em, const uint8_t *data, int len)
+{
+    emfrp_result_t res = EMFRP_OK;
+    if (len <= 0)
+        return res;
+    rx_time = uptime_ms();
+    while (len > 0)
+    {
+        int n = UART_BUF_SIZE - rx_len < len ? UART_BUF_SIZE - rx_len : len;
+        memcpy(rx + rx_len, data, n);
+        rx_len += n;
+        data += n;
+        len -= n;
+        while (rx_len > 0)
+        {
+            // skips bytes before a sync
+            int i = 0;
+            while (i < rx_len && !(rx[i] == PROTO_SYNC0 && (i + 1 == rx_len || rx[i + 1] == PROTO_SYNC1)))
+                ++i;
+            memmove(rx, rx + i, rx_len - i);
+            rx_len -= i;
+            if (rx_len < 2)
+                break;
+            int used = parse(em, &res);
+            if (used == 0)
+                break;
+            memmove(rx, rx + used, rx_len - used);
+            rx_len -= used;
+            if (res == EMFRP_OUTOF_MEMORY)
+                return res;
+        }
+    }
+    return res;
+}
+
+// payload : status of the update, and then len(u16) and the reply to PROTO_MSG_QUERY of each node
+void proto_telemetry(emfrp_machine_t *em, emfrp_result_t res)
+{
+    if (n_sub == 0 || ++n_update < every)
+        return;
+    n_update = 0;
+    int overflow = reply_overflow;
+    in_telemetry = true;
+    reply_overflow = false;
+    telemetry_len = 0;
+    reply_write((char *)&res, 1);
+    for (int i = 0; i < n_sub && !reply_overflow; ++i)
+    {
+        // the length is filled in after the value is written
+        int at = telemetry_len;
+        uint16_t size = 0;
+        reply_write((char *)&size, 2);
+        emfrp_query(em, PROTO_QUERY_NODE, sub_obj[i], sub_index[i]);
+        if (!reply_overflow)
+        {
+            size = telemetry_len - at - 2;
+            telemetry[at] = (uint8_t)size;
+            telemetry[at + 1] = (uint8_t)(size >> 8);
+        }
+    }
+    if (reply_overflow)
+    {
+        telemetry[0] = EMFRP_OUTOF_MEMORY;
+        telemetry_len = 1;
+    }
+    in_telemetry = false;
+    reply_overflow = overflow;
+    send_frame(PROTO_MSG_TELEMETRY, ++telemetry_seq, telemetry, telemetry_len);
+}
+
+void proto_poll(void)
+{
+    if (rx_len > 0 && uptime_ms() - rx_time > PROTO_TIMEOUT_MS)
+        rx_len = 0;
+}
@@ -0,0 +1,12 @@
+#define __ARDUINO
+// #define EMFRP_LOAD_IMAGE // loads include/emfrp_image.h made by `emfrp-compiler compile` at boot
+// #define EMFRP_MEASURE_HEAP
+//    #define EMFRP_MEASURE_LATENCY
+// #define EMFRP_DEBUG
+//   #define EMFRP_DEBUG_OBJCNT
+// #define EMFRP_DEBUG_LEVEL2
+// the nRF51 of the micro:bit has 16KB of RAM, so the buffers are larger than the ones of the Uno
+#define UART_BUF_SIZE 1024
+#define REPLY_BUF_SIZE 256
+#define TELEMETRY_BUF_SIZE 128
+#define STACK_SIZE 128
\ No newline at end of file
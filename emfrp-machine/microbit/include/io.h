@@ -0,0 +1,11 @@
+#include "config.h"
+#include <stdint.h>
+void setup_uart_with_bufsize(int buf_size);
+void uart_write(const char *buf, int len);
+void uart_flush_();
+int uart_read(char *buf, int maxlen);
+uint32_t uptime_ms(void);
+void restart(void);
+#ifdef EMFRP_DEBUG
+void dbg_int(const char *info, int n);
+#endif
\ No newline at end of file
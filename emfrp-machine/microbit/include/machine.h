@@ -1,27 +1,41 @@
-// #define EMFRP_MEASURE_HEAP
-//  #define EMFRP_MEASURE_LATENCY
-// #define EMFRP_DEBUG
+#include "config.h"
+#ifdef __ESP
+#ifndef INCLUDE_FREERTOS
+#define INCLUDE_FREERTOS
+#include "freertos/FreeRTOS.h"
+#endif
+#endif
+#ifdef __ARDUINO
 #include <Arduino.h>
+#endif
 
 #define true 1
 #define false 0
-
 typedef union value_t
 {
-    int num;
+    int32_t num;
+    float f;
     uint32_t obj_header;
     uint8_t *ip;
+    union value_t *bp;
     union value_t *obj;
 } value_t;
+// version of the image loaded by emfrp_load_image
+#define EMFRP_IMAGE_VERSION 1
 typedef void (*dev_input_t)(value_t *);
-
+typedef struct call_frame
+{
+    uint8_t *next_ip;
+    value_t *bp, *sp;
+    struct call_frame *caller;
+} call_frame;
 typedef enum
 {
-    EMFRP_NOTSTARTED,
     EMFRP_OK,
     EMFRP_RUNTIME_ERR,
     EMFRP_PANIC,
     EMFRP_TODO,
+    EMFRP_OUTOF_MEMORY
 
 } emfrp_result_t;
 typedef union
@@ -34,12 +48,6 @@ typedef void (*output_action_t)(value_t *);
 typedef value_t node_v_t;
 typedef value_t data_t;
 typedef uint8_t *func_t;
-typedef struct call_frame
-{
-    uint8_t *next_ip;
-    value_t *bp, *sp;
-    struct call_frame *caller;
-} call_frame;
 typedef struct obj_list
 {
     value_t obj;
@@ -67,6 +75,23 @@ typedef struct
     int cap;
 } node_list_t;
 typedef struct
+{
+    int offset;
+    uint8_t *body;
+} staged_body_t;
+// definitions kept by emfrp_stage until emfrp_commit swaps them in
+typedef struct
+{
+    int active;
+    int n_node, n_func, n_new_data;
+    staged_body_t *nodes, *funcs;
+    // NULL if the update code is kept
+    uint8_t *update;
+    value_t *node_last;
+    // NULL if there is no init code
+    uint8_t *init;
+} staged_t;
+typedef struct
 {
     value_t *v_stack, *node_last;
     uint8_t *update;
@@ -75,9 +100,8 @@ typedef struct
     node_list_t node_list;
     output_action_t *output_actions;
     int output_nd_len;
-    emfrp_result_t res;
-
-} emfrp_machine;
+    staged_t staged;
+} emfrp_machine_t;
 
 typedef enum
 {
@@ -102,9 +126,10 @@ typedef enum
     BC_BitOr = 19,
     BC_BitXor = 20,
     BC_Return = 21,
-    BC_Exit = 22,
-    BC_Halt = 23,
-    BC_Peek = 24,
+    BC_Print = 22,
+    BC_PRINTOBJ = 23,
+    BC_Halt = 24,
+    BC_Peek = 25,
     BC_PushTrue = 26,
     BC_PushFalse = 27,
     BC_ABORT = 28,
@@ -147,6 +172,7 @@ typedef enum
     BC_ALLOC_LOCAL_U8 = 67,
     BC_ALLOC_LOCAL_U16 = 68,
     BC_ALLOC_LOCAL_U32 = 69,
+    BC_POP_0 = 70,
     BC_POP_1 = 71,
     BC_POP_2 = 72,
     BC_POP_3 = 73,
@@ -185,8 +211,6 @@ typedef enum
     BC_OBJ_FIELD_5 = 105,
     BC_OBJ_FIELD_6 = 106,
     BC_OBJ_FIELD_U8 = 107,
-    BC_OBJ_FIELD_U16 = 108,
-    BC_OBJ_FIELD_U32 = 109,
 
     BC_UPD_DEV_0 = 110,
     BC_UPD_DEV_1 = 111,
@@ -210,10 +234,6 @@ typedef enum
     BC_GET_NODE_U8 = 133,
     BC_GET_NODE_U16 = 134,
     BC_GET_NODE_U32 = 135,
-    BC_MARK_NODE_U8 = 137,
-    BC_MARK_NODE_U16 = 138,
-    BC_MARK_NODE_U32 = 139,
-    BC_SWEEP = 140,
     BC_SET_DATA_U8 = 141,
     BC_SET_DATA_U16 = 142,
     BC_SET_DATA_U32 = 143,
@@ -274,8 +294,6 @@ typedef enum
     BC_OBJ_FIELD_REF_5 = 205,
     BC_OBJ_FIELD_REF_6 = 206,
     BC_OBJ_FIELD_REF_U8 = 207,
-    BC_OBJ_FIELD_REF_U16 = 208,
-    BC_OBJ_FIELD_REF_U32 = 209,
     BC_END_UPD_OBJ_U8 = 210,
     BC_END_UPD_OBJ_U16 = 211,
     BC_END_UPD_OBJ_U32 = 212,
@@ -308,15 +326,41 @@ typedef enum
     BC_DROP_LAST_U8 = 240,
     BC_DROP_LAST_U16 = 241,
     BC_DROP_LAST_U32 = 242,
+    BC_J0 = 243,
+    BC_J1 = 244,
+    BC_Je0 = 245,
+    BC_Je1 = 246,
+    BC_Jne0 = 247,
+    BC_Jne1 = 248,
+    BC_FAdd = 136,
+    BC_FSub = 137,
+    BC_FMul = 138,
+    BC_FDiv = 139,
+    BC_FMinus = 140,
+    BC_FLs = 145,
+    BC_FLeq = 146,
+    BC_FGt = 147,
+    BC_FGeq = 148,
+    BC_FEq = 149,
+    BC_FNeq = 168,
+    BC_FLOAT = 169,
+    BC_IntToFloat = 249,
+    BC_FloatToInt = 250,
 
 } bytecode;
-void emfrp_init(emfrp_machine *em, int n_input_node, int n_output_node);
-void emfrp_add_input_node(emfrp_machine *em, value_t init, dev_input_t driver);
-void emfrp_add_output_node(emfrp_machine *em, value_t init, output_action_t driver);
-void emfrp_update(emfrp_machine *em);
-value_t emfrp_new_bytecode(emfrp_machine *em, int data_len, uint8_t *data);
-value_t emfrp_int(int i);
+emfrp_result_t emfrp_init(emfrp_machine_t *em, int n_input_node, int n_output_node);
+void emfrp_add_input_node(emfrp_machine_t *em, value_t init, dev_input_t driver);
+void emfrp_add_output_node(emfrp_machine_t *em, value_t init, output_action_t driver);
+emfrp_result_t emfrp_update(emfrp_machine_t *em);
+emfrp_result_t emfrp_new_bytecode(emfrp_machine_t *em, int data_len, uint8_t *data);
+emfrp_result_t emfrp_stage(emfrp_machine_t *em, int data_len, uint8_t *data);
+emfrp_result_t emfrp_commit(emfrp_machine_t *em);
+void emfrp_abort(emfrp_machine_t *em);
+emfrp_result_t emfrp_load_image(emfrp_machine_t *em, const uint8_t *image, int len);
+emfrp_result_t emfrp_query(emfrp_machine_t *em, int kind, int is_obj, int index);
+emfrp_result_t emfrp_remove(emfrp_machine_t *em, int data_len, uint8_t *data);
+value_t emfrp_int(int32_t i);
+value_t emfrp_true();
+value_t emfrp_false();
 #ifdef EMFRP_MEASURE_HEAP
-uint32_t get_min_free_heap_size();
-void init_min_heap_free_size(uint32_t v);
 #endif
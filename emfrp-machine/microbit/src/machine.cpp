@@ -3,73 +3,207 @@
 #include "machine.h"
 #endif
 #include <string.h>
+#ifndef INCLUDE_IO
+#define INCLUDE_IO
+#include "io.h"
+#endif
+#include "protocol.h"
 #define READ_U8() (*ip++)
 #define READ_I8() (int8_t)(*ip++)
-#define READ_I32(dest)                                                               \
-    dest = (int)ip[0] + ((int)ip[1] << 8) + ((int)ip[2] << 16) + ((int)ip[3] << 24); \
+#define READ_I32(dest)                                                                               \
+    dest = (int32_t)ip[0] + ((int32_t)ip[1] << 8) + ((int32_t)ip[2] << 16) + ((int32_t)ip[3] << 24); \
     ip += 4;
-#define READ_I16(dest)                     \
-    dest = (int)ip[0] + ((int)ip[1] << 8); \
+#define READ_I16(dest)                             \
+    dest = (int16_t)ip[0] + ((int16_t)ip[1] << 8); \
+    ip += 2;
+#define READ_U16(dest)                               \
+    dest = (uint16_t)ip[0] + ((uint16_t)ip[1] << 8); \
     ip += 2;
 #define READ_U32(dest)                                                                                   \
     dest = (uint32_t)ip[0] + ((uint32_t)ip[1] << 8) + ((uint32_t)ip[2] << 16) + ((uint32_t)ip[3] << 24); \
     ip += 4;
+#ifdef __AVR__
+#include <avr/pgmspace.h>
+// images are kept in flash with PROGMEM
+#define IMAGE_BYTE(p) pgm_read_byte(p)
+#define IMAGE_COPY(dst, src, n) memcpy_P(dst, src, n)
+#else
+#define IMAGE_BYTE(p) (*(p))
+#define IMAGE_COPY(dst, src, n) memcpy(dst, src, n)
+#endif
 #define PUSH_PTR(val) sp++->ptr = (val)
 #define PUSH_NUM(val) sp++->num = (val)
-#define PUSH(val) *sp++ = (val)
 #define POP_NUM() ((--sp)->num)
-#define POP_PTR() ((--sp)->ptr)
+#define PUSH_FLOAT(val) sp++->f = (val)
+#define POP_FLOAT() ((--sp)->f)
+#define PUSH(val) *sp++ = (val)
 #define POP() (*--sp)
-// header tag:7bit/ reserved:1bit/ numentry:3bit/ objbit:7bit/ refcnt:14bit
+// header tag:7bit/ carry:1bit/ numentry:3bit/ objbit:7bit/ refcnt:14bit
+// tag 0 escapes to an extended header in the words before the header:
+// tag:16bit/ numentry:16bit at obj[-1], and objbit words of 32 fields each from obj[-2] down
 #define OBJ_HEADER(vobj) ((vobj).obj->obj_header)
-#define OBJ_TAG(vobj) (OBJ_HEADER(vobj) >> 25)
-#define OBJ_ENTRY_NUM(vobj) ((OBJ_HEADER(vobj) & 0xe00000) >> 21)
-#define OBJBIT_ITH(vobj, i) ((OBJ_HEADER(vobj) & (1 << (i + 14))) != 0)
+#define OBJ_EXTENDED(vobj) ((OBJ_HEADER(vobj) >> 25) == 0)
+#define OBJ_EXT(vobj) ((vobj).obj[-1].obj_header)
+#define OBJ_TAG(vobj) (OBJ_EXTENDED(vobj) ? OBJ_EXT(vobj) >> 16 : OBJ_HEADER(vobj) >> 25)
+#define OBJ_ENTRY_NUM(vobj) \
+    (OBJ_EXTENDED(vobj) ? OBJ_EXT(vobj) & 0xffff : (OBJ_HEADER(vobj) & 0xe00000) >> 21)
+#define OBJBIT_ITH(vobj, i)                                                            \
+    (OBJ_EXTENDED(vobj) ? (((vobj).obj[-2 - (i) / 32].obj_header >> ((i) % 32)) & 1) \
+                        : (OBJ_HEADER(vobj) & ((uint32_t)1 << (i + 14))) != 0)
+// words of the extended header
+#define OBJ_EXT_LEN(vobj) (OBJ_EXTENDED(vobj) ? 1 + (OBJ_ENTRY_NUM(vobj) + 31) / 32 : 0)
 #define OBJ_ITH(vobj, i) ((vobj).obj[(i) + 1])
-#define OBJ_INC_RC(vobj) OBJ_HEADER(vobj) += 1
-#define OBJ_DEC_RC_SHALLOW(vobj) OBJ_HEADER(vobj) -= 1
-#define OBJ_GET_RC(vobj) (OBJ_HEADER(vobj) & 0x3fff)
+#define OBJ_INC_RC(vobj)              \
+    if (obj_inc_rc(vobj) != EMFRP_OK) \
+    return EMFRP_OUTOF_MEMORY
+// the refcount is refcnt, plus OBJ_RC_MAX for each carry in rc_carry if the carry bit is set
+#define OBJ_RC_MAX 0x3fff
+#define OBJ_RC_CARRY ((uint32_t)1 << 24)
+#define OBJ_GET_RC(vobj) (OBJ_HEADER(vobj) & OBJ_RC_MAX)
 #define INITIAL_VARIABLE_CAPACITY 3
-#define STACK_SIZE 128
+#define CHECK_NULL(v) \
+    if (v == NULL)    \
+    return EMFRP_OUTOF_MEMORY
+#define CHECK_ERR(v)   \
+    if (v != EMFRP_OK) \
+        return v;
 #ifdef EMFRP_MEASURE_HEAP
 static uint32_t min_heap_free_size;
 #endif
-#ifdef EMFRP_DEBUG
+#ifdef EMFRP_DEBUG_OBJCNT
 static int obj_cnt = 0;
 #endif
+#ifdef EMFRP_MEASURE_HEAP
+static int stack_max_usage;
+#endif
 
-static value_t ZERO;
-static value_t OBJ_INIT_VALUE;
+static const value_t ZERO;
 
 #ifdef EMFRP_MEASURE_HEAP
-uint32_t get_min_free_heap_size()
-{
-    return min_heap_free_size;
-}
 static void update_min_free_heap_size(char *dbg_info)
 {
     uint32_t v = esp_get_free_heap_size();
-#ifdef EMFRP_MEASURE_HEAP
-    ESP_LOGI("[HEAP]", "%s: %lu", dbg_info, v);
+#ifdef EMFRP_DEBUG
+    dbg_int(dbg_info, v);
 #endif
     if (v < min_heap_free_size)
     {
-
         min_heap_free_size = v;
     }
 }
 
-void init_min_heap_free_size(uint32_t v)
+#endif
+#ifdef EMFRP_DEBUG
+static void dbg_u8array(char *name, uint8_t *v, int len)
 {
-    min_heap_free_size = v;
+    printf("%s[", name);
+    for (int i = 0; i < len; ++i)
+        printf("%d", (int)v[i]);
+    printf("]\n");
 }
-
 #endif
+static uint16_t object_size(value_t obj)
+{
+    uint16_t ret = 4 * (1 + OBJ_EXT_LEN(obj));
+    for (uint8_t i = 0, j = OBJ_ENTRY_NUM(obj); i < j; ++i)
+    {
+
+        if (OBJBIT_ITH(obj, i))
+        {
+            ret += object_size(OBJ_ITH(obj, i));
+        }
+        else
+        {
+            ret += 4;
+        }
+    }
+    return ret;
+}
+static void reply_write_object(value_t obj)
+{
+    reply_write((char *)&OBJ_HEADER(obj), 4);
+    for (int i = 1, j = OBJ_EXT_LEN(obj); i <= j; ++i)
+    {
+        reply_write((char *)&obj.obj[-i].obj_header, 4);
+    }
+    for (int i = 0, j = OBJ_ENTRY_NUM(obj); i < j; ++i)
+    {
+
+        if (OBJBIT_ITH(obj, i))
+        {
+            reply_write_object(OBJ_ITH(obj, i));
+        }
+        else
+        {
+            reply_write((char *)&(OBJ_ITH(obj, i).num), 4);
+        }
+    }
+}
+
+typedef struct
+{
+    value_t *obj;
+    uint32_t n;
+} rc_carry_t;
+// objects whose refcount has overflowed the header, and how many times OBJ_RC_MAX is carried
+static rc_carry_t *rc_carry;
+static int rc_carry_len;
+
+static rc_carry_t *find_rc_carry(value_t obj)
+{
+    for (int i = 0; i < rc_carry_len; ++i)
+    {
+        if (rc_carry[i].obj == obj.obj)
+            return &rc_carry[i];
+    }
+    return NULL;
+}
+static emfrp_result_t obj_inc_rc(value_t obj)
+{
+    if (OBJ_GET_RC(obj) != OBJ_RC_MAX)
+    {
+        OBJ_HEADER(obj) += 1;
+        return EMFRP_OK;
+    }
+    rc_carry_t *c = (OBJ_HEADER(obj) & OBJ_RC_CARRY) ? find_rc_carry(obj) : NULL;
+    if (c == NULL)
+    {
+        c = (rc_carry_t *)realloc(rc_carry, sizeof(rc_carry_t) * (rc_carry_len + 1));
+        CHECK_NULL(c);
+        rc_carry = c;
+        c = &rc_carry[rc_carry_len++];
+        c->obj = obj.obj;
+        c->n = 0;
+    }
+    ++c->n;
+    // the header counts from 1 again
+    OBJ_HEADER(obj) = (OBJ_HEADER(obj) - OBJ_RC_MAX + 1) | OBJ_RC_CARRY;
+    return EMFRP_OK;
+}
+// decrements the refcount, and returns whether it is 0
+static int obj_dec_rc(value_t obj)
+{
+    if (OBJ_GET_RC(obj) == 1 && (OBJ_HEADER(obj) & OBJ_RC_CARRY))
+    {
+        rc_carry_t *c = find_rc_carry(obj);
+        OBJ_HEADER(obj) += OBJ_RC_MAX - 1;
+        if (--c->n == 0)
+        {
+            *c = rc_carry[--rc_carry_len];
+            OBJ_HEADER(obj) &= ~OBJ_RC_CARRY;
+        }
+        return 0;
+    }
+    OBJ_HEADER(obj) -= 1;
+    return !OBJ_GET_RC(obj);
+}
 
 static void drop(value_t obj)
 {
-    OBJ_DEC_RC_SHALLOW(obj);
-    if (!OBJ_GET_RC(obj))
+
+    if (obj.obj == NULL)
+        return;
+    if (obj_dec_rc(obj))
     {
         for (int i = 0, j = OBJ_ENTRY_NUM(obj); i < j; ++i)
         {
@@ -78,16 +212,16 @@ static void drop(value_t obj)
                 drop(OBJ_ITH(obj, i));
             }
         }
-#ifdef EMFRP_DEBUG
+#ifdef EMFRP_DEBUG_OBJCNT
         --obj_cnt;
 #endif
-        free(obj.obj);
+        free(obj.obj - OBJ_EXT_LEN(obj));
     }
 }
 
-static inline value_t *alloc_obj(emfrp_machine *em, int len)
+static inline value_t *alloc_obj(emfrp_machine_t *em, int len)
 {
-#ifdef EMFRP_DEBUG
+#ifdef EMFRP_DEBUG_OBJCNT
     ++obj_cnt;
 #endif
 #ifdef EMFRP_MEASURE_HEAP
@@ -101,10 +235,37 @@ static inline value_t *alloc_obj(emfrp_machine *em, int len)
 #endif
 }
 
-static inline int next_int(uint8_t **p)
+// allocates an object of max entries behind its extended header, with the header of AllocObj
+static value_t *alloc_obj_insn(emfrp_machine_t *em, uint8_t **p, int max)
+{
+    uint8_t *ip = *p;
+    uint32_t header, ext = 0;
+    int n_ext = 0;
+    READ_U32(header);
+    if (header >> 25 == 0)
+    {
+        READ_U32(ext);
+        n_ext = 1 + ((ext & 0xffff) + 31) / 32;
+    }
+    value_t *obj = alloc_obj(em, n_ext + 1 + max);
+    if (obj == NULL)
+        return NULL;
+    obj += n_ext;
+    obj->obj_header = header;
+    if (n_ext)
+        obj[-1].obj_header = ext;
+    for (int i = 2; i <= n_ext; ++i)
+    {
+        READ_U32(obj[-i].obj_header);
+    }
+    *p = ip;
+    return obj;
+}
+
+static inline int next_word(uint8_t **p)
 { // little endian
-    int ret = (int)(**p) + (((int)(p[0][1])) << 8) + (((int)(p[0][2])) << 16) + (((int)(p[0][3])) << 24);
-    *p += 4;
+    int ret = (int)(**p) + (((int)(p[0][1])) << 8);
+    *p += 2;
     return ret;
 }
 static inline uint8_t next_byte(uint8_t **p)
@@ -114,24 +275,37 @@ static inline uint8_t next_byte(uint8_t **p)
     *p += 1;
     return ret;
 }
-void init_node_list(node_list_t *nd_list, int cap)
+emfrp_result_t init_node_list(node_list_t *nd_list, int cap)
 {
     nd_list->len = 0;
     nd_list->cap = cap;
+    if (cap == 0)
+        return EMFRP_OK;
     nd_list->values = (value_t *)malloc(sizeof(value_t) * cap);
     nd_list->action = (upd_action_t *)malloc(sizeof(upd_action_t) * cap);
+    CHECK_NULL(nd_list->values);
+    CHECK_NULL(nd_list->action);
+    return EMFRP_OK;
 }
-void init_func_list(func_list_t *f_list, int cap)
+emfrp_result_t init_func_list(func_list_t *f_list, int cap)
 {
     f_list->len = 0;
     f_list->cap = cap;
+    if (cap == 0)
+        return EMFRP_OK;
     f_list->lst = (func_t *)malloc(sizeof(func_t) * cap);
+    CHECK_NULL(f_list->lst);
+    return EMFRP_OK;
 }
-void init_data_list(data_list_t *d_list, int cap)
+emfrp_result_t init_data_list(data_list_t *d_list, int cap)
 {
     d_list->len = 0;
     d_list->cap = cap;
+    if (cap == 0)
+        return EMFRP_OK;
     d_list->lst = (data_t *)malloc(sizeof(data_t) * cap);
+    CHECK_NULL(d_list->lst);
+    return EMFRP_OK;
 }
 
 static inline void push_node_usr_uncheck(node_list_t *lst, value_t v, uint8_t *upd)
@@ -149,35 +323,42 @@ static inline void push_node_dev_uncheck(node_list_t *lst, value_t v, dev_input_
     lst->len += 1;
 }
 
-void extend_node_list(node_list_t *lst, const int new_cap)
+emfrp_result_t extend_node_list(node_list_t *lst, const int new_cap)
 {
     if (lst->cap < new_cap)
     {
+        // both are allocated first, so that the list is kept if either fails
         value_t *vs = (value_t *)malloc(sizeof(value_t) * new_cap);
+        upd_action_t *upds = (upd_action_t *)malloc(sizeof(upd_action_t) * new_cap);
+        if (vs == NULL || upds == NULL)
+        {
+            free(vs);
+            free(upds);
+            return EMFRP_OUTOF_MEMORY;
+        }
         memcpy(vs, lst->values, lst->len * sizeof(value_t));
+        memcpy(upds, lst->action, lst->len * sizeof(upd_action_t));
 #ifdef EMFRP_MEASURE_HEAP
         update_min_free_heap_size("extend node list");
 #endif
         free(lst->values);
-        upd_action_t *upds = (upd_action_t *)malloc(sizeof(upd_action_t) * new_cap);
-        memcpy(upds, lst->action, lst->len * sizeof(upd_action_t));
-
+        free(lst->action);
 #ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("extend node list");
+        update_min_free_heap_size("free node list");
 #endif
-        free(lst->action);
-
         lst->action = upds;
         lst->values = vs;
         lst->cap = new_cap;
     }
+    return EMFRP_OK;
 }
-void extend_func_list(func_list_t *lst, const int new_cap)
+emfrp_result_t extend_func_list(func_list_t *lst, const int new_cap)
 {
 
     if (lst->cap < new_cap)
     {
         func_t *f = (func_t *)malloc(sizeof(func_t) * new_cap);
+        CHECK_NULL(f);
         memcpy(f, lst->lst, lst->len * sizeof(func_t));
 
 #ifdef EMFRP_MEASURE_HEAP
@@ -187,12 +368,14 @@ void extend_func_list(func_list_t *lst, const int new_cap)
         lst->lst = f;
         lst->cap = new_cap;
     }
+    return EMFRP_OK;
 }
-void extend_data_list(data_list_t *lst, const int new_cap)
+emfrp_result_t extend_data_list(data_list_t *lst, const int new_cap)
 {
     if (lst->cap < new_cap)
     {
         data_t *d = (data_t *)malloc(sizeof(data_t) * new_cap);
+        CHECK_NULL(d);
         memcpy(d, lst->lst, lst->len * sizeof(data_t));
 
 #ifdef EMFRP_MEASURE_HEAP
@@ -202,6 +385,7 @@ void extend_data_list(data_list_t *lst, const int new_cap)
         lst->lst = d;
         lst->cap = new_cap;
     }
+    return EMFRP_OK;
 }
 static inline void push_func_uncheck(func_list_t *lst, func_t f)
 {
@@ -215,43 +399,61 @@ static inline void push_data_uncheck(data_list_t *lst, data_t d)
     lst->len += 1;
 }
 
-void emfrp_init(emfrp_machine *em, int n_input_node, int n_output_node)
+emfrp_result_t emfrp_init(emfrp_machine_t *em, int n_input_node, int n_output_node)
 {
-    ZERO.num = 0;
+    emfrp_result_t res;
     em->v_stack = (value_t *)malloc(sizeof(value_t) * STACK_SIZE);
-
-    init_node_list(&em->node_list, n_input_node + n_output_node);
-    init_func_list(&em->func_list, INITIAL_VARIABLE_CAPACITY);
-    init_data_list(&em->data_list, INITIAL_VARIABLE_CAPACITY);
+    CHECK_NULL(em->v_stack);
+    res = init_node_list(&em->node_list, n_input_node + n_output_node);
+    CHECK_ERR(res);
+    res = init_func_list(&em->func_list, 0);
+    CHECK_ERR(res);
+    res = init_data_list(&em->data_list, 0);
+    CHECK_ERR(res);
 
     em->update = NULL;
     em->node_last = NULL;
-    em->res = EMFRP_NOTSTARTED;
-    em->output_actions = (output_action_t *)malloc(n_output_node * sizeof(output_action_t));
+    memset(&em->staged, 0, sizeof(staged_t));
+    if (n_output_node != 0)
+    {
+        em->output_actions = (output_action_t *)malloc(n_output_node * sizeof(output_action_t));
+        CHECK_NULL(em->output_actions);
+    }
+
     em->output_nd_len = 0;
-    value_t *obj = (value_t *)malloc(sizeof(value_t));
-    obj->obj_header = 1;
-    OBJ_INIT_VALUE.obj = obj;
+    return EMFRP_OK;
 }
 
-value_t emfrp_int(int i)
+value_t emfrp_int(int32_t i)
 {
     value_t v;
     v.num = i;
     return v;
 }
+value_t emfrp_true()
+{
+    value_t v;
+    v.num = true;
+    return v;
+}
+value_t emfrp_false()
+{
+    value_t v;
+    v.num = false;
+    return v;
+}
 
-void emfrp_add_input_node(emfrp_machine *em, value_t init, dev_input_t driver)
+void emfrp_add_input_node(emfrp_machine_t *em, value_t init, dev_input_t driver)
 {
     push_node_dev_uncheck(&em->node_list, init, driver);
 }
-void emfrp_add_output_node(emfrp_machine *em, value_t init, output_action_t driver)
+void emfrp_add_output_node(emfrp_machine_t *em, value_t init, output_action_t driver)
 {
     em->output_actions[em->output_nd_len++] = driver;
     push_node_usr_uncheck(&em->node_list, init, NULL);
 }
 
-value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
+emfrp_result_t emfrp_exec(emfrp_machine_t *em, uint8_t *ip)
 {
     value_t *bp = em->v_stack;
     value_t *sp = em->v_stack;
@@ -261,29 +463,35 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
     data_t *data = em->data_list.lst;
     const func_t *func = em->func_list.lst;
     const output_action_t *output_actions = em->output_actions;
+    call_frame *frame_prev = NULL, *frame_tmp;
     value_t tmp_v;
     uint8_t tmp_byte;
-    int tmp_int;
-    call_frame *frame_prev = NULL, *frame_tmp;
+    uint16_t tmp_u16;
+    uint32_t tmp_int;
+    float tmp_float;
 
     while (1)
     {
-#ifdef EMFRP_DEBUG
+#ifdef EMFRP_DEBUG_LEVEL2
         printf("insn:%d, stack:", (int)*ip);
         for (int i = 0, j = sp - bp; i < j; ++i)
         {
-            printf(" %d ", sp[i].num);
+            printf(" %d ", bp[i].num);
         }
         printf("\n");
 #endif
-        switch (*ip++)
+#ifdef EMFRP_MEASURE_HEAP
+        if (stack_max_usage < sp - em->v_stack)
         {
-        case BC_None:
-#ifdef EMFRP_DEBUG
-            ESP_LOGI("[ERROR]", "invalid insn : BC_NONE\n");
+            stack_max_usage = sp - em->v_stack;
+        }
 #endif
-            em->res = EMFRP_PANIC;
-            return ZERO;
+        // TODO : A
+        uint8_t ip2 = *ip;
+        ++ip;
+        switch (ip2)
+        {
+
         case BC_Nil:
             PUSH(ZERO);
             break;
@@ -293,32 +501,32 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_Minus:
             sp[-1].num = -sp[-1].num;
             break;
-        case BC_Add: // a b sp -> (a+b) sp
+        case BC_Add:
             tmp_int = POP_NUM();
             tmp_int += POP_NUM();
             PUSH_NUM(tmp_int);
             break;
-        case BC_Sub: // a b sp -> (a+b) sp
+        case BC_Sub:
             tmp_int = POP_NUM();
             tmp_int = POP_NUM() - tmp_int;
             PUSH_NUM(tmp_int);
             break;
-        case BC_Mul: // a b sp -> (a+b) sp
+        case BC_Mul:
             tmp_int = POP_NUM();
             tmp_int *= POP_NUM();
             PUSH_NUM(tmp_int);
             break;
-        case BC_Div: // a b sp -> (a+b) sp
+        case BC_Div:
             tmp_int = POP_NUM();
             tmp_int = POP_NUM() / tmp_int;
             PUSH_NUM(tmp_int);
             break;
-        case BC_Mod: // a b sp -> (a+b) sp
+        case BC_Mod:
             tmp_int = POP_NUM();
             tmp_int = POP_NUM() % tmp_int;
             PUSH_NUM(tmp_int);
             break;
-        case BC_Ls: // a b sp -> (a+b) sp
+        case BC_Ls: // a b -> a < b
             tmp_int = POP_NUM();
             tmp_int = POP_NUM() < tmp_int;
             PUSH_NUM(tmp_int);
@@ -333,21 +541,121 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             tmp_int = POP_NUM() > tmp_int;
             PUSH_NUM(tmp_int);
             break;
-        case BC_Geq: // a b sp -> (a+b) sp
+        case BC_Geq:
             tmp_int = POP_NUM();
             tmp_int = POP_NUM() >= tmp_int;
             PUSH_NUM(tmp_int);
             break;
-        case BC_Eq: // a b sp -> (a+b) sp
+        case BC_Eq:
             tmp_int = POP_NUM();
             tmp_int = POP_NUM() == tmp_int;
             PUSH_NUM(tmp_int);
             break;
-        case BC_Neq: // a b sp -> (a+b) sp
+        case BC_Neq:
             tmp_int = POP_NUM();
             tmp_int = POP_NUM() != tmp_int;
             PUSH_NUM(tmp_int);
             break;
+
+        case BC_ShiftL:
+            tmp_int = POP_NUM();
+            tmp_int = POP_NUM() << tmp_int;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_ShiftR:
+            tmp_int = POP_NUM();
+            tmp_int = POP_NUM() >> tmp_int;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_BitAnd:
+            tmp_int = POP_NUM();
+            tmp_int = POP_NUM() & tmp_int;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_BitOr:
+            tmp_int = POP_NUM();
+            tmp_int = POP_NUM() | tmp_int;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_BitXor:
+            tmp_int = POP_NUM();
+            tmp_int = POP_NUM() ^ tmp_int;
+            PUSH_NUM(tmp_int);
+            break;
+
+        case BC_FLOAT:
+            READ_U32(tmp_v.obj_header);
+            PUSH(tmp_v);
+            break;
+        case BC_FMinus:
+            sp[-1].f = -sp[-1].f;
+            break;
+        case BC_FAdd:
+            tmp_float = POP_FLOAT();
+            tmp_float = POP_FLOAT() + tmp_float;
+            PUSH_FLOAT(tmp_float);
+            break;
+        case BC_FSub:
+            tmp_float = POP_FLOAT();
+            tmp_float = POP_FLOAT() - tmp_float;
+            PUSH_FLOAT(tmp_float);
+            break;
+        case BC_FMul:
+            tmp_float = POP_FLOAT();
+            tmp_float = POP_FLOAT() * tmp_float;
+            PUSH_FLOAT(tmp_float);
+            break;
+        case BC_FDiv:
+            tmp_float = POP_FLOAT();
+            tmp_float = POP_FLOAT() / tmp_float;
+            PUSH_FLOAT(tmp_float);
+            break;
+        case BC_FLs:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() < tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FLeq:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() <= tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FGt:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() > tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FGeq:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() >= tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FEq:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() == tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_FNeq:
+            tmp_float = POP_FLOAT();
+            tmp_int = POP_FLOAT() != tmp_float;
+            PUSH_NUM(tmp_int);
+            break;
+        case BC_IntToFloat:
+            sp[-1].f = (float)sp[-1].num;
+            break;
+        case BC_FloatToInt:
+            // truncated toward zero. out of range values are saturated and NaN is 0, as the compiler's VM does
+            tmp_float = sp[-1].f;
+            if (tmp_float != tmp_float)
+                sp[-1].num = 0;
+            else if (tmp_float >= 2147483648.0f)
+                sp[-1].num = INT32_MAX;
+            else if (tmp_float <= -2147483648.0f)
+                sp[-1].num = INT32_MIN;
+            else
+                sp[-1].num = (int32_t)tmp_float;
+            break;
+
         case BC_INT_0:
             PUSH_NUM(0);
             break;
@@ -366,6 +674,9 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_INT_5:
             PUSH_NUM(5);
             break;
+        case BC_INT_6:
+            PUSH_NUM(6);
+            break;
         case BC_INT_I8:
             PUSH_NUM((int)READ_I8());
             break;
@@ -387,6 +698,10 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             tmp_int = (int)READ_I8();
             ip += tmp_int;
             break;
+        case BC_J16:
+            READ_I16(tmp_int);
+            ip += tmp_int;
+            break;
         case BC_J32:;
             READ_I32(tmp_int);
             ip += tmp_int;
@@ -403,6 +718,30 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
                 ip += 1;
             }
             break;
+        case BC_Je16:
+            if (POP_NUM())
+            {
+
+                READ_I16(tmp_int);
+                ip += tmp_int;
+            }
+            else
+            {
+                ip += 2;
+            }
+            break;
+        case BC_Je32:
+            if (POP_NUM())
+            {
+
+                READ_I32(tmp_int);
+                ip += tmp_int;
+            }
+            else
+            {
+                ip += 4;
+            }
+            break;
         case BC_Jne8:
             if (!POP_NUM())
             {
@@ -415,6 +754,17 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
                 ip += 1;
             }
             break;
+        case BC_Jne16:
+            if (!POP_NUM())
+            {
+                READ_I16(tmp_int);
+                ip += tmp_int;
+            }
+            else
+            {
+                ip += 2;
+            }
+            break;
         case BC_Jne32:
             if (!POP_NUM())
             {
@@ -426,9 +776,32 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
                 ip += 4;
             }
             break;
+        case BC_J0:
+            break;
+        case BC_J1:
+            ++ip;
+            break;
+        case BC_Je0:
+            --sp;
+            break;
+        case BC_Je1:
+            if (POP_NUM())
+                ++ip;
+            break;
+        case BC_Jne0:
+            --sp;
+            break;
+        case BC_Jne1:
+            if (!POP_NUM())
+                ++ip;
+            break;
         case BC_GET_DATA_U8:
             PUSH(data[READ_U8()]);
             break;
+        case BC_GET_DATA_U16:
+            READ_U16(tmp_int);
+            PUSH(data[tmp_int]);
+            break;
         case BC_GET_DATA_U32:
             READ_U32(tmp_int);
             PUSH(data[tmp_int]);
@@ -457,6 +830,10 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_GET_LOCAL_I8:
             PUSH(*(bp + READ_I8()));
             break;
+        case BC_GET_LOCAL_I16:
+            READ_I16(tmp_int);
+            PUSH(*(bp + tmp_int));
+            break;
         case BC_GET_LOCAL_I32:
             READ_I32(tmp_int);
             PUSH(*(bp + tmp_int));
@@ -485,6 +862,10 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_SET_LOCAL_I8:
             bp[READ_I8()] = POP();
             break;
+        case BC_SET_LOCAL_I16:
+            READ_I16(tmp_int);
+            bp[tmp_int] = POP();
+            break;
         case BC_SET_LOCAL_I32:
             READ_I32(tmp_int);
             bp[tmp_int] = POP();
@@ -530,26 +911,56 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_SET_LAST_U8:
             node_vlast[READ_U8()] = POP();
             break;
+        case BC_SET_LAST_U16:
+            READ_U16(tmp_int);
+            node_vlast[tmp_int] = POP();
+            break;
         case BC_SET_LAST_U32:
             READ_U32(tmp_int);
             node_vlast[tmp_int] = POP();
             break;
         case BC_CALL_U8:;
+            /*tmp_byte = READ_U8(); // nargs
+            sp->bp = bp;
+            bp = sp - tmp_byte;
+            ++sp;
+            tmp_byte = READ_U8(); // func offset
+            sp->ip = ip;
+            ++sp;
+            ip = func[tmp_byte];*/
             tmp_byte = READ_U8(); // nargs
             frame_tmp = (call_frame *)malloc(sizeof(call_frame));
+            CHECK_NULL(frame_tmp);
             frame_tmp->caller = frame_prev;
             frame_tmp->bp = bp;
             frame_tmp->sp = sp - tmp_byte;
+
             frame_prev = frame_tmp;
             bp = sp - tmp_byte;
+
             tmp_byte = READ_U8();
             frame_tmp->next_ip = ip;
             ip = func[tmp_byte];
+            break;
+        case BC_CALL_U16:
+            tmp_byte = READ_U8(); // nargs
+            frame_tmp = (call_frame *)malloc(sizeof(call_frame));
+            CHECK_NULL(frame_tmp);
+            frame_tmp->caller = frame_prev;
+            frame_tmp->bp = bp;
+            frame_tmp->sp = sp - tmp_byte;
 
+            frame_prev = frame_tmp;
+            bp = sp - tmp_byte;
+
+            READ_U16(tmp_int);
+            frame_tmp->next_ip = ip;
+            ip = func[tmp_int];
             break;
         case BC_CALL_U32:
             tmp_byte = READ_U8(); // nargs
             frame_tmp = (call_frame *)malloc(sizeof(call_frame));
+            CHECK_NULL(frame_tmp);
             frame_tmp->caller = frame_prev;
             frame_tmp->bp = bp;
             frame_tmp->sp = sp - tmp_byte;
@@ -574,6 +985,10 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_SET_DATA_U8:
             data[READ_U8()] = POP();
             break;
+        case BC_SET_DATA_U16:
+            READ_U16(tmp_int);
+            data[tmp_int] = POP();
+            break;
         case BC_SET_DATA_U32:
             READ_U32(tmp_int);
             data[tmp_int] = POP();
@@ -581,6 +996,10 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_SET_NODE_U8:
             node_v[READ_U8()] = POP();
             break;
+        case BC_SET_NODE_U16:
+            READ_U16(tmp_int);
+            node_v[tmp_int] = POP();
+            break;
         case BC_SET_NODE_U32:
             READ_U32(tmp_int);
             node_v[tmp_int] = POP();
@@ -607,6 +1026,12 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             ++sp;
             ip = action[tmp_byte].insns;
             break;
+        case BC_UPD_NODE_U16:
+            READ_U16(tmp_int);
+            sp->ip = ip;
+            ++sp;
+            ip = action[tmp_int].insns;
+            break;
         case BC_UPD_NODE_U32:
             READ_U32(tmp_int);
             sp->ip = ip;
@@ -618,6 +1043,12 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             --sp;
             ip = sp->ip;
             break;
+        case BC_END_UPD_U16:
+            READ_U16(tmp_int);
+            node_v[tmp_int] = POP();
+            --sp;
+            ip = sp->ip;
+            break;
         case BC_END_UPD_U32:
             READ_U32(tmp_int);
             node_v[tmp_int] = POP();
@@ -625,9 +1056,8 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             ip = sp->ip;
             break;
         case BC_ALLOC_OBJ_0:
-            tmp_v.obj = alloc_obj(em, 1);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 0);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -636,9 +1066,8 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_1:
-            tmp_v.obj = alloc_obj(em, 2);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 1);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -647,20 +1076,23 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_2:
-            tmp_v.obj = alloc_obj(em, 3);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 2);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
                 tmp_v.obj[tmp_byte - i] = POP();
             }
             PUSH(tmp_v);
+            // for (int i = 0; i < 3; ++i)
+            // {
+            //     print_value(tmp_v.obj[i]);
+            // }
+
             break;
         case BC_ALLOC_OBJ_3:
-            tmp_v.obj = alloc_obj(em, 4);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 3);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -669,9 +1101,8 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_4:
-            tmp_v.obj = alloc_obj(em, 5);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 4);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -680,9 +1111,8 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_5:
-            tmp_v.obj = alloc_obj(em, 6);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 5);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -691,9 +1121,8 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_6:
-            tmp_v.obj = alloc_obj(em, 7);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_v.obj = alloc_obj_insn(em, &ip, 6);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -702,9 +1131,9 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_ALLOC_OBJ_U8:
-            tmp_v.obj = alloc_obj(em, (int)READ_U8() + 1);
-            READ_U32(tmp_int);
-            tmp_v.obj->obj_header = (uint32_t)tmp_int;
+            tmp_byte = READ_U8();
+            tmp_v.obj = alloc_obj_insn(em, &ip, tmp_byte);
+            CHECK_NULL(tmp_v.obj);
             tmp_byte = OBJ_ENTRY_NUM(tmp_v);
             for (int i = 0; i < tmp_byte; ++i)
             {
@@ -716,6 +1145,8 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             *sp = sp[-1];
             ++sp;
             break;
+        case BC_POP_0:
+            break;
         case BC_POP_1:
             --sp;
             break;
@@ -737,12 +1168,17 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_POP_U8:
             sp -= READ_U8();
             break;
+        case BC_POP_U16:
+            READ_U16(tmp_int);
+            sp -= tmp_int;
+            break;
         case BC_POP_U32:
             READ_U32(tmp_int);
             sp -= tmp_int;
             break;
         case BC_OBJ_TAG:
-            tmp_int = OBJ_TAG(POP());
+            tmp_v = POP();
+            tmp_int = OBJ_TAG(tmp_v);
             sp->obj_header = (uint32_t)tmp_int;
             ++sp;
             break;
@@ -775,12 +1211,8 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_OBJ_FIELD_U8:
-            tmp_v = POP().obj[READ_U8() + 1];
-            PUSH(tmp_v);
-            break;
-        case BC_OBJ_FIELD_U32:
-            READ_U32(tmp_int);
-            tmp_v = POP().obj[tmp_int + 1];
+            tmp_byte = READ_U8();
+            tmp_v = POP().obj[tmp_byte + 1];
             PUSH(tmp_v);
             break;
         case BC_ALLOC_LOCAL_1:
@@ -804,6 +1236,10 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
         case BC_ALLOC_LOCAL_U8:
             sp += READ_U8();
             break;
+        case BC_ALLOC_LOCAL_U16:
+            READ_U16(tmp_int);
+            sp += (uint32_t)tmp_int;
+            break;
         case BC_ALLOC_LOCAL_U32:
             READ_U32(tmp_int);
             sp += (uint32_t)tmp_int;
@@ -970,13 +1406,8 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
             PUSH(tmp_v);
             break;
         case BC_OBJ_FIELD_REF_U8:
-            tmp_v = OBJ_ITH(POP(), READ_U8());
-            OBJ_INC_RC(tmp_v);
-            PUSH(tmp_v);
-            break;
-        case BC_OBJ_FIELD_REF_U32:
-            READ_U32(tmp_int);
-            tmp_v = OBJ_ITH(POP(), (uint32_t)tmp_int);
+            tmp_byte = READ_U8();
+            tmp_v = POP().obj[tmp_byte + 1];
             OBJ_INC_RC(tmp_v);
             PUSH(tmp_v);
             break;
@@ -1104,171 +1535,419 @@ value_t emfrp_exec(emfrp_machine *em, uint8_t *ip)
 #ifdef EMFRP_DEBUG
             if (sp != em->v_stack)
             {
-                em->res = EMFRP_PANIC;
+                return EMFRP_PANIC;
             }
 #endif
-            return ZERO;
+            return EMFRP_OK;
         case BC_ABORT:
-            em->res = EMFRP_RUNTIME_ERR;
-            return ZERO;
+            return EMFRP_RUNTIME_ERR;
+        case BC_Print:
+            tmp_int = POP().num;
+            tmp_u16 = 4;
+            reply_write((const char *)&tmp_u16, 2);
+            reply_write((const char *)&tmp_int, 4);
+            break;
+        case BC_PRINTOBJ:
+            tmp_v = POP();
+            tmp_u16 = object_size(tmp_v);
+            reply_write((char *)&tmp_u16, 2);
+            reply_write_object(tmp_v);
+            drop(tmp_v);
+            break;
 
-        case BC_Exit:
+        default:
 #ifdef EMFRP_DEBUG
-            if ((em->v_stack + 1) != sp)
+            if ((sp - em->v_stack) < 0 || 128 <= (sp - em->v_stack))
             {
-                em->res = EMFRP_PANIC;
-                return ZERO;
+                dbg_int("invalid ip", (int)*ip);
+                return EMFRP_PANIC;
             }
 #endif
-            return *em->v_stack;
-        default:
-#ifdef EMFRP_DEBUG
-            ////ESP_LOGI("error", "todo : %d\n", *ip);
-            ESP_LOGI("error", "todo : %d\n", (int)ip[-1]);
-            em->res = EMFRP_TODO;
-#endif
+            return EMFRP_TODO;
 
-            return ZERO;
-        }
 #ifdef EMFRP_DEBUG
-        if ((sp - em->v_stack) < 0 || 128 <= (sp - em->v_stack))
-        {
-            ESP_LOGI("error", "stack overflow\n");
-            em->res = EMFRP_PANIC;
-            return ZERO;
-        }
+            if ((sp - em->v_stack) < 0 || 128 <= (sp - em->v_stack))
+            {
+                dbg_int("stack left", sp - em->v_stack);
+                return EMFRP_PANIC;
+            }
 #endif
+        }
     }
 }
 
-void emfrp_init_vars(emfrp_machine *em, int n_node, int n_func, uint8_t **data)
+emfrp_result_t emfrp_new_bytecode(emfrp_machine_t *em, int data_len, uint8_t *data)
 {
+    emfrp_result_t res;
+    if (data_len > 0 && data[0] != 0)
+    {
+        res = emfrp_exec(em, data + 1);
+        reply_write((char *)&res, 1);
+        return res;
+    }
+    res = emfrp_stage(em, data_len, data);
+    if (res == EMFRP_OK)
+        res = emfrp_commit(em);
+    reply_write((char *)&res, 1);
+    return res;
+}
+
+static uint8_t *copy_bytes(uint8_t **data, int len)
+{
+    uint8_t *p = (uint8_t *)malloc(len);
+    if (p != NULL)
+        memcpy(p, *data, len);
+    *data += len;
+#ifdef EMFRP_MEASURE_HEAP
+    update_min_free_heap_size("stage");
+#endif
+    return p;
+}
 
-    for (int i = 0; i < n_node; ++i)
+// keeps the definitions in a define request apart from the running program, which is left as it is
+// if they do not fit in memory
+emfrp_result_t emfrp_stage(emfrp_machine_t *em, int data_len, uint8_t *data)
+{
+    staged_t *st = &em->staged;
+    emfrp_result_t res = EMFRP_OUTOF_MEMORY;
+    int exp_len, upd_len, num_last, n;
+    emfrp_abort(em);
+    // is_eval(u8) and 8 words
+    if (data_len < 17 || next_byte(&data) != 0)
+        return EMFRP_PANIC;
+    exp_len = next_word(&data);
+    upd_len = next_word(&data);
+    num_last = next_word(&data);
+    st->n_node = next_word(&data);
+    st->n_func = next_word(&data);
+    // the numbers of new nodes and funcs are counted from the offsets
+    next_word(&data);
+    next_word(&data);
+    st->n_new_data = next_word(&data);
+    st->active = true;
+    st->nodes = (staged_body_t *)calloc(st->n_node + 1, sizeof(staged_body_t));
+    st->funcs = (staged_body_t *)calloc(st->n_func + 1, sizeof(staged_body_t));
+    if (st->nodes == NULL || st->funcs == NULL)
+        goto fail;
+    // a body is placed in a slot, or right after the last one
+    n = em->node_list.len;
+    for (int i = 0; i < st->n_node; ++i)
     {
-        int offset = next_int(data);
-        int body_len = next_int(data);
-        if (offset < em->node_list.len)
+        int offset = next_word(&data);
+        int body_len = next_word(&data);
+        if (offset > n)
         {
-            uint8_t *prev_body = em->node_list.action[offset].insns;
-            free(prev_body);
+            res = EMFRP_PANIC;
+            goto fail;
         }
-        uint8_t *body = (uint8_t *)malloc(body_len);
-        memcpy(body, *data, body_len);
-        *data += body_len;
-#ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("init node");
-#endif
+        n += offset == n;
+        st->nodes[i].offset = offset;
+        st->nodes[i].body = copy_bytes(&data, body_len);
+        if (st->nodes[i].body == NULL)
+            goto fail;
+    }
+    // the lists are extended now, so that the commit does not allocate them
+    res = extend_node_list(&em->node_list, n);
+    if (res != EMFRP_OK)
+        goto fail;
+    res = EMFRP_OUTOF_MEMORY;
+    n = em->func_list.len;
+    for (int i = 0; i < st->n_func; ++i)
+    {
+        int offset = next_word(&data);
+        int body_len = next_word(&data);
+        if (offset > n)
+        {
+            res = EMFRP_PANIC;
+            goto fail;
+        }
+        n += offset == n;
+        st->funcs[i].offset = offset;
+        st->funcs[i].body = copy_bytes(&data, body_len);
+        if (st->funcs[i].body == NULL)
+            goto fail;
+    }
+    res = extend_func_list(&em->func_list, n);
+    if (res != EMFRP_OK)
+        goto fail;
+    res = extend_data_list(&em->data_list, em->data_list.len + st->n_new_data);
+    if (res != EMFRP_OK)
+        goto fail;
+    res = EMFRP_OUTOF_MEMORY;
+    if (upd_len > 0 && (st->update = copy_bytes(&data, upd_len)) == NULL)
+        goto fail;
+    if (num_last > 0 && (st->node_last = (value_t *)malloc(num_last * sizeof(value_t))) == NULL)
+        goto fail;
+    if (exp_len > 0 && (st->init = copy_bytes(&data, exp_len)) == NULL)
+        goto fail;
+    return EMFRP_OK;
+fail:
+    emfrp_abort(em);
+    return res;
+}
+
+// swaps in the definitions kept by emfrp_stage, and runs their init code.
+// the replaced bodies are freed before init runs, so the new definitions stay in place if it fails
+emfrp_result_t emfrp_commit(emfrp_machine_t *em)
+{
+    staged_t *st = &em->staged;
+    emfrp_result_t res = EMFRP_OK;
+    if (!st->active)
+        return EMFRP_PANIC;
+    for (int i = 0; i < st->n_new_data; ++i)
+    {
+        push_data_uncheck(&em->data_list, ZERO);
+    }
+    for (int i = 0; i < st->n_node; ++i)
+    {
+        int offset = st->nodes[i].offset;
         if (offset < em->node_list.len)
         {
-            em->node_list.action[offset].insns = body;
+            free(em->node_list.action[offset].insns);
+            em->node_list.action[offset].insns = st->nodes[i].body;
         }
         else
         {
-            OBJ_INC_RC(OBJ_INIT_VALUE);
-            push_node_usr_uncheck(&em->node_list, OBJ_INIT_VALUE, body);
+            push_node_usr_uncheck(&em->node_list, ZERO, st->nodes[i].body);
         }
     }
-    for (int i = 0; i < n_func; ++i)
+    for (int i = 0; i < st->n_func; ++i)
     {
-        int offset = next_int(data);
-        int body_len = next_int(data);
+        int offset = st->funcs[i].offset;
         if (offset < em->func_list.len)
         {
             free(em->func_list.lst[offset]);
-        }
-        uint8_t *body = (uint8_t *)malloc(body_len);
-#ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("init func");
-
-#endif
-        memcpy(body, *data, body_len);
-        *data += body_len;
-        if (offset < em->func_list.len)
-        {
-            em->func_list.lst[offset] = body;
+            em->func_list.lst[offset] = st->funcs[i].body;
         }
         else
         {
-            push_func_uncheck(&em->func_list, body);
+            push_func_uncheck(&em->func_list, st->funcs[i].body);
         }
     }
+    if (st->update != NULL)
+    {
+        free(em->update);
+        em->update = st->update;
+    }
+    free(em->node_last);
+    em->node_last = st->node_last;
+    if (st->init != NULL)
+    {
+#ifdef EMFRP_MEASURE_HEAP
+        stack_max_usage = 0;
+#endif
+        res = emfrp_exec(em, st->init);
+#ifdef EMFRP_MEASURE_HEAP
+        dbg_int("max stack usage in init", stack_max_usage);
+#endif
+        free(st->init);
+    }
+    free(st->nodes);
+    free(st->funcs);
+    memset(st, 0, sizeof(staged_t));
+    return res;
 }
 
-value_t emfrp_new_bytecode(emfrp_machine *em, int data_len, uint8_t *data)
+// frees the definitions kept by emfrp_stage
+void emfrp_abort(emfrp_machine_t *em)
 {
-    int exp_len = next_int(&data);
-    int upd_len = next_int(&data);
-    int num_last = next_int(&data);
-    int n_node = next_int(&data);
-    int n_func = next_int(&data);
-    int tmp;
-    if (em->node_last != NULL)
-        free(em->node_last);
-    em->node_last = (value_t *)malloc(num_last * sizeof(value_t));
-
-    tmp = next_int(&data);
-    if (tmp > 0)
+    staged_t *st = &em->staged;
+    if (!st->active)
+        return;
+    for (int i = 0; st->nodes != NULL && i < st->n_node; ++i)
     {
-        extend_node_list(&em->node_list, tmp + em->node_list.len);
+        free(st->nodes[i].body);
     }
-    tmp = next_int(&data);
-    if (tmp > 0)
+    for (int i = 0; st->funcs != NULL && i < st->n_func; ++i)
     {
-        extend_func_list(&em->func_list, tmp + em->func_list.len);
+        free(st->funcs[i].body);
     }
-    tmp = next_int(&data);
+    free(st->nodes);
+    free(st->funcs);
+    free(st->update);
+    free(st->node_last);
+    free(st->init);
+    memset(st, 0, sizeof(staged_t));
+}
 
-    if (tmp > 0)
-    {
-        extend_data_list(&em->data_list, tmp + em->data_list.len);
-        for (int i = 0; i < tmp; ++i)
-        {
-            OBJ_INC_RC(OBJ_INIT_VALUE);
-            push_data_uncheck(&em->data_list, OBJ_INIT_VALUE);
-        }
-    }
-    emfrp_init_vars(em, n_node, n_func, &data);
-    if (upd_len > 0)
+// writes the value of a node (PROTO_QUERY_NODE) or data (PROTO_QUERY_DATA) in the same format as
+// BC_Print or BC_PRINTOBJ, or 1 if definitions are staged and 0 if not (PROTO_QUERY_STAGED), and the result
+emfrp_result_t emfrp_query(emfrp_machine_t *em, int kind, int is_obj, int index)
+{
+    emfrp_result_t res = EMFRP_PANIC;
+    value_t v;
+    uint16_t size;
+    uint8_t active;
+    if (kind == PROTO_QUERY_STAGED)
     {
-        if (em->update != NULL)
-            free(em->update);
-        uint8_t *update = (uint8_t *)malloc(upd_len);
-#ifdef EMFRP_MEASURE_HEAP
-        update_min_free_heap_size("init copy update");
-#endif
-        memcpy(update, data, upd_len);
-        data += upd_len;
-        em->update = update;
+        active = em->staged.active != 0;
+        reply_write((char *)&active, 1);
+        res = EMFRP_OK;
+        goto end;
     }
-    if (exp_len == 0)
+    if (kind == PROTO_QUERY_NODE && index < em->node_list.len)
+        v = em->node_list.values[index];
+    else if (kind == PROTO_QUERY_DATA && index < em->data_list.len)
+        v = em->data_list.lst[index];
+    else
+        goto end;
+    if (is_obj)
     {
-        return ZERO;
+        if (v.obj == NULL)
+            goto end;
+        size = object_size(v);
+        reply_write((char *)&size, 2);
+        reply_write_object(v);
     }
     else
     {
-        em->res = EMFRP_OK;
-        value_t tmp = emfrp_exec(em, data);
-        return tmp;
+        size = 4;
+        reply_write((char *)&size, 2);
+        reply_write((char *)&v.num, 4);
     }
+    res = EMFRP_OK;
+end:
+    reply_write((char *)&res, 1);
+    return res;
 }
 
-void emfrp_update(emfrp_machine *em)
+// frees the slots of removed nodes (PROTO_QUERY_NODE), data (PROTO_QUERY_DATA) and funcs (PROTO_REMOVE_FUNC).
+// every slot is checked before the update code is replaced, so that all of them are freed or none is
+emfrp_result_t emfrp_remove(emfrp_machine_t *em, int data_len, uint8_t *data)
 {
+    emfrp_result_t res = EMFRP_PANIC;
+    int kind, is_obj, index, num_last, n, upd_len;
+    uint8_t *slots, *update;
+    value_t *node_last = NULL;
+    value_t v;
+    if (data_len < 3)
+        goto end;
+    num_last = next_word(&data);
+    n = next_byte(&data);
+    upd_len = data_len - 3 - 4 * n;
+    if (n == 0 || upd_len <= 0)
+        goto end;
+    slots = data;
+    for (int i = 0; i < n; ++i)
+    {
+        kind = next_byte(&data);
+        next_byte(&data);
+        index = next_word(&data);
+        if (!(kind == PROTO_QUERY_NODE && index < em->node_list.len) &&
+            !(kind == PROTO_QUERY_DATA && index < em->data_list.len) &&
+            !(kind == PROTO_REMOVE_FUNC && index < em->func_list.len))
+            goto end;
+    }
+    update = (uint8_t *)malloc(upd_len);
+    if (num_last != 0)
+        node_last = (value_t *)malloc(num_last * sizeof(value_t));
+    if (update == NULL || (num_last != 0 && node_last == NULL))
+    {
+        free(update);
+        free(node_last);
+        res = EMFRP_OUTOF_MEMORY;
+        goto end;
+    }
+    memcpy(update, data, upd_len);
+    free(em->update);
+    em->update = update;
+    free(em->node_last);
+    em->node_last = node_last;
+    for (int i = 0; i < n; ++i)
+    {
+        kind = next_byte(&slots);
+        is_obj = next_byte(&slots);
+        index = next_word(&slots);
+        v = ZERO;
+        if (kind == PROTO_QUERY_NODE)
+        {
+            free(em->node_list.action[index].insns);
+            em->node_list.action[index].insns = NULL;
+            v = em->node_list.values[index];
+            em->node_list.values[index] = ZERO;
+        }
+        else if (kind == PROTO_QUERY_DATA)
+        {
+            v = em->data_list.lst[index];
+            em->data_list.lst[index] = ZERO;
+        }
+        else
+        {
+            free(em->func_list.lst[index]);
+            em->func_list.lst[index] = NULL;
+        }
+        if (is_obj)
+            drop(v);
+    }
+    res = EMFRP_OK;
+end:
+    reply_write((char *)&res, 1);
+    return res;
+}
 
-    if (em->update == NULL)
+// loads an image made by `emfrp-compiler compile` (see emfrp-compiler/src/image.rs)
+// input and output nodes of the image must be registered beforehand
+emfrp_result_t emfrp_load_image(emfrp_machine_t *em, const uint8_t *image, int len)
+{
+    uint8_t head[7];
+    if (len < 7)
+        return EMFRP_PANIC;
+    IMAGE_COPY(head, image, 7);
+    if (memcmp(head, "EMFP", 4) != 0 || head[4] != EMFRP_IMAGE_VERSION)
+        return EMFRP_PANIC;
+    int n_io = head[5] + head[6];
+    if (n_io != em->node_list.len || head[6] != em->output_nd_len)
+        return EMFRP_PANIC;
+    int p = 7;
+    // node table: type(u8) name_len(u8) name
+    for (int i = 0; i < n_io; ++i)
     {
-        em->res = EMFRP_NOTSTARTED;
-        return;
+        if (p + 2 > len)
+            return EMFRP_PANIC;
+        p += 2 + IMAGE_BYTE(image + p + 1);
     }
-    else
+    if (p + 2 > len)
+        return EMFRP_PANIC;
+    int n_frame = IMAGE_BYTE(image + p) + ((int)IMAGE_BYTE(image + p + 1) << 8);
+    p += 2;
+    for (int i = 0; i < n_frame; ++i)
     {
+        if (p + 2 > len)
+            return EMFRP_PANIC;
+        int data_len = IMAGE_BYTE(image + p) + ((int)IMAGE_BYTE(image + p + 1) << 8);
+        p += 2;
+        if (p + data_len > len)
+            return EMFRP_PANIC;
+        // the frame is read with next_word, so it is copied to RAM
+        uint8_t *data = (uint8_t *)malloc(data_len);
+        CHECK_NULL(data);
+        IMAGE_COPY(data, image + p, data_len);
+        emfrp_result_t res = emfrp_new_bytecode(em, data_len, data);
+        free(data);
+        CHECK_ERR(res);
+        p += data_len;
+    }
+    return EMFRP_OK;
+}
 
-        em->res = EMFRP_OK;
-        emfrp_exec(em, em->update);
-#ifdef EMFRP_DEBUG
-        ESP_LOGI("OBJ_CNT", "%d", obj_cnt);
+emfrp_result_t emfrp_update(emfrp_machine_t *em)
+{
+#ifdef EMFRP_DEBUG_OBJCNT
+    dbg_int("obj cnt", obj_cnt);
+#endif
+#ifdef EMFRP_MEASURE_HEAP
+    stack_max_usage = 0;
+#endif
+    if (em->update == NULL)
+        return EMFRP_OK;
+    else
+    {
+#ifdef EMFRP_MEASURE_HEAP
+        emfrp_result_t v = emfrp_exec(em, em->update);
+        dbg_int("max stack usage", stack_max_usage);
+        return v;
+#else
+        return emfrp_exec(em, em->update);
 #endif
-        return;
     }
 }